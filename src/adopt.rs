@@ -0,0 +1,557 @@
+use anyhow::Result;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::download::{ChecksumType, ChecksumVerifier};
+use crate::image::{ChecksumLayer, ImageFormat};
+use crate::iso9660::IsoImage;
+use crate::naming::{NameMap, NameRecord};
+use crate::registry::signature::SignatureStatus;
+use crate::registry::{DistroDefinition, IsoInfo, IsoRegistry, ReleaseType};
+
+/// Patterns of the isod naming scheme, tried after each distro's own pattern
+const STANDARD_PATTERNS: [&str; 2] = [
+    "{distro}-{version}-{arch}-{variant}.iso",
+    "{distro}-{version}-{arch}.iso",
+];
+
+/// What an ISO looks like from its name or volume label
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Guess {
+    pub distro: String,
+    pub version: Option<String>,
+    pub architecture: Option<String>,
+    pub variant: Option<String>,
+}
+
+impl Guess {
+    fn new(distro: &str) -> Self {
+        Self {
+            distro: distro.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Release the guess points at, when it names one completely
+    fn iso_info(&self, filename: &str) -> Option<IsoInfo> {
+        Some(IsoInfo {
+            distro: self.distro.clone(),
+            version: self.version.clone()?,
+            architecture: self.architecture.clone()?,
+            variant: self.variant.clone(),
+            filename: filename.to_string(),
+            local_filename: None,
+            format: ImageFormat::from_filename(filename).unwrap_or_default(),
+            download_sources: Vec::new(),
+            checksum: None,
+            checksum_type: None,
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        })
+    }
+
+    /// Release a name map record stands for
+    fn from_record(record: &NameRecord) -> Self {
+        Self {
+            distro: record.distro.clone(),
+            version: Some(record.version.clone()),
+            architecture: Some(record.architecture.clone()),
+            variant: record.variant.clone(),
+        }
+    }
+
+    /// Fill what this guess lacks from `other`, if both are about the same distro
+    fn merge(mut self, other: Option<Guess>) -> Self {
+        if let Some(other) = other.filter(|other| other.distro == self.distro) {
+            self.version = self.version.or(other.version);
+            self.architecture = self.architecture.or(other.architecture);
+            self.variant = self.variant.or(other.variant);
+        }
+        self
+    }
+}
+
+/// How an ISO was recognised
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Its hash is in an upstream checksum file
+    Checksum,
+    /// isod gave the file its name, see [`NameMap`]
+    Recorded,
+    Filename,
+    VolumeLabel,
+}
+
+/// An ISO recognised as a release of a known distro
+#[derive(Debug, Clone)]
+pub struct Identified {
+    /// Release, with the name the ISO currently has as its local filename
+    pub iso_info: IsoInfo,
+    pub method: Method,
+    pub signature: SignatureStatus,
+}
+
+/// Everything learnt about one ISO on a device
+#[derive(Debug)]
+pub struct Identification {
+    pub path: PathBuf,
+    /// Volume descriptors, unless the file is not an ISO9660 image
+    pub image: Option<IsoImage>,
+    pub found: Option<Identified>,
+}
+
+/// Distro, version, architecture and variant of a release
+type ReleaseKey = (String, String, String, Option<String>);
+
+/// Upstream checksums looked up during one run, so each release's checksum file is
+/// fetched once however many ISOs could be that release
+#[derive(Default)]
+pub struct ChecksumIndex {
+    latest: HashMap<String, Option<String>>,
+    /// Algorithm of the checksum each release publishes, `None` when it has none
+    releases: HashMap<ReleaseKey, Option<ChecksumType>>,
+    by_hash: HashMap<(ChecksumType, String), (IsoInfo, SignatureStatus)>,
+}
+
+impl ChecksumIndex {
+    async fn latest_version(&mut self, registry: &IsoRegistry, distro: &str) -> Option<String> {
+        if let Some(version) = self.latest.get(distro) {
+            return version.clone();
+        }
+        let version = registry
+            .get_latest_version(distro)
+            .await
+            .ok()
+            .map(|info| info.version);
+        self.latest.insert(distro.to_string(), version.clone());
+        version
+    }
+
+    /// Fetch the checksum of `release` unless it is already indexed, returning
+    /// its algorithm
+    async fn index(&mut self, registry: &IsoRegistry, release: ReleaseKey) -> Option<ChecksumType> {
+        if let Some(checksum_type) = self.releases.get(&release) {
+            return *checksum_type;
+        }
+
+        let (distro, version, architecture, variant) = &release;
+        let mut checksum_type = None;
+        if let Ok(mut iso_info) = registry
+            .get_iso_info(
+                distro,
+                Some(version),
+                Some(architecture),
+                variant.as_deref(),
+            )
+            .await
+            && let Ok(Some(checksum)) = registry.get_signed_checksum(&iso_info).await
+        {
+            iso_info.checksum = Some(checksum.value.to_lowercase());
+            iso_info.checksum_type = Some(checksum.checksum_type.algorithm().to_string());
+            iso_info.checksum_layer = checksum.layer;
+            self.by_hash.insert(
+                (checksum.checksum_type, checksum.value.to_lowercase()),
+                (iso_info, checksum.signature),
+            );
+            checksum_type = Some(checksum.checksum_type);
+        }
+        self.releases.insert(release, checksum_type);
+        checksum_type
+    }
+
+    /// Release whose published checksum is `value`
+    fn find(
+        &self,
+        checksum_type: ChecksumType,
+        value: &str,
+    ) -> Option<&(IsoInfo, SignatureStatus)> {
+        self.by_hash.get(&(checksum_type, value.to_lowercase()))
+    }
+}
+
+/// Guess the release from a filename, using each distro's filename pattern, the configured
+/// naming template and the isod scheme
+pub fn guess_from_filename(registry: &IsoRegistry, filename: &str) -> Option<Guess> {
+    for distro in registry.get_all_distros() {
+        let Some(definition) = registry.get_distro(distro) else {
+            continue;
+        };
+        let patterns = [
+            definition.filename_pattern.as_str(),
+            registry.naming_template(),
+        ]
+        .into_iter()
+        .chain(STANDARD_PATTERNS);
+        for pattern in patterns {
+            let Some(regex) = pattern_regex(pattern, definition) else {
+                continue;
+            };
+            if let Some(captures) = regex.captures(filename) {
+                let capture = |name: &str, values: &[String]| {
+                    captures
+                        .name(name)
+                        .filter(|m| !m.as_str().is_empty())
+                        .map(|m| canonical(m.as_str(), values))
+                };
+                return Some(Guess {
+                    distro: definition.name.clone(),
+                    version: captures.name("version").map(|m| m.as_str().to_string()),
+                    architecture: capture("arch", &definition.supported_architectures),
+                    variant: capture("variant", &definition.supported_variants)
+                        .or_else(|| definition.default_variant.clone()),
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Guess the release from a volume label such as `Debian 12.11.0 amd64 n`
+pub fn guess_from_label(registry: &IsoRegistry, label: &str) -> Option<Guess> {
+    let lower = label.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '.')
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let definition = registry
+        .get_all_distros()
+        .into_iter()
+        .filter_map(|distro| registry.get_distro(distro))
+        .find(|definition| {
+            let display = definition.display_name.to_lowercase();
+            let display = display.split_whitespace().next().unwrap_or_default();
+            lower.contains(&definition.name) || (!display.is_empty() && lower.contains(display))
+        })?;
+
+    let find = |values: &[String]| {
+        values
+            .iter()
+            .find(|value| words.contains(&value.to_lowercase().as_str()))
+            .cloned()
+    };
+    // Dates and build numbers in labels are rarely the release version, so only
+    // dotted versions are trusted
+    let version = Regex::new(r"\b\d+(?:\.\d+)+\b")
+        .ok()
+        .and_then(|regex| regex.find(&lower).map(|m| m.as_str().to_string()));
+
+    Some(Guess {
+        version,
+        architecture: find(&definition.supported_architectures),
+        variant: find(&definition.supported_variants),
+        ..Guess::new(&definition.name)
+    })
+}
+
+/// Identify the ISO at `path`: by checksum when the upstream checksum files know it,
+/// otherwise by its filename and volume label
+pub async fn identify(
+    registry: &IsoRegistry,
+    names: &NameMap,
+    checksums: &mut ChecksumIndex,
+    path: &Path,
+) -> Identification {
+    let image = IsoImage::open(path).ok();
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // A name isod handed out says exactly which release the file is
+    let recorded = path
+        .parent()
+        .and_then(|directory| names.find(directory, &filename));
+    let by_filename = guess_from_filename(registry, &filename);
+    let by_label = image
+        .as_ref()
+        .and_then(|image| guess_from_label(registry, &image.volume_id));
+    let guess = match (recorded, by_filename, by_label) {
+        (Some(record), _, _) => Some((Method::Recorded, Guess::from_record(record))),
+        (None, Some(by_filename), by_label) => {
+            Some((Method::Filename, by_filename.merge(by_label)))
+        }
+        (None, None, Some(by_label)) => Some((Method::VolumeLabel, by_label)),
+        (None, None, None) => None,
+    };
+
+    // Without any hint every distro is a candidate
+    let candidates = match &guess {
+        Some((_, guess)) => vec![guess.clone()],
+        None => registry
+            .get_all_distros()
+            .into_iter()
+            .map(Guess::new)
+            .collect(),
+    };
+
+    let found = match match_checksum(registry, checksums, path, &filename, candidates).await {
+        Some((iso_info, signature)) => Some(Identified {
+            iso_info,
+            method: Method::Checksum,
+            signature,
+        }),
+        None => guess.and_then(|(method, guess)| {
+            let mut iso_info = guess.iso_info(&filename)?;
+            if let Some(record) = recorded {
+                iso_info.filename = record.upstream_filename.clone();
+                iso_info.local_filename = Some(filename.clone());
+            }
+            Some(Identified {
+                iso_info,
+                method,
+                signature: SignatureStatus::unsigned("identified by name, not by checksum"),
+            })
+        }),
+    };
+
+    Identification {
+        path: path.to_path_buf(),
+        image,
+        found,
+    }
+}
+
+/// Look the file's hash up in the checksum files of every release the candidates allow
+async fn match_checksum(
+    registry: &IsoRegistry,
+    checksums: &mut ChecksumIndex,
+    path: &Path,
+    filename: &str,
+    candidates: Vec<Guess>,
+) -> Option<(IsoInfo, SignatureStatus)> {
+    let mut hashed: HashMap<ChecksumType, String> = HashMap::new();
+
+    for guess in candidates {
+        let Some(definition) = registry.get_distro(&guess.distro) else {
+            continue;
+        };
+        let version = match guess.version {
+            Some(version) => version,
+            None => match checksums.latest_version(registry, &guess.distro).await {
+                Some(version) => version,
+                None => continue,
+            },
+        };
+        let architectures = match guess.architecture {
+            Some(architecture) => vec![architecture],
+            None => definition.supported_architectures.clone(),
+        };
+        let variants: Vec<Option<String>> = match guess.variant {
+            Some(variant) => vec![Some(variant)],
+            None if definition.supported_variants.is_empty() => vec![None],
+            None => definition
+                .supported_variants
+                .iter()
+                .cloned()
+                .map(Some)
+                .collect(),
+        };
+
+        for architecture in &architectures {
+            for variant in &variants {
+                let release = (
+                    guess.distro.clone(),
+                    version.clone(),
+                    architecture.clone(),
+                    variant.clone(),
+                );
+                let Some(checksum_type) = checksums.index(registry, release).await else {
+                    continue;
+                };
+
+                let actual = match hashed.get(&checksum_type) {
+                    Some(actual) => actual.clone(),
+                    None => {
+                        let actual = hash(path, checksum_type).await.ok()?;
+                        hashed.insert(checksum_type, actual.clone());
+                        actual
+                    }
+                };
+
+                if let Some((iso_info, signature)) = checksums.find(checksum_type, &actual) {
+                    let mut iso_info = iso_info.clone();
+                    iso_info.local_filename = Some(filename.to_string());
+                    return Some((iso_info, signature.clone()));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+async fn hash(path: &Path, checksum_type: ChecksumType) -> Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        ChecksumVerifier::calculate_checksum_blocking(&path, checksum_type)
+    })
+    .await?
+}
+
+/// Case-insensitive regex for a filename pattern with `{distro}`, `{version}`,
+/// `{arch}` and `{variant}` placeholders
+fn pattern_regex(pattern: &str, definition: &DistroDefinition) -> Option<Regex> {
+    let alternation = |values: &[String]| {
+        values
+            .iter()
+            .map(|value| regex::escape(value))
+            .collect::<Vec<_>>()
+            .join("|")
+    };
+
+    let mut expression = String::from("(?i)^");
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        let end = start + rest[start..].find('}')?;
+        expression.push_str(&regex::escape(&rest[..start]));
+        match &rest[start + 1..end] {
+            "distro" => expression.push_str(&regex::escape(&definition.name)),
+            "version" => expression.push_str(r"(?P<version>\d[\w.+~]*?)"),
+            "arch" => expression.push_str(&format!(
+                "(?P<arch>{})",
+                alternation(&definition.supported_architectures)
+            )),
+            "variant" => expression.push_str(&format!(
+                "(?P<variant>{})",
+                alternation(&definition.supported_variants)
+            )),
+            _ => return None,
+        }
+        rest = &rest[end + 1..];
+    }
+    expression.push_str(&regex::escape(rest));
+    expression.push('$');
+
+    Regex::new(&expression).ok()
+}
+
+/// Spelling of `value` used in the distro definition
+fn canonical(value: &str, values: &[String]) -> String {
+    values
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(value))
+        .cloned()
+        .unwrap_or_else(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::naming;
+    use crate::registry::definitions::DistroSpec;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_guesses_from_filename_and_label() {
+        let registry = IsoRegistry::new();
+
+        let guess = guess_from_filename(&registry, "ubuntu-24.04.2-desktop-amd64.iso").unwrap();
+        assert_eq!(guess.distro, "ubuntu");
+        assert_eq!(guess.version.as_deref(), Some("24.04.2"));
+        assert_eq!(guess.architecture.as_deref(), Some("amd64"));
+        assert_eq!(guess.variant.as_deref(), Some("desktop"));
+
+        let guess = guess_from_filename(&registry, "archlinux-2025.06.01-x86_64.iso").unwrap();
+        assert_eq!(
+            guess
+                .iso_info("archlinux-2025.06.01-x86_64.iso")
+                .map(|i| naming::render(naming::DEFAULT_TEMPLATE, &i)),
+            Some("arch-2025.06.01-x86_64-base.iso".to_string())
+        );
+
+        // Already renamed to the isod scheme
+        let guess = guess_from_filename(&registry, "debian-12.11.0-amd64-netinst.iso").unwrap();
+        assert_eq!(guess.variant.as_deref(), Some("netinst"));
+        assert_eq!(guess_from_filename(&registry, "my-rescue-stick.iso"), None);
+
+        let guess = guess_from_label(&registry, "Debian 12.11.0 amd64 n").unwrap();
+        assert_eq!(guess.distro, "debian");
+        assert_eq!(guess.version.as_deref(), Some("12.11.0"));
+        assert_eq!(guess.architecture.as_deref(), Some("amd64"));
+
+        let guess = guess_from_label(&registry, "ARCH_202506").unwrap();
+        assert_eq!(guess, Guess::new("arch"));
+        assert_eq!(guess_from_label(&registry, "CDROM"), None);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_files_are_fetched_once_per_run() {
+        let amd64 = b"amd64 image".to_vec();
+        let arm64 = b"arm64 image".to_vec();
+        let sums = |content: &[u8]| format!("{:x}  labos-1.0.iso\n", Sha256::digest(content));
+        let (amd64_sums, arm64_sums) = (sums(&amd64), sums(&arm64));
+
+        // Serve one checksum file per architecture, counting requests
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let body = if request.contains("/arm64/") {
+                    &arm64_sums
+                } else {
+                    &amd64_sums
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let spec: DistroSpec = toml::from_str(&format!(
+            r#"
+name = "labos"
+architectures = ["amd64", "arm64"]
+filename_pattern = "labos-{{version}}.iso"
+checksum_urls = ["http://{addr}/{{arch}}/SHA256SUMS"]
+
+[detector]
+type = "static"
+versions = [{{ version = "1.0" }}]
+
+[[sources]]
+url = "http://{addr}/{{filename}}"
+"#
+        ))
+        .unwrap();
+        let mut registry = IsoRegistry::new();
+        registry.add_custom_distro(spec.into_definition().unwrap());
+
+        // Two copies of different architectures, both only named after the version
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("a").join("labos-1.0.iso");
+        let second = dir.path().join("b").join("labos-1.0.iso");
+        for (path, content) in [(&first, &arm64), (&second, &amd64)] {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let names = NameMap::load(NameMap::path_in(dir.path())).unwrap();
+        let mut checksums = ChecksumIndex::default();
+        for (path, architecture) in [(&first, "arm64"), (&second, "amd64")] {
+            let found = identify(&registry, &names, &mut checksums, path)
+                .await
+                .found
+                .unwrap();
+            assert_eq!(found.method, Method::Checksum);
+            assert_eq!(found.iso_info.architecture, architecture);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,5 +1,6 @@
 use clap::{Parser, Subcommand};
 use std::ffi::OsString;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "isod")]
@@ -81,6 +82,11 @@ pub enum Commands {
         #[arg(long)]
         #[arg(help = "Include beta and development versions")]
         include_beta: bool,
+
+        /// Download rate limit override
+        #[arg(long, value_name = "RATE")]
+        #[arg(help = "Limit download speed (e.g. 500K, 2M, unlimited), overrides config")]
+        limit_rate: Option<String>,
     },
 
     /// List available/installed ISOs
@@ -156,6 +162,11 @@ pub enum Commands {
         #[arg(short, long)]
         #[arg(help = "Download missing ISOs after sync")]
         download: bool,
+
+        /// Put exactly the ISOs recorded in isod.lock on the device
+        #[arg(long)]
+        #[arg(help = "Reproduce the ISO set recorded in isod.lock")]
+        locked: bool,
     },
 
     /// Manage configuration
@@ -231,6 +242,11 @@ pub enum Commands {
         #[arg(long)]
         #[arg(help = "Verify checksum after download")]
         verify: bool,
+
+        /// Download rate limit override
+        #[arg(long, value_name = "RATE")]
+        #[arg(help = "Limit download speed (e.g. 500K, 2M, unlimited), overrides config")]
+        limit_rate: Option<String>,
     },
 
     /// Search for distributions
@@ -269,6 +285,190 @@ pub enum Commands {
         #[arg(help = "Show supported architectures and variants")]
         details: bool,
     },
+
+    /// Manage the download queue
+    #[command(visible_alias = "q")]
+    Queue {
+        #[command(subcommand)]
+        action: QueueAction,
+    },
+
+    /// Show or reset the recorded mirror health
+    Mirrors {
+        #[command(subcommand)]
+        action: Option<MirrorAction>,
+    },
+
+    /// Hash ISOs on Ventoy devices and in the download library.
+    ///
+    /// Exits with 0 when all are OK, 2 on a mismatch, 3 when a recorded ISO is
+    /// missing and 4 when some ISOs have no known checksum. Exit code 1 means
+    /// the verification itself failed.
+    Verify {
+        /// Only verify ISOs of this distribution
+        distro: Option<String>,
+
+        /// USB mount point override
+        #[arg(short, long, value_name = "PATH")]
+        #[arg(help = "Verify the device mounted here instead of all Ventoy devices")]
+        mount_point: Option<String>,
+
+        /// Only verify ISOs on devices
+        #[arg(long, conflicts_with = "library")]
+        device: bool,
+
+        /// Only verify ISOs in the download library
+        #[arg(long)]
+        library: bool,
+
+        /// Also compare with freshly fetched upstream checksums
+        #[arg(short, long)]
+        upstream: bool,
+
+        /// Number of files hashed at once
+        #[arg(short, long, default_value = "2", value_name = "N")]
+        jobs: usize,
+    },
+
+    /// Identify ISOs copied onto a Ventoy device by hand and start tracking them
+    Adopt {
+        /// USB mount point override
+        #[arg(short, long, value_name = "PATH")]
+        #[arg(help = "Adopt ISOs on the device mounted here instead of all Ventoy devices")]
+        mount_point: Option<String>,
+
+        /// Rename identified ISOs to the isod naming scheme
+        #[arg(short, long)]
+        rename: bool,
+
+        /// Add the identified distros to the configuration
+        #[arg(short, long)]
+        add_distros: bool,
+
+        /// Only show what would be done
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Show where an ISO came from and how it was verified
+    Provenance {
+        /// ISO file on a device or in the library
+        file: PathBuf,
+
+        /// Print the raw provenance document
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Read an ISO's volume descriptors and boot catalog to check it is complete and bootable
+    Inspect {
+        /// ISO file to inspect
+        file: PathBuf,
+
+        /// Print the inspection result as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show the lockfile or what `isod update` would change in it
+    Lock {
+        #[command(subcommand)]
+        action: Option<LockAction>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum QueueAction {
+    /// List queued downloads
+    #[command(visible_alias = "ls")]
+    List {
+        /// Include finished downloads
+        #[arg(short, long)]
+        #[arg(help = "Include finished downloads")]
+        all: bool,
+    },
+
+    /// Pause a download, keeping the partial file
+    Pause {
+        /// Job ID (or unique prefix)
+        id: String,
+    },
+
+    /// Resume a paused or failed download
+    Resume {
+        /// Job ID (or unique prefix)
+        id: String,
+    },
+
+    /// Change the priority of a download
+    Priority {
+        /// Job ID (or unique prefix)
+        id: String,
+
+        /// New priority (higher runs first)
+        #[arg(allow_negative_numbers = true)]
+        priority: i32,
+    },
+
+    /// Remove a download from the queue
+    Cancel {
+        /// Job ID (or unique prefix)
+        id: String,
+
+        /// Also delete the partially downloaded file
+        #[arg(long)]
+        #[arg(help = "Also delete the partially downloaded file")]
+        delete: bool,
+    },
+
+    /// Run all queued downloads
+    Run {
+        /// Maximum concurrent downloads (defaults to config)
+        #[arg(short, long, value_name = "N")]
+        #[arg(help = "Maximum concurrent downloads")]
+        max_concurrent: Option<u8>,
+
+        /// Download rate limit override
+        #[arg(long, value_name = "RATE")]
+        #[arg(help = "Limit download speed (e.g. 500K, 2M, unlimited), overrides config")]
+        limit_rate: Option<String>,
+    },
+
+    /// Remove finished downloads from the queue
+    Clear,
+}
+
+#[derive(Subcommand)]
+pub enum MirrorAction {
+    /// Show success, failure and throughput history per mirror
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Forget the history of a mirror so it is ranked from scratch
+    Reset {
+        /// Mirror URL, host or host:port
+        #[arg(required_unless_present = "all")]
+        mirror: Option<String>,
+
+        /// Reset every mirror
+        #[arg(long, conflicts_with = "mirror")]
+        #[arg(help = "Reset every mirror")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum LockAction {
+    /// List the locked ISOs
+    #[command(visible_alias = "ls")]
+    Show,
+
+    /// Resolve the configured ISOs again and show how they differ from the lock
+    Diff {
+        /// Include beta releases, as `isod update --include-beta` would
+        #[arg(long)]
+        include_beta: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -469,11 +669,23 @@ impl Cli {
                     return Err("Max concurrent downloads must be between 1 and 10".to_string());
                 }
             }
+            Commands::Queue {
+                action:
+                    QueueAction::Run {
+                        max_concurrent: Some(max_concurrent),
+                        ..
+                    },
+            } if *max_concurrent == 0 || *max_concurrent > 10 => {
+                return Err("Max concurrent downloads must be between 1 and 10".to_string());
+            }
             Commands::Search { limit, .. } => {
                 if *limit == 0 || *limit > 100 {
                     return Err("Search limit must be between 1 and 100".to_string());
                 }
             }
+            Commands::Verify { jobs, .. } if *jobs == 0 || *jobs > 16 => {
+                return Err("Verify jobs must be between 1 and 16".to_string());
+            }
             _ => {}
         }
         Ok(())
@@ -516,6 +728,19 @@ impl Cli {
         )
     }
 
+    /// Check if command runs downloads that Ctrl-C should cancel cleanly
+    pub fn starts_downloads(&self) -> bool {
+        matches!(
+            self.command,
+            Commands::Download { .. }
+                | Commands::Update { .. }
+                | Commands::Sync { .. }
+                | Commands::Queue {
+                    action: QueueAction::Run { .. }
+                }
+        )
+    }
+
     /// Check if command modifies configuration
     pub fn modifies_config(&self) -> bool {
         matches!(
@@ -588,6 +813,121 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_queue_subcommands() {
+        let cli = Cli::try_parse_from(["isod", "queue", "priority", "ubuntu_1a2b", "-5"]).unwrap();
+        if let Commands::Queue {
+            action: QueueAction::Priority { id, priority },
+        } = cli.command
+        {
+            assert_eq!(id, "ubuntu_1a2b");
+            assert_eq!(priority, -5);
+        } else {
+            panic!("Expected Queue priority command");
+        }
+
+        let cli = Cli::try_parse_from(["isod", "q", "run", "--max-concurrent", "0"]).unwrap();
+        assert!(cli.validate().is_err());
+    }
+
+    #[test]
+    fn test_mirrors_subcommands() {
+        let cli = Cli::try_parse_from(["isod", "mirrors"]).unwrap();
+        assert!(matches!(cli.command, Commands::Mirrors { action: None }));
+
+        let cli = Cli::try_parse_from(["isod", "mirrors", "reset", "mirror.example.org"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Mirrors {
+                action: Some(MirrorAction::Reset {
+                    mirror: Some(_),
+                    all: false
+                })
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["isod", "mirrors", "reset"]).is_err());
+        assert!(Cli::try_parse_from(["isod", "mirrors", "reset", "x", "--all"]).is_err());
+    }
+
+    #[test]
+    fn test_provenance_command() {
+        let cli = Cli::try_parse_from(["isod", "provenance", "/media/ventoy/iso/x.iso", "--json"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Provenance { json: true, .. }
+        ));
+        assert!(Cli::try_parse_from(["isod", "provenance"]).is_err());
+    }
+
+    #[test]
+    fn test_adopt_command() {
+        let cli = Cli::try_parse_from(["isod", "adopt", "--rename", "-a", "-n"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Adopt {
+                mount_point: None,
+                rename: true,
+                add_distros: true,
+                dry_run: true,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_inspect_command() {
+        let cli = Cli::try_parse_from(["isod", "inspect", "archlinux.iso"]).unwrap();
+        assert!(matches!(cli.command, Commands::Inspect { json: false, .. }));
+        assert!(Cli::try_parse_from(["isod", "inspect"]).is_err());
+    }
+
+    #[test]
+    fn test_lock_commands() {
+        let cli = Cli::try_parse_from(["isod", "lock"]).unwrap();
+        assert!(matches!(cli.command, Commands::Lock { action: None }));
+
+        let cli = Cli::try_parse_from(["isod", "lock", "diff", "--include-beta"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Lock {
+                action: Some(LockAction::Diff { include_beta: true })
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["isod", "sync", "--locked", "--auto"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Sync {
+                locked: true,
+                auto: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_verify_command() {
+        let cli = Cli::try_parse_from(["isod", "verify", "debian", "--library", "-u", "-j", "4"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Verify {
+                distro: Some(_),
+                device: false,
+                library: true,
+                upstream: true,
+                jobs: 4,
+                ..
+            }
+        ));
+        assert!(cli.validate().is_ok());
+
+        let cli = Cli::try_parse_from(["isod", "verify", "-j", "0"]).unwrap();
+        assert!(cli.validate().is_err());
+        assert!(Cli::try_parse_from(["isod", "verify", "--device", "--library"]).is_err());
+    }
+
     #[test]
     fn test_validation() {
         let cli = Cli::try_parse_from(["isod", "add", "ubuntu"]).unwrap();
@@ -606,5 +946,11 @@ mod tests {
         let cli = Cli::try_parse_from(["isod", "add", "ubuntu"]).unwrap();
         assert!(!cli.requires_usb());
         assert!(cli.modifies_config());
+        assert!(!cli.starts_downloads());
+
+        let cli = Cli::try_parse_from(["isod", "queue", "run"]).unwrap();
+        assert!(cli.starts_downloads());
+        let cli = Cli::try_parse_from(["isod", "queue", "list"]).unwrap();
+        assert!(!cli.starts_downloads());
     }
 }
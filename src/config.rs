@@ -2,9 +2,14 @@ use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::download::SOURCE_SCHEMES;
+use crate::policy::PolicyConfig;
+use crate::registry::sources::SourcePriority;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
@@ -14,6 +19,13 @@ pub struct Config {
     #[serde(default)]
     pub sources: SourcesConfig,
     #[serde(default)]
+    pub network: NetworkConfig,
+    /// Credentials keyed by host (or host:port)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub credentials: HashMap<String, CredentialConfig>,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    #[serde(default)]
     pub distros: HashMap<String, DistroConfig>,
 }
 
@@ -27,6 +39,26 @@ pub struct GeneralConfig {
     pub auto_cleanup_old_versions: bool,
     #[serde(default = "default_check_interval_days")]
     pub check_interval_days: u32,
+    /// Global download rate limit, e.g. "2M" (bytes per second, K/M/G suffixes)
+    #[serde(default)]
+    pub bandwidth_limit: Option<String>,
+    /// Time-of-day windows overriding `bandwidth_limit`
+    #[serde(default)]
+    pub bandwidth_windows: Vec<BandwidthWindowConfig>,
+    /// Local ISO filename, from {distro}, {version}, {arch}, {variant} and {filename} (upstream name)
+    #[serde(default = "default_naming_template")]
+    pub naming_template: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BandwidthWindowConfig {
+    /// Start time (HH:MM, local time)
+    pub start: String,
+    /// End time (HH:MM, local time), may be earlier than start to wrap past midnight
+    pub end: String,
+    /// Rate limit during the window, unlimited if omitted
+    #[serde(default)]
+    pub limit: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,12 +75,182 @@ pub struct UsbConfig {
 pub struct SourcesConfig {
     #[serde(default = "default_enable_mirrors")]
     pub enable_mirrors: bool,
+    /// Mirrors used for every distro (or those listed in a mirror's `distros`)
     #[serde(default)]
-    pub custom_mirrors: Vec<String>,
+    pub custom_mirrors: Vec<CustomMirror>,
+    /// Drop the mirrors that ship with the built-in distro definitions
+    #[serde(default)]
+    pub disable_builtin_mirrors: bool,
     #[serde(default = "default_mirror_timeout_secs")]
     pub mirror_timeout_secs: u64,
+    /// Region code (e.g. "AU", "DE") whose mirrors are preferred
+    #[serde(default)]
+    pub preferred_region: Option<String>,
+    /// Measure latency and throughput of the best candidates before downloading
+    #[serde(default)]
+    pub probe_mirrors: bool,
+    /// Number of candidates to probe
+    #[serde(default = "default_probe_candidates")]
+    pub probe_candidates: usize,
+    /// Merge mirrors from the distros' official mirror lists with the built-in ones
+    #[serde(default = "default_enabled")]
+    pub discover_mirrors: bool,
+    /// How long a downloaded mirror list is reused before fetching it again
+    #[serde(default = "default_mirror_list_ttl_hours")]
+    pub mirror_list_ttl_hours: u64,
+    /// Compare size and digest headers of the top candidates and skip mirrors that disagree
+    #[serde(default = "default_enabled")]
+    pub check_consistency: bool,
+}
+
+impl SourcesConfig {
+    /// Candidates to probe before a download, 0 when probing is disabled
+    pub fn probe_count(&self) -> usize {
+        if self.probe_mirrors {
+            self.probe_candidates
+        } else {
+            0
+        }
+    }
+
+    /// Candidates to compare before a download, 0 when the check is disabled
+    pub fn consistency_count(&self) -> usize {
+        if self.check_consistency {
+            CONSISTENCY_CANDIDATES
+        } else {
+            0
+        }
+    }
+}
+
+/// User mirror, written either as a plain base URL or as a table.
+///
+/// URLs accept `{distro}`, `{version}`, `{arch}`, `{variant}` and `{filename}`;
+/// `/{filename}` is appended when the URL does not mention it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "MirrorEntry")]
+pub struct CustomMirror {
+    pub url: String,
+    pub priority: SourcePriority,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// Restrict a global mirror to these distros (all distros when empty)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub distros: Vec<String>,
+}
+
+impl CustomMirror {
+    /// URL template pointing at the ISO itself
+    pub fn url_template(&self) -> String {
+        if self.url.contains("{filename}") {
+            self.url.clone()
+        } else {
+            format!("{}/{{filename}}", self.url.trim_end_matches('/'))
+        }
+    }
+
+    pub fn applies_to(&self, distro: &str) -> bool {
+        self.distros.is_empty() || self.distros.iter().any(|d| d == distro)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MirrorEntry {
+    Url(String),
+    Table {
+        url: String,
+        #[serde(default = "default_mirror_priority")]
+        priority: SourcePriority,
+        #[serde(default)]
+        region: Option<String>,
+        #[serde(default)]
+        distros: Vec<String>,
+    },
+}
+
+impl From<MirrorEntry> for CustomMirror {
+    fn from(entry: MirrorEntry) -> Self {
+        match entry {
+            MirrorEntry::Url(url) => Self {
+                url,
+                priority: default_mirror_priority(),
+                region: None,
+                distros: Vec::new(),
+            },
+            MirrorEntry::Table {
+                url,
+                priority,
+                region,
+                distros,
+            } => Self {
+                url,
+                priority,
+                region,
+                distros,
+            },
+        }
+    }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Time allowed to establish a connection
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Time a transfer may stall without receiving data
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// Total time for metadata requests (feeds, APIs, checksums); ISO transfers are exempt
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    /// Proxy for all traffic, e.g. "http://proxy:3128" or "socks5h://127.0.0.1:1080"
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Proxy for plain HTTP requests, overriding `proxy`
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    /// Proxy for HTTPS requests, overriding `proxy`
+    #[serde(default)]
+    pub https_proxy: Option<String>,
+    /// Comma-separated hosts that bypass the configured proxies
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+    /// Extra PEM CA certificates to trust, e.g. for a TLS-inspecting proxy
+    #[serde(default)]
+    pub ca_certificates: Vec<PathBuf>,
+    /// Override the default "isod/<version>" user agent
+    #[serde(default)]
+    pub user_agent: Option<String>,
+}
+
+/// Basic auth (`username` + `password`) or a bearer `token` for one host
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CredentialConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for CredentialConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CredentialConfig")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("token", &self.token.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+/// Placeholder written instead of secrets when showing or exporting config
+pub const REDACTED: &str = "********";
+
+/// Mirrors compared with each other before a download
+const CONSISTENCY_CANDIDATES: usize = 5;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DistroConfig {
     #[serde(default)]
@@ -59,6 +261,12 @@ pub struct DistroConfig {
     pub check_interval_days: u32,
     #[serde(default = "default_enabled")]
     pub enabled: bool,
+    /// Mirrors for this distro only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<CustomMirror>,
+    /// Drop the mirrors that ship with this distro's built-in definition
+    #[serde(default)]
+    pub disable_builtin_mirrors: bool,
 }
 
 // Default value functions
@@ -74,6 +282,9 @@ fn default_auto_cleanup() -> bool {
 fn default_check_interval_days() -> u32 {
     7
 }
+fn default_naming_template() -> String {
+    crate::naming::DEFAULT_TEMPLATE.to_string()
+}
 fn default_iso_path() -> String {
     "iso".to_string()
 }
@@ -83,9 +294,27 @@ fn default_metadata_file() -> String {
 fn default_enable_mirrors() -> bool {
     true
 }
+fn default_mirror_priority() -> SourcePriority {
+    SourcePriority::Preferred
+}
 fn default_mirror_timeout_secs() -> u64 {
     30
 }
+fn default_connect_timeout_secs() -> u64 {
+    15
+}
+fn default_read_timeout_secs() -> u64 {
+    60
+}
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+fn default_probe_candidates() -> usize {
+    3
+}
+fn default_mirror_list_ttl_hours() -> u64 {
+    24
+}
 fn default_enabled() -> bool {
     true
 }
@@ -97,6 +326,9 @@ impl Default for GeneralConfig {
             prefer_torrents: default_prefer_torrents(),
             auto_cleanup_old_versions: default_auto_cleanup(),
             check_interval_days: default_check_interval_days(),
+            bandwidth_limit: None,
+            bandwidth_windows: Vec::new(),
+            naming_template: default_naming_template(),
         }
     }
 }
@@ -116,7 +348,30 @@ impl Default for SourcesConfig {
         Self {
             enable_mirrors: default_enable_mirrors(),
             custom_mirrors: Vec::new(),
+            disable_builtin_mirrors: false,
             mirror_timeout_secs: default_mirror_timeout_secs(),
+            preferred_region: None,
+            probe_mirrors: false,
+            probe_candidates: default_probe_candidates(),
+            discover_mirrors: default_enabled(),
+            mirror_list_ttl_hours: default_mirror_list_ttl_hours(),
+            check_consistency: default_enabled(),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+            request_timeout_secs: default_request_timeout_secs(),
+            proxy: None,
+            http_proxy: None,
+            https_proxy: None,
+            no_proxy: None,
+            ca_certificates: Vec::new(),
+            user_agent: None,
         }
     }
 }
@@ -128,6 +383,8 @@ impl Default for DistroConfig {
             architectures: Vec::new(),
             check_interval_days: default_check_interval_days(),
             enabled: default_enabled(),
+            mirrors: Vec::new(),
+            disable_builtin_mirrors: false,
         }
     }
 }
@@ -159,14 +416,34 @@ impl Default for Config {
             general: GeneralConfig::default(),
             usb: UsbConfig::default(),
             sources: SourcesConfig::default(),
+            network: NetworkConfig::default(),
+            credentials: HashMap::new(),
+            policy: PolicyConfig::default(),
             distros,
         }
     }
 }
 
+impl Config {
+    /// Copy of the config with passwords and tokens replaced, safe to print or export
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        for credential in config.credentials.values_mut() {
+            if credential.password.is_some() {
+                credential.password = Some(REDACTED.to_string());
+            }
+            if credential.token.is_some() {
+                credential.token = Some(REDACTED.to_string());
+            }
+        }
+        config
+    }
+}
+
 pub struct ConfigManager {
     config_dir: PathBuf,
     config_file: PathBuf,
+    state_dir: PathBuf,
     config: Config,
 }
 
@@ -178,6 +455,10 @@ impl ConfigManager {
 
         let config_dir = project_dirs.config_dir().to_path_buf();
         let config_file = config_dir.join("config.toml");
+        let state_dir = project_dirs
+            .state_dir()
+            .unwrap_or_else(|| project_dirs.data_local_dir())
+            .to_path_buf();
 
         // Create config directory if it doesn't exist
         if !config_dir.exists() {
@@ -196,6 +477,7 @@ impl ConfigManager {
         Ok(Self {
             config_dir,
             config_file,
+            state_dir,
             config,
         })
     }
@@ -231,6 +513,11 @@ impl ConfigManager {
         &self.config_file
     }
 
+    /// Get the directory holding runtime state such as the download queue
+    pub fn state_dir(&self) -> &Path {
+        &self.state_dir
+    }
+
     /// Add or update a distro configuration
     pub fn set_distro_config(&mut self, distro: String, config: DistroConfig) {
         self.config.distros.insert(distro, config);
@@ -286,6 +573,50 @@ impl ConfigManager {
             anyhow::bail!("check_interval_days must be greater than 0");
         }
 
+        crate::download::bandwidth::BandwidthPolicy::from_config(&self.config.general)?;
+        crate::naming::validate_template(&self.config.general.naming_template)?;
+
+        // Validate network config
+        if self.config.network.connect_timeout_secs == 0
+            || self.config.network.read_timeout_secs == 0
+            || self.config.network.request_timeout_secs == 0
+        {
+            anyhow::bail!("network timeouts must be greater than 0");
+        }
+
+        if self.config.sources.mirror_timeout_secs == 0 {
+            anyhow::bail!("mirror_timeout_secs must be greater than 0");
+        }
+
+        // Validate mirrors
+        let distro_mirrors = self.config.distros.values().flat_map(|d| &d.mirrors);
+        for mirror in self
+            .config
+            .sources
+            .custom_mirrors
+            .iter()
+            .chain(distro_mirrors)
+        {
+            let scheme = mirror.url.split_once("://").map(|(scheme, _)| scheme);
+            if !scheme.is_some_and(|s| SOURCE_SCHEMES.contains(&s)) {
+                anyhow::bail!(
+                    "mirror URL must use one of {}: '{}'",
+                    SOURCE_SCHEMES.join(", "),
+                    mirror.url
+                );
+            }
+        }
+
+        // Validate credentials
+        for (host, credential) in &self.config.credentials {
+            if credential.token.is_none() && credential.username.is_none() {
+                anyhow::bail!(
+                    "credentials for '{}' need either a token or a username",
+                    host
+                );
+            }
+        }
+
         // Validate USB config
         if self.config.usb.iso_path.is_empty() {
             anyhow::bail!("iso_path cannot be empty");
@@ -0,0 +1,298 @@
+use reqwest::{RequestBuilder, Url};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::config::{Config, REDACTED};
+
+/// Authentication attached to requests for a host
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credential {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+impl Credential {
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match self {
+            Credential::Basic { username, password } => {
+                request.basic_auth(username, password.as_ref())
+            }
+            Credential::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &REDACTED)
+                .finish(),
+            Credential::Bearer(_) => f.debug_tuple("Bearer").field(&REDACTED).finish(),
+        }
+    }
+}
+
+/// Credentials looked up per host from config, environment and `~/.netrc`.
+///
+/// Config entries win over environment variables, which win over netrc.
+/// reqwest drops the `Authorization` header when a redirect leaves the host,
+/// so credentials are never forwarded to a mirror they were not meant for.
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStore {
+    configured: HashMap<String, Credential>,
+    netrc: Netrc,
+}
+
+impl CredentialStore {
+    pub fn from_config(config: &Config) -> Self {
+        let configured = config
+            .credentials
+            .iter()
+            .filter_map(|(host, entry)| {
+                let credential = match (&entry.token, &entry.username) {
+                    (Some(token), _) => Credential::Bearer(token.clone()),
+                    (None, Some(username)) => Credential::Basic {
+                        username: username.clone(),
+                        password: entry.password.clone(),
+                    },
+                    (None, None) => return None,
+                };
+                Some((host.to_lowercase(), credential))
+            })
+            .collect();
+
+        let netrc = netrc_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|content| Netrc::parse(&content))
+            .unwrap_or_default();
+
+        Self { configured, netrc }
+    }
+
+    /// Credential for `url`, unless the URL already carries its own user info
+    pub fn for_url(&self, url: &Url) -> Option<Credential> {
+        if !url.username().is_empty() {
+            return None;
+        }
+
+        let host = url.host_str()?.to_lowercase();
+        let host_port = url.port().map(|port| format!("{}:{}", host, port));
+
+        host_port
+            .as_ref()
+            .and_then(|key| self.configured.get(key))
+            .or_else(|| self.configured.get(&host))
+            .cloned()
+            .or_else(|| env_credential(&host, |name| std::env::var(name).ok()))
+            .or_else(|| self.netrc.get(&host))
+    }
+}
+
+/// Credentials from `ISOD_TOKEN_<HOST>` or `ISOD_USERNAME_<HOST>`/`ISOD_PASSWORD_<HOST>`,
+/// where `<HOST>` is upper-cased with non-alphanumerics replaced by `_`.
+/// GitHub hosts also accept the usual `GITHUB_TOKEN`/`GH_TOKEN`.
+fn env_credential(host: &str, var: impl Fn(&str) -> Option<String>) -> Option<Credential> {
+    let suffix: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if let Some(token) = var(&format!("ISOD_TOKEN_{}", suffix)) {
+        return Some(Credential::Bearer(token));
+    }
+    if let Some(username) = var(&format!("ISOD_USERNAME_{}", suffix)) {
+        return Some(Credential::Basic {
+            username,
+            password: var(&format!("ISOD_PASSWORD_{}", suffix)),
+        });
+    }
+
+    if host == "github.com" || host == "api.github.com" {
+        return var("GITHUB_TOKEN")
+            .or_else(|| var("GH_TOKEN"))
+            .map(Credential::Bearer);
+    }
+
+    None
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    directories::BaseDirs::new().map(|dirs| dirs.home_dir().join(".netrc"))
+}
+
+/// Parsed `.netrc` entries.
+///
+/// The `default` entry is ignored: it would send credentials to every host isod
+/// talks to, public mirrors and keyservers included.
+#[derive(Debug, Clone, Default)]
+struct Netrc {
+    machines: HashMap<String, Credential>,
+}
+
+/// Entry being read
+struct NetrcEntry {
+    machine: String,
+    login: Option<String>,
+    password: Option<String>,
+}
+
+impl Netrc {
+    fn parse(content: &str) -> Self {
+        let mut netrc = Netrc::default();
+        let mut tokens = content.split_whitespace();
+        let mut current: Option<NetrcEntry> = None;
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => {
+                    netrc.push(current.take());
+                    current = tokens.next().map(|machine| NetrcEntry {
+                        machine: machine.to_lowercase(),
+                        login: None,
+                        password: None,
+                    });
+                }
+                // Its login and password are skipped along with it
+                "default" => netrc.push(current.take()),
+                "login" => {
+                    if let (Some(entry), Some(value)) = (current.as_mut(), tokens.next()) {
+                        entry.login = Some(value.to_string());
+                    }
+                }
+                "password" => {
+                    if let (Some(entry), Some(value)) = (current.as_mut(), tokens.next()) {
+                        entry.password = Some(value.to_string());
+                    }
+                }
+                "account" => {
+                    tokens.next();
+                }
+                // Macro bodies cannot be tokenized reliably; stop at the first one
+                "macdef" => break,
+                _ => {}
+            }
+        }
+        netrc.push(current);
+
+        netrc
+    }
+
+    fn push(&mut self, entry: Option<NetrcEntry>) {
+        let Some(NetrcEntry {
+            machine,
+            login: Some(username),
+            password,
+        }) = entry
+        else {
+            return;
+        };
+
+        // The first entry for a machine wins, as in curl
+        self.machines
+            .entry(machine)
+            .or_insert(Credential::Basic { username, password });
+    }
+
+    fn get(&self, host: &str) -> Option<Credential> {
+        self.machines.get(host).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CredentialConfig;
+
+    #[test]
+    fn test_parse_netrc() {
+        let netrc = Netrc::parse(
+            "machine isos.internal.example login builder password s3cret\n\
+             machine other.example login anon\n\
+             default login guest password guest\n",
+        );
+
+        assert_eq!(
+            netrc.get("isos.internal.example"),
+            Some(Credential::Basic {
+                username: "builder".to_string(),
+                password: Some("s3cret".to_string()),
+            })
+        );
+        assert_eq!(
+            netrc.get("other.example"),
+            Some(Credential::Basic {
+                username: "anon".to_string(),
+                password: None,
+            })
+        );
+        assert_eq!(netrc.get("unknown.example"), None);
+    }
+
+    #[test]
+    fn test_env_credentials() {
+        let vars: HashMap<&str, &str> = [
+            ("ISOD_TOKEN_ISOS_INTERNAL_EXAMPLE", "abc"),
+            ("GITHUB_TOKEN", "ghp_test"),
+        ]
+        .into_iter()
+        .collect();
+        let var = |name: &str| vars.get(name).map(|v| v.to_string());
+
+        assert_eq!(
+            env_credential("isos.internal.example", var),
+            Some(Credential::Bearer("abc".to_string()))
+        );
+        assert_eq!(
+            env_credential("api.github.com", var),
+            Some(Credential::Bearer("ghp_test".to_string()))
+        );
+        assert_eq!(env_credential("example.org", var), None);
+    }
+
+    #[test]
+    fn test_config_credentials_and_redaction() {
+        let mut config = Config::default();
+        config.credentials.insert(
+            "isos.internal.example:8443".to_string(),
+            CredentialConfig {
+                username: Some("builder".to_string()),
+                password: Some("s3cret".to_string()),
+                token: None,
+            },
+        );
+
+        let store = CredentialStore::from_config(&config);
+        let url = Url::parse("https://isos.internal.example:8443/x.iso").unwrap();
+        assert!(matches!(
+            store.for_url(&url),
+            Some(Credential::Basic { username, .. }) if username == "builder"
+        ));
+
+        // User info in the URL takes precedence over stored credentials
+        let url = Url::parse("https://me@isos.internal.example:8443/x.iso").unwrap();
+        assert_eq!(store.for_url(&url), None);
+
+        let credential = store.configured.values().next().unwrap();
+        assert!(!format!("{:?}", credential).contains("s3cret"));
+
+        let shown = toml::to_string(&config.redacted()).unwrap();
+        assert!(!shown.contains("s3cret"));
+        assert!(shown.contains("builder"));
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result, bail};
+use chrono::{Local, NaiveTime};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::GeneralConfig;
+
+/// Time-of-day range with its own rate limit (`None` = unlimited)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub limit: Option<u64>,
+}
+
+impl BandwidthWindow {
+    /// Whether `time` falls in this window. Windows may wrap past midnight (22:00-06:00).
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Rate limits in bytes per second, optionally varying with the time of day
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BandwidthPolicy {
+    pub default_limit: Option<u64>,
+    pub windows: Vec<BandwidthWindow>,
+}
+
+impl BandwidthPolicy {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// A single limit applied around the clock
+    pub fn fixed(limit: Option<u64>) -> Self {
+        Self {
+            default_limit: limit,
+            windows: Vec::new(),
+        }
+    }
+
+    pub fn from_config(config: &GeneralConfig) -> Result<Self> {
+        let default_limit = match &config.bandwidth_limit {
+            Some(limit) => parse_rate(limit)?,
+            None => None,
+        };
+
+        let windows = config
+            .bandwidth_windows
+            .iter()
+            .map(|window| {
+                Ok(BandwidthWindow {
+                    start: parse_time(&window.start)?,
+                    end: parse_time(&window.end)?,
+                    limit: match &window.limit {
+                        Some(limit) => parse_rate(limit)?,
+                        None => None,
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            default_limit,
+            windows,
+        })
+    }
+
+    /// Limit in effect at `time`; the first matching window wins
+    pub fn limit_at(&self, time: NaiveTime) -> Option<u64> {
+        self.windows
+            .iter()
+            .find(|w| w.contains(time))
+            .map_or(self.default_limit, |w| w.limit)
+    }
+}
+
+/// Parse a rate such as `500K`, `2M`, `1.5MB/s` or `unlimited` into bytes per second
+pub fn parse_rate(value: &str) -> Result<Option<u64>> {
+    let normalized = value.trim().to_lowercase();
+    if matches!(normalized.as_str(), "" | "0" | "none" | "unlimited") {
+        return Ok(None);
+    }
+
+    let number = normalized
+        .trim_end_matches("/s")
+        .trim_end_matches("ib")
+        .trim_end_matches('b');
+    let (number, multiplier) = match number.chars().last() {
+        Some('k') => (&number[..number.len() - 1], 1024.0),
+        Some('m') => (&number[..number.len() - 1], 1024.0 * 1024.0),
+        Some('g') => (&number[..number.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (number, 1.0),
+    };
+
+    let amount: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid bandwidth limit: '{}'", value))?;
+    if amount < 0.0 {
+        bail!("Bandwidth limit cannot be negative: '{}'", value);
+    }
+
+    let bytes = (amount * multiplier) as u64;
+    Ok((bytes > 0).then_some(bytes))
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .with_context(|| format!("Invalid time '{}', expected HH:MM", value))
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    rate: Option<u64>,
+}
+
+/// Token bucket shared by every download of an engine.
+///
+/// The limit is looked up on each acquire, so running downloads follow
+/// time windows and policy changes without restarting.
+#[derive(Debug)]
+pub struct RateLimiter {
+    policy: Mutex<BandwidthPolicy>,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(policy: BandwidthPolicy) -> Self {
+        Self {
+            policy: Mutex::new(policy),
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last_refill: Instant::now(),
+                rate: None,
+            }),
+        }
+    }
+
+    pub fn set_policy(&self, policy: BandwidthPolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    /// Current limit in bytes per second
+    pub fn current_limit(&self) -> Option<u64> {
+        self.policy.lock().unwrap().limit_at(Local::now().time())
+    }
+
+    /// Wait until `bytes` may be transferred under the current limit
+    pub async fn acquire(&self, bytes: usize) {
+        let rate = self.current_limit();
+        let wait = self.reserve(bytes, rate, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take `bytes` from the bucket and return how long the caller must wait
+    fn reserve(&self, bytes: usize, rate: Option<u64>, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+
+        let Some(rate) = rate else {
+            bucket.rate = None;
+            bucket.tokens = 0.0;
+            bucket.last_refill = now;
+            return Duration::ZERO;
+        };
+
+        // Start from a full second of burst when the limit changes
+        if bucket.rate != Some(rate) {
+            bucket.rate = Some(rate);
+            bucket.tokens = rate as f64;
+            bucket.last_refill = now;
+        }
+
+        let elapsed = now
+            .saturating_duration_since(bucket.last_refill)
+            .as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate as f64).min(rate as f64);
+        bucket.last_refill = now;
+
+        // Going into debt keeps concurrent callers queued fairly
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate as f64)
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(BandwidthPolicy::unlimited())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        parse_time(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("unlimited").unwrap(), None);
+        assert_eq!(parse_rate("0").unwrap(), None);
+        assert_eq!(parse_rate("1000").unwrap(), Some(1000));
+        assert_eq!(parse_rate("500K").unwrap(), Some(500 * 1024));
+        assert_eq!(parse_rate("2MB/s").unwrap(), Some(2 * 1024 * 1024));
+        assert_eq!(parse_rate("1.5m").unwrap(), Some(1572864));
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_windows_wrap_midnight() {
+        let policy = BandwidthPolicy {
+            default_limit: Some(1024 * 1024),
+            windows: vec![BandwidthWindow {
+                start: time("22:00"),
+                end: time("06:00"),
+                limit: None,
+            }],
+        };
+
+        assert_eq!(policy.limit_at(time("23:30")), None);
+        assert_eq!(policy.limit_at(time("05:59")), None);
+        assert_eq!(policy.limit_at(time("06:00")), Some(1024 * 1024));
+        assert_eq!(policy.limit_at(time("12:00")), Some(1024 * 1024));
+    }
+
+    #[test]
+    fn test_token_bucket_delays_after_burst() {
+        let limiter = RateLimiter::default();
+        let start = Instant::now();
+
+        // The first second of traffic is allowed as a burst
+        assert_eq!(limiter.reserve(1000, Some(1000), start), Duration::ZERO);
+        // The next 500 bytes have to wait half a second
+        let wait = limiter.reserve(500, Some(1000), start);
+        assert_eq!(wait, Duration::from_millis(500));
+        // Time passing pays back the debt
+        let later = start + Duration::from_secs(2);
+        assert_eq!(limiter.reserve(500, Some(1000), later), Duration::ZERO);
+        // Switching to unlimited never waits
+        assert_eq!(limiter.reserve(1 << 30, None, later), Duration::ZERO);
+    }
+}
@@ -1,14 +1,181 @@
 use anyhow::{Context, Result};
+use regex::Regex;
 use std::path::Path;
+use std::sync::OnceLock;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, BufReader};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ChecksumType {
     Md5,
     Sha1,
     Sha256,
+    Sha384,
     Sha512,
+    /// BLAKE2b-512, as produced by `b2sum`
+    Blake2b,
+}
+
+impl ChecksumType {
+    /// Algorithm name as used in `IsoInfo.checksum_type` and metalink hashes
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            ChecksumType::Md5 => "md5",
+            ChecksumType::Sha1 => "sha1",
+            ChecksumType::Sha256 => "sha256",
+            ChecksumType::Sha384 => "sha384",
+            ChecksumType::Sha512 => "sha512",
+            ChecksumType::Blake2b => "blake2b",
+        }
+    }
+
+    /// Parse an algorithm name in any common spelling (`SHA-256`, `sha256`, `BLAKE2b-512`)
+    pub fn from_algorithm(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_'], "").as_str() {
+            "md5" => Some(ChecksumType::Md5),
+            "sha1" => Some(ChecksumType::Sha1),
+            "sha256" => Some(ChecksumType::Sha256),
+            "sha384" => Some(ChecksumType::Sha384),
+            "sha512" => Some(ChecksumType::Sha512),
+            "blake2b" | "blake2b512" | "b2" => Some(ChecksumType::Blake2b),
+            _ => None,
+        }
+    }
+
+    /// Length of the hex digest
+    pub fn hex_len(&self) -> usize {
+        match self {
+            ChecksumType::Md5 => 32,
+            ChecksumType::Sha1 => 40,
+            ChecksumType::Sha256 => 64,
+            ChecksumType::Sha384 => 96,
+            ChecksumType::Sha512 | ChecksumType::Blake2b => 128,
+        }
+    }
+
+    /// Most likely algorithm for a bare hex digest; 128 digits is taken as SHA-512
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            32 => Some(ChecksumType::Md5),
+            40 => Some(ChecksumType::Sha1),
+            64 => Some(ChecksumType::Sha256),
+            96 => Some(ChecksumType::Sha384),
+            128 => Some(ChecksumType::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Algorithm implied by a checksum file name such as `SHA512SUMS` or `b2sums.txt`
+    fn from_file_name(name: &str) -> Option<Self> {
+        let name = name.rsplit('/').next().unwrap_or(name).to_lowercase();
+        [
+            ("sha512", ChecksumType::Sha512),
+            ("sha384", ChecksumType::Sha384),
+            ("sha256", ChecksumType::Sha256),
+            ("sha1", ChecksumType::Sha1),
+            ("md5", ChecksumType::Md5),
+            ("b2", ChecksumType::Blake2b),
+            ("blake2", ChecksumType::Blake2b),
+        ]
+        .into_iter()
+        .find(|(marker, _)| name.contains(marker))
+        .map(|(_, checksum_type)| checksum_type)
+    }
+}
+
+/// Hash a checksum file publishes for one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishedChecksum {
+    pub checksum_type: ChecksumType,
+    /// Lowercase hex digest
+    pub value: String,
+}
+
+/// Find the checksum of `filename` in a checksum file fetched from `source`.
+///
+/// Understands coreutils lines (`hash  file`, `hash *file`), BSD tags
+/// (`SHA256 (file) = hash`), `file: hash` and single-hash sidecars like
+/// `file.iso.sha256`. When a line does not name its algorithm, it comes from
+/// the checksum file name (`b2sums.txt`, `SHA512SUMS`) or the digest length.
+pub fn parse_checksum_file(
+    content: &str,
+    filename: &str,
+    source: &str,
+) -> Option<PublishedChecksum> {
+    static BSD_TAG: OnceLock<Regex> = OnceLock::new();
+    let bsd_tag = BSD_TAG.get_or_init(|| {
+        Regex::new(r"^([A-Za-z0-9_-]+) ?\((.+)\) ?= ?([0-9a-fA-F]+)$").expect("valid regex")
+    });
+
+    let named_type = ChecksumType::from_file_name(source);
+    let source_name = source.rsplit('/').next().unwrap_or(source);
+    // A sidecar holds the hash of the file it is named after, whatever name it lists
+    let sidecar = source_name.len() > filename.len() && source_name.starts_with(filename);
+
+    let mut entries = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (checksum_type, value, file) = if let Some(captures) = bsd_tag.captures(line) {
+            let Some(checksum_type) = ChecksumType::from_algorithm(&captures[1]) else {
+                continue;
+            };
+            (
+                Some(checksum_type),
+                captures[3].to_string(),
+                Some(captures[2].to_string()),
+            )
+        } else if let Some((value, file)) = line.split_once(char::is_whitespace)
+            && is_hex(value)
+        {
+            let file = file.trim_start().trim_start_matches('*');
+            (None, value.to_string(), Some(file.to_string()))
+        } else if let Some((file, value)) = line.split_once(':')
+            && is_hex(value.trim())
+        {
+            (
+                None,
+                value.trim().to_string(),
+                Some(file.trim().to_string()),
+            )
+        } else if is_hex(line) {
+            (None, line.to_string(), None)
+        } else {
+            continue;
+        };
+
+        let checksum_type = checksum_type
+            .or(named_type.filter(|t| t.hex_len() == value.len()))
+            .or_else(|| ChecksumType::from_hex_len(value.len()));
+        let Some(checksum_type) = checksum_type.filter(|t| t.hex_len() == value.len()) else {
+            continue;
+        };
+
+        let entry = PublishedChecksum {
+            checksum_type,
+            value: value.to_lowercase(),
+        };
+        let names_file = file.as_deref().is_some_and(|f| {
+            let f = f.trim_start_matches("./");
+            f.rsplit('/').next() == Some(filename)
+        });
+        if names_file {
+            return Some(entry);
+        }
+        entries.push(entry);
+    }
+
+    match entries.as_slice() {
+        [only] if sidecar => Some(only.clone()),
+        _ => None,
+    }
+}
+
+fn is_hex(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_hexdigit())
 }
 
 pub struct ChecksumVerifier;
@@ -47,6 +214,30 @@ impl ChecksumVerifier {
                 }
                 Ok(format!("{:x}", hasher.finalize()))
             }
+            ChecksumType::Sha384 => {
+                use sha2::{Digest, Sha384};
+                let mut hasher = Sha384::new();
+                loop {
+                    let bytes_read = reader.read(&mut buffer).await?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..bytes_read]);
+                }
+                Ok(format!("{:x}", hasher.finalize()))
+            }
+            ChecksumType::Blake2b => {
+                use blake2::{Blake2b512, Digest};
+                let mut hasher = Blake2b512::new();
+                loop {
+                    let bytes_read = reader.read(&mut buffer).await?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..bytes_read]);
+                }
+                Ok(format!("{:x}", hasher.finalize()))
+            }
             ChecksumType::Sha512 => {
                 use sha2::{Digest, Sha512};
                 let mut hasher = Sha512::new();
@@ -85,4 +276,238 @@ impl ChecksumVerifier {
             }
         }
     }
+
+    /// Synchronous variant of `calculate_checksum` for use on a blocking thread
+    pub fn calculate_checksum_blocking(
+        file_path: &Path,
+        checksum_type: ChecksumType,
+    ) -> Result<String> {
+        let file = std::fs::File::open(file_path)
+            .with_context(|| format!("Failed to open file: {:?}", file_path))?;
+        let mut reader = std::io::BufReader::with_capacity(1024 * 1024, file);
+
+        let digest = match checksum_type {
+            ChecksumType::Md5 => {
+                let mut context = md5::Context::new();
+                std::io::copy(&mut reader, &mut context)?;
+                context.finalize().to_vec()
+            }
+            ChecksumType::Sha1 => digest_reader::<sha1::Sha1>(&mut reader)?,
+            ChecksumType::Sha256 => digest_reader::<sha2::Sha256>(&mut reader)?,
+            ChecksumType::Sha384 => digest_reader::<sha2::Sha384>(&mut reader)?,
+            ChecksumType::Sha512 => digest_reader::<sha2::Sha512>(&mut reader)?,
+            ChecksumType::Blake2b => digest_reader::<blake2::Blake2b512>(&mut reader)?,
+        };
+
+        Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// Checksum of everything written to it, to hash a stream on its way elsewhere
+pub struct ChecksumWriter {
+    hasher: Hasher,
+}
+
+enum Hasher {
+    Md5(md5::Context),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Sha384(sha2::Sha384),
+    Sha512(sha2::Sha512),
+    Blake2b(blake2::Blake2b512),
+}
+
+impl ChecksumWriter {
+    pub fn new(checksum_type: ChecksumType) -> Self {
+        use sha2::Digest;
+        let hasher = match checksum_type {
+            ChecksumType::Md5 => Hasher::Md5(md5::Context::new()),
+            ChecksumType::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            ChecksumType::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            ChecksumType::Sha384 => Hasher::Sha384(sha2::Sha384::new()),
+            ChecksumType::Sha512 => Hasher::Sha512(sha2::Sha512::new()),
+            ChecksumType::Blake2b => Hasher::Blake2b(blake2::Blake2b512::new()),
+        };
+        Self { hasher }
+    }
+
+    /// Lowercase hex digest of the bytes written so far
+    pub fn finish(self) -> String {
+        use sha2::Digest;
+        let digest = match self.hasher {
+            Hasher::Md5(context) => context.finalize().to_vec(),
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha384(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake2b(hasher) => hasher.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl std::io::Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        use sha2::Digest;
+        match &mut self.hasher {
+            Hasher::Md5(context) => context.consume(buf),
+            Hasher::Sha1(hasher) => hasher.update(buf),
+            Hasher::Sha256(hasher) => hasher.update(buf),
+            Hasher::Sha384(hasher) => hasher.update(buf),
+            Hasher::Sha512(hasher) => hasher.update(buf),
+            Hasher::Blake2b(hasher) => hasher.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn digest_reader<D: sha2::Digest + std::io::Write>(
+    reader: &mut impl std::io::Read,
+) -> Result<Vec<u8>> {
+    let mut hasher = D::new();
+    std::io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ISO: &str = "Fedora-Workstation-Live-x86_64-41-1.4.iso";
+
+    #[test]
+    fn test_parse_checksum_file_formats() {
+        let sha256 = "a".repeat(64);
+        let fedora = format!(
+            "# {0}: 2398523392 bytes\nSHA256 ({0}) = {1}\nSHA256 (other.iso) = {2}\n",
+            ISO,
+            sha256.to_uppercase(),
+            "b".repeat(64)
+        );
+        assert_eq!(
+            parse_checksum_file(&fedora, ISO, "https://example.org/CHECKSUM"),
+            Some(PublishedChecksum {
+                checksum_type: ChecksumType::Sha256,
+                value: sha256.clone(),
+            })
+        );
+
+        // b2sum output has no tag; the file name decides between BLAKE2b and SHA-512
+        let b2 = "c".repeat(128);
+        let b2sums = format!("{}  archlinux-2025.01.01-x86_64.iso\n", b2);
+        let found = parse_checksum_file(
+            &b2sums,
+            "archlinux-2025.01.01-x86_64.iso",
+            "https://archlinux.org/iso/latest/b2sums.txt",
+        )
+        .unwrap();
+        assert_eq!(found.checksum_type, ChecksumType::Blake2b);
+        let found =
+            parse_checksum_file(&b2sums, "archlinux-2025.01.01-x86_64.iso", "SUMS").unwrap();
+        assert_eq!(found.checksum_type, ChecksumType::Sha512);
+
+        // Binary-mode marker, paths and file names with spaces
+        let gnu = format!("{} *./images/my disk.iso\n", "d".repeat(40));
+        let found = parse_checksum_file(&gnu, "my disk.iso", "SHA1SUMS").unwrap();
+        assert_eq!(found.checksum_type, ChecksumType::Sha1);
+        assert_eq!(parse_checksum_file(&gnu, "disk.iso", "SHA1SUMS"), None);
+
+        let tagged = format!(
+            "BLAKE2b ({}) = {}\nSHA384 (x.iso) = {}\n",
+            ISO,
+            b2,
+            "e".repeat(96)
+        );
+        assert_eq!(
+            parse_checksum_file(&tagged, "x.iso", "CHECKSUM")
+                .unwrap()
+                .checksum_type,
+            ChecksumType::Sha384
+        );
+        assert_eq!(
+            parse_checksum_file(&tagged, ISO, "CHECKSUM")
+                .unwrap()
+                .checksum_type,
+            ChecksumType::Blake2b
+        );
+    }
+
+    #[test]
+    fn test_parse_single_hash_sidecars() {
+        let hash = "f".repeat(64);
+        let iso = "nixos-minimal-24.11-x86_64-linux.iso";
+
+        // NixOS: bare hash
+        let found = parse_checksum_file(&format!("{}\n", hash), iso, &format!("{}.sha256", iso));
+        assert_eq!(found.unwrap().value, hash);
+
+        // openSUSE: the sidecar of a "Current" link names the actual build
+        let suse = format!(
+            "{}  openSUSE-Leap-15.6-DVD-x86_64-Build710.3-Media.iso\n",
+            hash
+        );
+        let current = "openSUSE-Leap-15.6-DVD-x86_64-Current.iso";
+        let found = parse_checksum_file(&suse, current, &format!("{}.sha256", current));
+        assert_eq!(found.unwrap().checksum_type, ChecksumType::Sha256);
+
+        // A bare hash in a shared checksum file does not belong to any one ISO
+        assert_eq!(parse_checksum_file(&hash, iso, "SHA256SUMS"), None);
+    }
+
+    #[tokio::test]
+    async fn test_new_algorithms() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.txt");
+        std::fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            ChecksumVerifier::calculate_checksum(&path, ChecksumType::Sha384)
+                .await
+                .unwrap(),
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed\
+             8086072ba1e7cc2358baeca134c825a7"
+        );
+        assert_eq!(
+            ChecksumVerifier::calculate_checksum(&path, ChecksumType::Blake2b)
+                .await
+                .unwrap(),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        assert_eq!(
+            ChecksumType::from_algorithm("SHA-384"),
+            Some(ChecksumType::Sha384)
+        );
+        assert_eq!(
+            ChecksumType::from_algorithm("BLAKE2b-512"),
+            Some(ChecksumType::Blake2b)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_checksum_matches_async() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.iso");
+        std::fs::write(&path, vec![0x5au8; 3 * 1024 * 1024 + 17]).unwrap();
+
+        for checksum_type in [
+            ChecksumType::Md5,
+            ChecksumType::Sha1,
+            ChecksumType::Sha256,
+            ChecksumType::Sha384,
+            ChecksumType::Sha512,
+            ChecksumType::Blake2b,
+        ] {
+            assert_eq!(
+                ChecksumVerifier::calculate_checksum_blocking(&path, checksum_type).unwrap(),
+                ChecksumVerifier::calculate_checksum(&path, checksum_type)
+                    .await
+                    .unwrap()
+            );
+        }
+    }
 }
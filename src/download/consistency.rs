@@ -0,0 +1,298 @@
+use anyhow::{Context, Result, bail};
+use futures_util::future::join_all;
+use reqwest::header::{ETAG, HeaderMap};
+
+use super::metalink::{self, MetalinkHash};
+use crate::http::HttpContext;
+
+/// What a mirror says about the file in its response headers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub size: Option<u64>,
+    /// Unquoted ETag
+    pub etag: Option<String>,
+    pub hashes: Vec<MetalinkHash>,
+}
+
+impl Fingerprint {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let file = metalink::parse_headers(headers);
+        let etag = headers
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim_start_matches("W/").trim_matches('"').to_string());
+
+        // Object stores commonly use the content hash as ETag
+        let mut hashes = file.hashes;
+        if let Some(etag) = &etag
+            && let Some(algorithm) = hex_algorithm(etag)
+        {
+            hashes.push(MetalinkHash {
+                algorithm: algorithm.to_string(),
+                value: etag.to_lowercase(),
+            });
+        }
+
+        Self {
+            size: file.size,
+            etag,
+            hashes,
+        }
+    }
+
+    fn hash(&self, algorithm: &str) -> Option<&str> {
+        self.hashes
+            .iter()
+            .find(|h| h.algorithm == algorithm)
+            .map(|h| h.value.as_str())
+    }
+}
+
+/// Hash algorithm a bare hex digest of this length would come from
+fn hex_algorithm(value: &str) -> Option<&'static str> {
+    if !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match value.len() {
+        32 => Some("md5"),
+        40 => Some("sha1"),
+        64 => Some("sha256"),
+        96 => Some("sha384"),
+        128 => Some("sha512"),
+        _ => None,
+    }
+}
+
+/// Outcome of comparing one mirror with the others and the official checksum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Consistency {
+    /// Size or digest matched
+    Consistent,
+    /// Nothing to compare against
+    Unverified,
+    /// The mirror serves a different file
+    Mismatch(String),
+    Unreachable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct MirrorCheck {
+    pub url: String,
+    pub consistency: Consistency,
+}
+
+/// Published size and hash of the file, when known
+#[derive(Debug, Clone, Default)]
+pub struct Expected {
+    pub size: Option<u64>,
+    pub hash: Option<MetalinkHash>,
+}
+
+/// Request the headers of `url`, asking for a digest where supported
+pub async fn fingerprint(http: &HttpContext, url: &str) -> Result<Fingerprint> {
+    let response = http
+        .head(url)
+        .timeout(http.mirror_timeout())
+        .header("Want-Digest", "sha-256, sha-512;q=0.5")
+        .header("Want-Repr-Digest", "sha-256=10, sha-512=5")
+        .send()
+        .await
+        .with_context(|| format!("Failed to query mirror: {}", url))?;
+
+    if !response.status().is_success() {
+        bail!("HEAD request failed with status: {}", response.status());
+    }
+
+    Ok(Fingerprint::from_headers(response.headers()))
+}
+
+/// Compare what `urls` report about the file with `expected` and with each other.
+///
+/// Without a published size or hash, the value at least two mirrors agree on
+/// (and no other value is reported as often) is taken as the reference.
+pub async fn check(http: &HttpContext, urls: &[&str], expected: &Expected) -> Vec<MirrorCheck> {
+    let results = join_all(urls.iter().map(|url| async move {
+        // Only HTTP mirrors describe the file before it is fetched
+        if url.starts_with("http://") || url.starts_with("https://") {
+            fingerprint(http, url).await
+        } else {
+            Ok(Fingerprint::default())
+        }
+    }))
+    .await;
+    compare(urls, results, expected)
+}
+
+fn compare(
+    urls: &[&str],
+    results: Vec<Result<Fingerprint>>,
+    expected: &Expected,
+) -> Vec<MirrorCheck> {
+    let fingerprints: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+    let size = expected
+        .size
+        .or_else(|| consensus(fingerprints.iter().filter_map(|f| f.size)));
+    let hash = expected
+        .hash
+        .clone()
+        .or_else(|| consensus(fingerprints.iter().flat_map(|f| f.hashes.iter().cloned())));
+
+    urls.iter()
+        .zip(results)
+        .map(|(url, result)| MirrorCheck {
+            url: url.to_string(),
+            consistency: match result {
+                Ok(fingerprint) => judge(&fingerprint, size, hash.as_ref()),
+                Err(e) => Consistency::Unreachable(e.to_string()),
+            },
+        })
+        .collect()
+}
+
+fn judge(fingerprint: &Fingerprint, size: Option<u64>, hash: Option<&MetalinkHash>) -> Consistency {
+    let mut verified = false;
+
+    if let (Some(expected), Some(actual)) = (size, fingerprint.size) {
+        if expected != actual {
+            return Consistency::Mismatch(format!(
+                "size {} does not match {} bytes",
+                actual, expected
+            ));
+        }
+        verified = true;
+    }
+
+    if let Some(hash) = hash
+        && let Some(actual) = fingerprint.hash(&hash.algorithm)
+    {
+        if !actual.eq_ignore_ascii_case(&hash.value) {
+            return Consistency::Mismatch(format!("{} digest does not match", hash.algorithm));
+        }
+        verified = true;
+    }
+
+    if verified {
+        Consistency::Consistent
+    } else {
+        Consistency::Unverified
+    }
+}
+
+/// Value reported at least twice and more often than any other
+fn consensus<T: PartialEq + Clone>(values: impl IntoIterator<Item = T>) -> Option<T> {
+    let mut counts: Vec<(T, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    match counts.as_slice() {
+        [(value, count), rest @ ..]
+            if *count >= 2 && rest.first().is_none_or(|(_, other)| other < count) =>
+        {
+            Some(value.clone())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use reqwest::header::HeaderValue;
+
+    fn fingerprint(size: u64, sha256: Option<&str>) -> Result<Fingerprint> {
+        Ok(Fingerprint {
+            size: Some(size),
+            etag: None,
+            hashes: sha256
+                .map(|value| MetalinkHash {
+                    algorithm: "sha256".to_string(),
+                    value: value.to_string(),
+                })
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_outlier_mirrors_are_flagged() {
+        let urls = [
+            "https://a/x.iso",
+            "https://b/x.iso",
+            "https://c/x.iso",
+            "https://d/x.iso",
+        ];
+        let results = vec![
+            fingerprint(1000, None),
+            fingerprint(1000, None),
+            fingerprint(900, None),
+            Err(anyhow!("connection refused")),
+        ];
+
+        let checks = compare(&urls, results, &Expected::default());
+        assert_eq!(checks[0].consistency, Consistency::Consistent);
+        assert_eq!(checks[1].consistency, Consistency::Consistent);
+        assert!(matches!(checks[2].consistency, Consistency::Mismatch(_)));
+        assert!(matches!(checks[3].consistency, Consistency::Unreachable(_)));
+
+        // The official checksum wins over any majority
+        let expected = Expected {
+            size: None,
+            hash: Some(MetalinkHash {
+                algorithm: "sha256".to_string(),
+                value: "aa".repeat(32),
+            }),
+        };
+        let results = vec![
+            fingerprint(1000, Some(&"bb".repeat(32))),
+            fingerprint(1000, Some(&"bb".repeat(32))),
+            fingerprint(1000, Some(&"AA".repeat(32))),
+        ];
+        let checks = compare(&urls[..3], results, &expected);
+        assert!(matches!(checks[0].consistency, Consistency::Mismatch(_)));
+        assert_eq!(checks[2].consistency, Consistency::Consistent);
+
+        // A tie says nothing
+        let checks = compare(
+            &urls[..2],
+            vec![fingerprint(1, None), fingerprint(2, None)],
+            &Expected::default(),
+        );
+        assert!(
+            checks
+                .iter()
+                .all(|c| c.consistency == Consistency::Unverified)
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-length", HeaderValue::from_static("4"));
+        // sha-256 of "test"
+        headers.insert(
+            "repr-digest",
+            HeaderValue::from_static("sha-256=:n4bQgYhMfWWaL+qgxVrQFaO/TxsrC4Is0V1sFbDwCgg=:"),
+        );
+        headers.insert(
+            "etag",
+            HeaderValue::from_static("\"098f6bcd4621d373cade4e832627b4f6\""),
+        );
+
+        let fingerprint = Fingerprint::from_headers(&headers);
+        assert_eq!(fingerprint.size, Some(4));
+        assert_eq!(
+            fingerprint.hash("sha256"),
+            Some("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")
+        );
+        assert_eq!(
+            fingerprint.hash("md5"),
+            Some("098f6bcd4621d373cade4e832627b4f6")
+        );
+    }
+}
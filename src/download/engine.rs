@@ -1,13 +1,26 @@
-use anyhow::{Context, Result, bail};
+use anyhow::Result;
 use futures_util::StreamExt;
-use reqwest::Client;
+use reqwest::{StatusCode, Url};
 use std::fs::File;
 use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
-use super::{ChecksumVerifier, DownloadProgress, DownloadRequest};
+use super::error::backoff_delay;
+use super::resume::ResumeState;
+use super::{
+    ChecksumType, ChecksumVerifier, Decompression, DownloadError, DownloadProgress,
+    DownloadRequest, ErrorClass, RateLimiter, ftp, local, rsync, zsync,
+};
+use crate::http::{self, HttpContext};
+use crate::image::{self, ChecksumLayer, Decompressed};
+use std::sync::Arc;
+
+/// Longest server-requested `Retry-After` we are willing to sit through
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
 
 #[derive(Debug)]
 pub struct DownloadResult {
@@ -16,6 +29,62 @@ pub struct DownloadResult {
     pub duration: Duration,
     pub error: Option<String>,
     pub checksum_verified: bool,
+    pub cancelled: bool,
+    /// Per-URL outcomes, oldest first, for the mirror health history
+    pub attempts: Vec<AttemptOutcome>,
+}
+
+/// What happened when a URL was tried
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttemptStatus {
+    Succeeded,
+    Failed(String),
+    ChecksumMismatch,
+}
+
+/// One attempt against one URL
+#[derive(Debug, Clone)]
+pub struct AttemptOutcome {
+    pub url: String,
+    /// Bytes received from the URL during this attempt
+    pub bytes: u64,
+    pub duration: Duration,
+    pub status: AttemptStatus,
+}
+
+/// Unpack a complete compressed download into its image, stopping when `cancel` fires
+async fn decompress_download(
+    request: &DownloadRequest,
+    decompression: &Decompression,
+    cancel: &CancellationToken,
+) -> Result<Decompressed> {
+    let input = request.output_path.clone();
+    let output = decompression.image_path.clone();
+    let compression = decompression.compression;
+    let checksum_type = request.checksum_type.unwrap_or(ChecksumType::Sha256);
+    let cancel = cancel.clone();
+    tokio::task::spawn_blocking(move || {
+        image::decompress(&input, &output, compression, checksum_type, &cancel)
+    })
+    .await?
+}
+
+/// Remove a compressed download together with anything unpacked from it
+fn remove_unpacked(request: &DownloadRequest) {
+    if let Some(decompression) = &request.decompress {
+        let _ = std::fs::remove_file(&request.output_path);
+        let _ = std::fs::remove_file(&decompression.image_path);
+    }
+}
+
+/// Bytes on disk after an attempt and how many of them this attempt fetched
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Transfer {
+    pub(crate) total: u64,
+    pub(crate) received: u64,
+    /// HTTP validators of the response, when the source has them
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
 }
 
 #[derive(Debug)]
@@ -26,27 +95,36 @@ pub struct DownloadTask {
 }
 
 pub struct DownloadEngine {
-    client: Client,
+    http: HttpContext,
+    rate_limiter: Arc<RateLimiter>,
     max_retries: u32,
     retry_delay: Duration,
+    max_retry_delay: Duration,
 }
 
 impl DownloadEngine {
     pub fn new() -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("isod/0.1.0")
-            .build()
-            .context("Failed to create HTTP client")?;
-
         Ok(Self {
-            client,
+            http: http::context().clone(),
+            rate_limiter: Arc::new(RateLimiter::default()),
             max_retries: 3,
             retry_delay: Duration::from_secs(2),
+            max_retry_delay: Duration::from_secs(60),
         })
     }
 
-    pub async fn download(&self, task: DownloadTask) -> DownloadResult {
+    /// HTTP context shared with source resolution (metalink, zsync)
+    pub(crate) fn http(&self) -> &HttpContext {
+        &self.http
+    }
+
+    /// Rate limiter shared by all downloads running on this engine
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Download a file, stopping cleanly with resumable state when `cancel` fires
+    pub async fn download(&self, task: DownloadTask, cancel: CancellationToken) -> DownloadResult {
         let start_time = Instant::now();
         let mut attempt = 0;
 
@@ -57,12 +135,95 @@ impl DownloadEngine {
             output_path: task.request.output_path.clone(),
         });
 
+        // Try a zsync delta transfer first and fall back to a full download if it fails
+        let mut delta_result = None;
+        if let Some(seed) = &task.request.zsync {
+            let delta = tokio::select! {
+                result = zsync::download(&self.http, &self.rate_limiter, &task, seed, &task.request.url) => Some(result),
+                _ = cancel.cancelled() => None,
+            };
+            match delta {
+                Some(Ok(bytes)) => delta_result = Some(bytes),
+                Some(Err(e)) => {
+                    let _ = std::fs::remove_file(&task.request.output_path);
+                    let _ = task.progress_sender.send(DownloadProgress::DeltaFailed {
+                        id: task.id.clone(),
+                        error: format!("{:#}", e),
+                    });
+                }
+                None => {
+                    // A half-built delta file cannot be resumed with a plain range request
+                    let _ = std::fs::remove_file(&task.request.output_path);
+                    return self.cancelled(&task, start_time, Vec::new());
+                }
+            }
+        }
+
+        // Give every mirror at least one attempt before giving up
+        let mut candidates = task.request.candidate_urls();
+        let mut max_attempts = self.max_retries.max(candidates.len() as u32);
+        // Next candidate to try; stays put when the current one is dropped
+        let mut cursor = 0;
+        let mut outcomes = Vec::new();
+
         loop {
             attempt += 1;
 
-            match self.download_attempt(&task).await {
-                Ok(result) => {
+            cursor %= candidates.len();
+            let url = candidates[cursor];
+            let attempt_started = Instant::now();
+            // Delta transfers mix the seed file with ranges, so they say little about the mirror
+            let from_delta = delta_result.is_some();
+            let attempt_result = match delta_result.take() {
+                Some(bytes) => Ok(Transfer {
+                    total: bytes,
+                    received: bytes,
+                    ..Default::default()
+                }),
+                None => self.download_attempt(&task, url, &cancel).await,
+            };
+            let outcome = |status| AttemptOutcome {
+                url: url.to_string(),
+                bytes: 0,
+                duration: attempt_started.elapsed(),
+                status,
+            };
+
+            match attempt_result {
+                Ok(transfer) => {
                     let duration = start_time.elapsed();
+                    let result = transfer.total;
+                    let succeeded = AttemptOutcome {
+                        bytes: transfer.received,
+                        ..outcome(AttemptStatus::Succeeded)
+                    };
+
+                    // Compressed downloads are unpacked once complete, hashing both layers on the way
+                    let mut image_checksum = None;
+                    let mut unpacked_checksum = None;
+                    if let Some(decompression) = &task.request.decompress {
+                        let _ = task.progress_sender.send(DownloadProgress::Decompressing {
+                            id: task.id.clone(),
+                        });
+                        match decompress_download(&task.request, decompression, &cancel).await {
+                            Ok(hashes) => {
+                                unpacked_checksum = Some(match decompression.checksum_layer {
+                                    ChecksumLayer::Download => hashes.download,
+                                    ChecksumLayer::Image => hashes.image.clone(),
+                                });
+                                image_checksum = task.request.checksum_type.map(|_| hashes.image);
+                            }
+                            Err(e) => {
+                                // The transfer is complete, so the data itself is broken
+                                remove_unpacked(&task.request);
+                                if cancel.is_cancelled() {
+                                    return self.cancelled(&task, start_time, outcomes);
+                                }
+                                let error = DownloadError::Other(e);
+                                return self.failed(&task, start_time, &error, attempt, outcomes);
+                            }
+                        }
+                    }
 
                     // Verify checksum if provided
                     let checksum_verified = if let (Some(expected), Some(checksum_type)) =
@@ -74,29 +235,45 @@ impl DownloadEngine {
                                 id: task.id.clone(),
                             });
 
-                        match ChecksumVerifier::verify_file(
-                            &task.request.output_path,
-                            expected,
-                            *checksum_type,
-                        )
-                        .await
-                        {
-                            Ok(verified) => {
-                                if verified {
-                                    let _ = task.progress_sender.send(
-                                        DownloadProgress::ChecksumVerified {
-                                            id: task.id.clone(),
-                                        },
-                                    );
-                                } else {
-                                    let _ = task.progress_sender.send(
-                                        DownloadProgress::ChecksumFailed {
+                        let verified = match &unpacked_checksum {
+                            Some(actual) => Ok(actual.eq_ignore_ascii_case(expected)),
+                            None => {
+                                ChecksumVerifier::verify_file(
+                                    &task.request.output_path,
+                                    expected,
+                                    *checksum_type,
+                                )
+                                .await
+                            }
+                        };
+                        match verified {
+                            Ok(true) => {
+                                let _ =
+                                    task.progress_sender
+                                        .send(DownloadProgress::ChecksumVerified {
                                             id: task.id.clone(),
-                                            expected: expected.clone(),
-                                        },
-                                    );
+                                        });
+                                true
+                            }
+                            Ok(false) => {
+                                let _ =
+                                    task.progress_sender.send(DownloadProgress::ChecksumFailed {
+                                        id: task.id.clone(),
+                                        expected: expected.clone(),
+                                    });
+
+                                remove_unpacked(&task.request);
+                                // Downloading the same bytes again will not fix a bad checksum
+                                let error = DownloadError::ChecksumMismatch {
+                                    expected: expected.clone(),
+                                };
+                                if !from_delta {
+                                    outcomes.push(AttemptOutcome {
+                                        status: AttemptStatus::ChecksumMismatch,
+                                        ..succeeded
+                                    });
                                 }
-                                verified
+                                return self.failed(&task, start_time, &error, attempt, outcomes);
                             }
                             Err(e) => {
                                 let _ = task.progress_sender.send(DownloadProgress::Error {
@@ -110,82 +287,212 @@ impl DownloadEngine {
                         true // No checksum to verify
                     };
 
+                    // Only the image is kept
+                    if task.request.decompress.is_some() {
+                        let _ = std::fs::remove_file(&task.request.output_path);
+                    }
+
                     let _ = task.progress_sender.send(DownloadProgress::Completed {
                         id: task.id.clone(),
                         bytes_downloaded: result,
                         checksum_verified,
+                        url: url.to_string(),
+                        etag: transfer.etag,
+                        last_modified: transfer.last_modified,
+                        image_checksum,
                     });
 
+                    if !from_delta {
+                        outcomes.push(succeeded);
+                    }
+
                     return DownloadResult {
                         success: true,
                         bytes_downloaded: result,
                         duration,
                         error: None,
                         checksum_verified,
+                        cancelled: false,
+                        attempts: outcomes,
                     };
                 }
+                Err(DownloadError::Cancelled) => {
+                    return self.cancelled(&task, start_time, outcomes);
+                }
                 Err(e) => {
-                    if attempt >= self.max_retries {
-                        let _ = task.progress_sender.send(DownloadProgress::Failed {
-                            id: task.id.clone(),
-                            error: e.to_string(),
-                            attempts: attempt,
-                        });
+                    if e.is_mirror_fault() && !from_delta {
+                        outcomes.push(outcome(AttemptStatus::Failed(e.to_string())));
+                    }
 
-                        return DownloadResult {
-                            success: false,
-                            bytes_downloaded: 0,
-                            duration: start_time.elapsed(),
-                            error: Some(e.to_string()),
-                            checksum_verified: false,
-                        };
+                    let delay = match e.class() {
+                        // A URL that is gone on one mirror may still exist on the others
+                        ErrorClass::Fatal if e.is_source_error() && candidates.len() > 1 => {
+                            candidates.retain(|candidate| *candidate != url);
+                            // Mirrors after this one have not been tried yet
+                            let untried = candidates.len().saturating_sub(cursor) as u32;
+                            max_attempts = max_attempts.max(attempt + untried);
+                            Duration::ZERO
+                        }
+                        ErrorClass::Fatal => {
+                            return self.failed(&task, start_time, &e, attempt, outcomes);
+                        }
+                        ErrorClass::Transient => {
+                            cursor += 1;
+                            match e.retry_after() {
+                                Some(retry_after) => retry_after.min(MAX_RETRY_AFTER),
+                                None => {
+                                    backoff_delay(self.retry_delay, self.max_retry_delay, attempt)
+                                }
+                            }
+                        }
+                    };
+
+                    if attempt >= max_attempts {
+                        return self.failed(&task, start_time, &e, attempt, outcomes);
                     }
 
                     let _ = task.progress_sender.send(DownloadProgress::Retry {
                         id: task.id.clone(),
                         attempt,
-                        max_attempts: self.max_retries,
-                        delay: self.retry_delay,
+                        max_attempts,
+                        delay,
                     });
 
-                    sleep(self.retry_delay).await;
+                    tokio::select! {
+                        _ = sleep(delay) => {}
+                        _ = cancel.cancelled() => return self.cancelled(&task, start_time, outcomes),
+                    }
                 }
             }
         }
     }
 
-    async fn download_attempt(&self, task: &DownloadTask) -> Result<u64> {
+    fn failed(
+        &self,
+        task: &DownloadTask,
+        start_time: Instant,
+        error: &DownloadError,
+        attempts: u32,
+        outcomes: Vec<AttemptOutcome>,
+    ) -> DownloadResult {
+        let _ = task.progress_sender.send(DownloadProgress::Failed {
+            id: task.id.clone(),
+            error: error.to_string(),
+            class: error.class(),
+            attempts,
+        });
+
+        DownloadResult {
+            success: false,
+            bytes_downloaded: 0,
+            duration: start_time.elapsed(),
+            error: Some(error.to_string()),
+            checksum_verified: false,
+            cancelled: false,
+            attempts: outcomes,
+        }
+    }
+
+    fn cancelled(
+        &self,
+        task: &DownloadTask,
+        start_time: Instant,
+        outcomes: Vec<AttemptOutcome>,
+    ) -> DownloadResult {
+        let _ = task.progress_sender.send(DownloadProgress::Cancelled {
+            id: task.id.clone(),
+        });
+
+        DownloadResult {
+            success: false,
+            bytes_downloaded: 0,
+            duration: start_time.elapsed(),
+            error: Some(DownloadError::Cancelled.to_string()),
+            checksum_verified: false,
+            cancelled: true,
+            attempts: outcomes,
+        }
+    }
+
+    async fn download_attempt(
+        &self,
+        task: &DownloadTask,
+        url: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Transfer, DownloadError> {
         let request = &task.request;
+        let scheme = Url::parse(url)
+            .map(|u| u.scheme().to_string())
+            .unwrap_or_default();
 
-        // Check if file exists and we should resume
-        let (resume_from, existing_size) = if request.resume && request.output_path.exists() {
-            let metadata = std::fs::metadata(&request.output_path)
-                .context("Failed to get existing file metadata")?;
-            (metadata.len(), metadata.len())
-        } else {
-            (0, 0)
+        let transfer = match scheme.as_str() {
+            "file" => local::fetch(task, url, cancel).await?,
+            "ftp" => ftp::fetch(&self.http, &self.rate_limiter, task, url, cancel).await?,
+            "rsync" => rsync::fetch(&self.http, &self.rate_limiter, task, url, cancel).await?,
+            _ => self.http_attempt(task, url, cancel).await?,
         };
 
+        if let Some(expected_size) = request.expected_size
+            && transfer.total != expected_size
+        {
+            // Start over on the next attempt rather than resuming a bad file
+            let _ = std::fs::remove_file(&request.output_path);
+            ResumeState::remove(&request.output_path);
+            return Err(DownloadError::SizeMismatch {
+                expected: expected_size,
+                actual: transfer.total,
+            });
+        }
+
+        ResumeState::remove(&request.output_path);
+        Ok(transfer)
+    }
+
+    async fn http_attempt(
+        &self,
+        task: &DownloadTask,
+        url: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Transfer, DownloadError> {
+        let request = &task.request;
+
+        // Check if file exists and we should resume
+        let existing_size = existing_size(request)?;
+
         // Build request with range header for resume
-        let mut req_builder = self.client.get(&request.url);
+        let mut req_builder = self.http.transfer(url);
 
         if let Some(user_agent) = &request.user_agent {
             req_builder = req_builder.header("User-Agent", user_agent);
         }
 
-        if resume_from > 0 {
-            req_builder = req_builder.header("Range", format!("bytes={}-", resume_from));
+        if existing_size > 0 {
+            req_builder = req_builder.header("Range", format!("bytes={}-", existing_size));
+
+            // Only accept the range if the file has not changed since the partial download
+            if let Some(state) = ResumeState::load(&request.output_path)
+                && let Some(validator) = state.validator()
+            {
+                req_builder = req_builder.header("If-Range", validator);
+            }
         }
 
         let response = req_builder
             .send()
             .await
-            .context("Failed to send HTTP request")?;
+            .map_err(DownloadError::network("Failed to send HTTP request"))?;
 
-        if !response.status().is_success() && response.status().as_u16() != 206 {
-            bail!("HTTP request failed with status: {}", response.status());
+        if !response.status().is_success() {
+            return Err(DownloadError::http(response.status(), response.headers()));
         }
 
+        // A full 200 response means the range was ignored or the file changed, so start over
+        let resume_from = if response.status() == StatusCode::PARTIAL_CONTENT {
+            existing_size
+        } else {
+            0
+        };
+
         let total_size = if resume_from > 0 {
             // For resume, get content-range header
             response
@@ -196,77 +503,65 @@ impl DownloadEngine {
                     // Parse "bytes 1024-2047/2048" format
                     s.split('/').nth(1)?.parse().ok()
                 })
-                .unwrap_or(existing_size + response.content_length().unwrap_or(0))
+                .unwrap_or(resume_from + response.content_length().unwrap_or(0))
         } else {
             response.content_length().unwrap_or(0)
         };
 
-        // Open file for writing
-        let mut file = if resume_from > 0 {
-            let mut f = std::fs::OpenOptions::new()
-                .write(true)
-                .append(true)
-                .open(&request.output_path)
-                .context("Failed to open file for resume")?;
-            f.seek(SeekFrom::End(0))
-                .context("Failed to seek to end of file")?;
-            f
-        } else {
-            // Create parent directories if they don't exist
-            if let Some(parent) = request.output_path.parent() {
-                std::fs::create_dir_all(parent).context("Failed to create parent directories")?;
-            }
+        let mut file = open_output(&request.output_path, resume_from)?;
 
-            File::create(&request.output_path).context("Failed to create output file")?
-        };
+        let mut resume_state = ResumeState::from_headers(url, response.headers(), total_size);
+        resume_state.bytes_downloaded = resume_from;
+        resume_state.save(&request.output_path)?;
 
-        let mut downloaded = existing_size;
-        let mut last_progress_update = Instant::now();
-        let mut last_bytes = downloaded;
-        const PROGRESS_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+        let mut downloaded = resume_from;
+        let mut progress = ProgressTracker::new(task, total_size, downloaded);
 
         // Download with progress tracking
         let mut stream = response.bytes_stream();
 
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result.context("Failed to read chunk from response")?;
+        loop {
+            let chunk_result = tokio::select! {
+                biased;
+                _ = cancel.cancelled() => {
+                    // Leave a consistent partial file behind for the next resume
+                    file.flush().map_err(DownloadError::io("Failed to flush file"))?;
+                    file.sync_all().map_err(DownloadError::io("Failed to sync file"))?;
+                    resume_state.bytes_downloaded = downloaded;
+                    resume_state.save(&request.output_path)?;
+                    return Err(DownloadError::Cancelled);
+                }
+                chunk = async {
+                    let chunk = stream.next().await;
+                    if let Some(Ok(bytes)) = &chunk {
+                        self.rate_limiter.acquire(bytes.len()).await;
+                    }
+                    chunk
+                } => match chunk {
+                    Some(chunk) => chunk,
+                    None => break,
+                },
+            };
+            let chunk = chunk_result
+                .map_err(DownloadError::network("Failed to read chunk from response"))?;
 
             file.write_all(&chunk)
-                .context("Failed to write chunk to file")?;
+                .map_err(DownloadError::io("Failed to write chunk to file"))?;
 
             downloaded += chunk.len() as u64;
 
-            // Send progress updates periodically
-            if last_progress_update.elapsed() >= PROGRESS_UPDATE_INTERVAL {
-                let progress = if total_size > 0 {
-                    (downloaded as f64 / total_size as f64 * 100.0) as u8
-                } else {
-                    0
-                };
-
-                // Calculate speed in bytes per second
-                let elapsed = last_progress_update.elapsed().as_secs_f64();
-                let speed_bps = if elapsed > 0.0 {
-                    ((downloaded - last_bytes) as f64 / elapsed) as u64
-                } else {
-                    0
-                };
-
-                let _ = task.progress_sender.send(DownloadProgress::Progress {
-                    id: task.id.clone(),
-                    bytes_downloaded: downloaded,
-                    total_bytes: total_size,
-                    progress_percent: progress,
-                    speed_bps,
-                });
-
-                last_progress_update = Instant::now();
-                last_bytes = downloaded;
-            }
+            progress.update(downloaded);
         }
 
-        file.flush().context("Failed to flush file")?;
-        Ok(downloaded)
+        file.flush()
+            .map_err(DownloadError::io("Failed to flush file"))?;
+
+        Ok(Transfer {
+            total: downloaded,
+            received: downloaded - resume_from,
+            etag: resume_state.etag,
+            last_modified: resume_state.last_modified,
+        })
     }
 }
 
@@ -275,3 +570,455 @@ impl Default for DownloadEngine {
         Self::new().expect("Failed to create default DownloadEngine")
     }
 }
+
+/// Size of the partial file to resume from, or 0 when starting over
+pub(crate) fn existing_size(request: &DownloadRequest) -> Result<u64, DownloadError> {
+    if request.resume && request.output_path.exists() {
+        Ok(std::fs::metadata(&request.output_path)
+            .map_err(DownloadError::io("Failed to get existing file metadata"))?
+            .len())
+    } else {
+        Ok(0)
+    }
+}
+
+/// Open the output for appending after `resume_from` bytes, or truncate it
+pub(crate) fn open_output(path: &Path, resume_from: u64) -> Result<File, DownloadError> {
+    if resume_from > 0 {
+        let mut f = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(DownloadError::io("Failed to open file for resume"))?;
+        f.seek(SeekFrom::End(0))
+            .map_err(DownloadError::io("Failed to seek to end of file"))?;
+        Ok(f)
+    } else {
+        // Create parent directories if they don't exist
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(DownloadError::io("Failed to create parent directories"))?;
+        }
+
+        File::create(path).map_err(DownloadError::io("Failed to create output file"))
+    }
+}
+
+/// Sends `Progress` updates for a transfer at a fixed interval
+pub(crate) struct ProgressTracker<'a> {
+    task: &'a DownloadTask,
+    total_size: u64,
+    last_update: Instant,
+    last_bytes: u64,
+}
+
+impl<'a> ProgressTracker<'a> {
+    const INTERVAL: Duration = Duration::from_millis(250);
+
+    pub(crate) fn new(task: &'a DownloadTask, total_size: u64, downloaded: u64) -> Self {
+        Self {
+            task,
+            total_size,
+            last_update: Instant::now(),
+            last_bytes: downloaded,
+        }
+    }
+
+    pub(crate) fn update(&mut self, downloaded: u64) {
+        if self.last_update.elapsed() < Self::INTERVAL {
+            return;
+        }
+
+        let progress = if self.total_size > 0 {
+            (downloaded as f64 / self.total_size as f64 * 100.0) as u8
+        } else {
+            0
+        };
+
+        // Calculate speed in bytes per second
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        let speed_bps = if elapsed > 0.0 {
+            (downloaded.saturating_sub(self.last_bytes) as f64 / elapsed) as u64
+        } else {
+            0
+        };
+
+        let _ = self.task.progress_sender.send(DownloadProgress::Progress {
+            id: self.task.id.clone(),
+            bytes_downloaded: downloaded,
+            total_bytes: self.total_size,
+            progress_percent: progress,
+            speed_bps,
+        });
+
+        self.last_update = Instant::now();
+        self.last_bytes = downloaded;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Compression;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `size` bytes slowly, honouring `Range: bytes=N-` with a 206
+    async fn slow_server(size: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                    let start: usize = request
+                        .split("range: bytes=")
+                        .nth(1)
+                        .and_then(|r| r.split('-').next())
+                        .and_then(|r| r.parse().ok())
+                        .unwrap_or(0);
+
+                    let header = if start > 0 {
+                        format!(
+                            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nETag: \"v1\"\r\n\r\n",
+                            size - start,
+                            start,
+                            size - 1,
+                            size
+                        )
+                    } else {
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"v1\"\r\n\r\n",
+                            size
+                        )
+                    };
+                    let _ = socket.write_all(header.as_bytes()).await;
+                    for _ in (start..size).step_by(1024) {
+                        if socket.write_all(&[7u8; 1024]).await.is_err() {
+                            return;
+                        }
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                    }
+                });
+            }
+        });
+
+        format!("http://{}/test.iso", addr)
+    }
+
+    /// Answer every request with an empty response of the given status
+    async fn status_server(status: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("http://{}/test.iso", addr)
+    }
+
+    #[tokio::test]
+    async fn test_fatal_errors_skip_retries_but_try_mirrors() {
+        let missing = status_server("404 Not Found").await;
+        let dir = tempfile::tempdir().unwrap();
+        let engine = DownloadEngine::new().unwrap();
+
+        // A 404 is not retried against the same URL
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(missing.clone(), dir.path().join("missing.iso")),
+            progress_sender: sender,
+        };
+        let result = engine.download(task, CancellationToken::new()).await;
+        assert!(!result.success);
+
+        let mut failure = None;
+        while let Ok(progress) = receiver.try_recv() {
+            assert!(!matches!(progress, DownloadProgress::Retry { .. }));
+            if let DownloadProgress::Failed {
+                class, attempts, ..
+            } = progress
+            {
+                failure = Some((class, attempts));
+            }
+        }
+        assert_eq!(failure, Some((ErrorClass::Fatal, 1)));
+
+        // ...but the next mirror is tried straight away
+        let url = slow_server(4096).await;
+        let output = dir.path().join("mirrored.iso");
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(missing, output.clone()).with_mirrors(vec![url]),
+            progress_sender: sender,
+        };
+        let started = Instant::now();
+        let result = engine.download(task, CancellationToken::new()).await;
+        assert!(result.success);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), 4096);
+    }
+
+    #[tokio::test]
+    async fn test_failed_delta_is_reported_before_full_download() {
+        let control_url = status_server("404 Not Found").await;
+        let url = slow_server(4096).await;
+        let dir = tempfile::tempdir().unwrap();
+        let seed = dir.path().join("seed.iso");
+        std::fs::write(&seed, [7u8; 1024]).unwrap();
+        let output = dir.path().join("test.iso");
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(url, output.clone()).with_zsync(control_url, seed),
+            progress_sender: sender,
+        };
+        let result = DownloadEngine::new()
+            .unwrap()
+            .download(task, CancellationToken::new())
+            .await;
+        assert!(result.success);
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), 4096);
+
+        let mut delta_error = None;
+        while let Ok(progress) = receiver.try_recv() {
+            if let DownloadProgress::DeltaFailed { error, .. } = progress {
+                delta_error = Some(error);
+            }
+        }
+        assert!(delta_error.unwrap().contains("404"));
+    }
+
+    #[tokio::test]
+    async fn test_transfers_use_the_shared_user_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (agent_sender, mut agent_receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let agent = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("user-agent: "))
+                    .map(str::to_string);
+                let _ = agent_sender.send(agent);
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\niso!")
+                    .await;
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(
+                format!("http://{}/test.iso", addr),
+                dir.path().join("test.iso"),
+            ),
+            progress_sender: sender,
+        };
+        let mut config = crate::config::Config::default();
+        config.network.user_agent = Some("lab-mirror-bot/2".to_string());
+        let mut engine = DownloadEngine::new().unwrap();
+        engine.http = HttpContext::from_config(&config).unwrap();
+        let result = engine.download(task, CancellationToken::new()).await;
+        assert!(result.success);
+        assert_eq!(
+            agent_receiver.recv().await.unwrap().as_deref(),
+            Some("lab-mirror-bot/2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_every_mirror_is_tried_after_one_is_dropped() {
+        let busy = status_server("503 Service Unavailable").await;
+        let missing = status_server("404 Not Found").await;
+        let url = slow_server(4096).await;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("test.iso");
+
+        let mut engine = DownloadEngine::new().unwrap();
+        engine.retry_delay = Duration::from_millis(10);
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(busy.clone(), output.clone())
+                .with_mirrors(vec![missing, url.clone()]),
+            progress_sender: sender,
+        };
+        let result = engine.download(task, CancellationToken::new()).await;
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.attempts.last().unwrap().url, url);
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), 4096);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_leaves_resumable_file() {
+        let size = 256 * 1024;
+        let url = slow_server(size).await;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("test.iso");
+
+        let engine = DownloadEngine::new().unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(url.clone(), output.clone()),
+            progress_sender: sender.clone(),
+        };
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            trigger.cancel();
+        });
+
+        let result = engine.download(task, cancel).await;
+        assert!(result.cancelled);
+        assert!(!result.success);
+
+        let partial = std::fs::metadata(&output).unwrap().len();
+        assert!(partial > 0 && partial < size as u64);
+        let state = ResumeState::load(&output).unwrap();
+        assert_eq!(state.bytes_downloaded, partial);
+        assert_eq!(state.etag.as_deref(), Some("\"v1\""));
+
+        let mut saw_cancelled = false;
+        while let Ok(progress) = receiver.try_recv() {
+            saw_cancelled |= matches!(progress, DownloadProgress::Cancelled { .. });
+        }
+        assert!(saw_cancelled);
+
+        // Resume picks up where the cancelled attempt stopped
+        let task = DownloadTask {
+            id: "test".to_string(),
+            request: DownloadRequest::new(url, output.clone()),
+            progress_sender: sender,
+        };
+        let result = engine.download(task, CancellationToken::new()).await;
+        assert!(result.success);
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), size as u64);
+        assert!(ResumeState::load(&output).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_source_resumes_and_verifies_checksum() {
+        use sha2::{Digest, Sha256};
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("nfs").join("test iso.iso");
+        let output = dir.path().join("test.iso");
+        let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::write(&source, &content).unwrap();
+        std::fs::write(&output, &content[..1000]).unwrap();
+
+        let url = Url::from_file_path(&source).unwrap().to_string();
+        let checksum = format!("{:x}", Sha256::digest(&content));
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "file".to_string(),
+            request: DownloadRequest::new(url.clone(), output.clone())
+                .with_checksum(checksum, ChecksumType::Sha256),
+            progress_sender: sender,
+        };
+
+        let result = DownloadEngine::new()
+            .unwrap()
+            .download(task, CancellationToken::new())
+            .await;
+        assert!(result.success);
+        assert!(result.checksum_verified);
+        assert_eq!(result.attempts[0].url, url);
+        assert_eq!(result.attempts[0].bytes, content.len() as u64 - 1000);
+        assert_eq!(std::fs::read(&output).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_download_is_unpacked_and_checked_per_layer() {
+        use sha2::{Digest, Sha256};
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let content: Vec<u8> = (0..1024 * 1024).map(|i| (i % 241) as u8).collect();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder.write_all(&content).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let source = dir.path().join("mirror").join("pfsense.iso.gz");
+        std::fs::create_dir_all(source.parent().unwrap()).unwrap();
+        std::fs::write(&source, &compressed).unwrap();
+        let url = Url::from_file_path(&source).unwrap().to_string();
+
+        let image_checksum = format!("{:x}", Sha256::digest(&content));
+        for (layer, checksum) in [
+            (
+                ChecksumLayer::Download,
+                format!("{:x}", Sha256::digest(&compressed)),
+            ),
+            (ChecksumLayer::Image, image_checksum.clone()),
+        ] {
+            let download = dir.path().join("pfsense.iso.gz");
+            let image = dir.path().join("pfsense.iso");
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            let task = DownloadTask {
+                id: "gz".to_string(),
+                request: DownloadRequest::new(url.clone(), download.clone())
+                    .with_checksum(checksum, ChecksumType::Sha256)
+                    .with_decompression(Compression::Gzip, image.clone(), layer),
+                progress_sender: sender,
+            };
+
+            let result = DownloadEngine::new()
+                .unwrap()
+                .download(task, CancellationToken::new())
+                .await;
+            assert!(result.success, "{:?}: {:?}", layer, result.error);
+            assert!(result.checksum_verified);
+            assert_eq!(std::fs::read(&image).unwrap(), content);
+            assert!(!download.exists());
+
+            let mut reported = None;
+            while let Ok(progress) = receiver.try_recv() {
+                if let DownloadProgress::Completed { image_checksum, .. } = progress {
+                    reported = image_checksum;
+                }
+            }
+            assert_eq!(reported.as_deref(), Some(image_checksum.as_str()));
+            std::fs::remove_file(&image).unwrap();
+        }
+
+        // A mismatch leaves neither the download nor the image behind
+        let download = dir.path().join("pfsense.iso.gz");
+        let image = dir.path().join("pfsense.iso");
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "gz".to_string(),
+            request: DownloadRequest::new(url, download.clone())
+                .with_checksum("0".repeat(64), ChecksumType::Sha256)
+                .with_decompression(Compression::Gzip, image.clone(), ChecksumLayer::Image),
+            progress_sender: sender,
+        };
+        let result = DownloadEngine::new()
+            .unwrap()
+            .download(task, CancellationToken::new())
+            .await;
+        assert!(!result.success);
+        assert!(!download.exists());
+        assert!(!image.exists());
+    }
+}
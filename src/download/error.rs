@@ -0,0 +1,232 @@
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Whether retrying a failed download can help
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Retrying the same request will fail the same way (404, 403, checksum mismatch, disk full)
+    Fatal,
+    /// The failure is likely temporary (timeouts, resets, 5xx, 429)
+    Transient,
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorClass::Fatal => write!(f, "fatal"),
+            ErrorClass::Transient => write!(f, "transient"),
+        }
+    }
+}
+
+/// Error from a single download attempt
+#[derive(Debug, thiserror::Error)]
+pub enum DownloadError {
+    #[error("HTTP request failed with status: {status}")]
+    Http {
+        status: StatusCode,
+        retry_after: Option<Duration>,
+    },
+    #[error("{context}: {source}")]
+    Network {
+        context: &'static str,
+        source: reqwest::Error,
+    },
+    #[error("{context}: {source}")]
+    Io {
+        context: &'static str,
+        source: io::Error,
+    },
+    /// FTP, rsync or local file source refused or broke off the transfer
+    #[error("{scheme} transfer failed: {message}")]
+    Source {
+        scheme: &'static str,
+        message: String,
+        transient: bool,
+    },
+    #[error("Size mismatch: expected {expected} bytes, got {actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+    #[error("Checksum mismatch: expected {expected}")]
+    ChecksumMismatch { expected: String },
+    #[error("Download cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl DownloadError {
+    /// Build an HTTP error from a non-success response, keeping its `Retry-After` hint
+    pub fn http(status: StatusCode, headers: &HeaderMap) -> Self {
+        DownloadError::Http {
+            status,
+            retry_after: parse_retry_after(headers, Utc::now()),
+        }
+    }
+
+    pub fn network(context: &'static str) -> impl FnOnce(reqwest::Error) -> Self {
+        move |source| DownloadError::Network { context, source }
+    }
+
+    pub fn io(context: &'static str) -> impl FnOnce(io::Error) -> Self {
+        move |source| DownloadError::Io { context, source }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            DownloadError::Http { status, .. } => {
+                if status.is_server_error()
+                    || matches!(
+                        *status,
+                        StatusCode::REQUEST_TIMEOUT
+                            | StatusCode::TOO_EARLY
+                            | StatusCode::TOO_MANY_REQUESTS
+                    )
+                {
+                    ErrorClass::Transient
+                } else {
+                    ErrorClass::Fatal
+                }
+            }
+            DownloadError::Network { source, .. } => {
+                if source.is_builder() || source.is_redirect() {
+                    ErrorClass::Fatal
+                } else {
+                    ErrorClass::Transient
+                }
+            }
+            DownloadError::Io { source, .. } => match source.kind() {
+                io::ErrorKind::TimedOut
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::UnexpectedEof => ErrorClass::Transient,
+                _ => ErrorClass::Fatal,
+            },
+            DownloadError::Source { transient, .. } => {
+                if *transient {
+                    ErrorClass::Transient
+                } else {
+                    ErrorClass::Fatal
+                }
+            }
+            // A truncated transfer is worth another try from scratch
+            DownloadError::SizeMismatch { .. } => ErrorClass::Transient,
+            DownloadError::ChecksumMismatch { .. }
+            | DownloadError::Cancelled
+            | DownloadError::Other(_) => ErrorClass::Fatal,
+        }
+    }
+
+    /// Whether the error is specific to the URL, so another mirror may still work
+    pub fn is_source_error(&self) -> bool {
+        matches!(
+            self,
+            DownloadError::Http { .. }
+                | DownloadError::Network { .. }
+                | DownloadError::Source { .. }
+        )
+    }
+
+    /// Whether the error says something about the mirror that served the request
+    pub fn is_mirror_fault(&self) -> bool {
+        self.is_source_error()
+            || matches!(
+                self,
+                DownloadError::SizeMismatch { .. } | DownloadError::ChecksumMismatch { .. }
+            )
+    }
+
+    /// Delay requested by the server through `Retry-After`
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            DownloadError::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Parse `Retry-After` as either delta seconds or an HTTP date
+fn parse_retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&Utc) - now;
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Exponential backoff with equal jitter: half the delay is fixed, the other half random
+pub fn backoff_delay(base: Duration, max: Duration, attempt: u32) -> Duration {
+    let exponential = base
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(max);
+    let half = exponential / 2;
+    half + half.mul_f64(fastrand::f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_classification_and_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            DownloadError::http(StatusCode::NOT_FOUND, &headers).class(),
+            ErrorClass::Fatal
+        );
+        assert_eq!(
+            DownloadError::http(StatusCode::BAD_GATEWAY, &headers).class(),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            DownloadError::io("Failed to write chunk to file")(io::Error::from(
+                io::ErrorKind::StorageFull
+            ))
+            .class(),
+            ErrorClass::Fatal
+        );
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        let error = DownloadError::http(StatusCode::TOO_MANY_REQUESTS, &headers);
+        assert_eq!(error.class(), ErrorClass::Transient);
+        assert_eq!(error.retry_after(), Some(Duration::from_secs(120)));
+
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:30 GMT"),
+        );
+        assert_eq!(
+            parse_retry_after(&headers, now),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let base = Duration::from_secs(2);
+        let max = Duration::from_secs(60);
+
+        for _ in 0..20 {
+            let first = backoff_delay(base, max, 1);
+            assert!(first >= Duration::from_secs(1) && first <= base);
+
+            let third = backoff_delay(base, max, 3);
+            assert!(third >= Duration::from_secs(4) && third <= Duration::from_secs(8));
+
+            let capped = backoff_delay(base, max, 30);
+            assert!(capped >= max / 2 && capped <= max);
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use reqwest::Url;
+use std::io::Write;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+use super::engine::{DownloadTask, ProgressTracker, Transfer, existing_size, open_output};
+use super::{DownloadError, RateLimiter};
+use crate::credentials::Credential;
+use crate::http::HttpContext;
+
+const DEFAULT_PORT: u16 = 21;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn error(message: impl Into<String>, transient: bool) -> DownloadError {
+    DownloadError::Source {
+        scheme: "FTP",
+        message: message.into(),
+        transient,
+    }
+}
+
+/// Complete server reply; the text of multi-line replies is joined
+#[derive(Debug)]
+struct Reply {
+    code: u16,
+    text: String,
+}
+
+impl Reply {
+    /// 4xx replies are temporary by definition, 5xx ones permanent
+    fn into_error(self) -> DownloadError {
+        error(
+            format!("{} {}", self.code, self.text),
+            (400..500).contains(&self.code),
+        )
+    }
+}
+
+/// Control connection of a passive-mode session
+struct Control {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    peer: IpAddr,
+    timeout: Duration,
+}
+
+impl Control {
+    async fn connect(
+        host: &str,
+        port: u16,
+        timeout_after: Duration,
+    ) -> Result<Self, DownloadError> {
+        let stream = timeout(timeout_after, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| error(format!("connection to {} timed out", host), true))?
+            .map_err(DownloadError::io("Failed to connect to FTP server"))?;
+        let peer = stream
+            .peer_addr()
+            .map_err(DownloadError::io("Failed to connect to FTP server"))?
+            .ip();
+        let (reader, writer) = stream.into_split();
+
+        let mut control = Self {
+            reader: BufReader::new(reader),
+            writer,
+            peer,
+            timeout: timeout_after,
+        };
+        let greeting = control.reply().await?;
+        if greeting.code != 220 {
+            return Err(greeting.into_error());
+        }
+        Ok(control)
+    }
+
+    async fn read_line(&mut self) -> Result<String, DownloadError> {
+        let mut line = String::new();
+        let read = timeout(self.timeout, self.reader.read_line(&mut line))
+            .await
+            .map_err(|_| error("server stopped responding", true))?
+            .map_err(DownloadError::io("Failed to read FTP reply"))?;
+        if read == 0 {
+            return Err(error("server closed the connection", true));
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    async fn reply(&mut self) -> Result<Reply, DownloadError> {
+        let first = self.read_line().await?;
+        let code: u16 = first
+            .get(..3)
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(|| error(format!("malformed reply: {}", first), false))?;
+        let mut text = first[3..].trim_start_matches(['-', ' ']).to_string();
+
+        // "123-first line" ... "123 last line"
+        if first.as_bytes().get(3) == Some(&b'-') {
+            let last = format!("{} ", code);
+            loop {
+                let line = self.read_line().await?;
+                let done = line.starts_with(&last) || line == last.trim_end();
+                text.push(' ');
+                text.push_str(line.strip_prefix(&last).unwrap_or(&line).trim());
+                if done {
+                    break;
+                }
+            }
+        }
+
+        Ok(Reply { code, text })
+    }
+
+    async fn command(&mut self, command: &str) -> Result<Reply, DownloadError> {
+        self.writer
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await
+            .map_err(DownloadError::io("Failed to send FTP command"))?;
+        self.reply().await
+    }
+
+    /// Send `command` and fail unless the reply code is one of `expected`
+    async fn expect(&mut self, command: &str, expected: &[u16]) -> Result<Reply, DownloadError> {
+        let reply = self.command(command).await?;
+        if expected.contains(&reply.code) {
+            Ok(reply)
+        } else {
+            Err(reply.into_error())
+        }
+    }
+
+    /// Open a passive data connection, preferring EPSV over PASV.
+    ///
+    /// The advertised address of a PASV reply is ignored in favour of the
+    /// control connection's peer, which is what NATed servers need anyway.
+    async fn open_data(&mut self) -> Result<TcpStream, DownloadError> {
+        let reply = self.command("EPSV").await?;
+        let port = if reply.code == 229 {
+            parse_epsv(&reply.text)
+        } else {
+            let reply = self.expect("PASV", &[227]).await?;
+            parse_pasv(&reply.text)
+        }
+        .ok_or_else(|| error("could not parse passive mode reply", false))?;
+
+        timeout(self.timeout, TcpStream::connect((self.peer, port)))
+            .await
+            .map_err(|_| error("data connection timed out", true))?
+            .map_err(DownloadError::io("Failed to open FTP data connection"))
+    }
+}
+
+/// Port from "229 Entering Extended Passive Mode (|||6446|)"
+fn parse_epsv(text: &str) -> Option<u16> {
+    let start = text.find("|||")? + 3;
+    let end = text[start..].find('|')? + start;
+    text[start..end].parse().ok()
+}
+
+/// Port from "227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)"
+fn parse_pasv(text: &str) -> Option<u16> {
+    let start = text.find('(')? + 1;
+    let end = text[start..].find(')')? + start;
+    let numbers: Vec<u16> = text[start..end]
+        .split(',')
+        .map(|n| n.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers.as_slice() {
+        [_, _, _, _, high, low] if *high < 256 && *low < 256 => Some(high * 256 + low),
+        _ => None,
+    }
+}
+
+/// Decode %XX escapes of a URL path or userinfo component
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Login from the URL, then the credential store, then anonymous
+fn login(http: &HttpContext, url: &Url) -> (String, String) {
+    if !url.username().is_empty() {
+        return (
+            decode(url.username()),
+            url.password().map(decode).unwrap_or_default(),
+        );
+    }
+
+    match http.credential(url) {
+        Some(Credential::Basic { username, password }) => (username, password.unwrap_or_default()),
+        _ => ("anonymous".to_string(), "anonymous@".to_string()),
+    }
+}
+
+/// Download an `ftp://` source in binary passive mode, resuming with REST
+pub(crate) async fn fetch(
+    http: &HttpContext,
+    rate_limiter: &RateLimiter,
+    task: &DownloadTask,
+    url: &str,
+    cancel: &CancellationToken,
+) -> Result<Transfer, DownloadError> {
+    let request = &task.request;
+    let parsed = Url::parse(url).map_err(|e| error(format!("invalid URL: {}", e), false))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| error("URL has no host", false))?;
+    let path = decode(parsed.path());
+
+    let mut control = Control::connect(
+        host,
+        parsed.port().unwrap_or(DEFAULT_PORT),
+        http.mirror_timeout(),
+    )
+    .await?;
+
+    let (username, password) = login(http, &parsed);
+    let reply = control.command(&format!("USER {}", username)).await?;
+    match reply.code {
+        230 => {}
+        331 | 332 => {
+            control
+                .expect(&format!("PASS {}", password), &[202, 230])
+                .await?;
+        }
+        _ => return Err(reply.into_error()),
+    }
+    control.expect("TYPE I", &[200]).await?;
+
+    let total_size = match control.command(&format!("SIZE {}", path)).await? {
+        reply if reply.code == 213 => reply.text.trim().parse().ok(),
+        _ => None,
+    };
+
+    let existing = existing_size(request)?;
+    let mut resume_from = match total_size {
+        Some(total) if existing > total => 0,
+        _ => existing,
+    };
+
+    let mut data = control.open_data().await?;
+    if resume_from > 0
+        && control
+            .command(&format!("REST {}", resume_from))
+            .await?
+            .code
+            != 350
+    {
+        resume_from = 0;
+    }
+    control
+        .expect(&format!("RETR {}", path), &[125, 150])
+        .await?;
+
+    let mut file = open_output(&request.output_path, resume_from)?;
+    let mut downloaded = resume_from;
+    let mut progress = ProgressTracker::new(task, total_size.unwrap_or(0), downloaded);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let read_timeout = http.read_timeout();
+
+    loop {
+        let read = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                file.flush().map_err(DownloadError::io("Failed to flush file"))?;
+                file.sync_all().map_err(DownloadError::io("Failed to sync file"))?;
+                let _ = control.command("ABOR").await;
+                return Err(DownloadError::Cancelled);
+            }
+            read = timeout(read_timeout, data.read(&mut buffer)) => read
+                .map_err(|_| error("transfer stalled", true))?
+                .map_err(DownloadError::io("Failed to read FTP data"))?,
+        };
+        if read == 0 {
+            break;
+        }
+
+        rate_limiter.acquire(read).await;
+        file.write_all(&buffer[..read])
+            .map_err(DownloadError::io("Failed to write chunk to file"))?;
+        downloaded += read as u64;
+        progress.update(downloaded);
+    }
+
+    file.flush()
+        .map_err(DownloadError::io("Failed to flush file"))?;
+    drop(data);
+
+    // The server confirms the transfer after closing the data connection
+    let reply = control.reply().await?;
+    if !matches!(reply.code, 226 | 250) {
+        return Err(reply.into_error());
+    }
+    let _ = control.command("QUIT").await;
+
+    Ok(Transfer {
+        total: downloaded,
+        received: downloaded - resume_from,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::DownloadRequest;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Minimal passive-mode server for one file, honouring REST
+    async fn ftp_server(content: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (reader, mut writer) = socket.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    let data = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                    let data_port = data.local_addr().unwrap().port();
+                    let mut offset = 0;
+
+                    let _ = writer.write_all(b"220-Welcome\r\n220 Ready\r\n").await;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let (command, argument) = line.split_once(' ').unwrap_or((&line, ""));
+                        let reply = match command {
+                            "USER" => "331 Password required".to_string(),
+                            "PASS" => "230 Logged in".to_string(),
+                            "TYPE" => "200 Binary".to_string(),
+                            "SIZE" if argument == "/pub/test.iso" => {
+                                format!("213 {}", content.len())
+                            }
+                            "SIZE" | "RETR" => "550 No such file".to_string(),
+                            "EPSV" => format!("229 Extended Passive Mode (|||{}|)", data_port),
+                            "REST" => {
+                                offset = argument.parse().unwrap();
+                                "350 Restarting".to_string()
+                            }
+                            "QUIT" => "221 Bye".to_string(),
+                            _ => "502 Not implemented".to_string(),
+                        };
+
+                        if command == "RETR" && argument == "/pub/test.iso" {
+                            let _ = writer.write_all(b"150 Opening data connection\r\n").await;
+                            let (mut socket, _) = data.accept().await.unwrap();
+                            let _ = socket.write_all(&content[offset..]).await;
+                            drop(socket);
+                            let _ = writer.write_all(b"226 Transfer complete\r\n").await;
+                            continue;
+                        }
+                        let _ = writer.write_all(format!("{}\r\n", reply).as_bytes()).await;
+                    }
+                });
+            }
+        });
+
+        format!("ftp://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_ftp_download_resumes() {
+        const CONTENT: &[u8] = b"0123456789abcdef";
+        let server = ftp_server(CONTENT).await;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("test.iso");
+        std::fs::write(&output, &CONTENT[..6]).unwrap();
+
+        let (progress_sender, _progress) = mpsc::unbounded_channel();
+        let task = DownloadTask {
+            id: "ftp".to_string(),
+            request: DownloadRequest::new(format!("{}/pub/test.iso", server), output.clone()),
+            progress_sender,
+        };
+        let http = crate::http::context();
+        let limiter = RateLimiter::default();
+        let cancel = CancellationToken::new();
+
+        let transfer = fetch(http, &limiter, &task, &task.request.url, &cancel)
+            .await
+            .unwrap();
+        assert_eq!(transfer.total, CONTENT.len() as u64);
+        assert_eq!(transfer.received, 10);
+        assert_eq!(std::fs::read(&output).unwrap(), CONTENT);
+
+        // Missing files are permanent, so other mirrors are tried instead
+        let error = fetch(
+            http,
+            &limiter,
+            &task,
+            &format!("{}/pub/missing.iso", server),
+            &cancel,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            DownloadError::Source {
+                transient: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_passive_replies() {
+        assert_eq!(
+            parse_epsv("Entering Extended Passive Mode (|||6446|)"),
+            Some(6446)
+        );
+        assert_eq!(
+            parse_pasv("Entering Passive Mode (192,168,1,2,25,46)."),
+            Some(25 * 256 + 46)
+        );
+        assert_eq!(parse_pasv("Entering Passive Mode (1,2,3)"), None);
+        assert_eq!(decode("/pub/my%20file.iso"), "/pub/my file.iso");
+    }
+}
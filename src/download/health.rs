@@ -0,0 +1,397 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::engine::{AttemptOutcome, AttemptStatus};
+use super::probe;
+use crate::registry::DownloadSource;
+
+const HEALTH_FILE: &str = "mirror_health.json";
+
+/// Consecutive failures before a mirror is quarantined
+const QUARANTINE_AFTER: u32 = 3;
+
+/// First quarantine period in hours, doubled for every further failure
+const QUARANTINE_HOURS: i64 = 1;
+const MAX_QUARANTINE_HOURS: i64 = 24;
+
+/// A checksum mismatch usually means a stale mirror, which rarely fixes itself quickly
+const STALE_QUARANTINE_HOURS: i64 = 24;
+
+/// Score deducted per consecutive failure, capped at four priority tiers
+const FAILURE_PENALTY: u32 = 1000;
+const MAX_FAILURE_PENALTY: u32 = 4000;
+
+/// Transfers smaller than this say little about a mirror's throughput
+const MIN_THROUGHPUT_SAMPLE: u64 = 1024 * 1024;
+
+/// Recorded outcomes for one mirror
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MirrorHealth {
+    pub successes: u32,
+    pub failures: u32,
+    pub consecutive_failures: u32,
+    pub checksum_mismatches: u32,
+    /// Times the mirror disagreed with other mirrors or the checksum file before a download
+    #[serde(default)]
+    pub inconsistencies: u32,
+    /// Moving average of achieved download speed
+    pub throughput_bps: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub quarantined_until: Option<DateTime<Utc>>,
+}
+
+impl MirrorHealth {
+    fn record_success(&mut self, bytes: u64, duration: std::time::Duration, now: DateTime<Utc>) {
+        self.successes += 1;
+        self.consecutive_failures = 0;
+        self.last_success = Some(now);
+        self.quarantined_until = None;
+
+        if bytes >= MIN_THROUGHPUT_SAMPLE {
+            let sample = (bytes as f64 / duration.as_secs_f64().max(0.001)) as u64;
+            self.throughput_bps = Some(match self.throughput_bps {
+                Some(average) => (average as f64 * 0.7 + sample as f64 * 0.3) as u64,
+                None => sample,
+            });
+        }
+    }
+
+    fn record_failure(&mut self, error: &str, now: DateTime<Utc>) {
+        self.failures += 1;
+        self.consecutive_failures += 1;
+        self.last_error = Some(error.to_string());
+        self.last_failure = Some(now);
+
+        if self.consecutive_failures >= QUARANTINE_AFTER {
+            let doublings = (self.consecutive_failures - QUARANTINE_AFTER).min(8);
+            let hours = (QUARANTINE_HOURS << doublings).min(MAX_QUARANTINE_HOURS);
+            self.quarantine(now + Duration::hours(hours));
+        }
+    }
+
+    fn record_checksum_mismatch(&mut self, now: DateTime<Utc>) {
+        self.checksum_mismatches += 1;
+        self.record_failure("Checksum mismatch (stale or corrupt content)", now);
+        self.quarantine(now + Duration::hours(STALE_QUARANTINE_HOURS));
+    }
+
+    fn record_inconsistency(&mut self, reason: &str, now: DateTime<Utc>) {
+        self.inconsistencies += 1;
+        self.record_failure(&format!("Inconsistent with other mirrors: {}", reason), now);
+        self.quarantine(now + Duration::hours(STALE_QUARANTINE_HOURS));
+    }
+
+    fn quarantine(&mut self, until: DateTime<Utc>) {
+        self.quarantined_until = Some(self.quarantined_until.map_or(until, |t| t.max(until)));
+    }
+
+    pub fn is_quarantined(&self, now: DateTime<Utc>) -> bool {
+        self.quarantined_until.is_some_and(|until| until > now)
+    }
+
+    /// Selection score deducted for recent failures and a poor overall success rate
+    pub fn penalty(&self) -> u32 {
+        let streak = (self.consecutive_failures * FAILURE_PENALTY).min(MAX_FAILURE_PENALTY);
+        let attempts = self.successes + self.failures;
+        let failure_rate = (self.failures * FAILURE_PENALTY)
+            .checked_div(attempts)
+            .unwrap_or(0);
+        streak + failure_rate
+    }
+}
+
+/// Key a URL's mirror is tracked under: scheme, host and port
+pub fn mirror_key(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}://{}:{}", url.scheme(), host, port),
+        None => format!("{}://{}", url.scheme(), host),
+    })
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct HealthFile {
+    #[serde(default)]
+    mirrors: BTreeMap<String, MirrorHealth>,
+}
+
+/// Per-mirror download history persisted in the state directory
+#[derive(Debug)]
+pub struct MirrorHealthStore {
+    path: PathBuf,
+    mirrors: BTreeMap<String, MirrorHealth>,
+}
+
+impl MirrorHealthStore {
+    /// Location of the health file inside a state directory
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir.join(HEALTH_FILE)
+    }
+
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let file: HealthFile = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read mirror health: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse mirror health: {:?}", path))?
+        } else {
+            HealthFile::default()
+        };
+
+        Ok(Self {
+            path,
+            mirrors: file.mirrors,
+        })
+    }
+
+    /// Write the history to disk atomically
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {:?}", parent))?;
+        }
+
+        let file = HealthFile {
+            mirrors: self.mirrors.clone(),
+        };
+        let content =
+            serde_json::to_string_pretty(&file).context("Failed to serialize mirror health")?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write mirror health: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace mirror health: {:?}", self.path))?;
+
+        Ok(())
+    }
+
+    /// Tracked mirrors, sorted by key
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &MirrorHealth)> {
+        self.mirrors.iter()
+    }
+
+    /// History of the mirror serving `url`
+    pub fn get(&self, url: &str) -> Option<&MirrorHealth> {
+        self.mirrors.get(&mirror_key(url)?)
+    }
+
+    /// Fold the attempts of one download into the history
+    pub fn record(&mut self, outcomes: &[AttemptOutcome], now: DateTime<Utc>) {
+        for outcome in outcomes {
+            let Some(key) = mirror_key(&outcome.url) else {
+                continue;
+            };
+            let health = self.mirrors.entry(key).or_default();
+
+            match &outcome.status {
+                AttemptStatus::Succeeded => {
+                    health.record_success(outcome.bytes, outcome.duration, now)
+                }
+                AttemptStatus::Failed(error) => health.record_failure(error, now),
+                AttemptStatus::ChecksumMismatch => health.record_checksum_mismatch(now),
+            }
+        }
+    }
+
+    /// Record that the mirror serving `url` disagreed with the others
+    pub fn record_inconsistency(&mut self, url: &str, reason: &str, now: DateTime<Utc>) {
+        if let Some(key) = mirror_key(url) {
+            self.mirrors
+                .entry(key)
+                .or_default()
+                .record_inconsistency(reason, now);
+        }
+    }
+
+    /// Forget a mirror, given as a URL, `host` or `host:port`. Returns the number removed.
+    pub fn reset(&mut self, mirror: &str) -> usize {
+        let key = mirror_key(mirror);
+        let before = self.mirrors.len();
+        self.mirrors.retain(|candidate, _| {
+            let host_port = candidate
+                .split_once("://")
+                .map_or(candidate.as_str(), |(_, rest)| rest);
+            let host = host_port.split(':').next().unwrap_or(host_port);
+            !(key.as_ref() == Some(candidate) || host_port == mirror || host == mirror)
+        });
+        before - self.mirrors.len()
+    }
+
+    /// Forget every mirror. Returns the number removed.
+    pub fn clear(&mut self) -> usize {
+        let removed = self.mirrors.len();
+        self.mirrors.clear();
+        removed
+    }
+
+    /// Re-rank `sources` using the recorded history.
+    ///
+    /// Measured throughput replaces the hardcoded speed rating and recent
+    /// failures lower the score. Quarantined mirrors are dropped unless every
+    /// source is quarantined, in which case they are kept as a last resort.
+    pub fn apply(&self, sources: &mut Vec<DownloadSource>, now: DateTime<Utc>) {
+        let mut quarantined = Vec::new();
+        let mut healthy = Vec::with_capacity(sources.len());
+
+        for mut source in sources.drain(..) {
+            let Some(health) = source.get_url().and_then(|url| self.get(url)) else {
+                healthy.push(source);
+                continue;
+            };
+
+            source.health_penalty = health.penalty();
+            if let Some(throughput_bps) = health.throughput_bps {
+                source.speed_rating = Some(probe::speed_rating(throughput_bps));
+            }
+
+            if health.is_quarantined(now) {
+                quarantined.push(source);
+            } else {
+                healthy.push(source);
+            }
+        }
+
+        healthy.sort();
+        if healthy.is_empty() {
+            quarantined.sort();
+            healthy = quarantined;
+        }
+        *sources = healthy;
+    }
+}
+
+/// Mirror health file shared by concurrent downloads in one process
+#[derive(Debug, Clone)]
+pub(crate) struct SharedHealth {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl SharedHealth {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub(crate) async fn load(&self) -> Result<MirrorHealthStore> {
+        let _guard = self.lock.lock().await;
+        MirrorHealthStore::load(&self.path)
+    }
+
+    pub(crate) async fn update<T>(&self, f: impl FnOnce(&mut MirrorHealthStore) -> T) -> Result<T> {
+        let _guard = self.lock.lock().await;
+        let mut store = MirrorHealthStore::load(&self.path)?;
+        let result = f(&mut store);
+        store.save()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::sources::SourcePriority;
+
+    fn outcome(url: &str, status: AttemptStatus) -> AttemptOutcome {
+        AttemptOutcome {
+            url: url.to_string(),
+            bytes: 8 * 1024 * 1024,
+            duration: std::time::Duration::from_secs(1),
+            status,
+        }
+    }
+
+    #[test]
+    fn test_failing_mirrors_are_demoted_then_quarantined() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = MirrorHealthStore::load(MirrorHealthStore::path_in(dir.path())).unwrap();
+        let now = Utc::now();
+
+        let flaky = "https://flaky.example.org/iso/a.iso";
+        let steady = "https://steady.example.org/iso/a.iso";
+        store.record(
+            &[
+                outcome(flaky, AttemptStatus::Failed("HTTP 503".to_string())),
+                outcome(steady, AttemptStatus::Succeeded),
+            ],
+            now,
+        );
+
+        let sources = || {
+            vec![
+                DownloadSource::mirror(flaky, SourcePriority::Preferred, None),
+                DownloadSource::mirror(steady, SourcePriority::Medium, None),
+            ]
+        };
+
+        // One failure demotes the preferred mirror below the one that worked
+        let mut ranked = sources();
+        store.apply(&mut ranked, now);
+        assert_eq!(ranked[0].get_url(), Some(steady));
+        assert_eq!(ranked[1].get_url(), Some(flaky));
+        assert_eq!(ranked[0].speed_rating, Some(8));
+
+        // Repeated failures take it out of rotation for a while
+        for _ in 0..2 {
+            store.record(
+                &[outcome(flaky, AttemptStatus::Failed("timeout".to_string()))],
+                now,
+            );
+        }
+        let health = store.get(flaky).unwrap();
+        assert_eq!(health.consecutive_failures, 3);
+        assert!(health.is_quarantined(now));
+        assert!(!health.is_quarantined(now + Duration::hours(2)));
+
+        let mut ranked = sources();
+        store.apply(&mut ranked, now);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].get_url(), Some(steady));
+
+        store.save().unwrap();
+        let mut store = MirrorHealthStore::load(MirrorHealthStore::path_in(dir.path())).unwrap();
+        assert_eq!(store.get(flaky).unwrap().failures, 3);
+        assert_eq!(store.reset("flaky.example.org"), 1);
+        assert!(store.get(flaky).is_none());
+        assert_eq!(store.clear(), 1);
+    }
+
+    #[test]
+    fn test_stale_mirror_quarantine_keeps_last_resort() {
+        let mut store = MirrorHealthStore::load("/nonexistent/mirror_health.json").unwrap();
+        let now = Utc::now();
+        let stale = "http://stale.example.org:8080/a.iso";
+
+        store.record(&[outcome(stale, AttemptStatus::ChecksumMismatch)], now);
+        let health = store.get(stale).unwrap();
+        assert_eq!(health.checksum_mismatches, 1);
+        assert!(health.is_quarantined(now + Duration::hours(12)));
+
+        // With nothing else available the quarantined mirror is still offered
+        let mut sources = vec![DownloadSource::mirror(stale, SourcePriority::High, None)];
+        store.apply(&mut sources, now);
+        assert_eq!(sources.len(), 1);
+        assert!(sources[0].health_penalty > 0);
+
+        assert_eq!(
+            mirror_key(stale).as_deref(),
+            Some("http://stale.example.org:8080")
+        );
+        assert_eq!(store.reset("stale.example.org:8080"), 1);
+    }
+}
@@ -0,0 +1,91 @@
+use reqwest::Url;
+use std::io::{ErrorKind, SeekFrom, Write};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::sync::CancellationToken;
+
+use super::DownloadError;
+use super::engine::{DownloadTask, ProgressTracker, Transfer, existing_size, open_output};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+fn error(message: String) -> DownloadError {
+    DownloadError::Source {
+        scheme: "file",
+        message,
+        transient: false,
+    }
+}
+
+/// Copy a `file://` source, e.g. an ISO on a mounted NFS share
+pub(crate) async fn fetch(
+    task: &DownloadTask,
+    url: &str,
+    cancel: &CancellationToken,
+) -> Result<Transfer, DownloadError> {
+    let request = &task.request;
+    let path = Url::parse(url)
+        .ok()
+        .and_then(|u| u.to_file_path().ok())
+        .ok_or_else(|| error(format!("not a local path: {}", url)))?;
+
+    let mut source = tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound | ErrorKind::PermissionDenied => {
+                error(format!("{}: {}", path.display(), e))
+            }
+            _ => DownloadError::io("Failed to open source file")(e),
+        })?;
+    let total_size = source
+        .metadata()
+        .await
+        .map_err(DownloadError::io("Failed to get source file metadata"))?
+        .len();
+
+    // A partial file longer than the source cannot be a prefix of it
+    let existing = existing_size(request)?;
+    let resume_from = if existing <= total_size { existing } else { 0 };
+    if resume_from > 0 {
+        source
+            .seek(SeekFrom::Start(resume_from))
+            .await
+            .map_err(DownloadError::io("Failed to seek source file"))?;
+    }
+
+    let mut file = open_output(&request.output_path, resume_from)?;
+    let mut downloaded = resume_from;
+    let mut progress = ProgressTracker::new(task, total_size, downloaded);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        if cancel.is_cancelled() {
+            file.flush()
+                .map_err(DownloadError::io("Failed to flush file"))?;
+            file.sync_all()
+                .map_err(DownloadError::io("Failed to sync file"))?;
+            return Err(DownloadError::Cancelled);
+        }
+
+        let read = source
+            .read(&mut buffer)
+            .await
+            .map_err(DownloadError::io("Failed to read source file"))?;
+        if read == 0 {
+            break;
+        }
+
+        file.write_all(&buffer[..read])
+            .map_err(DownloadError::io("Failed to write chunk to file"))?;
+        downloaded += read as u64;
+        progress.update(downloaded);
+    }
+
+    file.flush()
+        .map_err(DownloadError::io("Failed to flush file"))?;
+
+    Ok(Transfer {
+        total: downloaded,
+        received: downloaded - resume_from,
+        ..Default::default()
+    })
+}
@@ -1,12 +1,21 @@
 use anyhow::{Context, Result};
+use chrono::Utc;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{RwLock, Semaphore, mpsc};
-use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-use super::{ChecksumType, DownloadEngine, DownloadProgress, DownloadRequest, DownloadTask};
+use super::consistency::{self, Consistency, Expected};
+use super::health::SharedHealth;
+use super::metalink::MetalinkHash;
+use super::queue::{DownloadQueue, JobState, SharedQueue};
+use super::{
+    BandwidthPolicy, ChecksumType, DownloadEngine, DownloadProgress, DownloadRequest, DownloadTask,
+    MetalinkFile, metalink, probe, shutdown_token, zsync,
+};
+use crate::policy::PolicyConfig;
 use crate::registry::sources::SourceType;
 use crate::registry::{DownloadSource, IsoInfo};
 
@@ -34,8 +43,13 @@ impl Default for DownloadOptions {
 pub struct DownloadManager {
     engine: Arc<DownloadEngine>,
     semaphore: Arc<Semaphore>,
-    active_downloads: Arc<RwLock<HashMap<String, JoinHandle<()>>>>,
+    active_downloads: Arc<RwLock<HashMap<String, CancellationToken>>>,
     progress_sender: mpsc::UnboundedSender<DownloadProgress>,
+    queue: Option<SharedQueue>,
+    health: Option<SharedHealth>,
+    probe_candidates: usize,
+    consistency_candidates: usize,
+    policy: PolicyConfig,
 }
 
 impl DownloadManager {
@@ -53,11 +67,52 @@ impl DownloadManager {
                 semaphore,
                 active_downloads,
                 progress_sender,
+                queue: None,
+                health: None,
+                probe_candidates: 0,
+                consistency_candidates: 0,
+                policy: PolicyConfig::default(),
             },
             progress_receiver,
         ))
     }
 
+    /// Apply a bandwidth policy to all downloads started by this manager
+    pub fn with_bandwidth(self, policy: BandwidthPolicy) -> Self {
+        self.engine.rate_limiter().set_policy(policy);
+        self
+    }
+
+    /// Record downloads in a persistent queue file so they survive restarts
+    pub fn with_queue(mut self, queue_path: PathBuf) -> Self {
+        self.queue = Some(SharedQueue::new(queue_path));
+        self
+    }
+
+    /// Rank mirrors by their recorded history and record the outcome of every download
+    pub fn with_mirror_health(mut self, health_path: PathBuf) -> Self {
+        self.health = Some(SharedHealth::new(health_path));
+        self
+    }
+
+    /// Probe this many top mirrors before each download (0 disables probing)
+    pub fn with_mirror_probe(mut self, candidates: usize) -> Self {
+        self.probe_candidates = candidates;
+        self
+    }
+
+    /// Compare this many top candidates with each other before each download (0 disables)
+    pub fn with_consistency_check(mut self, candidates: usize) -> Self {
+        self.consistency_candidates = candidates;
+        self
+    }
+
+    /// Refuse distros, sources and unverifiable ISOs the security policy does not allow
+    pub fn with_policy(mut self, policy: PolicyConfig) -> Self {
+        self.policy = policy;
+        self
+    }
+
     pub async fn download_iso(
         &self,
         iso_info: &IsoInfo,
@@ -69,49 +124,258 @@ impl DownloadManager {
             Uuid::new_v4().to_string()[..8].to_string()
         );
 
-        // Select best download source
-        let source = self.select_best_source(&iso_info.download_sources, options)?;
-        let url = source
-            .get_url()
-            .context("Selected source has no URL")?
-            .to_string();
+        let request = self.prepare_request(iso_info, options).await?;
+
+        if let Some(queue) = &self.queue {
+            queue
+                .update(|q| {
+                    q.enqueue(download_id.clone(), iso_info, options, 0);
+                })
+                .await?;
+        }
+
+        self.start_download(download_id.clone(), request).await?;
+        Ok(download_id)
+    }
+
+    /// Build the download request for an ISO without starting it
+    pub async fn prepare_request(
+        &self,
+        iso_info: &IsoInfo,
+        options: &DownloadOptions,
+    ) -> Result<DownloadRequest> {
+        self.policy.check_distro(&iso_info.distro)?;
+
+        // Expand metalink sources into their mirrors before picking one
+        let (sources, metalink_file) = self.expand_metalink_sources(iso_info).await;
+
+        // Select best download source, keeping the rest as fallbacks
+        let mut candidates = Vec::new();
+        let mut violations = Vec::new();
+        for source in self.select_http_sources(&sources, options)? {
+            let url = source.get_url().context("Selected source has no URL")?;
+            let mut source = source.clone();
+            source.url = Some(self.resolve_url_template(url, iso_info)?);
+            match self.policy.check_source(&source) {
+                Ok(()) => candidates.push(source),
+                Err(violation) => violations.push(violation.to_string()),
+            }
+        }
+
+        if candidates.is_empty() {
+            anyhow::bail!(
+                "No download source for {} is allowed by the security policy:\n  {}",
+                iso_info.filename,
+                violations.join("\n  ")
+            );
+        }
+
+        // Demote mirrors that failed recently and skip quarantined ones
+        if let Some(health) = &self.health
+            && let Ok(health) = health.load().await
+        {
+            health.apply(&mut candidates, Utc::now());
+        }
+
+        // Measure the best candidates instead of trusting hardcoded speed ratings
+        if self.probe_candidates > 0 {
+            probe::rank(self.engine.http(), &mut candidates, self.probe_candidates).await;
+        }
 
-        // Handle URL template replacement
-        let resolved_url = self.resolve_url_template(&url, iso_info)?;
+        let mut urls = Vec::with_capacity(candidates.len());
+        for url in candidates.into_iter().filter_map(|s| s.url) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        let resolved_url = urls.remove(0);
 
-        let output_path = options.output_directory.join(&iso_info.filename);
+        let output_path = options.output_directory.join(iso_info.download_filename());
 
         // Create download request
-        let mut request = DownloadRequest::new(resolved_url, output_path);
-
-        if options.verify_checksums {
-            if let Some(checksum) = &iso_info.checksum {
-                let checksum_type = match iso_info.checksum_type.as_deref() {
-                    Some("md5") => ChecksumType::Md5,
-                    Some("sha1") => ChecksumType::Sha1,
-                    Some("sha256") => ChecksumType::Sha256,
-                    Some("sha512") => ChecksumType::Sha512,
-                    _ => ChecksumType::Sha256, // Default
-                };
-
-                request = request.with_checksum(checksum.clone(), checksum_type);
+        let mut request = DownloadRequest::new(resolved_url, output_path).with_mirrors(urls);
+        if let Some(compression) = iso_info.format.compression {
+            request = request.with_decompression(
+                compression,
+                options.output_directory.join(iso_info.local_filename()),
+                iso_info.checksum_layer,
+            );
+        }
+
+        // Fall back to the size and hash published in the metalink
+        let metalink_hash = metalink_file.as_ref().and_then(|f| f.best_hash());
+        let (checksum, checksum_type) = match (&iso_info.checksum, metalink_hash) {
+            (Some(checksum), _) => (Some(checksum.as_str()), iso_info.checksum_type.as_deref()),
+            (None, Some(hash)) => (Some(hash.value.as_str()), Some(hash.algorithm.as_str())),
+            (None, None) => (None, None),
+        };
+        self.policy.check_checksum(&iso_info.filename, checksum)?;
+
+        if options.verify_checksums || self.policy.require_checksum {
+            if let Some(checksum) = checksum {
+                let checksum_type = checksum_type
+                    .and_then(ChecksumType::from_algorithm)
+                    .unwrap_or(ChecksumType::Sha256); // Default
+
+                request = request.with_checksum(checksum.to_string(), checksum_type);
             }
         }
 
+        if let Some(size) = metalink_file.as_ref().and_then(|f| f.size) {
+            request = request.with_expected_size(size);
+        }
+
+        if self.consistency_candidates > 0 {
+            self.check_consistency(&mut request).await;
+        }
+
         if !options.resume_downloads {
             request = request.no_resume();
         }
 
-        self.start_download(download_id.clone(), request).await?;
-        Ok(download_id)
+        // Delta-update from an older copy in the library when a zsync control file is published
+        if !request.output_path.exists() && request.decompress.is_none() {
+            let zsync_source = iso_info
+                .download_sources
+                .iter()
+                .find(|s| s.source_type == SourceType::Zsync && s.is_usable());
+
+            if let (Some(source), Some(seed)) = (
+                zsync_source,
+                zsync::find_seed(
+                    &options.output_directory,
+                    iso_info.local_filename(),
+                    &iso_info.version,
+                ),
+            ) {
+                let control_url = self.resolve_url_template(
+                    source.get_url().context("zsync source has no URL")?,
+                    iso_info,
+                )?;
+                // Blocks come from the checked sources, but the control file is fetched too
+                if self.policy.check_url(&control_url).is_ok() {
+                    request = request.with_zsync(control_url, seed);
+                }
+            }
+        }
+
+        Ok(request)
+    }
+
+    /// Skip mirrors whose headers disagree with the checksum or with the other mirrors
+    async fn check_consistency(&self, request: &mut DownloadRequest) {
+        let urls: Vec<String> = request
+            .candidate_urls()
+            .into_iter()
+            .take(self.consistency_candidates)
+            .map(String::from)
+            .collect();
+        let expected = expected_values(request);
+        // A single mirror can only be compared with published values
+        if urls.len() < 2 && expected.size.is_none() && expected.hash.is_none() {
+            return;
+        }
+
+        let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+        let rejected: Vec<(String, String)> =
+            consistency::check(self.engine.http(), &urls, &expected)
+                .await
+                .into_iter()
+                .filter_map(|check| match check.consistency {
+                    Consistency::Mismatch(reason) => Some((check.url, reason)),
+                    _ => None,
+                })
+                .collect();
+        if rejected.is_empty() {
+            return;
+        }
+
+        if let Some(health) = &self.health {
+            let _ = health
+                .update(|h| {
+                    for (url, reason) in &rejected {
+                        h.record_inconsistency(url, reason, Utc::now());
+                    }
+                })
+                .await;
+        }
+
+        let urls: Vec<String> = rejected.into_iter().map(|(url, _)| url).collect();
+        request.remove_urls(&urls);
+    }
+
+    /// Start every queued job, highest priority first. Returns the started job IDs.
+    pub async fn run_queue(&self, options: &DownloadOptions) -> Result<Vec<String>> {
+        let queue = self
+            .queue
+            .as_ref()
+            .context("No download queue configured")?;
+        let jobs: Vec<_> = queue
+            .load()
+            .await?
+            .runnable()
+            .into_iter()
+            .cloned()
+            .collect();
+
+        let mut started = Vec::new();
+        for job in jobs {
+            let job_options = job.options(options.max_concurrent);
+            match self.prepare_request(&job.iso_info, &job_options).await {
+                Ok(request) => {
+                    self.start_download(job.id.clone(), request).await?;
+                    started.push(job.id);
+                }
+                Err(e) => {
+                    queue
+                        .update(|q| q.mark_failed(&job.id, &e.to_string()))
+                        .await?;
+                }
+            }
+        }
+
+        Ok(started)
+    }
+
+    /// Stop downloads that were paused or cancelled from another `isod` process
+    pub async fn sync_queue(&self) -> Result<()> {
+        let Some(queue) = &self.queue else {
+            return Ok(());
+        };
+        let queue = queue.load().await?;
+
+        let stopped: Vec<String> = self
+            .active_downloads
+            .read()
+            .await
+            .keys()
+            .filter(|id| {
+                queue
+                    .get(id)
+                    .is_none_or(|job| job.state == JobState::Paused)
+            })
+            .cloned()
+            .collect();
+
+        for id in stopped {
+            self.stop_download(&id).await;
+        }
+        Ok(())
     }
 
+    /// Start a download. The task waits for a free slot in the background.
     pub async fn start_download(&self, id: String, request: DownloadRequest) -> Result<()> {
-        let permit = self.semaphore.clone().acquire_owned().await?;
+        let semaphore = Arc::clone(&self.semaphore);
         let engine = Arc::clone(&self.engine);
         let progress_sender = self.progress_sender.clone();
         let active_downloads = Arc::clone(&self.active_downloads);
+        let queue = self.queue.clone();
+        let health = self.health.clone();
         let id_clone = id.clone();
+        let url = request.url.clone();
+        // Child of the process-wide token so Ctrl-C stops every download
+        let cancel = shutdown_token().child_token();
+        let task_cancel = cancel.clone();
 
         let task = DownloadTask {
             id: id.clone(),
@@ -119,45 +383,171 @@ impl DownloadManager {
             progress_sender: progress_sender.clone(),
         };
 
-        let handle = tokio::spawn(async move {
-            let _permit = permit; // Keep permit until download completes
-            let result = engine.download(task).await;
+        // Registered before the task runs, so its removal cannot come first
+        self.active_downloads.write().await.insert(id, cancel);
+
+        tokio::spawn(async move {
+            let permit = tokio::select! {
+                permit = semaphore.acquire_owned() => permit.ok(),
+                _ = task_cancel.cancelled() => {
+                    let _ = progress_sender.send(DownloadProgress::Cancelled {
+                        id: id_clone.clone(),
+                    });
+                    None
+                }
+            };
+            let Some(_permit) = permit else {
+                active_downloads.write().await.remove(&id_clone);
+                return;
+            };
+
+            if let Some(queue) = &queue {
+                let _ = queue.update(|q| q.mark_running(&id_clone, &url)).await;
+            }
+
+            let result = engine.download(task, task_cancel).await;
 
             // Remove from active downloads when complete
             active_downloads.write().await.remove(&id_clone);
 
-            // Send final result if not already sent
-            if result.success {
-                // The engine already sends Completed progress
-            } else {
-                // The engine already sends Failed progress
+            if let Some(health) = &health
+                && !result.attempts.is_empty()
+            {
+                let _ = health
+                    .update(|h| h.record(&result.attempts, Utc::now()))
+                    .await;
+            }
+
+            // The engine already sends Completed/Failed/Cancelled progress
+            if let Some(queue) = &queue {
+                let _ = queue
+                    .update(|q| {
+                        if result.cancelled {
+                            q.mark_interrupted(&id_clone);
+                        } else if result.success && result.checksum_verified {
+                            q.mark_done(&id_clone, result.bytes_downloaded);
+                        } else {
+                            let error = result
+                                .error
+                                .as_deref()
+                                .unwrap_or("Checksum verification failed");
+                            q.mark_failed(&id_clone, error);
+                        }
+                    })
+                    .await;
             }
         });
 
-        self.active_downloads.write().await.insert(id, handle);
         Ok(())
     }
 
+    /// Cancel a download and remove it from the queue
     pub async fn cancel_download(&self, id: &str) -> Result<()> {
-        let mut downloads = self.active_downloads.write().await;
-        if let Some(handle) = downloads.remove(id) {
-            handle.abort();
-            let _ = self
-                .progress_sender
-                .send(DownloadProgress::Cancelled { id: id.to_string() });
+        self.stop_download(id).await;
+        if let Some(queue) = &self.queue {
+            queue
+                .update(|q| {
+                    let _ = q.cancel(id);
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Pause a download, keeping the partial file for a later resume
+    pub async fn pause_download(&self, id: &str) -> Result<()> {
+        self.stop_download(id).await;
+        if let Some(queue) = &self.queue {
+            queue.update(|q| q.pause(id)).await??;
         }
         Ok(())
     }
 
+    /// Cancel every active download, leaving partial files resumable
+    pub async fn cancel_all(&self) {
+        for cancel in self.active_downloads.read().await.values() {
+            cancel.cancel();
+        }
+    }
+
+    /// Signal a download to stop. The task flushes its file and sends `Cancelled` itself.
+    async fn stop_download(&self, id: &str) {
+        if let Some(cancel) = self.active_downloads.read().await.get(id) {
+            cancel.cancel();
+        }
+    }
+
+    /// Load the current state of the download queue
+    pub async fn queue(&self) -> Result<DownloadQueue> {
+        self.queue
+            .as_ref()
+            .context("No download queue configured")?
+            .load()
+            .await
+    }
+
     pub async fn get_active_downloads(&self) -> Vec<String> {
         self.active_downloads.read().await.keys().cloned().collect()
     }
 
-    fn select_best_source<'a>(
+    /// Replace metalink sources with the mirrors they advertise.
+    ///
+    /// The metalink URL itself stays as a direct source since redirectors such as
+    /// Fedora's MirrorManager also serve the file. Only the first metalink that
+    /// resolves contributes size and hash information.
+    async fn expand_metalink_sources(
+        &self,
+        iso_info: &IsoInfo,
+    ) -> (Vec<DownloadSource>, Option<MetalinkFile>) {
+        let mut sources = Vec::with_capacity(iso_info.download_sources.len());
+        let mut metalink_file = None;
+
+        for source in &iso_info.download_sources {
+            if source.source_type != SourceType::Metalink {
+                sources.push(source.clone());
+                continue;
+            }
+
+            let Some(url) = source.get_url() else {
+                continue;
+            };
+            let Ok(resolved) = self.resolve_url_template(url, iso_info) else {
+                continue;
+            };
+
+            // Sources are checked against the policy later, but the metalink is fetched now
+            if self.policy.check_url(&resolved).is_ok()
+                && let Ok(file) = metalink::resolve(self.engine.http(), &resolved).await
+            {
+                // Mirrors advertised by a verified metalink are as trusted as the metalink
+                sources.extend(file.to_mirror_sources().into_iter().map(|mirror| {
+                    if source.verified {
+                        mirror.verified()
+                    } else {
+                        mirror
+                    }
+                }));
+                if metalink_file.is_none() {
+                    metalink_file = Some(file);
+                }
+            }
+
+            if !resolved.ends_with(".meta4") && !resolved.ends_with(".metalink") {
+                let mut direct = source.clone();
+                direct.source_type = SourceType::Direct;
+                sources.push(direct);
+            }
+        }
+
+        (sources, metalink_file)
+    }
+
+    /// Usable HTTP sources, best first
+    fn select_http_sources<'a>(
         &self,
         sources: &'a [DownloadSource],
         options: &DownloadOptions,
-    ) -> Result<&'a DownloadSource> {
+    ) -> Result<Vec<&'a DownloadSource>> {
         if sources.is_empty() {
             anyhow::bail!("No download sources available");
         }
@@ -197,14 +587,16 @@ impl DownloadManager {
             });
         }
 
-        // Return the best usable source
-        sorted_sources
-            .iter()
-            .find(|s| {
-                s.is_usable() && matches!(s.source_type, SourceType::Direct | SourceType::Mirror)
-            })
-            .copied()
-            .context("No usable HTTP sources found")
+        // Keep only usable HTTP sources
+        sorted_sources.retain(|s| {
+            s.is_usable() && matches!(s.source_type, SourceType::Direct | SourceType::Mirror)
+        });
+
+        if sorted_sources.is_empty() {
+            anyhow::bail!("No usable HTTP sources found");
+        }
+
+        Ok(sorted_sources)
     }
 
     fn resolve_url_template(&self, url: &str, iso_info: &IsoInfo) -> Result<String> {
@@ -226,3 +618,164 @@ impl DownloadManager {
         Ok(resolved)
     }
 }
+
+/// Size and hash mirror headers can be compared with before downloading
+fn expected_values(request: &DownloadRequest) -> Expected {
+    // Mirrors serve the compressed file, so a checksum of the image says nothing about them
+    let image_checksum = request
+        .decompress
+        .as_ref()
+        .is_some_and(|d| !d.checksum_layer.is_download());
+    Expected {
+        size: request.expected_size,
+        hash: request
+            .expected_checksum
+            .as_ref()
+            .zip(request.checksum_type)
+            .filter(|_| !image_checksum)
+            .map(|(value, checksum_type)| MetalinkHash {
+                algorithm: checksum_type.algorithm().to_string(),
+                value: value.to_lowercase(),
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ChecksumLayer, Compression, ImageFormat};
+    use crate::registry::ReleaseType;
+    use crate::registry::sources::SourcePriority;
+    use reqwest::Url;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_policy_applies_to_metalink_and_zsync_urls() {
+        // Count connections to a plain HTTP host the policy does not allow
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                drop(socket);
+            }
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("debian-12.10.0-amd64-netinst.iso"), b"seed").unwrap();
+        let iso_info = IsoInfo {
+            distro: "debian".to_string(),
+            version: "12.11.0".to_string(),
+            architecture: "amd64".to_string(),
+            variant: None,
+            filename: "debian-12.11.0-amd64-netinst.iso".to_string(),
+            local_filename: None,
+            format: ImageFormat::default(),
+            download_sources: vec![
+                DownloadSource::metalink(
+                    &format!("http://{}/{{filename}}.meta4", addr),
+                    SourcePriority::High,
+                ),
+                DownloadSource::zsync(
+                    &format!("http://{}/{{filename}}.zsync", addr),
+                    SourcePriority::High,
+                ),
+                DownloadSource::direct(
+                    "https://cdimage.debian.org/debian-cd/{filename}",
+                    SourcePriority::Preferred,
+                ),
+            ],
+            checksum: None,
+            checksum_type: None,
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        };
+        let options = DownloadOptions {
+            output_directory: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let (manager, _progress) = DownloadManager::new(options.clone()).unwrap();
+        let manager = manager.with_policy(PolicyConfig {
+            https_only: true,
+            ..Default::default()
+        });
+        let request = manager.prepare_request(&iso_info, &options).await.unwrap();
+        assert!(request.zsync.is_none());
+        assert_eq!(
+            request.url,
+            "https://cdimage.debian.org/debian-cd/debian-12.11.0-amd64-netinst.iso"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+
+        // Without the policy both are used
+        let (manager, _progress) = DownloadManager::new(options.clone()).unwrap();
+        let request = manager.prepare_request(&iso_info, &options).await.unwrap();
+        assert!(request.zsync.is_some());
+        assert!(connections.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_image_checksums_are_not_compared_with_mirror_headers() {
+        let request = DownloadRequest::new(
+            "https://example.org/raspios.img.xz".to_string(),
+            PathBuf::from("raspios.img.xz"),
+        )
+        .with_checksum("AB".repeat(32), ChecksumType::Sha256);
+        assert_eq!(
+            expected_values(&request).hash.unwrap().value,
+            "ab".repeat(32)
+        );
+
+        let compressed = request.clone().with_decompression(
+            Compression::Xz,
+            PathBuf::from("raspios.img"),
+            ChecksumLayer::Download,
+        );
+        assert!(expected_values(&compressed).hash.is_some());
+
+        let image = request.with_decompression(
+            Compression::Xz,
+            PathBuf::from("raspios.img"),
+            ChecksumLayer::Image,
+        );
+        assert!(expected_values(&image).hash.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_finished_downloads_leave_no_active_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DownloadOptions {
+            output_directory: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let (manager, mut progress) = DownloadManager::new(options).unwrap();
+
+        let missing = Url::from_file_path(dir.path().join("missing.iso")).unwrap();
+        for n in 0..20 {
+            let request =
+                DownloadRequest::new(missing.to_string(), dir.path().join(format!("{}.iso", n)));
+            manager
+                .start_download(n.to_string(), request)
+                .await
+                .unwrap();
+        }
+
+        let mut finished = 0;
+        while finished < 20 {
+            if let DownloadProgress::Failed { .. } = progress.recv().await.unwrap() {
+                finished += 1;
+            }
+        }
+        // Entries are removed just after the final progress event
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(manager.get_active_downloads().await.is_empty());
+    }
+}
@@ -0,0 +1,488 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+
+use super::ChecksumType;
+use crate::http::HttpContext;
+use crate::registry::DownloadSource;
+use crate::registry::sources::SourcePriority;
+
+/// Media types accepted when asking a redirector for a metalink document
+const METALINK_ACCEPT: &str =
+    "application/metalink4+xml, application/metalink+xml;q=0.9, */*;q=0.1";
+
+/// Mirrors beyond this count are ignored
+const MAX_MIRRORS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetalinkHash {
+    /// Normalized algorithm name as used by `IsoInfo.checksum_type` (e.g. `sha256`)
+    pub algorithm: String,
+    /// Lowercase hex digest
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetalinkUrl {
+    pub url: String,
+    /// Lower is preferred (RFC 5854 semantics)
+    pub priority: Option<u32>,
+    pub location: Option<String>,
+}
+
+/// A single file description from a metalink document or metalink HTTP headers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetalinkFile {
+    pub name: Option<String>,
+    pub size: Option<u64>,
+    pub hashes: Vec<MetalinkHash>,
+    pub urls: Vec<MetalinkUrl>,
+}
+
+impl MetalinkFile {
+    /// Strongest hash this file advertises that isod can verify
+    pub fn best_hash(&self) -> Option<&MetalinkHash> {
+        const PREFERENCE: &[&str] = &["sha512", "blake2b", "sha384", "sha256", "sha1", "md5"];
+        PREFERENCE
+            .iter()
+            .find_map(|algo| self.hashes.iter().find(|h| h.algorithm == *algo))
+    }
+
+    /// Convert the advertised URLs into mirror sources, best first
+    pub fn to_mirror_sources(&self) -> Vec<DownloadSource> {
+        let mut urls: Vec<&MetalinkUrl> = self
+            .urls
+            .iter()
+            .filter(|u| u.url.starts_with("https://") || u.url.starts_with("http://"))
+            .collect();
+        urls.sort_by_key(|u| u.priority.unwrap_or(u32::MAX));
+
+        urls.into_iter()
+            .take(MAX_MIRRORS)
+            .enumerate()
+            .map(|(rank, u)| {
+                let priority = if rank < 3 {
+                    SourcePriority::High
+                } else {
+                    SourcePriority::Medium
+                };
+                let mut source = DownloadSource::mirror(&u.url, priority, None)
+                    .with_description("Metalink mirror");
+                if let Some(location) = &u.location {
+                    source = source.with_region(&location.to_uppercase());
+                }
+                source
+            })
+            .collect()
+    }
+
+    fn merge(&mut self, other: MetalinkFile) {
+        if self.name.is_none() {
+            self.name = other.name;
+        }
+        if self.size.is_none() {
+            self.size = other.size;
+        }
+        for hash in other.hashes {
+            if !self.hashes.contains(&hash) {
+                self.hashes.push(hash);
+            }
+        }
+        for url in other.urls {
+            if !self.urls.iter().any(|u| u.url == url.url) {
+                self.urls.push(url);
+            }
+        }
+    }
+}
+
+/// Normalize metalink/Digest algorithm names (`sha-256`, `SHA-256`, `sha256`)
+fn normalize_algorithm(name: &str) -> Option<String> {
+    ChecksumType::from_algorithm(name).map(|t| t.algorithm().to_string())
+}
+
+/// Parse a metalink document (RFC 5854 `.meta4` or the older 3.0 format)
+pub fn parse_metalink(xml: &str) -> Result<Vec<MetalinkFile>> {
+    let document = roxmltree::Document::parse(xml).context("Failed to parse metalink XML")?;
+    let root = document.root_element();
+
+    if root.tag_name().name() != "metalink" {
+        bail!("Not a metalink document");
+    }
+
+    let mut files = Vec::new();
+
+    for file_node in root
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "file")
+    {
+        let mut file = MetalinkFile {
+            name: file_node.attribute("name").map(|s| s.to_string()),
+            ..Default::default()
+        };
+
+        for node in file_node.descendants().filter(|n| n.is_element()) {
+            let text = node.text().map(str::trim).unwrap_or_default();
+
+            match node.tag_name().name() {
+                "size" => file.size = text.parse().ok(),
+                "hash" => {
+                    // Piece hashes describe chunks, not the whole file
+                    if node
+                        .parent_element()
+                        .is_some_and(|p| p.tag_name().name() == "pieces")
+                    {
+                        continue;
+                    }
+                    if let Some(algorithm) = node.attribute("type").and_then(normalize_algorithm)
+                        && !text.is_empty()
+                        && text.chars().all(|c| c.is_ascii_hexdigit())
+                    {
+                        file.hashes.push(MetalinkHash {
+                            algorithm,
+                            value: text.to_lowercase(),
+                        });
+                    }
+                }
+                "url" if !text.is_empty() => {
+                    // Metalink 4 uses priority (1 = best), 3.0 uses preference (100 = best)
+                    let priority = node
+                        .attribute("priority")
+                        .and_then(|p| p.parse().ok())
+                        .or_else(|| {
+                            node.attribute("preference")
+                                .and_then(|p| p.parse::<u32>().ok())
+                                .map(|p| 101u32.saturating_sub(p))
+                        });
+                    file.urls.push(MetalinkUrl {
+                        url: text.to_string(),
+                        priority,
+                        location: node.attribute("location").map(|s| s.to_string()),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        files.push(file);
+    }
+
+    if files.is_empty() {
+        bail!("Metalink document does not describe any files");
+    }
+
+    Ok(files)
+}
+
+/// Extract mirrors and hashes from RFC 6249 `Link` and RFC 3230/9530 digest headers
+pub fn parse_headers(headers: &HeaderMap) -> MetalinkFile {
+    let mut file = MetalinkFile {
+        size: headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()),
+        ..Default::default()
+    };
+
+    for value in headers.get_all(reqwest::header::LINK) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for link in parse_link_header(value) {
+            if link.rel.as_deref() == Some("duplicate") {
+                file.urls.push(MetalinkUrl {
+                    url: link.url,
+                    priority: link.pri,
+                    location: link.geo,
+                });
+            }
+        }
+    }
+
+    // RFC 9530 `Repr-Digest` wraps the base64 value in colons
+    let digests = headers
+        .get_all("digest")
+        .into_iter()
+        .chain(headers.get_all("repr-digest"));
+    for value in digests {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for digest in value.split(',') {
+            let Some((algo, encoded)) = digest.trim().split_once('=') else {
+                continue;
+            };
+            let Some(algorithm) = normalize_algorithm(algo) else {
+                continue;
+            };
+            let encoded = encoded.trim().trim_matches(':');
+            if let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(encoded) {
+                file.hashes.push(MetalinkHash {
+                    algorithm,
+                    value: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+                });
+            }
+        }
+    }
+
+    file
+}
+
+#[derive(Debug, Default)]
+struct LinkValue {
+    url: String,
+    rel: Option<String>,
+    media_type: Option<String>,
+    pri: Option<u32>,
+    geo: Option<String>,
+}
+
+/// Split a `Link` header into its entries (`<url>; rel=duplicate; pri=1; geo=de, <url2>; ...`)
+fn parse_link_header(value: &str) -> Vec<LinkValue> {
+    let mut links = Vec::new();
+
+    // Commas may appear inside parameters, so only split where a new `<url>` starts
+    let mut entries: Vec<String> = Vec::new();
+    for part in value.split(',') {
+        match entries.last_mut() {
+            Some(last) if !part.trim_start().starts_with('<') => {
+                last.push(',');
+                last.push_str(part);
+            }
+            _ => entries.push(part.trim_start().to_string()),
+        }
+    }
+
+    for entry in entries.iter().map(|e| e.trim_start_matches('<')) {
+        let Some((url, params)) = entry.split_once('>') else {
+            continue;
+        };
+        let mut link = LinkValue {
+            url: url.trim().to_string(),
+            ..Default::default()
+        };
+
+        for param in params.split(';') {
+            let Some((key, value)) = param.trim().split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match key.trim().to_lowercase().as_str() {
+                "rel" => link.rel = Some(value.to_lowercase()),
+                "type" => link.media_type = Some(value.to_lowercase()),
+                "pri" => link.pri = value.parse().ok(),
+                "geo" => link.geo = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        links.push(link);
+    }
+
+    links
+}
+
+fn is_metalink_media_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("metalink"))
+}
+
+/// Pick the file entry matching `url` from a multi-file document
+fn select_file(files: Vec<MetalinkFile>, url: &str) -> MetalinkFile {
+    let basename = url.rsplit('/').next().unwrap_or_default();
+    let mut files = files;
+    let index = files
+        .iter()
+        .position(|f| f.name.as_deref() == Some(basename))
+        .unwrap_or(0);
+    files.swap_remove(index)
+}
+
+async fn fetch_document(http: &HttpContext, url: &str) -> Result<Vec<MetalinkFile>> {
+    let response = http
+        .get(url)
+        .timeout(http.mirror_timeout())
+        .header("Accept", METALINK_ACCEPT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch metalink from: {}", url))?;
+
+    if !response.status().is_success() {
+        bail!("Metalink request failed with status: {}", response.status());
+    }
+
+    let content = response
+        .text()
+        .await
+        .context("Failed to read metalink response")?;
+    parse_metalink(&content)
+}
+
+/// Resolve a metalink source URL into mirrors, size and hashes.
+///
+/// `.meta4`/`.metalink` URLs are fetched directly. Anything else is treated as a
+/// redirector such as Fedora's MirrorManager: a HEAD request asking for metalink
+/// is sent first, so that a server that ignores the `Accept` header does not
+/// start streaming the ISO itself.
+pub async fn resolve(http: &HttpContext, url: &str) -> Result<MetalinkFile> {
+    if url.ends_with(".meta4") || url.ends_with(".metalink") {
+        return Ok(select_file(fetch_document(http, url).await?, url));
+    }
+
+    let response = http
+        .head(url)
+        .timeout(http.mirror_timeout())
+        .header("Accept", METALINK_ACCEPT)
+        .send()
+        .await
+        .with_context(|| format!("Failed to query metalink headers from: {}", url))?;
+
+    if !response.status().is_success() {
+        bail!("Metalink request failed with status: {}", response.status());
+    }
+
+    let headers = response.headers().clone();
+
+    if is_metalink_media_type(&headers) {
+        return Ok(select_file(fetch_document(http, url).await?, url));
+    }
+
+    let mut file = parse_headers(&headers);
+
+    // A described-by link points at a full metalink document for this file
+    let described_by = headers
+        .get_all(reqwest::header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(parse_link_header)
+        .find(|l| {
+            l.rel.as_deref() == Some("describedby")
+                && l.media_type
+                    .as_deref()
+                    .is_some_and(|t| t.contains("metalink"))
+        });
+
+    if let Some(link) = described_by {
+        let document_url = reqwest::Url::parse(url)
+            .and_then(|base| base.join(&link.url))
+            .map(|u| u.to_string())
+            .unwrap_or(link.url);
+        if let Ok(files) = fetch_document(http, &document_url).await {
+            file.merge(select_file(files, url));
+        }
+    }
+
+    if file.urls.is_empty() && file.hashes.is_empty() {
+        bail!("No metalink information available for: {}", url);
+    }
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const META4: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metalink xmlns="urn:ietf:params:xml:ns:metalink">
+  <file name="Fedora-Workstation-Live-x86_64-40-1.14.iso">
+    <size>2295853056</size>
+    <hash type="sha-256">DEADBEEF00112233445566778899AABBCCDDEEFF00112233445566778899AABB</hash>
+    <pieces length="262144" type="sha-1">
+      <hash>0123456789abcdef0123456789abcdef01234567</hash>
+    </pieces>
+    <url location="de" priority="2">https://ftp.fau.de/fedora/Fedora-Workstation-Live-x86_64-40-1.14.iso</url>
+    <url location="us" priority="1">https://mirrors.kernel.org/fedora/Fedora-Workstation-Live-x86_64-40-1.14.iso</url>
+    <url priority="3">rsync://mirror.example.org/fedora/Fedora-Workstation-Live-x86_64-40-1.14.iso</url>
+  </file>
+</metalink>"#;
+
+    const METALINK3: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<metalink version="3.0" xmlns="http://www.metalinker.org/">
+  <files>
+    <file name="repomd.xml">
+      <size>4231</size>
+      <verification>
+        <hash type="md5">0123456789abcdef0123456789abcdef</hash>
+        <hash type="sha512">aa00</hash>
+      </verification>
+      <resources>
+        <url protocol="https" type="https" location="US" preference="100">https://a.example/repomd.xml</url>
+        <url protocol="https" type="https" location="AU" preference="90">https://b.example/repomd.xml</url>
+      </resources>
+    </file>
+  </files>
+</metalink>"#;
+
+    #[test]
+    fn test_parse_metalink4() {
+        let files = parse_metalink(META4).unwrap();
+        assert_eq!(files.len(), 1);
+
+        let file = &files[0];
+        assert_eq!(file.size, Some(2295853056));
+        assert_eq!(file.hashes.len(), 1);
+        assert_eq!(file.best_hash().unwrap().algorithm, "sha256");
+        assert_eq!(
+            file.best_hash().unwrap().value,
+            "deadbeef00112233445566778899aabbccddeeff00112233445566778899aabb"
+        );
+
+        let sources = file.to_mirror_sources();
+        assert_eq!(sources.len(), 2);
+        assert!(sources[0].get_url().unwrap().contains("kernel.org"));
+        assert_eq!(sources[0].region.as_deref(), Some("US"));
+        assert_eq!(sources[1].region.as_deref(), Some("DE"));
+    }
+
+    #[test]
+    fn test_parse_metalink3_preference() {
+        let file = &parse_metalink(METALINK3).unwrap()[0];
+        assert_eq!(file.size, Some(4231));
+        assert_eq!(file.best_hash().unwrap().algorithm, "sha512");
+        assert_eq!(file.urls[0].priority, Some(1));
+        assert_eq!(file.urls[1].priority, Some(11));
+    }
+
+    #[test]
+    fn test_parse_link_and_digest_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            "link",
+            HeaderValue::from_static(
+                "<https://mirror.one/f.iso>; rel=duplicate; pri=2; geo=fr, <https://mirror.two/f.iso>; rel=duplicate; pri=1; geo=nl",
+            ),
+        );
+        headers.append(
+            "link",
+            HeaderValue::from_static(
+                "<https://mirror.one/f.iso.meta4>; rel=describedby; type=\"application/metalink4+xml\"",
+            ),
+        );
+        headers.append(
+            "digest",
+            HeaderValue::from_static("SHA-256=LCa0a2j/xo/5m0U8HTBBNBNCLXBkg7+g+YpeiGJm564="),
+        );
+        headers.append("content-length", HeaderValue::from_static("1024"));
+
+        let file = parse_headers(&headers);
+        assert_eq!(file.size, Some(1024));
+        assert_eq!(file.urls.len(), 2);
+        assert_eq!(file.urls[1].location.as_deref(), Some("nl"));
+        assert_eq!(
+            file.best_hash().unwrap().value,
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+
+        let sources = file.to_mirror_sources();
+        assert_eq!(sources[0].get_url(), Some("https://mirror.two/f.iso"));
+    }
+
+    #[test]
+    fn test_rejects_non_metalink_xml() {
+        assert!(parse_metalink("<html><body/></html>").is_err());
+    }
+}
@@ -1,15 +1,46 @@
+pub mod bandwidth;
 pub mod checksum;
+pub mod consistency;
 pub mod engine;
+pub mod error;
+pub(crate) mod ftp;
+pub mod health;
+pub(crate) mod local;
 pub mod manager;
+pub mod metalink;
+pub mod probe;
 pub mod progress;
+pub mod queue;
+pub mod resume;
+pub(crate) mod rsync;
 pub mod torrent;
+pub mod zsync;
 
+pub use bandwidth::{BandwidthPolicy, RateLimiter};
 pub use checksum::{ChecksumType, ChecksumVerifier};
 pub use engine::{DownloadEngine, DownloadTask};
+pub use error::{DownloadError, ErrorClass};
+pub use health::{MirrorHealth, MirrorHealthStore};
 pub use manager::{DownloadManager, DownloadOptions};
+pub use metalink::MetalinkFile;
 pub use progress::DownloadProgress;
+pub use queue::{DownloadQueue, JobState, QueueJob};
+pub use zsync::ZsyncSeed;
 
+use crate::image::{ChecksumLayer, Compression};
 use std::path::PathBuf;
+use std::sync::OnceLock;
+use tokio_util::sync::CancellationToken;
+
+/// URL schemes `DownloadEngine` can fetch from
+pub const SOURCE_SCHEMES: &[&str] = &["http", "https", "ftp", "file", "rsync"];
+
+static SHUTDOWN: OnceLock<CancellationToken> = OnceLock::new();
+
+/// Process-wide token cancelled when the user interrupts isod (Ctrl-C)
+pub fn shutdown_token() -> &'static CancellationToken {
+    SHUTDOWN.get_or_init(CancellationToken::new)
+}
 
 #[derive(Debug, Clone)]
 pub struct DownloadRequest {
@@ -17,8 +48,24 @@ pub struct DownloadRequest {
     pub output_path: PathBuf,
     pub expected_checksum: Option<String>,
     pub checksum_type: Option<ChecksumType>,
+    /// Overrides the User-Agent of the shared HTTP client for this request
     pub user_agent: Option<String>,
     pub resume: bool,
+    pub zsync: Option<ZsyncSeed>,
+    /// Alternative URLs for the same file, tried in order when an attempt fails
+    pub mirrors: Vec<String>,
+    pub expected_size: Option<u64>,
+    /// Decompress the download into the image once it is complete
+    pub decompress: Option<Decompression>,
+}
+
+/// Where and how a compressed download is unpacked
+#[derive(Debug, Clone)]
+pub struct Decompression {
+    pub compression: Compression,
+    pub image_path: PathBuf,
+    /// Whether the expected checksum covers the download or the image
+    pub checksum_layer: ChecksumLayer,
 }
 
 impl DownloadRequest {
@@ -28,8 +75,12 @@ impl DownloadRequest {
             output_path,
             expected_checksum: None,
             checksum_type: None,
-            user_agent: Some("isod/0.1.0".to_string()),
+            user_agent: None,
             resume: true,
+            zsync: None,
+            mirrors: Vec::new(),
+            expected_size: None,
+            decompress: None,
         }
     }
 
@@ -44,6 +95,63 @@ impl DownloadRequest {
         self
     }
 
+    /// Build the file from an older local copy using a `.zsync` control file
+    pub fn with_zsync(mut self, control_url: String, seed_path: PathBuf) -> Self {
+        self.zsync = Some(ZsyncSeed {
+            control_url,
+            seed_path,
+        });
+        self
+    }
+
+    /// Add fallback URLs serving the same file
+    pub fn with_mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors.extend(mirrors);
+        self
+    }
+
+    pub fn with_expected_size(mut self, size: u64) -> Self {
+        self.expected_size = Some(size);
+        self
+    }
+
+    /// Unpack the compressed download into `image_path`, checking the checksum against `checksum_layer`
+    pub fn with_decompression(
+        mut self,
+        compression: Compression,
+        image_path: PathBuf,
+        checksum_layer: ChecksumLayer,
+    ) -> Self {
+        self.decompress = Some(Decompression {
+            compression,
+            image_path,
+            checksum_layer,
+        });
+        self
+    }
+
+    /// All URLs for this request, primary first
+    pub fn candidate_urls(&self) -> Vec<&str> {
+        std::iter::once(self.url.as_str())
+            .chain(self.mirrors.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Drop `rejected` URLs, always keeping at least one candidate
+    pub fn remove_urls(&mut self, rejected: &[String]) {
+        let mut urls: Vec<String> = self
+            .candidate_urls()
+            .into_iter()
+            .filter(|url| !rejected.iter().any(|r| r == url))
+            .map(String::from)
+            .collect();
+        if urls.is_empty() {
+            return;
+        }
+        self.url = urls.remove(0);
+        self.mirrors = urls;
+    }
+
     pub fn no_resume(mut self) -> Self {
         self.resume = false;
         self
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result, bail};
+use futures_util::StreamExt;
+use futures_util::future::join_all;
+use std::time::{Duration, Instant};
+
+use crate::http::HttpContext;
+use crate::registry::DownloadSource;
+
+/// Bytes fetched from each mirror to estimate throughput
+const PROBE_BYTES: u64 = 256 * 1024;
+
+/// Upper bound for a single probe so a dead mirror cannot stall the download
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Latency and throughput measured against one mirror
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub latency: Duration,
+    pub throughput_bps: u64,
+}
+
+impl ProbeResult {
+    pub fn speed_rating(&self) -> u8 {
+        speed_rating(self.throughput_bps)
+    }
+}
+
+/// Speed rating (1-10) on a log scale: 64 KiB/s rates 1, 32 MiB/s and above rate 10
+pub fn speed_rating(throughput_bps: u64) -> u8 {
+    let ratio = throughput_bps / (64 * 1024);
+    let rating = if ratio == 0 { 1 } else { ratio.ilog2() + 1 };
+    rating.clamp(1, 10) as u8
+}
+
+fn is_http(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Fetch the first bytes of `url` with a ranged GET and time it
+pub async fn probe(http: &HttpContext, url: &str) -> Result<ProbeResult> {
+    let started = Instant::now();
+    let response = http
+        .transfer(url)
+        .timeout(PROBE_TIMEOUT)
+        .header("Range", format!("bytes=0-{}", PROBE_BYTES - 1))
+        .send()
+        .await
+        .with_context(|| format!("Failed to probe mirror: {}", url))?;
+    let latency = started.elapsed();
+
+    if !response.status().is_success() {
+        bail!("Mirror probe failed with status: {}", response.status());
+    }
+
+    // Servers ignoring the range send the whole ISO, so stop after the sample
+    let body_started = Instant::now();
+    let mut received = 0u64;
+    let mut stream = response.bytes_stream();
+    while received < PROBE_BYTES
+        && let Some(chunk) = stream.next().await
+    {
+        received += chunk.context("Failed to read probe response")?.len() as u64;
+    }
+
+    let elapsed = body_started.elapsed().as_secs_f64().max(0.001);
+    Ok(ProbeResult {
+        latency,
+        throughput_bps: (received as f64 / elapsed) as u64,
+    })
+}
+
+/// Probe the first `count` sources concurrently and re-rank them.
+///
+/// Measured speed replaces the hardcoded `speed_rating` and the latency is
+/// recorded, so both feed `get_selection_score`. Mirrors that fail the probe
+/// are moved to the end but kept as a last resort.
+pub async fn rank(http: &HttpContext, sources: &mut Vec<DownloadSource>, count: usize) {
+    let count = count.min(sources.len());
+    if count < 2 {
+        return;
+    }
+
+    // FTP, rsync and local sources cannot be probed with a ranged GET and keep their place
+    let results = join_all(sources[..count].iter().map(|source| async move {
+        match source.get_url() {
+            Some(url) if !is_http(url) => None,
+            Some(url) => Some(probe(http, url).await.ok()),
+            None => Some(None),
+        }
+    }))
+    .await;
+
+    let mut unreachable = Vec::new();
+    let mut ranked = Vec::with_capacity(sources.len());
+    for (index, source) in sources.drain(..).enumerate() {
+        match results.get(index) {
+            Some(Some(Some(result))) => {
+                let mut source = source;
+                source.speed_rating = Some(result.speed_rating());
+                source.latency_ms = Some(result.latency.as_millis().min(u32::MAX as u128) as u32);
+                ranked.push(source);
+            }
+            Some(Some(None)) => unreachable.push(source),
+            Some(None) | None => ranked.push(source),
+        }
+    }
+
+    ranked.sort();
+    ranked.extend(unreachable);
+    *sources = ranked;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::sources::SourcePriority;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve a fixed response to every request
+    async fn server(response: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket.write_all(response).await;
+            }
+        });
+        format!("http://{}/test.iso", addr)
+    }
+
+    #[tokio::test]
+    async fn test_rank_moves_unreachable_mirrors_last() {
+        let broken = server(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+        let working =
+            server(b"HTTP/1.1 206 Partial Content\r\nContent-Length: 4\r\n\r\nabcd").await;
+
+        let mut sources = vec![
+            DownloadSource::mirror(&broken, SourcePriority::Preferred, None),
+            DownloadSource::mirror(&working, SourcePriority::Low, None),
+        ];
+        rank(crate::http::context(), &mut sources, 2).await;
+
+        assert_eq!(sources[0].get_url(), Some(working.as_str()));
+        assert!(sources[0].latency_ms.is_some());
+        assert!(sources[0].speed_rating.is_some());
+        assert_eq!(sources[1].get_url(), Some(broken.as_str()));
+    }
+
+    #[test]
+    fn test_speed_rating_scale() {
+        let rating = |throughput_bps| {
+            ProbeResult {
+                latency: Duration::ZERO,
+                throughput_bps,
+            }
+            .speed_rating()
+        };
+
+        assert_eq!(rating(0), 1);
+        assert_eq!(rating(64 * 1024), 1);
+        assert_eq!(rating(1024 * 1024), 5);
+        assert_eq!(rating(32 * 1024 * 1024), 10);
+        assert_eq!(rating(u64::MAX), 10);
+    }
+}
@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 use std::time::Duration;
 
+use super::ErrorClass;
+
 #[derive(Debug, Clone)]
 pub enum DownloadProgress {
     Started {
@@ -15,6 +17,20 @@ pub enum DownloadProgress {
         progress_percent: u8,
         speed_bps: u64,
     },
+    DeltaSeeded {
+        id: String,
+        seed_path: PathBuf,
+        reused_bytes: u64,
+        total_bytes: u64,
+    },
+    /// The zsync delta transfer failed and the file is fetched in full instead
+    DeltaFailed {
+        id: String,
+        error: String,
+    },
+    Decompressing {
+        id: String,
+    },
     VerifyingChecksum {
         id: String,
     },
@@ -29,10 +45,17 @@ pub enum DownloadProgress {
         id: String,
         bytes_downloaded: u64,
         checksum_verified: bool,
+        /// URL the file was finally fetched from
+        url: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        /// Checksum of the decompressed image, for compressed downloads
+        image_checksum: Option<String>,
     },
     Failed {
         id: String,
         error: String,
+        class: ErrorClass,
         attempts: u32,
     },
     Retry {
@@ -50,6 +73,27 @@ pub enum DownloadProgress {
     },
 }
 
+impl DownloadProgress {
+    /// ID of the download this event belongs to
+    pub fn id(&self) -> &str {
+        match self {
+            DownloadProgress::Started { id, .. }
+            | DownloadProgress::Progress { id, .. }
+            | DownloadProgress::DeltaSeeded { id, .. }
+            | DownloadProgress::DeltaFailed { id, .. }
+            | DownloadProgress::Decompressing { id }
+            | DownloadProgress::VerifyingChecksum { id }
+            | DownloadProgress::ChecksumVerified { id }
+            | DownloadProgress::ChecksumFailed { id, .. }
+            | DownloadProgress::Completed { id, .. }
+            | DownloadProgress::Failed { id, .. }
+            | DownloadProgress::Retry { id, .. }
+            | DownloadProgress::Cancelled { id }
+            | DownloadProgress::Error { id, .. } => id,
+        }
+    }
+}
+
 pub struct ProgressTracker {
     // Could add methods to track multiple downloads, calculate ETA, etc.
 }
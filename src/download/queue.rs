@@ -0,0 +1,490 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::DownloadOptions;
+use crate::registry::IsoInfo;
+
+const QUEUE_FILE: &str = "queue.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Paused,
+    Failed,
+    Done,
+}
+
+/// Where a partially downloaded job left off
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResumeData {
+    pub bytes_downloaded: u64,
+    pub total_bytes: Option<u64>,
+    /// URL the partial data was fetched from
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueJob {
+    pub id: String,
+    /// Higher runs first
+    pub priority: i32,
+    pub state: JobState,
+    pub iso_info: IsoInfo,
+    pub output_path: PathBuf,
+    pub output_directory: PathBuf,
+    pub prefer_torrents: bool,
+    pub verify_checksums: bool,
+    #[serde(default)]
+    pub resume: ResumeData,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Process currently running this job
+    #[serde(default)]
+    pub pid: Option<u32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl QueueJob {
+    /// Rebuild download options for this job
+    pub fn options(&self, max_concurrent: usize) -> DownloadOptions {
+        DownloadOptions {
+            max_concurrent,
+            prefer_torrents: self.prefer_torrents,
+            output_directory: self.output_directory.clone(),
+            verify_checksums: self.verify_checksums,
+            resume_downloads: true,
+        }
+    }
+
+    /// Whether the job still has work left
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.state, JobState::Done)
+    }
+
+    fn touch(&mut self) {
+        self.updated_at = Utc::now();
+    }
+
+    /// Record how much of the file is already on disk
+    fn refresh_resume_data(&mut self) {
+        self.resume.bytes_downloaded = fs::metadata(&self.output_path)
+            .map(|m| m.len())
+            .unwrap_or(0);
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueueFile {
+    #[serde(default)]
+    jobs: Vec<QueueJob>,
+}
+
+/// Download jobs persisted in the state directory
+#[derive(Debug)]
+pub struct DownloadQueue {
+    path: PathBuf,
+    jobs: Vec<QueueJob>,
+}
+
+impl DownloadQueue {
+    /// Location of the queue file inside a state directory
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir.join(QUEUE_FILE)
+    }
+
+    /// Load the queue, recovering jobs left running by a process that has exited
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let mut file: QueueFile = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read download queue: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse download queue: {:?}", path))?
+        } else {
+            QueueFile::default()
+        };
+
+        for job in &mut file.jobs {
+            if job.state == JobState::Running && !job.pid.is_some_and(process_alive) {
+                job.state = JobState::Queued;
+                job.pid = None;
+                job.refresh_resume_data();
+                job.touch();
+            }
+        }
+
+        Ok(Self {
+            path,
+            jobs: file.jobs,
+        })
+    }
+
+    /// Write the queue to disk atomically
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {:?}", parent))?;
+        }
+
+        let file = QueueFile {
+            jobs: self.jobs.clone(),
+        };
+        let content =
+            serde_json::to_string_pretty(&file).context("Failed to serialize download queue")?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write download queue: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write download queue: {:?}", self.path))?;
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// All jobs, highest priority first, then oldest first
+    pub fn jobs(&self) -> Vec<&QueueJob> {
+        let mut jobs: Vec<&QueueJob> = self.jobs.iter().collect();
+        jobs.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then(a.created_at.cmp(&b.created_at))
+        });
+        jobs
+    }
+
+    /// Jobs waiting to be started, in the order they should run
+    pub fn runnable(&self) -> Vec<&QueueJob> {
+        self.jobs()
+            .into_iter()
+            .filter(|j| j.state == JobState::Queued)
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&QueueJob> {
+        self.jobs.iter().find(|j| j.id == id)
+    }
+
+    /// Resolve a full job ID from an ID or unique prefix
+    pub fn resolve_id(&self, id: &str) -> Result<String> {
+        if self.get(id).is_some() {
+            return Ok(id.to_string());
+        }
+
+        let matches: Vec<&QueueJob> = self.jobs.iter().filter(|j| j.id.starts_with(id)).collect();
+        match matches.as_slice() {
+            [job] => Ok(job.id.clone()),
+            [] => bail!("No queued download matches '{}'", id),
+            _ => bail!(
+                "'{}' matches {} downloads, be more specific",
+                id,
+                matches.len()
+            ),
+        }
+    }
+
+    fn job_mut(&mut self, id: &str) -> Result<&mut QueueJob> {
+        let id = self.resolve_id(id)?;
+        Ok(self
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == id)
+            .expect("resolved job exists"))
+    }
+
+    /// Add a job, replacing any unfinished job writing to the same file
+    pub fn enqueue(
+        &mut self,
+        id: String,
+        iso_info: &IsoInfo,
+        options: &DownloadOptions,
+        priority: i32,
+    ) -> &QueueJob {
+        let output_path = options.output_directory.join(iso_info.download_filename());
+        let now = Utc::now();
+
+        self.jobs
+            .retain(|j| j.output_path != output_path || j.state == JobState::Done);
+
+        let mut job = QueueJob {
+            id,
+            priority,
+            state: JobState::Queued,
+            iso_info: iso_info.clone(),
+            output_path,
+            output_directory: options.output_directory.clone(),
+            prefer_torrents: options.prefer_torrents,
+            verify_checksums: options.verify_checksums,
+            resume: ResumeData {
+                total_bytes: iso_info.size_bytes,
+                ..Default::default()
+            },
+            error: None,
+            pid: None,
+            created_at: now,
+            updated_at: now,
+        };
+        job.refresh_resume_data();
+
+        self.jobs.push(job);
+        self.jobs.last().expect("job was just pushed")
+    }
+
+    pub fn pause(&mut self, id: &str) -> Result<()> {
+        let job = self.job_mut(id)?;
+        match job.state {
+            JobState::Queued | JobState::Running => {
+                job.state = JobState::Paused;
+                job.pid = None;
+                job.refresh_resume_data();
+                job.touch();
+                Ok(())
+            }
+            state => bail!("Cannot pause a {} download", state),
+        }
+    }
+
+    pub fn resume(&mut self, id: &str) -> Result<()> {
+        let job = self.job_mut(id)?;
+        match job.state {
+            JobState::Paused | JobState::Failed => {
+                job.state = JobState::Queued;
+                job.error = None;
+                job.touch();
+                Ok(())
+            }
+            state => bail!("Cannot resume a {} download", state),
+        }
+    }
+
+    pub fn set_priority(&mut self, id: &str, priority: i32) -> Result<()> {
+        let job = self.job_mut(id)?;
+        job.priority = priority;
+        job.touch();
+        Ok(())
+    }
+
+    /// Remove a job from the queue, returning it
+    pub fn cancel(&mut self, id: &str) -> Result<QueueJob> {
+        let id = self.resolve_id(id)?;
+        let index = self
+            .jobs
+            .iter()
+            .position(|j| j.id == id)
+            .expect("resolved job exists");
+        Ok(self.jobs.remove(index))
+    }
+
+    /// Remove completed jobs, returning how many were removed
+    pub fn clear_finished(&mut self) -> usize {
+        let before = self.jobs.len();
+        self.jobs.retain(|j| j.is_pending());
+        before - self.jobs.len()
+    }
+
+    pub fn mark_running(&mut self, id: &str, url: &str) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            job.state = JobState::Running;
+            job.pid = Some(std::process::id());
+            job.error = None;
+            job.resume.url = Some(url.to_string());
+            job.touch();
+        }
+    }
+
+    /// Put a job that was stopped mid-download back in line
+    pub fn mark_interrupted(&mut self, id: &str) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id)
+            && job.state == JobState::Running
+        {
+            job.state = JobState::Queued;
+            job.pid = None;
+            job.refresh_resume_data();
+            job.touch();
+        }
+    }
+
+    pub fn mark_done(&mut self, id: &str, bytes_downloaded: u64) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            job.state = JobState::Done;
+            job.pid = None;
+            job.resume.bytes_downloaded = bytes_downloaded;
+            job.resume.total_bytes = Some(bytes_downloaded);
+            job.touch();
+        }
+    }
+
+    pub fn mark_failed(&mut self, id: &str, error: &str) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+            job.state = JobState::Failed;
+            job.pid = None;
+            job.error = Some(error.to_string());
+            job.refresh_resume_data();
+            job.touch();
+        }
+    }
+}
+
+/// Queue file shared between the download manager and its tasks.
+///
+/// Every update re-reads the file so that changes made by other `isod`
+/// processes (e.g. `isod queue pause`) are not overwritten.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedQueue {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl SharedQueue {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub(crate) async fn load(&self) -> Result<DownloadQueue> {
+        let _guard = self.lock.lock().await;
+        DownloadQueue::load(&self.path)
+    }
+
+    pub(crate) async fn update<T>(&self, f: impl FnOnce(&mut DownloadQueue) -> T) -> Result<T> {
+        let _guard = self.lock.lock().await;
+        let mut queue = DownloadQueue::load(&self.path)?;
+        let result = f(&mut queue);
+        queue.save()?;
+        Ok(result)
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobState::Queued => write!(f, "queued"),
+            JobState::Running => write!(f, "running"),
+            JobState::Paused => write!(f, "paused"),
+            JobState::Failed => write!(f, "failed"),
+            JobState::Done => write!(f, "done"),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    // Signal 0 only checks that the process exists
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(pid: u32) -> bool {
+    pid == std::process::id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ChecksumLayer, ImageFormat};
+    use crate::registry::ReleaseType;
+
+    fn iso(filename: &str) -> IsoInfo {
+        IsoInfo {
+            distro: "ubuntu".to_string(),
+            version: "24.04".to_string(),
+            architecture: "amd64".to_string(),
+            variant: Some("desktop".to_string()),
+            filename: filename.to_string(),
+            local_filename: None,
+            format: ImageFormat::default(),
+            download_sources: Vec::new(),
+            checksum: None,
+            checksum_type: None,
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::LTS,
+        }
+    }
+
+    fn options(dir: &Path) -> DownloadOptions {
+        DownloadOptions {
+            output_directory: dir.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_queue_roundtrip_and_ordering() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = DownloadQueue::path_in(dir.path());
+
+        let mut queue = DownloadQueue::load(&path).unwrap();
+        queue.enqueue("a_1".into(), &iso("a.iso"), &options(dir.path()), 0);
+        queue.enqueue("b_2".into(), &iso("b.iso"), &options(dir.path()), 5);
+        queue.save().unwrap();
+
+        let mut queue = DownloadQueue::load(&path).unwrap();
+        let order: Vec<&str> = queue.runnable().iter().map(|j| j.id.as_str()).collect();
+        assert_eq!(order, ["b_2", "a_1"]);
+
+        queue.set_priority("a", 10).unwrap();
+        queue.pause("b_2").unwrap();
+        let order: Vec<&str> = queue.runnable().iter().map(|j| j.id.as_str()).collect();
+        assert_eq!(order, ["a_1"]);
+
+        assert!(queue.pause("b_2").is_err());
+        queue.resume("b_2").unwrap();
+        assert_eq!(queue.cancel("a_1").unwrap().id, "a_1");
+        assert!(queue.get("a_1").is_none());
+    }
+
+    #[test]
+    fn test_running_job_from_dead_process_is_requeued() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = DownloadQueue::path_in(dir.path());
+        std::fs::write(dir.path().join("a.iso"), vec![0u8; 1234]).unwrap();
+
+        let mut queue = DownloadQueue::load(&path).unwrap();
+        queue.enqueue("a_1".into(), &iso("a.iso"), &options(dir.path()), 0);
+        queue.mark_running("a_1", "https://example.org/a.iso");
+        // A PID that cannot belong to a live process
+        queue.jobs[0].pid = Some(i32::MAX as u32);
+        queue.save().unwrap();
+
+        let queue = DownloadQueue::load(&path).unwrap();
+        let job = queue.get("a_1").unwrap();
+        assert_eq!(job.state, JobState::Queued);
+        assert_eq!(job.resume.bytes_downloaded, 1234);
+        assert_eq!(job.resume.url.as_deref(), Some("https://example.org/a.iso"));
+    }
+
+    #[test]
+    fn test_enqueue_replaces_unfinished_job_for_same_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = DownloadQueue::load(DownloadQueue::path_in(dir.path())).unwrap();
+
+        queue.enqueue("a_1".into(), &iso("a.iso"), &options(dir.path()), 0);
+        queue.mark_failed("a_1", "connection reset");
+        queue.enqueue("a_2".into(), &iso("a.iso"), &options(dir.path()), 0);
+
+        assert_eq!(queue.jobs().len(), 1);
+        assert_eq!(queue.jobs()[0].id, "a_2");
+        assert!(queue.resolve_id("missing").is_err());
+    }
+}
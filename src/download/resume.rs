@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+use reqwest::header::{ETAG, HeaderMap, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sidecar stored next to a partial download so it can be resumed safely
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeState {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub bytes_downloaded: u64,
+    pub total_bytes: u64,
+}
+
+impl ResumeState {
+    /// Capture the validators of a response so a later `If-Range` can detect changes
+    pub fn from_headers(url: &str, headers: &HeaderMap, total_bytes: u64) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+
+        Self {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            bytes_downloaded: 0,
+            total_bytes,
+        }
+    }
+
+    /// Value for an `If-Range` header, preferring a strong ETag
+    pub fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+
+    pub fn path_for(output_path: &Path) -> PathBuf {
+        let mut name = output_path.as_os_str().to_os_string();
+        name.push(".isod-resume");
+        PathBuf::from(name)
+    }
+
+    pub fn load(output_path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(Self::path_for(output_path)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, output_path: &Path) -> Result<()> {
+        let path = Self::path_for(output_path);
+        let content = serde_json::to_string(self).context("Failed to serialize resume state")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write resume state: {:?}", path))
+    }
+
+    pub fn remove(output_path: &Path) {
+        let _ = fs::remove_file(Self::path_for(output_path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_resume_state_roundtrip_and_validator() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("test.iso");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("W/\"weak\""));
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+
+        let mut state = ResumeState::from_headers("https://example.org/test.iso", &headers, 100);
+        // Weak ETags cannot be used with If-Range
+        assert_eq!(state.validator(), Some("Wed, 21 Oct 2015 07:28:00 GMT"));
+
+        state.etag = Some("\"strong\"".to_string());
+        state.bytes_downloaded = 42;
+        state.save(&output).unwrap();
+        assert!(dir.path().join("test.iso.isod-resume").exists());
+
+        let loaded = ResumeState::load(&output).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.validator(), Some("\"strong\""));
+
+        ResumeState::remove(&output);
+        assert!(ResumeState::load(&output).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::ErrorKind;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+use super::engine::{DownloadTask, ProgressTracker, Transfer, existing_size};
+use super::{DownloadError, RateLimiter};
+use crate::http::HttpContext;
+
+/// How often the growing output file is checked for progress
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+fn error(message: impl Into<String>, transient: bool) -> DownloadError {
+    DownloadError::Source {
+        scheme: "rsync",
+        message: message.into(),
+        transient,
+    }
+}
+
+fn spawn_error(e: std::io::Error) -> DownloadError {
+    if e.kind() == ErrorKind::NotFound {
+        error("the rsync binary is not installed", false)
+    } else {
+        DownloadError::io("Failed to run rsync")(e)
+    }
+}
+
+/// Map a failed rsync exit status to an error.
+///
+/// Protocol, socket, I/O and timeout failures (5, 10, 12, 30, 35) are worth
+/// retrying; anything else, like a missing file (23), is not.
+fn exit_error(status: ExitStatus, stderr: &str) -> DownloadError {
+    let code = status.code();
+    let message = stderr
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .unwrap_or("no error output");
+
+    error(
+        match code {
+            Some(code) => format!("exit code {}: {}", code, message),
+            None => format!("terminated by signal: {}", message),
+        },
+        matches!(code, Some(5 | 10 | 12 | 30 | 35)),
+    )
+}
+
+/// Size column of `rsync --list-only` output for a single file
+fn parse_list_only(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .find(|line| line.starts_with('-'))?
+        .split_whitespace()
+        .nth(1)?
+        .replace([',', '.'], "")
+        .parse()
+        .ok()
+}
+
+async fn remote_size(http: &HttpContext, url: &str) -> Result<Option<u64>, DownloadError> {
+    let output = timeout(
+        http.mirror_timeout(),
+        Command::new("rsync")
+            .arg("--list-only")
+            .arg(url)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| error("listing timed out", true))?
+    .map_err(spawn_error)?;
+
+    if !output.status.success() {
+        return Err(exit_error(
+            output.status,
+            &String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+    Ok(parse_list_only(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Download an `rsync://` source with the system rsync binary.
+///
+/// A partial file is continued with `--append-verify`; the current bandwidth
+/// limit is passed on as `--bwlimit` since the transfer bypasses the limiter.
+pub(crate) async fn fetch(
+    http: &HttpContext,
+    rate_limiter: &RateLimiter,
+    task: &DownloadTask,
+    url: &str,
+    cancel: &CancellationToken,
+) -> Result<Transfer, DownloadError> {
+    let request = &task.request;
+    let total_size = match request.expected_size {
+        Some(size) => Some(size),
+        None => remote_size(http, url).await?,
+    };
+
+    let existing = existing_size(request)?;
+    let resume_from = match total_size {
+        Some(total) if existing > total => 0,
+        _ => existing,
+    };
+    if resume_from == 0 {
+        match std::fs::remove_file(&request.output_path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(DownloadError::io("Failed to remove stale file")(e));
+            }
+            _ => {}
+        }
+        if let Some(parent) = request.output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(DownloadError::io("Failed to create parent directories"))?;
+        }
+    }
+
+    let mut command = Command::new("rsync");
+    command
+        .args(["--partial", "--inplace", "--times"])
+        .arg(format!(
+            "--timeout={}",
+            http.read_timeout().as_secs().max(1)
+        ));
+    if resume_from > 0 {
+        command.arg("--append-verify");
+    }
+    if let Some(limit) = rate_limiter.current_limit() {
+        command.arg(format!("--bwlimit={}", (limit / 1024).max(1)));
+    }
+    let mut child = command
+        .arg(url)
+        .arg(&request.output_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(spawn_error)?;
+
+    let mut stderr = child.stderr.take();
+    let stderr = tokio::spawn(async move {
+        let mut output = String::new();
+        if let Some(stderr) = stderr.as_mut() {
+            let _ = stderr.read_to_string(&mut output).await;
+        }
+        output
+    });
+
+    let mut progress = ProgressTracker::new(task, total_size.unwrap_or(0), resume_from);
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    let status = loop {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                // --partial --inplace keeps what arrived for the next resume
+                let _ = child.kill().await;
+                return Err(DownloadError::Cancelled);
+            }
+            status = child.wait() => {
+                break status.map_err(DownloadError::io("Failed to wait for rsync"))?;
+            }
+            _ = poll.tick() => {
+                if let Ok(metadata) = std::fs::metadata(&request.output_path) {
+                    progress.update(metadata.len());
+                }
+            }
+        }
+    };
+
+    if !status.success() {
+        return Err(exit_error(status, &stderr.await.unwrap_or_default()));
+    }
+
+    let total = std::fs::metadata(&request.output_path)
+        .map_err(DownloadError::io("Failed to get downloaded file metadata"))?
+        .len();
+    Ok(Transfer {
+        total,
+        received: total.saturating_sub(resume_from),
+        ..Default::default()
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn test_list_only_and_exit_codes() {
+        let listing = "-rw-r--r--  1,234,567,890 2025/01/01 12:00:00 archlinux.iso\n";
+        assert_eq!(parse_list_only(listing), Some(1_234_567_890));
+        assert_eq!(
+            parse_list_only("drwxr-xr-x 4,096 2025/01/01 12:00:00 iso\n"),
+            None
+        );
+
+        let transient = exit_error(ExitStatus::from_raw(30 << 8), "timeout in data send\n");
+        assert!(matches!(
+            transient,
+            DownloadError::Source {
+                transient: true,
+                ..
+            }
+        ));
+        assert!(transient.to_string().contains("timeout in data send"));
+
+        let missing = exit_error(
+            ExitStatus::from_raw(23 << 8),
+            "rsync: link_stat \"/iso.iso\" failed: No such file\n\n",
+        );
+        assert!(matches!(
+            missing,
+            DownloadError::Source {
+                transient: false,
+                ..
+            }
+        ));
+    }
+}
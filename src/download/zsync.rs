@@ -0,0 +1,796 @@
+use anyhow::{Context, Result, bail};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{DownloadProgress, DownloadTask, RateLimiter};
+use crate::http::HttpContext;
+use crate::registry::{ReleaseType, VersionInfo};
+
+/// Gaps of known blocks up to this size are re-fetched to save round trips
+const MERGE_GAP_BLOCKS: u64 = 8;
+/// Upper bound for a single ranged request
+const MAX_RANGE_BYTES: u64 = 32 * 1024 * 1024;
+/// Amount of seed data kept in memory while scanning
+const SCAN_CHUNK_BYTES: usize = 16 * 1024 * 1024;
+
+/// Local file used as the block source for a zsync delta download
+#[derive(Debug, Clone)]
+pub struct ZsyncSeed {
+    pub control_url: String,
+    pub seed_path: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+struct BlockSum {
+    rsum: u32,
+    checksum: Vec<u8>,
+}
+
+/// Parsed `.zsync` control file
+#[derive(Debug, Clone)]
+pub struct ZsyncControl {
+    pub filename: Option<String>,
+    pub length: u64,
+    pub blocksize: usize,
+    pub seq_matches: usize,
+    pub rsum_bytes: usize,
+    pub checksum_bytes: usize,
+    pub urls: Vec<String>,
+    pub sha1: Option<String>,
+    blocks: Vec<BlockSum>,
+}
+
+impl ZsyncControl {
+    /// Parse a control file (text header, blank line, binary block sums)
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let header_end = data
+            .windows(2)
+            .position(|w| w == b"\n\n")
+            .context("Malformed zsync file: missing header terminator")?;
+        let header =
+            std::str::from_utf8(&data[..header_end]).context("zsync header is not valid UTF-8")?;
+
+        let mut filename = None;
+        let mut length = None;
+        let mut blocksize = None;
+        let mut hash_lengths = (1, 4, 16);
+        let mut urls = Vec::new();
+        let mut sha1 = None;
+
+        for line in header.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim() {
+                "Filename" => filename = Some(value.to_string()),
+                "Length" => length = Some(value.parse::<u64>().context("Invalid zsync Length")?),
+                "Blocksize" => {
+                    blocksize = Some(value.parse::<usize>().context("Invalid zsync Blocksize")?)
+                }
+                "Hash-Lengths" => {
+                    let parts: Vec<usize> = value
+                        .split(',')
+                        .map(|p| p.trim().parse())
+                        .collect::<std::result::Result<_, _>>()
+                        .context("Invalid zsync Hash-Lengths")?;
+                    if parts.len() != 3 {
+                        bail!("Invalid zsync Hash-Lengths: {}", value);
+                    }
+                    hash_lengths = (parts[0], parts[1], parts[2]);
+                }
+                "URL" => urls.push(value.to_string()),
+                "SHA-1" => sha1 = Some(value.to_lowercase()),
+                _ => {}
+            }
+        }
+
+        let length = length.context("zsync file has no Length header")?;
+        let blocksize = blocksize.context("zsync file has no Blocksize header")?;
+        let (seq_matches, rsum_bytes, checksum_bytes) = hash_lengths;
+
+        if blocksize == 0 || !blocksize.is_power_of_two() {
+            bail!("Unsupported zsync block size: {}", blocksize);
+        }
+        if !(1..=2).contains(&seq_matches)
+            || !(1..=4).contains(&rsum_bytes)
+            || !(3..=16).contains(&checksum_bytes)
+        {
+            bail!("Unsupported zsync Hash-Lengths: {:?}", hash_lengths);
+        }
+
+        let block_count = length.div_ceil(blocksize as u64) as usize;
+        let entry_len = rsum_bytes + checksum_bytes;
+        let body = &data[header_end + 2..];
+
+        if body.len() < block_count * entry_len {
+            bail!(
+                "Truncated zsync file: expected {} bytes of block sums, found {}",
+                block_count * entry_len,
+                body.len()
+            );
+        }
+
+        let blocks = body
+            .chunks_exact(entry_len)
+            .take(block_count)
+            .map(|entry| {
+                let rsum = entry[..rsum_bytes]
+                    .iter()
+                    .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+                BlockSum {
+                    rsum,
+                    checksum: entry[rsum_bytes..].to_vec(),
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            filename,
+            length,
+            blocksize,
+            seq_matches,
+            rsum_bytes,
+            checksum_bytes,
+            urls,
+            sha1,
+            blocks,
+        })
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Mask applied to a full 32-bit rolling checksum to compare with stored sums
+    fn rsum_mask(&self) -> u32 {
+        if self.rsum_bytes >= 4 {
+            u32::MAX
+        } else {
+            (1u32 << (8 * self.rsum_bytes)) - 1
+        }
+    }
+
+    fn strong_checksum(&self, block: &[u8]) -> Vec<u8> {
+        use md4::{Digest, Md4};
+        let mut hasher = Md4::new();
+        hasher.update(block);
+        hasher.finalize()[..self.checksum_bytes].to_vec()
+    }
+
+    /// Length of the given block inside the target file
+    fn block_len(&self, index: usize) -> usize {
+        let start = index as u64 * self.blocksize as u64;
+        (self.length - start).min(self.blocksize as u64) as usize
+    }
+
+    /// Scan a local file for blocks of the target, returning the seed offset of every known block
+    pub fn scan_seed(&self, seed_path: &Path) -> Result<Vec<Option<u64>>> {
+        let mut seed = File::open(seed_path)
+            .with_context(|| format!("Failed to open zsync seed: {:?}", seed_path))?;
+
+        let bs = self.blocksize;
+        let window = bs * self.seq_matches;
+        let mask = self.rsum_mask();
+        let block_count = self.blocks.len();
+        let mut known: Vec<Option<u64>> = vec![None; block_count];
+
+        if block_count == 0 {
+            return Ok(known);
+        }
+
+        // Index blocks by their weak checksum (or pair of checksums for seq_matches = 2)
+        let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+        let chained = self.seq_matches > 1 && block_count > 1;
+        let lookup_blocks = if chained {
+            block_count - 1
+        } else {
+            block_count
+        };
+        for i in 0..lookup_blocks {
+            index.entry(self.lookup_key(i)).or_default().push(i);
+        }
+
+        // With chained matching the final block has no successor and is checked on its own
+        let last = block_count - 1;
+        let last_rsum = self.blocks[last].rsum;
+
+        let mut buf: Vec<u8> = Vec::with_capacity(SCAN_CHUNK_BYTES + window);
+        let mut buf_start: u64 = 0;
+        let mut eof = false;
+        let mut padding_left = window;
+
+        // Make sure `buf` holds at least `needed` bytes past `pos`, padding with zeros after EOF
+        let mut fill =
+            |buf: &mut Vec<u8>, buf_start: u64, pos: u64, needed: usize| -> Result<bool> {
+                let offset = (pos - buf_start) as usize;
+                while buf.len() < offset + needed {
+                    if eof {
+                        if padding_left == 0 {
+                            return Ok(false);
+                        }
+                        let pad = padding_left.min(offset + needed - buf.len());
+                        buf.resize(buf.len() + pad, 0);
+                        padding_left -= pad;
+                        continue;
+                    }
+                    let mut chunk = vec![0u8; SCAN_CHUNK_BYTES];
+                    let read = seed.read(&mut chunk).context("Failed to read zsync seed")?;
+                    if read == 0 {
+                        eof = true;
+                    } else {
+                        buf.extend_from_slice(&chunk[..read]);
+                    }
+                }
+                Ok(true)
+            };
+
+        let mut pos: u64 = 0;
+        if !fill(&mut buf, buf_start, pos, window)? {
+            return Ok(known);
+        }
+
+        let mut r0 = Rsum::of(&buf[0..bs]);
+        let mut r1 = if chained {
+            Rsum::of(&buf[bs..2 * bs])
+        } else {
+            Rsum::default()
+        };
+
+        loop {
+            let start = (pos - buf_start) as usize;
+            let key = if chained {
+                (((r0.value() & mask) as u64) << 32) | (r1.value() & mask) as u64
+            } else {
+                (r0.value() & mask) as u64
+            };
+
+            let mut matched = false;
+            let mut computed: Option<Vec<u8>> = None;
+
+            if let Some(candidates) = index.get(&key) {
+                let first = self.strong_checksum(&buf[start..start + bs]);
+                let mut second: Option<Vec<u8>> = None;
+                for &i in candidates {
+                    if known[i].is_some() || self.blocks[i].checksum != first {
+                        continue;
+                    }
+                    if chained {
+                        let next = second.get_or_insert_with(|| {
+                            self.strong_checksum(&buf[start + bs..start + 2 * bs])
+                        });
+                        if self.blocks[i + 1].checksum != *next {
+                            continue;
+                        }
+                        if known[i + 1].is_none() {
+                            known[i + 1] = Some(pos + bs as u64);
+                        }
+                    }
+                    known[i] = Some(pos);
+                    matched = true;
+                }
+                computed = Some(first);
+            }
+
+            if chained && known[last].is_none() && (r0.value() & mask) == last_rsum {
+                let checksum =
+                    computed.unwrap_or_else(|| self.strong_checksum(&buf[start..start + bs]));
+                if checksum == self.blocks[last].checksum {
+                    known[last] = Some(pos);
+                }
+            }
+
+            if known.iter().all(|k| k.is_some()) {
+                break;
+            }
+
+            // Drop consumed data once the buffer grows past the chunk size
+            if start > SCAN_CHUNK_BYTES {
+                buf.drain(..start);
+                buf_start = pos;
+            }
+
+            if matched {
+                // Jump past the matched block and recompute the window
+                pos += bs as u64;
+                if !fill(&mut buf, buf_start, pos, window)? {
+                    break;
+                }
+                let start = (pos - buf_start) as usize;
+                r0 = Rsum::of(&buf[start..start + bs]);
+                if chained {
+                    r1 = Rsum::of(&buf[start + bs..start + 2 * bs]);
+                }
+            } else {
+                if !fill(&mut buf, buf_start, pos + 1, window)? {
+                    break;
+                }
+                let start = (pos - buf_start) as usize;
+                r0.roll(buf[start], buf[start + bs], bs);
+                if chained {
+                    r1.roll(buf[start + bs], buf[start + 2 * bs], bs);
+                }
+                pos += 1;
+            }
+        }
+
+        Ok(known)
+    }
+
+    fn lookup_key(&self, index: usize) -> u64 {
+        if self.seq_matches > 1 && self.blocks.len() > 1 {
+            ((self.blocks[index].rsum as u64) << 32) | self.blocks[index + 1].rsum as u64
+        } else {
+            self.blocks[index].rsum as u64
+        }
+    }
+
+    /// Group unknown blocks into byte ranges to fetch, merging small gaps
+    pub fn missing_ranges(&self, known: &[Option<u64>]) -> Vec<(u64, u64)> {
+        let bs = self.blocksize as u64;
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+        for (i, block) in known.iter().enumerate() {
+            if block.is_some() {
+                continue;
+            }
+            let start = i as u64 * bs;
+            let end = start + self.block_len(i) as u64;
+
+            match ranges.last_mut() {
+                Some((range_start, range_end))
+                    if start <= *range_end + MERGE_GAP_BLOCKS * bs
+                        && end - *range_start <= MAX_RANGE_BYTES =>
+                {
+                    *range_end = end;
+                }
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        ranges
+    }
+}
+
+/// zsync's rolling checksum: `a` is the byte sum, `b` the position-weighted sum
+#[derive(Debug, Default, Clone, Copy)]
+struct Rsum {
+    a: u16,
+    b: u16,
+}
+
+impl Rsum {
+    fn of(block: &[u8]) -> Self {
+        let mut a: u16 = 0;
+        let mut b: u16 = 0;
+        let len = block.len();
+        for (i, &c) in block.iter().enumerate() {
+            a = a.wrapping_add(c as u16);
+            b = b.wrapping_add(((len - i) as u16).wrapping_mul(c as u16));
+        }
+        Self { a, b }
+    }
+
+    fn roll(&mut self, old: u8, new: u8, blocksize: usize) {
+        self.a = self.a.wrapping_sub(old as u16).wrapping_add(new as u16);
+        self.b = self
+            .b
+            .wrapping_sub((blocksize as u16).wrapping_mul(old as u16))
+            .wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        ((self.a as u32) << 16) | self.b as u32
+    }
+}
+
+/// Look for an older local copy of the same image to use as a zsync seed.
+///
+/// Candidates share the target filename with the version part swapped out
+/// (e.g. `ubuntu-24.04.1-desktop-amd64.iso` for `ubuntu-24.04.2-desktop-amd64.iso`).
+/// The highest version below `version` wins, as it is the closest to the target.
+pub fn find_seed(directory: &Path, filename: &str, version: &str) -> Option<PathBuf> {
+    let (prefix, suffix) = filename.split_once(version)?;
+    let target = VersionInfo::new(version, ReleaseType::Stable);
+    let entries = std::fs::read_dir(directory).ok()?;
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            if name.len() <= prefix.len() + suffix.len() {
+                return None;
+            }
+            let candidate = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            let candidate = VersionInfo::new(candidate, ReleaseType::Stable);
+            (candidate < target).then(|| (candidate, entry.path()))
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, path)| path)
+}
+
+/// Build the target file from seed blocks plus ranged HTTP fetches of `url`
+pub async fn download(
+    http: &HttpContext,
+    rate_limiter: &RateLimiter,
+    task: &DownloadTask,
+    seed: &ZsyncSeed,
+    url: &str,
+) -> Result<u64> {
+    let response = http
+        .get(&seed.control_url)
+        .send()
+        .await
+        .context("Failed to fetch zsync control file")?;
+
+    if !response.status().is_success() {
+        bail!(
+            "zsync control file request failed with status: {}",
+            response.status()
+        );
+    }
+
+    let data = response
+        .bytes()
+        .await
+        .context("Failed to read zsync control file")?;
+    let control = ZsyncControl::parse(&data)?;
+
+    // Scanning multi-gigabyte seeds is CPU bound
+    let known = {
+        let control = control.clone();
+        let seed_path = seed.seed_path.clone();
+        tokio::task::spawn_blocking(move || control.scan_seed(&seed_path))
+            .await
+            .context("zsync seed scan panicked")??
+    };
+
+    let reused_bytes: u64 = known
+        .iter()
+        .enumerate()
+        .filter(|(_, k)| k.is_some())
+        .map(|(i, _)| control.block_len(i) as u64)
+        .sum();
+
+    let _ = task.progress_sender.send(DownloadProgress::DeltaSeeded {
+        id: task.id.clone(),
+        seed_path: seed.seed_path.clone(),
+        reused_bytes,
+        total_bytes: control.length,
+    });
+
+    let output_path = task.request.output_path.clone();
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create parent directories")?;
+    }
+
+    // Copy the reusable blocks into place
+    {
+        let control = control.clone();
+        let known = known.clone();
+        let seed_path = seed.seed_path.clone();
+        let output_path = output_path.clone();
+        tokio::task::spawn_blocking(move || {
+            copy_known_blocks(&control, &known, &seed_path, &output_path)
+        })
+        .await
+        .context("zsync block copy panicked")??;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&output_path)
+        .context("Failed to open zsync output file")?;
+
+    let mut done = reused_bytes;
+    let mut last_progress_update = Instant::now();
+    let mut last_bytes = done;
+    const PROGRESS_UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+    for (start, end) in control.missing_ranges(&known) {
+        let response = http
+            .transfer(url)
+            .header("Range", format!("bytes={}-{}", start, end - 1))
+            .send()
+            .await
+            .context("Failed to send ranged HTTP request")?;
+
+        if response.status().as_u16() != 206 {
+            bail!(
+                "Server does not support range requests (status: {})",
+                response.status()
+            );
+        }
+
+        file.seek(SeekFrom::Start(start))
+            .context("Failed to seek in zsync output file")?;
+
+        let mut received: u64 = 0;
+        let mut stream = response.bytes_stream();
+        while let Some(chunk_result) = stream.next().await {
+            let chunk = chunk_result.context("Failed to read chunk from response")?;
+            rate_limiter.acquire(chunk.len()).await;
+            let remaining = (end - start - received) as usize;
+            let chunk = &chunk[..chunk.len().min(remaining)];
+
+            file.write_all(chunk)
+                .context("Failed to write chunk to file")?;
+            received += chunk.len() as u64;
+
+            let fresh = received.min(end - start);
+            if last_progress_update.elapsed() >= PROGRESS_UPDATE_INTERVAL {
+                let current = done + fresh;
+                let elapsed = last_progress_update.elapsed().as_secs_f64();
+                let _ = task.progress_sender.send(DownloadProgress::Progress {
+                    id: task.id.clone(),
+                    bytes_downloaded: current,
+                    total_bytes: control.length,
+                    progress_percent: (current as f64 / control.length as f64 * 100.0) as u8,
+                    speed_bps: ((current - last_bytes) as f64 / elapsed) as u64,
+                });
+                last_progress_update = Instant::now();
+                last_bytes = current;
+            }
+        }
+
+        if received < end - start {
+            bail!(
+                "Short ranged response: expected {} bytes, got {}",
+                end - start,
+                received
+            );
+        }
+
+        // Merged ranges may re-fetch blocks that were already copied from the seed
+        done += (start..end)
+            .step_by(control.blocksize)
+            .filter(|offset| known[(*offset / control.blocksize as u64) as usize].is_none())
+            .map(|offset| (end - offset).min(control.blocksize as u64))
+            .sum::<u64>();
+    }
+
+    file.flush().context("Failed to flush file")?;
+    drop(file);
+
+    if let Some(expected) = &control.sha1 {
+        let actual =
+            super::ChecksumVerifier::calculate_checksum(&output_path, super::ChecksumType::Sha1)
+                .await?;
+        if actual != *expected {
+            bail!("zsync result does not match the control file SHA-1");
+        }
+    }
+
+    Ok(control.length)
+}
+
+fn copy_known_blocks(
+    control: &ZsyncControl,
+    known: &[Option<u64>],
+    seed_path: &Path,
+    output_path: &Path,
+) -> Result<()> {
+    let mut seed = File::open(seed_path)
+        .with_context(|| format!("Failed to open zsync seed: {:?}", seed_path))?;
+    let mut output = File::create(output_path).context("Failed to create output file")?;
+    output
+        .set_len(control.length)
+        .context("Failed to allocate output file")?;
+
+    let mut block = vec![0u8; control.blocksize];
+    for (i, offset) in known.iter().enumerate() {
+        let Some(offset) = offset else {
+            continue;
+        };
+        let len = control.block_len(i);
+
+        // Blocks matched against the zero padding past the end of the seed read short
+        block.fill(0);
+        seed.seek(SeekFrom::Start(*offset))
+            .context("Failed to seek in zsync seed")?;
+        let mut filled = 0;
+        while filled < len {
+            let read = seed
+                .read(&mut block[filled..len])
+                .context("Failed to read zsync seed")?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+
+        output
+            .seek(SeekFrom::Start(i as u64 * control.blocksize as u64))
+            .context("Failed to seek in zsync output file")?;
+        output
+            .write_all(&block[..len])
+            .context("Failed to write zsync block")?;
+    }
+
+    output.flush().context("Failed to flush file")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Build a control file for `data` the way zsyncmake does
+    fn make_control(data: &[u8], blocksize: usize, hash_lengths: (usize, usize, usize)) -> Vec<u8> {
+        use md4::{Digest, Md4};
+        use sha1::Sha1;
+
+        let (seq, rsum_bytes, checksum_bytes) = hash_lengths;
+        let mut out = format!(
+            "zsync: 0.6.2\nFilename: test.iso\nBlocksize: {}\nLength: {}\nHash-Lengths: {},{},{}\nURL: test.iso\nSHA-1: {:x}\n\n",
+            blocksize,
+            data.len(),
+            seq,
+            rsum_bytes,
+            checksum_bytes,
+            Sha1::digest(data)
+        )
+        .into_bytes();
+
+        for chunk in data.chunks(blocksize) {
+            let mut block = chunk.to_vec();
+            block.resize(blocksize, 0);
+            let rsum = Rsum::of(&block).value().to_be_bytes();
+            out.extend_from_slice(&rsum[4 - rsum_bytes..]);
+            out.extend_from_slice(&Md4::digest(&block)[..checksum_bytes]);
+        }
+        out
+    }
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_control_file() {
+        let data = pseudo_random(10_000, 1);
+        let control = ZsyncControl::parse(&make_control(&data, 1024, (2, 2, 5))).unwrap();
+
+        assert_eq!(control.filename.as_deref(), Some("test.iso"));
+        assert_eq!(control.length, 10_000);
+        assert_eq!(control.blocksize, 1024);
+        assert_eq!(control.block_count(), 10);
+        assert_eq!(control.seq_matches, 2);
+        assert_eq!(control.urls, vec!["test.iso".to_string()]);
+        assert!(control.sha1.is_some());
+    }
+
+    #[test]
+    fn test_parse_rejects_truncated_file() {
+        let data = pseudo_random(10_000, 1);
+        let control = make_control(&data, 1024, (1, 4, 16));
+        assert!(ZsyncControl::parse(&control[..control.len() - 10]).is_err());
+    }
+
+    #[test]
+    fn test_rolling_checksum_matches_full_computation() {
+        let data = pseudo_random(300, 7);
+        let mut rolling = Rsum::of(&data[0..64]);
+        for start in 1..=(data.len() - 64) {
+            rolling.roll(data[start - 1], data[start + 63], 64);
+            let full = Rsum::of(&data[start..start + 64]);
+            assert_eq!(rolling.value(), full.value());
+        }
+    }
+
+    #[test]
+    fn test_scan_seed_finds_shifted_blocks() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = pseudo_random(64 * 1024 + 100, 3);
+
+        // Seed: a few changed bytes plus an insertion that shifts everything after it
+        let mut seed = target.clone();
+        seed[5000] ^= 0xff;
+        seed.splice(20_000..20_000, pseudo_random(37, 9));
+        let seed_path = temp_dir.path().join("seed.iso");
+        std::fs::write(&seed_path, &seed).unwrap();
+
+        for hash_lengths in [(1, 4, 16), (2, 2, 5)] {
+            let control = ZsyncControl::parse(&make_control(&target, 1024, hash_lengths)).unwrap();
+            let known = control.scan_seed(&seed_path).unwrap();
+
+            let missing = known.iter().filter(|k| k.is_none()).count();
+            assert!(missing <= 4, "too many missing blocks: {}", missing);
+            assert!(known[0].is_some());
+            assert!(known[4].is_none());
+            assert!(known[known.len() - 1].is_some());
+
+            // Every known block must point at matching data in the seed
+            for (i, offset) in known.iter().enumerate() {
+                if let Some(offset) = offset {
+                    let len = control.block_len(i);
+                    let offset = *offset as usize;
+                    assert_eq!(
+                        &seed[offset..offset + len],
+                        &target[i * 1024..i * 1024 + len]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_ranges_merge_small_gaps() {
+        let data = pseudo_random(100 * 1024, 5);
+        let control = ZsyncControl::parse(&make_control(&data, 1024, (1, 4, 16))).unwrap();
+
+        let mut known: Vec<Option<u64>> = (0..100).map(|i| Some(i * 1024)).collect();
+        known[10] = None;
+        known[12] = None;
+        known[60] = None;
+        known[99] = None;
+
+        let ranges = control.missing_ranges(&known);
+        assert_eq!(
+            ranges,
+            vec![
+                (10 * 1024, 13 * 1024),
+                (60 * 1024, 61 * 1024),
+                (99 * 1024, 100 * 1024)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_seed_picks_older_version() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join("ubuntu-24.04.1-desktop-amd64.iso"),
+            b"old",
+        )
+        .unwrap();
+        // Older still, but touched last
+        std::fs::write(
+            temp_dir.path().join("ubuntu-24.04-desktop-amd64.iso"),
+            b"oldest",
+        )
+        .unwrap();
+        // Newer than the target, so not a seed for it
+        std::fs::write(
+            temp_dir.path().join("ubuntu-24.04.3-desktop-amd64.iso"),
+            b"newer",
+        )
+        .unwrap();
+        std::fs::write(
+            temp_dir.path().join("ubuntu-24.04.1-server-amd64.iso"),
+            b"other",
+        )
+        .unwrap();
+        std::fs::write(
+            temp_dir.path().join("ubuntu-24.04.2-desktop-amd64.iso"),
+            b"new",
+        )
+        .unwrap();
+
+        let seed = find_seed(
+            temp_dir.path(),
+            "ubuntu-24.04.2-desktop-amd64.iso",
+            "24.04.2",
+        );
+        assert_eq!(
+            seed,
+            Some(temp_dir.path().join("ubuntu-24.04.1-desktop-amd64.iso"))
+        );
+
+        assert!(find_seed(temp_dir.path(), "debian-12.5-amd64-netinst.iso", "12.5").is_none());
+    }
+}
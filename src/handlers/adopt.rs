@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use console::{Term, style};
+use isod::adopt::{self, ChecksumIndex, Identification, Method};
+use isod::config::ConfigManager;
+use isod::manifest::{DeviceManifest, ManifestEntry};
+use isod::naming::{self, NameMap};
+use isod::registry::{IsoInfo, IsoRegistry};
+use isod::usb::UsbManager;
+use isod::verify;
+use std::path::PathBuf;
+use std::process;
+
+/// What `isod adopt` changes
+pub struct AdoptOptions {
+    pub mount_point: Option<String>,
+    pub rename: bool,
+    pub add_distros: bool,
+    pub dry_run: bool,
+}
+
+pub async fn handle_adopt(
+    config_manager: &mut ConfigManager,
+    usb_manager: &UsbManager,
+    iso_registry: &IsoRegistry,
+    options: AdoptOptions,
+) -> Result<()> {
+    let term = Term::stdout();
+
+    let mount_points: Vec<PathBuf> = match &options.mount_point {
+        Some(mount_point) => vec![PathBuf::from(mount_point)],
+        None => usb_manager
+            .find_ventoy_devices()
+            .await?
+            .into_iter()
+            .filter_map(|device| device.mount_point)
+            .collect(),
+    };
+    if mount_points.is_empty() {
+        term.write_line(&format!(
+            "{} No mounted Ventoy devices found.",
+            style("❌").red()
+        ))?;
+        process::exit(1);
+    }
+    if options.dry_run {
+        term.write_line(&format!(
+            "{} Dry run, nothing will be changed",
+            style("💡").yellow()
+        ))?;
+    }
+
+    let naming_template = config_manager.config().general.naming_template.clone();
+    let mut names = NameMap::load(NameMap::path_in(config_manager.state_dir()))?;
+    let mut checksums = ChecksumIndex::default();
+    let mut adopted = Vec::new();
+    let mut unknown = 0;
+    let mut proposed_renames = 0;
+    for mount_point in &mount_points {
+        term.write_line(&format!(
+            "{} Adopting ISOs on {}",
+            style("🔌").cyan(),
+            style(mount_point.display()).cyan()
+        ))?;
+
+        let iso_dir = mount_point.join("iso");
+        let mut manifest = DeviceManifest::load(DeviceManifest::on_device(mount_point))?;
+        for path in verify::find_isos(mount_point, true) {
+            let tracked = manifest
+                .entries()
+                .iter()
+                .any(|entry| entry.path(&iso_dir) == path);
+            if tracked {
+                continue;
+            }
+
+            let identification = adopt::identify(iso_registry, &names, &mut checksums, &path).await;
+            let Some(found) = report(&term, &identification)? else {
+                unknown += 1;
+                continue;
+            };
+
+            let mut iso_info = found.iso_info;
+            let directory = path.parent().unwrap_or(&iso_dir);
+            let current = iso_info.local_filename().to_string();
+            let mut renamed = iso_info.clone();
+            renamed.local_filename = Some(naming::render(&naming_template, &iso_info));
+            // Other files already in the directory keep their names
+            let proposed = names.free_name(&renamed, directory, |name| {
+                name != current && directory.join(name).exists()
+            });
+            if proposed != current {
+                proposed_renames += 1;
+                if !options.rename || options.dry_run {
+                    term.write_line(&format!(
+                        "      {} rename to {}",
+                        style("→").dim(),
+                        style(&proposed).cyan()
+                    ))?;
+                } else {
+                    std::fs::rename(&path, directory.join(&proposed))
+                        .with_context(|| format!("Failed to rename {:?}", path))?;
+                    term.write_line(&format!(
+                        "      {} renamed to {}",
+                        style("✏️").cyan(),
+                        style(&proposed).cyan()
+                    ))?;
+                    iso_info.local_filename = Some(proposed);
+                }
+            }
+
+            names.record(&iso_info, directory);
+            let mut entry = ManifestEntry::new(&iso_info, found.signature);
+            if directory != iso_dir {
+                entry = entry.with_directory(directory);
+            }
+            manifest.record(entry);
+            adopted.push((iso_info, found.method));
+        }
+
+        if !options.dry_run {
+            manifest.save()?;
+        }
+    }
+
+    if !options.dry_run {
+        names.save()?;
+    }
+
+    if options.add_distros {
+        add_distros(&term, config_manager, &adopted, options.dry_run)?;
+    }
+
+    let by_checksum = adopted
+        .iter()
+        .filter(|(_, method)| *method == Method::Checksum)
+        .count();
+    term.write_line("")?;
+    term.write_line(&format!(
+        "{} Adopted {} ISOs ({} by checksum, {} by name), {} not identified",
+        style("📝").cyan().bold(),
+        adopted.len(),
+        by_checksum,
+        adopted.len() - by_checksum,
+        unknown
+    ))?;
+    if proposed_renames > 0 && (!options.rename || options.dry_run) {
+        term.write_line(&format!(
+            "{} Run with --rename to apply the {} proposed names",
+            style("💡").yellow(),
+            proposed_renames
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Describe what was found out about an ISO, returning it when it can be adopted
+fn report(term: &Term, identification: &Identification) -> Result<Option<adopt::Identified>> {
+    let filename = identification
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let label = identification
+        .image
+        .as_ref()
+        .map(|image| format!(" [{}]", image.volume_id))
+        .unwrap_or_default();
+
+    if let Some(image) = &identification.image
+        && image.is_truncated()
+    {
+        term.write_line(&format!(
+            "   {} {}{}: truncated, not adopting it",
+            style("❌").red(),
+            style(&filename).cyan(),
+            style(label).dim()
+        ))?;
+        return Ok(None);
+    }
+
+    let Some(found) = &identification.found else {
+        term.write_line(&format!(
+            "   {} {}{}: not identified",
+            style("❓").yellow(),
+            style(&filename).cyan(),
+            style(label).dim()
+        ))?;
+        return Ok(None);
+    };
+
+    let iso_info = &found.iso_info;
+    let how = match found.method {
+        Method::Checksum => style(format!("by checksum, {}", found.signature)).green(),
+        Method::Recorded => style("by recorded name, unverified".to_string()).yellow(),
+        Method::Filename => style("by filename, unverified".to_string()).yellow(),
+        Method::VolumeLabel => style("by volume label, unverified".to_string()).yellow(),
+    };
+    term.write_line(&format!(
+        "   {} {}: {} {} {}{} ({})",
+        style("✅").green(),
+        style(&filename).cyan(),
+        iso_info.distro,
+        style(&iso_info.version).green(),
+        iso_info.architecture,
+        iso_info
+            .variant
+            .as_ref()
+            .map(|variant| format!(" {}", variant))
+            .unwrap_or_default(),
+        how
+    ))?;
+
+    Ok(Some(found.clone()))
+}
+
+/// Enable the adopted distros, architectures and variants in the configuration
+fn add_distros(
+    term: &Term,
+    config_manager: &mut ConfigManager,
+    adopted: &[(IsoInfo, Method)],
+    dry_run: bool,
+) -> Result<()> {
+    let mut changed = Vec::new();
+    for (iso_info, _) in adopted {
+        let existing = config_manager.get_distro_config(&iso_info.distro);
+        let mut changes_made = existing.is_none_or(|config| !config.enabled);
+        let mut distro_config = existing.cloned().unwrap_or_default();
+
+        distro_config.enabled = true;
+        if !distro_config.architectures.contains(&iso_info.architecture) {
+            distro_config
+                .architectures
+                .push(iso_info.architecture.clone());
+            changes_made = true;
+        }
+        if let Some(variant) = &iso_info.variant
+            && !distro_config.variants.contains(variant)
+        {
+            distro_config.variants.push(variant.clone());
+            changes_made = true;
+        }
+
+        if changes_made {
+            config_manager.set_distro_config(iso_info.distro.clone(), distro_config);
+            if !changed.contains(&iso_info.distro) {
+                changed.push(iso_info.distro.clone());
+            }
+        }
+    }
+
+    if changed.is_empty() {
+        return Ok(());
+    }
+    if !dry_run {
+        config_manager.save()?;
+    }
+    term.write_line(&format!(
+        "{} {} configuration for {}",
+        style("⚙️").cyan(),
+        if dry_run { "Would update" } else { "Updated" },
+        changed.join(", ")
+    ))?;
+    Ok(())
+}
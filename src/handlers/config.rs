@@ -10,7 +10,8 @@ pub async fn handle_config(config_manager: &mut ConfigManager, action: ConfigAct
 
     match action {
         ConfigAction::Show { section, format: _ } => {
-            let config_content = std::fs::read_to_string(config_manager.config_file())?;
+            // Render from the parsed config so credentials can be redacted
+            let config_content = toml::to_string_pretty(&config_manager.config().redacted())?;
 
             match section.as_deref() {
                 Some("general") => {
@@ -109,6 +110,24 @@ pub async fn handle_config(config_manager: &mut ConfigManager, action: ConfigAct
                 }
             }
 
+            let config = config_manager.config();
+            let conflicts = config.policy.conflicts(config);
+            if !conflicts.is_empty() {
+                term.write_line(&format!(
+                    "{} Configuration conflicts with the security policy:",
+                    style("🛡️").red()
+                ))?;
+                for conflict in &conflicts {
+                    term.write_line(&format!("   {}", conflict))?;
+                }
+                process::exit(1);
+            } else if config.policy.is_enabled() {
+                term.write_line(&format!(
+                    "{} Security policy is consistent with the configuration",
+                    style("🛡️").green()
+                ))?;
+            }
+
             if warnings {
                 term.write_line(&format!(
                     "{} TODO: Implement warning checks",
@@ -1,12 +1,22 @@
 use anyhow::Result;
+use chrono::Utc;
 use console::{Term, style};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use isod::download::{DownloadManager, DownloadOptions, DownloadProgress};
+use isod::config::ConfigManager;
+use isod::download::{
+    DownloadManager, DownloadOptions, DownloadProgress, DownloadQueue, ErrorClass,
+    MirrorHealthStore,
+};
+use isod::image::ImageFormat;
+use isod::provenance::{DownloadOrigin, Provenance};
 use isod::registry::IsoRegistry;
+use isod::registry::signature::{InvalidSignature, SignatureStatus};
+use std::path::Path;
 use std::process;
 use std::time::Duration;
 
 pub async fn handle_download(
+    config_manager: &ConfigManager,
     iso_registry: &IsoRegistry,
     distro: String,
     output_dir: Option<String>,
@@ -16,6 +26,7 @@ pub async fn handle_download(
     prefer_torrent: bool,
     max_concurrent: u8,
     verify_checksum: bool,
+    limit_rate: Option<String>,
 ) -> Result<()> {
     let term = Term::stdout();
     term.write_line(&format!(
@@ -33,6 +44,12 @@ pub async fn handle_download(
         process::exit(1);
     }
 
+    let policy = config_manager.config().policy.clone();
+    if let Err(violation) = policy.check_distro(&distro) {
+        super::report_policy_violation(&term, &violation)?;
+        process::exit(1);
+    }
+
     // Show a spinner while fetching ISO info
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(
@@ -43,7 +60,7 @@ pub async fn handle_download(
     spinner.set_message("Fetching ISO information...");
     spinner.enable_steady_tick(Duration::from_millis(100));
 
-    let iso_info = iso_registry
+    let mut iso_info = iso_registry
         .get_iso_info(
             &distro,
             version.as_deref(),
@@ -54,6 +71,15 @@ pub async fn handle_download(
 
     spinner.finish_and_clear();
 
+    let download_dir = output_dir.unwrap_or_else(|| {
+        std::env::current_dir()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    });
+
+    super::assign_local_filename(config_manager, &mut iso_info, Path::new(&download_dir))?;
+
     term.write_line(&format!("{} ISO details:", style("📦").cyan()))?;
     term.write_line(&format!(
         "   {}: {}",
@@ -76,20 +102,35 @@ pub async fn handle_download(
     term.write_line(&format!(
         "   {}: {}",
         style("Filename").dim(),
-        style(&iso_info.filename).cyan()
+        style(iso_info.local_filename()).cyan()
     ))?;
+    if iso_info.format != ImageFormat::default() {
+        term.write_line(&format!(
+            "   {}: {}",
+            style("Format").dim(),
+            iso_info.format
+        ))?;
+    }
+    if iso_info.local_filename() != iso_info.filename {
+        term.write_line(&format!(
+            "   {}: {}",
+            style("Upstream filename").dim(),
+            iso_info.filename
+        ))?;
+    }
     term.write_line(&format!(
         "   {}: {}",
         style("Sources available").dim(),
         iso_info.download_sources.len()
     ))?;
 
-    let download_dir = output_dir.unwrap_or_else(|| {
-        std::env::current_dir()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string()
-    });
+    if policy.require_checksum && !verify_checksum {
+        term.write_line(&format!(
+            "{} The security policy requires checksum verification; verifying anyway",
+            style("🛡️").cyan()
+        ))?;
+    }
+    let verify_checksum = verify_checksum || policy.require_checksum;
 
     let options = DownloadOptions {
         max_concurrent: max_concurrent as usize,
@@ -124,28 +165,70 @@ pub async fn handle_download(
     }
 
     // Fetch checksum if verification is enabled and not already present
-    let mut iso_info = iso_info;
+    let mut signature = SignatureStatus::unsigned("checksum verification disabled");
+    let mut checksum_url = None;
     if verify_checksum && iso_info.checksum.is_none() {
         term.write_line(&format!("{} Fetching checksum...", style("🔍").cyan()))?;
 
-        if let Ok(Some(checksum)) = iso_registry.get_checksum(&iso_info).await {
-            iso_info.checksum = Some(checksum);
-            iso_info.checksum_type = Some("sha256".to_string());
-        } else {
-            term.write_line(&format!(
-                "{} No checksum available for verification",
-                style("⚠️").yellow()
-            ))?;
+        match iso_registry.get_signed_checksum(&iso_info).await {
+            Ok(Some(checksum)) => {
+                iso_info.checksum = Some(checksum.value);
+                iso_info.checksum_type = Some(checksum.checksum_type.algorithm().to_string());
+                iso_info.checksum_layer = checksum.layer;
+                signature = checksum.signature;
+                checksum_url = Some(checksum.url);
+                super::print_signature(&term, "Checksum file", &signature)?;
+            }
+            Ok(None) => {
+                if let Err(violation) = policy.check_checksum(&iso_info.filename, None) {
+                    super::report_policy_violation(&term, &violation)?;
+                    process::exit(1);
+                }
+                signature = SignatureStatus::unsigned("no checksum available");
+                term.write_line(&format!(
+                    "{} No checksum available for verification",
+                    style("⚠️").yellow()
+                ))?;
+            }
+            Err(e) => {
+                term.write_line(&format!("{} {:#}", style("❌").red(), e))?;
+                if e.downcast_ref::<InvalidSignature>().is_some() {
+                    term.write_line(&format!(
+                        "{} The checksum file may have been tampered with; aborting",
+                        style("⚠️").yellow()
+                    ))?;
+                }
+                process::exit(1);
+            }
         }
     }
 
+    // ISOs signed by the distro are checked against the policy once they are complete
+    if !iso_registry.signs_iso(&iso_info.distro)
+        && let Err(violation) = policy.check_signature(&iso_info.filename, &signature)
+    {
+        super::report_policy_violation(&term, &violation)?;
+        process::exit(1);
+    }
+
     // Create download manager
     let (download_manager, mut progress_receiver) = DownloadManager::new(options.clone())?;
+    let download_manager = download_manager
+        .with_queue(DownloadQueue::path_in(config_manager.state_dir()))
+        .with_bandwidth(super::bandwidth_policy(
+            config_manager,
+            limit_rate.as_deref(),
+        )?)
+        .with_mirror_health(MirrorHealthStore::path_in(config_manager.state_dir()))
+        .with_mirror_probe(config_manager.config().sources.probe_count())
+        .with_consistency_check(config_manager.config().sources.consistency_count())
+        .with_policy(policy.clone());
 
     // Start the download
     term.write_line("")?;
     term.write_line(&format!("{} Starting download...", style("🚀").green()))?;
 
+    let started_at = Utc::now();
     let _download_id = download_manager.download_iso(&iso_info, &options).await?;
 
     // Create progress bar
@@ -157,7 +240,7 @@ pub async fn handle_download(
             .unwrap()
             .progress_chars("#>-")
     );
-    progress_bar.set_message(format!("Downloading {}", iso_info.filename));
+    progress_bar.set_message(format!("Downloading {}", iso_info.local_filename()));
 
     // Handle progress updates
     let mut download_completed = false;
@@ -169,7 +252,7 @@ pub async fn handle_download(
                     style("🔗").cyan(),
                     style(&url).dim()
                 ))?;
-                progress_bar.set_message(format!("Downloading {}", iso_info.filename));
+                progress_bar.set_message(format!("Downloading {}", iso_info.local_filename()));
             }
             DownloadProgress::Progress {
                 bytes_downloaded,
@@ -184,6 +267,35 @@ pub async fn handle_download(
                     progress_bar.set_position(progress_percent as u64);
                 }
             }
+            DownloadProgress::DeltaSeeded {
+                seed_path,
+                reused_bytes,
+                total_bytes,
+                ..
+            } => {
+                let reused_percent = if total_bytes > 0 {
+                    reused_bytes as f64 / total_bytes as f64 * 100.0
+                } else {
+                    0.0
+                };
+                term.write_line(&format!(
+                    "{} Reusing {:.1} MB ({:.0}%) from {}",
+                    style("♻️").green(),
+                    reused_bytes as f64 / (1024.0 * 1024.0),
+                    reused_percent,
+                    style(seed_path.display()).dim()
+                ))?;
+            }
+            DownloadProgress::DeltaFailed { error, .. } => {
+                term.write_line(&format!(
+                    "{} Delta transfer failed ({}), downloading the full file",
+                    style("⚠️").yellow(),
+                    error
+                ))?;
+            }
+            DownloadProgress::Decompressing { .. } => {
+                progress_bar.set_message("Decompressing...");
+            }
             DownloadProgress::VerifyingChecksum { .. } => {
                 progress_bar.set_message("Verifying checksum...");
             }
@@ -209,6 +321,10 @@ pub async fn handle_download(
             DownloadProgress::Completed {
                 bytes_downloaded,
                 checksum_verified,
+                url,
+                etag,
+                last_modified,
+                image_checksum,
                 ..
             } => {
                 let size_mb = bytes_downloaded as f64 / (1024.0 * 1024.0);
@@ -232,22 +348,78 @@ pub async fn handle_download(
                 term.write_line(&format!(
                     "{} Successfully downloaded {} ({:.1} MB){}",
                     style("✅").green(),
-                    style(&iso_info.filename).cyan(),
+                    style(iso_info.local_filename()).cyan(),
                     size_mb,
                     checksum_msg
                 ))?;
 
+                let output_path = options.output_directory.join(iso_info.local_filename());
+
+                let provenance = Provenance::new(
+                    &iso_info,
+                    DownloadOrigin {
+                        url,
+                        etag,
+                        last_modified,
+                    },
+                    bytes_downloaded,
+                    started_at,
+                )
+                .with_checksum(
+                    checksum_url.take(),
+                    checksum_verified,
+                    signature.clone(),
+                );
+                let accepted = match super::finish_download(
+                    &term,
+                    config_manager,
+                    iso_registry,
+                    super::FinishedDownload {
+                        iso_info: &iso_info,
+                        output_dir: &options.output_directory,
+                        checksum_signature: signature.clone(),
+                        provenance,
+                        image_checksum,
+                    },
+                )
+                .await
+                {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        term.write_line(&format!("{} {:#}", style("❌").red(), e))?;
+                        process::exit(1);
+                    }
+                };
                 term.write_line(&format!(
                     "{} File saved to: {}",
                     style("📁").cyan(),
-                    style(options.output_directory.join(&iso_info.filename).display()).cyan()
+                    style(output_path.display()).cyan()
                 ))?;
 
+                if let Some(manifest_path) = &accepted.device_manifest {
+                    term.write_line(&format!(
+                        "{} Recorded in device manifest: {}",
+                        style("📝").cyan(),
+                        style(manifest_path.display()).dim()
+                    ))?;
+                }
+
+                for path in accepted.provenance {
+                    term.write_line(&format!(
+                        "{} Provenance recorded: {}",
+                        style("🧾").cyan(),
+                        style(path.display()).dim()
+                    ))?;
+                }
+
                 download_completed = true;
                 break;
             }
             DownloadProgress::Failed {
-                error, attempts, ..
+                error,
+                class,
+                attempts,
+                ..
             } => {
                 progress_bar.finish_with_message(format!(
                     "{} Download failed after {} attempts",
@@ -255,6 +427,16 @@ pub async fn handle_download(
                     attempts
                 ));
                 term.write_line(&format!("{} Download failed: {}", style("❌").red(), error))?;
+                match class {
+                    ErrorClass::Fatal => term.write_line(&format!(
+                        "{} This error is permanent; retrying the same source will not help",
+                        style("💡").yellow()
+                    ))?,
+                    ErrorClass::Transient => term.write_line(&format!(
+                        "{} This looks temporary; run the same command later to resume",
+                        style("💡").yellow()
+                    ))?,
+                }
                 process::exit(1);
             }
             DownloadProgress::Retry {
@@ -271,9 +453,13 @@ pub async fn handle_download(
                 ));
             }
             DownloadProgress::Cancelled { .. } => {
-                progress_bar.finish_with_message("Download cancelled");
+                progress_bar.abandon_with_message("Download cancelled");
                 term.write_line(&format!("{} Download cancelled", style("❌").red()))?;
-                break;
+                term.write_line(&format!(
+                    "{} Partial download kept, run the same command again to resume",
+                    style("💡").yellow()
+                ))?;
+                process::exit(130);
             }
             DownloadProgress::Error { error, .. } => {
                 progress_bar.finish_with_message(format!("Error: {}", error));
@@ -1,11 +1,18 @@
 use anyhow::Result;
+use chrono::Utc;
 use console::{Term, style};
 use indicatif::{ProgressBar, ProgressStyle};
+use isod::config::ConfigManager;
+use isod::download::MirrorHealthStore;
+use isod::download::consistency::{self, Consistency, Expected};
+use isod::download::metalink::MetalinkHash;
 use isod::registry::IsoRegistry;
+use isod::registry::sources::SourceType;
 use std::process;
 use std::time::Duration;
 
 pub async fn handle_info(
+    config_manager: &ConfigManager,
     iso_registry: &IsoRegistry,
     distro: String,
     show_versions: bool,
@@ -170,6 +177,8 @@ pub async fn handle_info(
                 term.write_line(&format!("      {}", style("✅ Verified").green()))?;
             }
         }
+
+        check_mirror_consistency(&term, config_manager, iso_registry, &distro).await?;
     }
 
     term.write_line(&format!("\n{} Example commands:", style("💡").yellow()))?;
@@ -200,3 +209,103 @@ pub async fn handle_info(
 
     Ok(())
 }
+
+/// Compare the latest ISO's mirrors with each other and the checksum file,
+/// recording mirrors that disagree so downloads skip them
+async fn check_mirror_consistency(
+    term: &Term,
+    config_manager: &ConfigManager,
+    iso_registry: &IsoRegistry,
+    distro: &str,
+) -> Result<()> {
+    term.write_line(&format!(
+        "\n{} Mirror consistency for the latest release:",
+        style("🔎").cyan()
+    ))?;
+
+    let iso_info = match iso_registry.get_iso_info(distro, None, None, None).await {
+        Ok(iso_info) => iso_info,
+        Err(e) => {
+            term.write_line(&format!(
+                "   {} Could not resolve the latest release: {}",
+                style("❌").red(),
+                e
+            ))?;
+            return Ok(());
+        }
+    };
+
+    let checksum = iso_registry
+        .get_signed_checksum(&iso_info)
+        .await
+        .ok()
+        .flatten();
+    let expected = Expected {
+        size: iso_info.size_bytes,
+        hash: checksum.map(|checksum| MetalinkHash {
+            algorithm: checksum.checksum_type.algorithm().to_string(),
+            value: checksum.value,
+        }),
+    };
+
+    let urls: Vec<&str> = iso_info
+        .download_sources
+        .iter()
+        .filter(|s| matches!(s.source_type, SourceType::Direct | SourceType::Mirror))
+        .filter_map(|s| s.get_url())
+        .collect();
+
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.blue} Querying mirrors...")
+            .unwrap(),
+    );
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let checks = consistency::check(isod::http::context(), &urls, &expected).await;
+    spinner.finish_and_clear();
+
+    let health_path = MirrorHealthStore::path_in(config_manager.state_dir());
+    let mut health = MirrorHealthStore::load(&health_path)?;
+    let now = Utc::now();
+    let mut flagged = 0;
+
+    for check in &checks {
+        let status = match &check.consistency {
+            Consistency::Consistent => style("consistent".to_string()).green(),
+            Consistency::Unverified => style("unverified".to_string()).dim(),
+            Consistency::Mismatch(reason) => {
+                flagged += 1;
+                health.record_inconsistency(&check.url, reason, now);
+                style(format!("mismatch: {}", reason)).red()
+            }
+            Consistency::Unreachable(_) => style("unreachable".to_string()).yellow(),
+        };
+        let quarantined = health
+            .get(&check.url)
+            .is_some_and(|h| h.is_quarantined(now));
+
+        term.write_line(&format!(
+            "   {} {} [{}]{}",
+            style("•").dim(),
+            style(&check.url).dim(),
+            status,
+            if quarantined {
+                style(" (skipped by downloads)").yellow().to_string()
+            } else {
+                String::new()
+            }
+        ))?;
+    }
+
+    if flagged > 0 {
+        health.save()?;
+        term.write_line(&format!(
+            "   {} {} mirrors disagree and will be skipped; see 'isod mirrors'",
+            style("⚠️").yellow(),
+            flagged
+        ))?;
+    }
+
+    Ok(())
+}
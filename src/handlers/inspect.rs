@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use console::{Term, style};
+use isod::iso9660::IsoImage;
+use std::path::PathBuf;
+use std::process;
+
+pub fn handle_inspect(file: PathBuf, json: bool) -> Result<()> {
+    let term = Term::stdout();
+
+    let iso = match IsoImage::open(&file) {
+        Ok(iso) => iso,
+        Err(e) => {
+            term.write_line(&format!("{} {:#}", style("❌").red(), e))?;
+            process::exit(1);
+        }
+    };
+
+    if json {
+        let content = serde_json::to_string_pretty(&iso).context("Failed to serialize ISO")?;
+        term.write_line(&content)?;
+        if iso.is_truncated() {
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let field = |name: &str, value: String| format!("   {}: {}", style(name).dim(), value);
+    let megabytes = |bytes: u64| format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0));
+
+    term.write_line(&format!(
+        "{} {}",
+        style("💿").cyan().bold(),
+        style(file.display()).cyan().bold()
+    ))?;
+    term.write_line(&field(
+        "Volume ID",
+        style(&iso.volume_id).green().to_string(),
+    ))?;
+    if !iso.publisher.is_empty() {
+        term.write_line(&field("Publisher", iso.publisher.clone()))?;
+    }
+    if !iso.application.is_empty() {
+        term.write_line(&field("Application", iso.application.clone()))?;
+    }
+    if let Some(created) = iso.created {
+        term.write_line(&field(
+            "Created",
+            created.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+        ))?;
+    }
+
+    let size = if iso.is_truncated() {
+        style(format!(
+            "{} declared, {} on disk (truncated)",
+            megabytes(iso.volume_size),
+            megabytes(iso.file_size)
+        ))
+        .red()
+        .to_string()
+    } else {
+        format!(
+            "{} declared, {} on disk",
+            megabytes(iso.volume_size),
+            megabytes(iso.file_size)
+        )
+    };
+    term.write_line(&field("Size", size))?;
+
+    let extensions: Vec<&str> = [("Joliet", iso.joliet), ("Rock Ridge", iso.rock_ridge)]
+        .into_iter()
+        .filter_map(|(name, present)| present.then_some(name))
+        .collect();
+    term.write_line(&field(
+        "Extensions",
+        if extensions.is_empty() {
+            "none".to_string()
+        } else {
+            extensions.join(", ")
+        },
+    ))?;
+
+    if iso.boot_entries.is_empty() {
+        term.write_line(&field("Boot", style("not bootable").yellow().to_string()))?;
+    }
+    for entry in &iso.boot_entries {
+        term.write_line(&field(
+            "Boot",
+            format!(
+                "{}{}{}",
+                entry.platform,
+                if entry.no_emulation {
+                    ""
+                } else {
+                    " (emulated disk)"
+                },
+                if entry.bootable {
+                    String::new()
+                } else {
+                    style(" (not bootable)").yellow().to_string()
+                }
+            ),
+        ))?;
+    }
+    term.write_line(&field(
+        "BIOS / UEFI",
+        format!(
+            "{} / {}",
+            yes_no(iso.boots_bios()),
+            yes_no(iso.boots_uefi())
+        ),
+    ))?;
+    term.write_line(&field(
+        "Hybrid",
+        if iso.hybrid.is_hybrid() {
+            format!("yes ({})", iso.hybrid)
+        } else {
+            "no, the image cannot boot when written to a USB stick".to_string()
+        },
+    ))?;
+
+    if iso.is_truncated() {
+        term.write_line(&format!(
+            "{} The file is {} shorter than its volume; the download or copy is incomplete",
+            style("❌").red(),
+            megabytes(iso.volume_size - iso.file_size)
+        ))?;
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn yes_no(value: bool) -> String {
+    if value {
+        style("yes").green().to_string()
+    } else {
+        style("no").yellow().to_string()
+    }
+}
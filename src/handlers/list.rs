@@ -2,6 +2,7 @@ use anyhow::Result;
 use console::{Term, style};
 use indicatif::{ProgressBar, ProgressStyle};
 use isod::config::ConfigManager;
+use isod::image;
 use isod::registry::IsoRegistry;
 use isod::usb::UsbManager;
 use std::time::Duration;
@@ -55,7 +56,7 @@ pub async fn handle_list(
                             for entry in entries {
                                 if let Ok(entry) = entry {
                                     let path = entry.path();
-                                    if path.extension().and_then(|s| s.to_str()) == Some("iso") {
+                                    if image::is_bootable(&path) {
                                         if let Some(name) =
                                             path.file_name().and_then(|s| s.to_str())
                                         {
@@ -82,7 +83,7 @@ pub async fn handle_list(
                                     ))?;
                                 } else {
                                     term.write_line(&format!(
-                                        "   {} No ISO files found",
+                                        "   {} No bootable images found",
                                         style("📭").dim()
                                     ))?;
                                 }
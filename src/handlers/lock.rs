@@ -0,0 +1,196 @@
+use crate::cli::LockAction;
+use anyhow::Result;
+use console::{Term, style};
+use isod::config::ConfigManager;
+use isod::lock::{self, LockChange, LockEntry, Lockfile};
+use isod::registry::{IsoRegistry, ReleaseType};
+
+pub async fn handle_lock(
+    config_manager: &ConfigManager,
+    iso_registry: &IsoRegistry,
+    action: Option<LockAction>,
+) -> Result<()> {
+    let term = Term::stdout();
+    let lockfile = Lockfile::load(Lockfile::path_in(config_manager.config_dir()))?;
+
+    match action.unwrap_or(LockAction::Show) {
+        LockAction::Show => {
+            if lockfile.entries().is_empty() {
+                term.write_line(&format!(
+                    "{} No lockfile yet, run 'isod update' to create one",
+                    style("📭").dim()
+                ))?;
+                return Ok(());
+            }
+
+            term.write_line(&format!(
+                "{} Locked ISOs ({}):",
+                style("🔒").cyan().bold(),
+                style(lockfile.path().display()).dim()
+            ))?;
+            for entry in lockfile.entries() {
+                term.write_line(&format!(
+                    "   {} {}",
+                    describe(entry),
+                    style(&entry.filename).dim()
+                ))?;
+                if let Some(checksum) = &entry.checksum {
+                    term.write_line(&format!(
+                        "      {}: {}",
+                        entry.checksum_type.as_deref().unwrap_or("checksum"),
+                        style(checksum).dim()
+                    ))?;
+                }
+                if let Some(fingerprint) = &entry.signed_by {
+                    term.write_line(&format!("      signed by: {}", style(fingerprint).green()))?;
+                }
+            }
+        }
+        LockAction::Diff { include_beta } => {
+            let resolved =
+                resolve_configured(&term, config_manager, iso_registry, &lockfile, include_beta)
+                    .await?;
+            let changes = lock::diff(lockfile.entries(), &resolved);
+
+            if changes.is_empty() {
+                term.write_line(&format!("{} Lockfile is up to date", style("✅").green()))?;
+                return Ok(());
+            }
+
+            term.write_line(&format!(
+                "{} 'isod update' would change {} locked ISOs:",
+                style("📝").cyan().bold(),
+                changes.len()
+            ))?;
+            for change in &changes {
+                let line = match change {
+                    LockChange::Added(entry) => {
+                        format!("{} {}", style("+").green().bold(), describe(entry))
+                    }
+                    LockChange::Removed(entry) => {
+                        format!("{} {}", style("-").red().bold(), describe(entry))
+                    }
+                    LockChange::Changed { old, new } => format!(
+                        "{} {} → {}",
+                        style("~").yellow().bold(),
+                        describe(old),
+                        style(changed_fields(old, new)).yellow()
+                    ),
+                };
+                term.write_line(&format!("   {}", line))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve every configured (distro, architecture, variant) the way `isod update` does.
+///
+/// Whatever cannot be resolved keeps its locked entry, so the diff only shows real changes.
+async fn resolve_configured(
+    term: &Term,
+    config_manager: &ConfigManager,
+    iso_registry: &IsoRegistry,
+    lockfile: &Lockfile,
+    include_beta: bool,
+) -> Result<Vec<LockEntry>> {
+    let mut distros: Vec<_> = config_manager
+        .config()
+        .distros
+        .iter()
+        .filter(|(_, config)| config.enabled)
+        .collect();
+    distros.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut resolved = Vec::new();
+    for (distro, config) in distros {
+        let locked = |arch: &str, variant: &str| {
+            lockfile
+                .entries()
+                .iter()
+                .find(|e| e.distro == *distro && e.architecture == arch && e.variant == variant)
+                .cloned()
+        };
+
+        let version = match iso_registry.get_latest_version(distro).await {
+            Ok(info)
+                if include_beta
+                    || matches!(info.release_type, ReleaseType::Stable | ReleaseType::LTS) =>
+            {
+                Some(info.version)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                term.write_line(&format!(
+                    "{} Could not check {}: {}",
+                    style("⚠️").yellow(),
+                    distro,
+                    e
+                ))?;
+                None
+            }
+        };
+
+        for arch in &config.architectures {
+            for variant in &config.variants {
+                let entry = match &version {
+                    Some(version) => {
+                        match lock::resolve(iso_registry, distro, version, arch, variant).await {
+                            Ok((_, entry)) => Some(entry),
+                            Err(e) => {
+                                term.write_line(&format!(
+                                    "{} Could not resolve {}-{}-{}: {}",
+                                    style("⚠️").yellow(),
+                                    distro,
+                                    arch,
+                                    variant,
+                                    e
+                                ))?;
+                                locked(arch, variant)
+                            }
+                        }
+                    }
+                    None => locked(arch, variant),
+                };
+                resolved.extend(entry);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+fn describe(entry: &LockEntry) -> String {
+    format!(
+        "{} {}/{} {}",
+        style(&entry.distro).cyan(),
+        entry.architecture,
+        entry.variant,
+        style(&entry.version).green()
+    )
+}
+
+/// Short description of how `new` differs from `old`
+fn changed_fields(old: &LockEntry, new: &LockEntry) -> String {
+    let mut changes = Vec::new();
+    if old.version != new.version {
+        changes.push(new.version.clone());
+    }
+    if old.filename != new.filename {
+        changes.push(new.filename.clone());
+    }
+    if old.checksum != new.checksum || old.checksum_type != new.checksum_type {
+        changes.push("checksum changed".to_string());
+    }
+    if old.signed_by != new.signed_by {
+        changes.push(match &new.signed_by {
+            Some(fingerprint) => format!("signed by {}", fingerprint),
+            None => "no longer signed".to_string(),
+        });
+    }
+    if old.sources != new.sources {
+        changes.push("sources changed".to_string());
+    }
+    changes.join(", ")
+}
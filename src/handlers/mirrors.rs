@@ -0,0 +1,92 @@
+use crate::cli::MirrorAction;
+use anyhow::{Result, bail};
+use chrono::Utc;
+use console::{Term, style};
+use isod::config::ConfigManager;
+use isod::download::MirrorHealthStore;
+
+pub fn handle_mirrors(config_manager: &ConfigManager, action: Option<MirrorAction>) -> Result<()> {
+    let term = Term::stdout();
+    let health_path = MirrorHealthStore::path_in(config_manager.state_dir());
+
+    match action.unwrap_or(MirrorAction::List) {
+        MirrorAction::List => {
+            let store = MirrorHealthStore::load(&health_path)?;
+            let now = Utc::now();
+            let mut entries = store.entries().peekable();
+
+            if entries.peek().is_none() {
+                term.write_line(&format!(
+                    "{} No mirror history recorded yet",
+                    style("📭").dim()
+                ))?;
+                return Ok(());
+            }
+
+            term.write_line(&format!("{} Mirror health:", style("🌐").cyan().bold()))?;
+            for (mirror, health) in entries {
+                let status = if health.is_quarantined(now) {
+                    style("quarantined".to_string()).red()
+                } else if health.consecutive_failures > 0 {
+                    style("degraded".to_string()).yellow()
+                } else {
+                    style("healthy".to_string()).green()
+                };
+
+                let throughput = health
+                    .throughput_bps
+                    .map(|bps| format!(" {:.1} MB/s", bps as f64 / (1024.0 * 1024.0)))
+                    .unwrap_or_default();
+
+                term.write_line(&format!(
+                    "  {} [{}] {} ok, {} failed, {} checksum mismatches{}",
+                    style(mirror).cyan(),
+                    status,
+                    health.successes,
+                    health.failures,
+                    health.checksum_mismatches,
+                    style(throughput).dim()
+                ))?;
+
+                if let Some(until) = health
+                    .quarantined_until
+                    .filter(|_| health.is_quarantined(now))
+                {
+                    term.write_line(&format!(
+                        "     {} Skipped until {}",
+                        style("⏳").yellow(),
+                        until.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                    ))?;
+                }
+                if let Some(error) = &health.last_error
+                    && health.consecutive_failures > 0
+                {
+                    term.write_line(&format!("     {} {}", style("❌").red(), error))?;
+                }
+            }
+        }
+        MirrorAction::Reset { mirror, all } => {
+            let mut store = MirrorHealthStore::load(&health_path)?;
+            let removed = match (mirror, all) {
+                (_, true) => store.clear(),
+                (Some(mirror), false) => {
+                    let removed = store.reset(&mirror);
+                    if removed == 0 {
+                        bail!("No recorded history for mirror: {}", mirror);
+                    }
+                    removed
+                }
+                (None, false) => bail!("Specify a mirror or --all"),
+            };
+            store.save()?;
+
+            term.write_line(&format!(
+                "{} Reset history of {} mirrors",
+                style("✅").green(),
+                removed
+            ))?;
+        }
+    }
+
+    Ok(())
+}
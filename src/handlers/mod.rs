@@ -1,30 +1,226 @@
 pub mod add;
+pub mod adopt;
 pub mod clean;
 pub mod config;
 pub mod download;
 pub mod info;
+pub mod inspect;
 pub mod list;
+pub mod lock;
+pub mod mirrors;
+pub mod provenance;
+pub mod queue;
 pub mod remove;
 pub mod search;
 pub mod sync;
 pub mod update;
+pub mod verify;
 
 use crate::cli::{Commands, ConfigAction};
-use anyhow::Result;
+use anyhow::{Result, bail};
+use console::{Term, style};
+use isod::download::BandwidthPolicy;
+use isod::download::bandwidth::parse_rate;
+use isod::manifest::{DeviceManifest, ManifestEntry};
+use isod::naming::NameMap;
+use isod::policy::PolicyViolation;
+use isod::provenance::Provenance;
+use isod::registry::signature::SignatureStatus;
 use isod::usb::UsbManager;
-use isod::{ConfigManager, IsoRegistry};
+use isod::{ConfigManager, IsoInfo, IsoRegistry};
+use std::path::{Path, PathBuf};
 
 // Re-export all handlers
 pub use add::handle_add;
+pub use adopt::handle_adopt;
 pub use clean::handle_clean;
 pub use config::handle_config;
 pub use download::handle_download;
 pub use info::handle_info;
+pub use inspect::handle_inspect;
 pub use list::handle_list;
+pub use lock::handle_lock;
+pub use mirrors::handle_mirrors;
+pub use provenance::handle_provenance;
+pub use queue::handle_queue;
 pub use remove::handle_remove;
 pub use search::handle_search;
 pub use sync::handle_sync;
 pub use update::handle_update;
+pub use verify::handle_verify;
+
+/// Store the provenance of a completed download in the library, and on the device
+/// as well when `output_dir` is on one
+pub fn record_provenance(
+    config_manager: &ConfigManager,
+    provenance: &Provenance,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![provenance.save(&Provenance::dir_in(config_manager.state_dir()))?];
+    if let Some(device_dir) = Provenance::device_dir(output_dir) {
+        paths.push(provenance.save(&device_dir)?);
+    }
+    Ok(paths)
+}
+
+/// A download the manager reported as complete, before isod accepts it
+pub struct FinishedDownload<'a> {
+    pub iso_info: &'a IsoInfo,
+    pub output_dir: &'a Path,
+    /// Signature of the checksum file the download was verified against
+    pub checksum_signature: SignatureStatus,
+    pub provenance: Provenance,
+    /// Checksum of the unpacked image, when the download was decompressed
+    pub image_checksum: Option<String>,
+}
+
+/// A download that passed its signature checks
+pub struct AcceptedDownload {
+    /// Where its provenance was recorded
+    pub provenance: Vec<PathBuf>,
+    /// Entry recorded for it in the device manifest or the download library,
+    /// signed by the ISO's own signature when the distro has one
+    pub entry: ManifestEntry,
+    /// Device manifest it was recorded in, `None` for the download library
+    pub device_manifest: Option<PathBuf>,
+}
+
+/// Check the signature of a completed download against the security policy and
+/// record its provenance and manifest entry.
+///
+/// An ISO whose own signature is invalid, or that the policy does not accept as
+/// signed, is removed and an error returned.
+pub async fn finish_download(
+    term: &Term,
+    config_manager: &ConfigManager,
+    iso_registry: &IsoRegistry,
+    finished: FinishedDownload<'_>,
+) -> Result<AcceptedDownload> {
+    let iso_info = finished.iso_info;
+    let path = finished.output_dir.join(iso_info.local_filename());
+
+    // Distros that sign the ISO itself are checked once it is complete
+    let iso_signature = iso_registry.verify_iso_signature(iso_info, &path).await?;
+    let mut signature = finished.checksum_signature;
+    if let Some(status) = &iso_signature {
+        print_signature(term, iso_info.local_filename(), status)?;
+        if status.is_invalid() {
+            let _ = std::fs::remove_file(&path);
+            bail!(
+                "Removed {}; the ISO may have been tampered with",
+                iso_info.local_filename()
+            );
+        }
+        signature = status.clone();
+    }
+    if let Err(violation) = config_manager
+        .config()
+        .policy
+        .check_signature(&iso_info.filename, &signature)
+    {
+        let _ = std::fs::remove_file(&path);
+        report_policy_violation(term, &violation)?;
+        bail!("Removed {}", iso_info.local_filename());
+    }
+
+    let provenance = finished.provenance.with_iso_signature(iso_signature);
+    let provenance = record_provenance(config_manager, &provenance, finished.output_dir)?;
+
+    let entry = ManifestEntry::new(iso_info, signature)
+        .with_image_checksum(finished.image_checksum, iso_info.checksum_type.clone());
+    let device_manifest = DeviceManifest::locate(finished.output_dir);
+    let entry = match &device_manifest {
+        Some(manifest_path) => {
+            let mut manifest = DeviceManifest::load(manifest_path)?;
+            manifest.record(entry.clone());
+            manifest.save()?;
+            entry
+        }
+        None => {
+            // Library downloads are scrubbed by `isod verify` from their directory
+            let directory = std::path::absolute(finished.output_dir)
+                .unwrap_or_else(|_| finished.output_dir.to_path_buf());
+            let entry = entry.with_directory(directory);
+            let mut manifest =
+                DeviceManifest::load(DeviceManifest::library_in(config_manager.state_dir()))?;
+            manifest.record(entry.clone());
+            manifest.save()?;
+            entry
+        }
+    };
+
+    Ok(AcceptedDownload {
+        provenance,
+        entry,
+        device_manifest,
+    })
+}
+
+/// Report the outcome of a signature check of `what`
+pub fn print_signature(term: &Term, what: &str, status: &SignatureStatus) -> Result<()> {
+    let line = match status {
+        SignatureStatus::Signed { fingerprint } => format!(
+            "{} {} signed by pinned key {}",
+            style("🔏").green(),
+            what,
+            style(fingerprint).green()
+        ),
+        SignatureStatus::Unsigned { reason } => format!(
+            "{} {} is not signature-checked: {}",
+            style("⚠️").yellow(),
+            what,
+            reason
+        ),
+        SignatureStatus::Unverifiable { reason } => format!(
+            "{} {} signature cannot be verified: {}",
+            style("⚠️").yellow(),
+            what,
+            reason
+        ),
+        SignatureStatus::Invalid { reason } => format!(
+            "{} {} has an invalid signature: {}",
+            style("❌").red(),
+            what,
+            reason
+        ),
+    };
+    term.write_line(&line)?;
+    Ok(())
+}
+
+/// Settle the local filename of a download into `directory`, avoiding names already
+/// taken by other releases, and record it in the name map
+pub fn assign_local_filename(
+    config_manager: &ConfigManager,
+    iso_info: &mut IsoInfo,
+    directory: &Path,
+) -> Result<()> {
+    let directory = std::path::absolute(directory).unwrap_or_else(|_| directory.to_path_buf());
+    let mut names = NameMap::load(NameMap::path_in(config_manager.state_dir()))?;
+    names.assign(iso_info, &directory);
+    names.save()
+}
+
+/// Bandwidth policy from config, or a fixed limit when `--limit-rate` is given
+pub fn bandwidth_policy(
+    config_manager: &ConfigManager,
+    limit_rate: Option<&str>,
+) -> Result<BandwidthPolicy> {
+    match limit_rate {
+        Some(rate) => Ok(BandwidthPolicy::fixed(parse_rate(rate)?)),
+        None => BandwidthPolicy::from_config(&config_manager.config().general),
+    }
+}
+
+/// Explain why the security policy refused something
+pub fn report_policy_violation(term: &Term, violation: &PolicyViolation) -> Result<()> {
+    term.write_line(&format!(
+        "{} Blocked by security policy: {}",
+        style("🛡️").red(),
+        violation
+    ))?;
+    Ok(())
+}
 
 /// Check if config validation should be skipped for certain commands
 pub fn should_skip_config_validation(command: &Commands) -> bool {
@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use console::{Term, style};
+use isod::config::ConfigManager;
+use isod::provenance::Provenance;
+use isod::registry::signature::SignatureStatus;
+use std::path::PathBuf;
+use std::process;
+
+pub fn handle_provenance(config_manager: &ConfigManager, file: PathBuf, json: bool) -> Result<()> {
+    let term = Term::stdout();
+    let iso_path = std::path::absolute(&file).unwrap_or(file);
+
+    let Some(path) = Provenance::locate(&iso_path, config_manager.state_dir()) else {
+        term.write_line(&format!(
+            "{} No provenance recorded for {}",
+            style("❌").red(),
+            style(iso_path.display()).cyan()
+        ))?;
+        term.write_line(&format!(
+            "{} Provenance is recorded for ISOs downloaded by isod",
+            style("💡").yellow()
+        ))?;
+        process::exit(1);
+    };
+    let provenance = Provenance::load(&path)?;
+
+    if json {
+        let content =
+            serde_json::to_string_pretty(&provenance).context("Failed to serialize provenance")?;
+        term.write_line(&content)?;
+        return Ok(());
+    }
+
+    let field = |name: &str, value: String| format!("   {}: {}", style(name).dim(), value);
+    let iso = &provenance.iso;
+    let verification = &provenance.verification;
+
+    term.write_line(&format!(
+        "{} Provenance of {}",
+        style("🧾").cyan().bold(),
+        style(iso.local_filename()).cyan().bold()
+    ))?;
+    term.write_line(&field(
+        "Release",
+        format!(
+            "{} {} ({}{})",
+            iso.distro,
+            style(&iso.version).green(),
+            iso.architecture,
+            iso.variant
+                .as_ref()
+                .map(|variant| format!(", {}", variant))
+                .unwrap_or_default()
+        ),
+    ))?;
+    term.write_line(&field(
+        "Size",
+        format!("{:.1} MB", provenance.size_bytes as f64 / (1024.0 * 1024.0)),
+    ))?;
+    term.write_line(&field("Source", provenance.origin.url.clone()))?;
+    if let Some(etag) = &provenance.origin.etag {
+        term.write_line(&field("ETag", etag.clone()))?;
+    }
+    if let Some(last_modified) = &provenance.origin.last_modified {
+        term.write_line(&field("Last-Modified", last_modified.clone()))?;
+    }
+
+    if let (Some(checksum), Some(checksum_type)) = (&iso.checksum, &iso.checksum_type) {
+        term.write_line(&field(
+            "Checksum",
+            format!("{}:{}", checksum_type, style(checksum).dim()),
+        ))?;
+    }
+    if let Some(url) = &verification.checksum_url {
+        term.write_line(&field("Checksum file", url.clone()))?;
+    }
+    term.write_line(&field(
+        "Checksum verified",
+        if verification.checksum_verified {
+            style("yes".to_string()).green().to_string()
+        } else {
+            style("no".to_string()).yellow().to_string()
+        },
+    ))?;
+    term.write_line(&field(
+        "Checksum signature",
+        signature(&verification.checksum_signature),
+    ))?;
+    if let Some(status) = &verification.iso_signature {
+        term.write_line(&field("ISO signature", signature(status)))?;
+    }
+
+    term.write_line(&field(
+        "Downloaded",
+        format!(
+            "{} → {}",
+            provenance.started_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            provenance.completed_at.format("%Y-%m-%d %H:%M:%S UTC")
+        ),
+    ))?;
+    term.write_line(&field(
+        "Recorded by",
+        format!("isod {}", provenance.isod_version),
+    ))?;
+    term.write_line(&field("Record", style(path.display()).dim().to_string()))?;
+
+    Ok(())
+}
+
+fn signature(status: &SignatureStatus) -> String {
+    match status {
+        SignatureStatus::Signed { .. } => style(status.to_string()).green().to_string(),
+        SignatureStatus::Unsigned { .. } | SignatureStatus::Unverifiable { .. } => {
+            style(status.to_string()).yellow().to_string()
+        }
+        SignatureStatus::Invalid { .. } => style(status.to_string()).red().to_string(),
+    }
+}
@@ -0,0 +1,280 @@
+use crate::cli::QueueAction;
+use anyhow::Result;
+use console::{Term, style};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use isod::config::ConfigManager;
+use isod::download::{
+    BandwidthPolicy, DownloadManager, DownloadOptions, DownloadProgress, DownloadQueue, JobState,
+    MirrorHealthStore,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+
+pub async fn handle_queue(config_manager: &ConfigManager, action: QueueAction) -> Result<()> {
+    let term = Term::stdout();
+    let queue_path = DownloadQueue::path_in(config_manager.state_dir());
+
+    match action {
+        QueueAction::List { all } => {
+            let queue = DownloadQueue::load(&queue_path)?;
+            let jobs: Vec<_> = queue
+                .jobs()
+                .into_iter()
+                .filter(|j| all || j.is_pending())
+                .collect();
+
+            if jobs.is_empty() {
+                term.write_line(&format!("{} Download queue is empty", style("📭").dim()))?;
+                return Ok(());
+            }
+
+            term.write_line(&format!("{} Download queue:", style("📋").cyan().bold()))?;
+            for job in jobs {
+                let state = match job.state {
+                    JobState::Queued => style(job.state.to_string()).yellow(),
+                    JobState::Running => style(job.state.to_string()).cyan(),
+                    JobState::Paused => style(job.state.to_string()).dim(),
+                    JobState::Failed => style(job.state.to_string()).red(),
+                    JobState::Done => style(job.state.to_string()).green(),
+                };
+
+                let progress = match (job.resume.bytes_downloaded, job.resume.total_bytes) {
+                    (0, _) => String::new(),
+                    (bytes, Some(total)) if total > 0 => format!(
+                        " {:.1}/{:.1} MB",
+                        bytes as f64 / (1024.0 * 1024.0),
+                        total as f64 / (1024.0 * 1024.0)
+                    ),
+                    (bytes, _) => format!(" {:.1} MB", bytes as f64 / (1024.0 * 1024.0)),
+                };
+
+                term.write_line(&format!(
+                    "  {} [{}] {} (priority {}){}",
+                    style(&job.id).cyan(),
+                    state,
+                    job.iso_info.local_filename(),
+                    job.priority,
+                    style(progress).dim()
+                ))?;
+
+                if let Some(error) = &job.error {
+                    term.write_line(&format!("     {} {}", style("❌").red(), error))?;
+                }
+            }
+        }
+        QueueAction::Pause { id } => {
+            let mut queue = DownloadQueue::load(&queue_path)?;
+            let id = queue.resolve_id(&id)?;
+            queue.pause(&id)?;
+            queue.save()?;
+            term.write_line(&format!(
+                "{} Paused {}",
+                style("⏸️").yellow(),
+                style(&id).cyan()
+            ))?;
+        }
+        QueueAction::Resume { id } => {
+            let mut queue = DownloadQueue::load(&queue_path)?;
+            let id = queue.resolve_id(&id)?;
+            queue.resume(&id)?;
+            queue.save()?;
+            term.write_line(&format!(
+                "{} Queued {} again",
+                style("▶️").green(),
+                style(&id).cyan()
+            ))?;
+            term.write_line(&format!(
+                "{} Use 'isod queue run' to start queued downloads",
+                style("💡").yellow()
+            ))?;
+        }
+        QueueAction::Priority { id, priority } => {
+            let mut queue = DownloadQueue::load(&queue_path)?;
+            let id = queue.resolve_id(&id)?;
+            queue.set_priority(&id, priority)?;
+            queue.save()?;
+            term.write_line(&format!(
+                "{} Priority of {} set to {}",
+                style("✅").green(),
+                style(&id).cyan(),
+                priority
+            ))?;
+        }
+        QueueAction::Cancel { id, delete } => {
+            let mut queue = DownloadQueue::load(&queue_path)?;
+            let job = queue.cancel(&id)?;
+            queue.save()?;
+
+            if delete && job.state != JobState::Done && job.output_path.exists() {
+                std::fs::remove_file(&job.output_path)?;
+                term.write_line(&format!(
+                    "{} Deleted partial file {}",
+                    style("🗑️").dim(),
+                    job.output_path.display()
+                ))?;
+            }
+            term.write_line(&format!(
+                "{} Removed {} from the queue",
+                style("✅").green(),
+                style(&job.id).cyan()
+            ))?;
+        }
+        QueueAction::Clear => {
+            let mut queue = DownloadQueue::load(&queue_path)?;
+            let removed = queue.clear_finished();
+            queue.save()?;
+            term.write_line(&format!(
+                "{} Removed {} finished downloads",
+                style("✅").green(),
+                removed
+            ))?;
+        }
+        QueueAction::Run {
+            max_concurrent,
+            limit_rate,
+        } => {
+            let max_concurrent = max_concurrent
+                .unwrap_or(config_manager.config().general.max_concurrent_downloads)
+                as usize;
+            let bandwidth = super::bandwidth_policy(config_manager, limit_rate.as_deref())?;
+            run_queue(&term, config_manager, queue_path, max_concurrent, bandwidth).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_queue(
+    term: &Term,
+    config_manager: &ConfigManager,
+    queue_path: std::path::PathBuf,
+    max_concurrent: usize,
+    bandwidth: BandwidthPolicy,
+) -> Result<()> {
+    let options = DownloadOptions {
+        max_concurrent,
+        ..Default::default()
+    };
+
+    let (download_manager, mut progress_receiver) = DownloadManager::new(options.clone())?;
+    let config = config_manager.config();
+    let download_manager = download_manager
+        .with_queue(queue_path)
+        .with_bandwidth(bandwidth)
+        .with_mirror_health(MirrorHealthStore::path_in(config_manager.state_dir()))
+        .with_mirror_probe(config.sources.probe_count())
+        .with_consistency_check(config.sources.consistency_count())
+        .with_policy(config.policy.clone());
+
+    let queue = download_manager.queue().await?;
+    let started = download_manager.run_queue(&options).await?;
+
+    if started.is_empty() {
+        term.write_line(&format!("{} No queued downloads", style("📭").dim()))?;
+        return Ok(());
+    }
+
+    term.write_line(&format!(
+        "{} Running {} queued downloads...",
+        style("🚀").green(),
+        started.len()
+    ))?;
+
+    let multi_progress = MultiProgress::new();
+    let mut progress_bars = HashMap::new();
+    for id in &started {
+        let progress_bar = multi_progress.add(ProgressBar::new(100));
+        progress_bar.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:.cyan/blue}] {bytes}/{total_bytes} {msg}")
+                .unwrap()
+                .progress_chars("#>-")
+        );
+        let filename = queue
+            .get(id)
+            .map(|j| j.iso_info.local_filename().to_string())
+            .unwrap_or_else(|| id.clone());
+        progress_bar.set_message(filename);
+        progress_bars.insert(id.clone(), progress_bar);
+    }
+
+    let mut remaining = started.len();
+    let mut succeeded = 0;
+    let mut sync_interval = tokio::time::interval(Duration::from_secs(1));
+
+    while remaining > 0 {
+        let progress = tokio::select! {
+            progress = progress_receiver.recv() => match progress {
+                Some(progress) => progress,
+                None => break,
+            },
+            _ = sync_interval.tick() => {
+                // Pick up pauses and cancellations made from another terminal
+                download_manager.sync_queue().await?;
+                continue;
+            }
+        };
+
+        let Some(progress_bar) = progress_bars.get(progress.id()) else {
+            continue;
+        };
+
+        match &progress {
+            DownloadProgress::Progress {
+                bytes_downloaded,
+                total_bytes,
+                ..
+            } if *total_bytes > 0 => {
+                progress_bar.set_length(*total_bytes);
+                progress_bar.set_position(*bytes_downloaded);
+            }
+            DownloadProgress::DeltaFailed { error, .. } => {
+                progress_bar.set_message(format!(
+                    "Delta transfer failed ({}), downloading in full",
+                    error
+                ));
+            }
+            DownloadProgress::Decompressing { .. } => {
+                progress_bar.set_message("Decompressing...");
+            }
+            DownloadProgress::VerifyingChecksum { .. } => {
+                progress_bar.set_message("Verifying checksum...");
+            }
+            DownloadProgress::Completed {
+                checksum_verified, ..
+            } => {
+                if *checksum_verified {
+                    progress_bar.finish_with_message(format!("{} Done", style("✅").green()));
+                    succeeded += 1;
+                } else {
+                    progress_bar
+                        .finish_with_message(format!("{} Checksum mismatch", style("❌").red()));
+                }
+                remaining -= 1;
+            }
+            DownloadProgress::Failed { error, class, .. } => {
+                progress_bar.finish_with_message(format!(
+                    "{} Failed ({}): {}",
+                    style("❌").red(),
+                    class,
+                    error
+                ));
+                remaining -= 1;
+            }
+            DownloadProgress::Cancelled { .. } => {
+                progress_bar.abandon_with_message(format!("{} Stopped", style("⏸️").yellow()));
+                remaining -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    term.write_line(&format!(
+        "{} {}/{} queued downloads completed",
+        style("✅").green(),
+        succeeded,
+        started.len()
+    ))?;
+
+    Ok(())
+}
@@ -1,17 +1,40 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::Utc;
 use console::{Term, style};
 use dialoguer::Select;
 use isod::config::ConfigManager;
+use isod::download::{
+    ChecksumType, ChecksumVerifier, DownloadManager, DownloadOptions, DownloadProgress,
+    DownloadQueue, MirrorHealthStore,
+};
+use isod::image::ImageType;
+use isod::iso9660::IsoImage;
+use isod::lock::Lockfile;
+use isod::manifest::{DeviceManifest, ManifestEntry};
+use isod::naming;
+use isod::provenance::{DownloadOrigin, Provenance};
+use isod::registry::IsoRegistry;
+use isod::registry::signature::SignatureStatus;
 use isod::usb::UsbManager;
+use isod::verify::VerifyStatus;
+use std::collections::HashMap;
+use std::path::Path;
 use std::process;
 
+/// What `isod sync` does besides showing the device
+pub struct SyncOptions {
+    pub mount_point: Option<String>,
+    pub auto_select: bool,
+    pub verify_checksums: bool,
+    pub download_missing: bool,
+    pub locked: bool,
+}
+
 pub async fn handle_sync(
-    _config_manager: &ConfigManager,
+    config_manager: &ConfigManager,
     usb_manager: &mut UsbManager,
-    _mount_point: Option<String>,
-    auto_select: bool,
-    verify_checksums: bool,
-    download_missing: bool,
+    iso_registry: &IsoRegistry,
+    options: SyncOptions,
 ) -> Result<()> {
     let term = Term::stdout();
     term.write_line(&format!(
@@ -38,7 +61,20 @@ pub async fn handle_sync(
     }
 
     // Select device
-    let selected_device = if ventoy_devices.len() == 1 || auto_select {
+    let selected_device = if let Some(mount_point) = &options.mount_point {
+        let Some(device) = ventoy_devices
+            .iter()
+            .find(|device| device.mount_point.as_deref() == Some(Path::new(mount_point)))
+        else {
+            term.write_line(&format!(
+                "{} No Ventoy device is mounted at {}",
+                style("❌").red(),
+                mount_point
+            ))?;
+            process::exit(1);
+        };
+        device
+    } else if ventoy_devices.len() == 1 || options.auto_select {
         &ventoy_devices[0]
     } else {
         term.write_line(&format!(
@@ -116,14 +152,58 @@ pub async fn handle_sync(
         available_space as f64 / (1024.0 * 1024.0 * 1024.0)
     ))?;
 
-    if verify_checksums {
-        term.write_line(&format!(
-            "{} TODO: Implement checksum verification",
-            style("🔍").yellow()
-        ))?;
+    let manifest = DeviceManifest::load(DeviceManifest::path_in(&metadata_dir))?;
+    if !manifest.entries().is_empty() {
+        term.write_line(&format!("{} ISOs on device:", style("📜").cyan()))?;
+        for entry in manifest.entries() {
+            let signature = match &entry.signature {
+                SignatureStatus::Signed { .. } => style(entry.signature.to_string()).green(),
+                SignatureStatus::Unsigned { .. } | SignatureStatus::Unverifiable { .. } => {
+                    style(entry.signature.to_string()).yellow()
+                }
+                SignatureStatus::Invalid { .. } => style(entry.signature.to_string()).red(),
+            };
+            term.write_line(&format!(
+                "   {} {} [{}]",
+                style(&entry.filename).cyan(),
+                style(&entry.version).dim(),
+                signature
+            ))?;
+        }
+    }
+
+    let mut verify_status = VerifyStatus::Ok;
+    if options.verify_checksums
+        && let Some(mount_point) = &selected_device.mount_point
+    {
+        let items = super::verify::device_items(mount_point)?;
+        verify_status = super::verify::scrub_and_report(&term, "device", items, 2).await?;
+    }
+
+    let mut locked_failures = 0;
+    if options.locked {
+        let lockfile = Lockfile::load(Lockfile::path_in(config_manager.config_dir()))?;
+        if lockfile.entries().is_empty() {
+            term.write_line(&format!(
+                "{} No locked ISOs in {}, run 'isod update' first",
+                style("❌").red(),
+                lockfile.path().display()
+            ))?;
+            process::exit(1);
+        }
+        let iso_dir = usb_manager.get_iso_directory().await?;
+        locked_failures = sync_locked(
+            &term,
+            config_manager,
+            iso_registry,
+            &lockfile,
+            &iso_dir,
+            &DeviceManifest::path_in(&metadata_dir),
+        )
+        .await?;
     }
 
-    if download_missing {
+    if options.download_missing {
         term.write_line(&format!(
             "{} TODO: Implement missing ISO download",
             style("⬇️").yellow()
@@ -131,5 +211,240 @@ pub async fn handle_sync(
     }
 
     term.write_line(&format!("{} USB sync complete", style("✅").green()))?;
+    if verify_status != VerifyStatus::Ok {
+        process::exit(verify_status.exit_code());
+    }
+    if locked_failures > 0 {
+        process::exit(1);
+    }
     Ok(())
 }
+
+/// Download every locked ISO that is missing from the device or differs from the
+/// lock, returning how many could not be put in place
+async fn sync_locked(
+    term: &Term,
+    config_manager: &ConfigManager,
+    iso_registry: &IsoRegistry,
+    lockfile: &Lockfile,
+    iso_dir: &Path,
+    manifest_path: &Path,
+) -> Result<usize> {
+    term.write_line(&format!(
+        "{} Reproducing {} locked ISOs from {}",
+        style("🔒").cyan(),
+        lockfile.entries().len(),
+        style(lockfile.path().display()).dim()
+    ))?;
+
+    let policy = &config_manager.config().policy;
+    let naming_template = &config_manager.config().general.naming_template;
+    let mut manifest = DeviceManifest::load(manifest_path)?;
+    let mut pending = Vec::new();
+    let mut failures = 0;
+    for entry in lockfile.entries() {
+        // The lock is all sync has to go on, so it must satisfy the policy by itself
+        if let Err(violation) = policy
+            .check_distro(&entry.distro)
+            .and_then(|()| policy.check_checksum(&entry.filename, entry.checksum.as_deref()))
+            .and_then(|()| policy.check_signature(&entry.filename, &entry.signature()))
+        {
+            super::report_policy_violation(term, &violation)?;
+            failures += 1;
+            continue;
+        }
+
+        // The lock only pins upstream names; local names follow this machine's template
+        let mut iso_info = entry.iso_info();
+        iso_info.local_filename = Some(naming::render(naming_template, &iso_info));
+        super::assign_local_filename(config_manager, &mut iso_info, iso_dir)?;
+
+        let path = iso_dir.join(iso_info.local_filename());
+        if path.is_file() {
+            // A checksum of a compressed download cannot be compared with the unpacked image
+            let comparable =
+                !(iso_info.format.is_compressed() && entry.checksum_layer.is_download());
+            let recorded = manifest.entries().iter().any(|m| {
+                m.directory.is_none()
+                    && m.filename == iso_info.local_filename()
+                    && if comparable {
+                        entry.checksum.is_some() && m.checksum == entry.checksum
+                    } else {
+                        m.version == entry.version
+                    }
+            });
+            let matches = recorded
+                || match (&entry.checksum, &entry.checksum_type) {
+                    _ if !comparable => false,
+                    (Some(checksum), Some(checksum_type)) => {
+                        match ChecksumType::from_algorithm(checksum_type) {
+                            Some(checksum_type) => {
+                                ChecksumVerifier::verify_file(&path, checksum, checksum_type)
+                                    .await?
+                            }
+                            None => false,
+                        }
+                    }
+                    // Nothing to compare with, so only make sure the copy is complete
+                    _ if iso_info.format.image_type == ImageType::Iso => {
+                        IsoImage::open(&path).is_ok_and(|iso| !iso.is_truncated())
+                    }
+                    _ => true,
+                };
+
+            if matches {
+                if !recorded {
+                    manifest.record(ManifestEntry::new(&iso_info, entry.signature()));
+                }
+                term.write_line(&format!(
+                    "   {} {} is up to date",
+                    style("✅").green(),
+                    style(iso_info.local_filename()).cyan()
+                ))?;
+                continue;
+            }
+
+            term.write_line(&format!(
+                "   {} {} differs from the lock, replacing it",
+                style("♻️").yellow(),
+                style(iso_info.local_filename()).cyan()
+            ))?;
+            std::fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+        }
+        pending.push((entry, iso_info));
+    }
+
+    if !pending.is_empty() {
+        let options = DownloadOptions {
+            max_concurrent: config_manager.config().general.max_concurrent_downloads as usize,
+            prefer_torrents: config_manager.config().general.prefer_torrents,
+            output_directory: iso_dir.to_path_buf(),
+            verify_checksums: true,
+            resume_downloads: true,
+        };
+        let (download_manager, mut progress_receiver) = DownloadManager::new(options.clone())?;
+        let download_manager = download_manager
+            .with_queue(DownloadQueue::path_in(config_manager.state_dir()))
+            .with_bandwidth(super::bandwidth_policy(config_manager, None)?)
+            .with_mirror_health(MirrorHealthStore::path_in(config_manager.state_dir()))
+            .with_mirror_probe(config_manager.config().sources.probe_count())
+            .with_consistency_check(config_manager.config().sources.consistency_count())
+            .with_policy(config_manager.config().policy.clone());
+
+        let mut active = HashMap::new();
+        let started_at = Utc::now();
+        for (entry, iso_info) in pending {
+            term.write_line(&format!(
+                "   {} Downloading {}",
+                style("⬇️").cyan(),
+                style(iso_info.local_filename()).cyan()
+            ))?;
+            let id = download_manager.download_iso(&iso_info, &options).await?;
+            active.insert(id, (entry, iso_info));
+        }
+
+        while !active.is_empty() {
+            let Some(progress) = progress_receiver.recv().await else {
+                break;
+            };
+            match &progress {
+                DownloadProgress::Completed {
+                    bytes_downloaded,
+                    checksum_verified,
+                    url,
+                    etag,
+                    last_modified,
+                    image_checksum,
+                    ..
+                } => {
+                    if let Some((entry, iso_info)) = active.remove(progress.id()) {
+                        let provenance = Provenance::new(
+                            &iso_info,
+                            DownloadOrigin {
+                                url: url.clone(),
+                                etag: etag.clone(),
+                                last_modified: last_modified.clone(),
+                            },
+                            *bytes_downloaded,
+                            started_at,
+                        )
+                        .with_checksum(
+                            entry.checksum_url.clone(),
+                            *checksum_verified,
+                            entry.signature(),
+                        );
+                        let finished = super::finish_download(
+                            term,
+                            config_manager,
+                            iso_registry,
+                            super::FinishedDownload {
+                                iso_info: &iso_info,
+                                output_dir: iso_dir,
+                                checksum_signature: entry.signature(),
+                                provenance,
+                                image_checksum: image_checksum.clone(),
+                            },
+                        )
+                        .await;
+                        match finished {
+                            Ok(accepted) => {
+                                // Already saved; kept here so the final save does not drop it
+                                manifest.record(accepted.entry);
+                                term.write_line(&format!(
+                                    "   {} {}",
+                                    style("✅").green(),
+                                    style(iso_info.local_filename()).cyan()
+                                ))?;
+                            }
+                            Err(e) => {
+                                failures += 1;
+                                term.write_line(&format!("   {} {:#}", style("❌").red(), e))?;
+                            }
+                        }
+                    }
+                }
+                DownloadProgress::Failed { error, .. } => {
+                    if let Some((_, iso_info)) = active.remove(progress.id()) {
+                        failures += 1;
+                        term.write_line(&format!(
+                            "   {} {}: {}",
+                            style("❌").red(),
+                            style(iso_info.local_filename()).cyan(),
+                            error
+                        ))?;
+                    }
+                }
+                DownloadProgress::Cancelled { .. } => {
+                    if let Some((_, iso_info)) = active.remove(progress.id()) {
+                        failures += 1;
+                        term.write_line(&format!(
+                            "   {} {} cancelled",
+                            style("⏸️").yellow(),
+                            style(iso_info.local_filename()).cyan()
+                        ))?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        failures += active.len();
+    }
+    manifest.save()?;
+
+    // Unlocked ISOs are left alone; removing them is up to the user
+    for entry in manifest.entries() {
+        if !lockfile
+            .entries()
+            .iter()
+            .any(|locked| locked.filename == entry.iso_info().filename)
+        {
+            term.write_line(&format!(
+                "   {} {} is not in the lockfile (remove it with 'isod remove')",
+                style("⚠️").yellow(),
+                style(&entry.filename).cyan()
+            ))?;
+        }
+    }
+
+    Ok(failures)
+}
@@ -1,8 +1,14 @@
 use anyhow::Result;
+use chrono::Utc;
 use console::{Term, style};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use isod::config::ConfigManager;
-use isod::download::{DownloadManager, DownloadOptions, DownloadProgress};
+use isod::download::{
+    BandwidthPolicy, DownloadManager, DownloadOptions, DownloadProgress, DownloadQueue,
+    MirrorHealthStore,
+};
+use isod::lock::{self, Lockfile};
+use isod::provenance::{DownloadOrigin, Provenance};
 use isod::registry::{IsoRegistry, ReleaseType};
 use std::collections::HashMap;
 use std::process;
@@ -15,7 +21,10 @@ pub async fn handle_update(
     force: bool,
     check_only: bool,
     include_beta: bool,
+    limit_rate: Option<String>,
 ) -> Result<()> {
+    let bandwidth = super::bandwidth_policy(config_manager, limit_rate.as_deref())?;
+
     match distro {
         Some(d) => {
             let distro_str = d.to_string_lossy();
@@ -26,6 +35,7 @@ pub async fn handle_update(
                 force,
                 check_only,
                 include_beta,
+                &bandwidth,
             )
             .await
         }
@@ -36,6 +46,7 @@ pub async fn handle_update(
                 force,
                 check_only,
                 include_beta,
+                &bandwidth,
             )
             .await
         }
@@ -49,6 +60,7 @@ async fn update_single_distro(
     force: bool,
     check_only: bool,
     include_beta: bool,
+    bandwidth: &BandwidthPolicy,
 ) -> Result<()> {
     let term = Term::stdout();
 
@@ -153,21 +165,30 @@ async fn update_single_distro(
 
             let (download_manager, mut progress_receiver) =
                 DownloadManager::new(download_options.clone())?;
+            let download_manager = download_manager
+                .with_queue(DownloadQueue::path_in(config_manager.state_dir()))
+                .with_bandwidth(bandwidth.clone())
+                .with_mirror_health(MirrorHealthStore::path_in(config_manager.state_dir()))
+                .with_mirror_probe(config_manager.config().sources.probe_count())
+                .with_consistency_check(config_manager.config().sources.consistency_count())
+                .with_policy(config_manager.config().policy.clone());
             let multi_progress = MultiProgress::new();
             let mut active_downloads = HashMap::new();
+            let started_at = Utc::now();
+            let mut lockfile = Lockfile::load(Lockfile::path_in(config_manager.config_dir()))?;
 
             for arch in &config.architectures {
                 for variant in &config.variants {
-                    let iso_info = match iso_registry
-                        .get_iso_info(
-                            distro,
-                            Some(&version_info.version),
-                            Some(arch),
-                            Some(variant),
-                        )
-                        .await
+                    let (mut iso_info, entry) = match lock::resolve(
+                        iso_registry,
+                        distro,
+                        &version_info.version,
+                        arch,
+                        variant,
+                    )
+                    .await
                     {
-                        Ok(info) => info,
+                        Ok(resolved) => resolved,
                         Err(e) => {
                             term.write_line(&format!(
                                 "{} Skipping {}-{}-{}: {}",
@@ -181,6 +202,11 @@ async fn update_single_distro(
                         }
                     };
 
+                    super::assign_local_filename(
+                        config_manager,
+                        &mut iso_info,
+                        &download_options.output_directory,
+                    )?;
                     let download_id = download_manager
                         .download_iso(&iso_info, &download_options)
                         .await?;
@@ -194,7 +220,7 @@ async fn update_single_distro(
                     );
                     progress_bar.set_message(format!("{}-{}-{}", distro, arch, variant));
 
-                    active_downloads.insert(download_id, (progress_bar, iso_info));
+                    active_downloads.insert(download_id, (progress_bar, iso_info, entry));
                 }
             }
 
@@ -214,21 +240,7 @@ async fn update_single_distro(
             let total_downloads = active_downloads.len();
 
             while let Some(progress) = progress_receiver.recv().await {
-                if let Some((progress_bar, iso_info)) = active_downloads.get(
-                    &match &progress {
-                        DownloadProgress::Started { id, .. } => id,
-                        DownloadProgress::Progress { id, .. } => id,
-                        DownloadProgress::VerifyingChecksum { id } => id,
-                        DownloadProgress::ChecksumVerified { id } => id,
-                        DownloadProgress::ChecksumFailed { id, .. } => id,
-                        DownloadProgress::Completed { id, .. } => id,
-                        DownloadProgress::Failed { id, .. } => id,
-                        DownloadProgress::Retry { id, .. } => id,
-                        DownloadProgress::Cancelled { id } => id,
-                        DownloadProgress::Error { id, .. } => id,
-                    }
-                    .clone(),
-                ) {
+                if let Some((progress_bar, iso_info, entry)) = active_downloads.get(progress.id()) {
                     match &progress {
                         DownloadProgress::Progress {
                             bytes_downloaded,
@@ -240,25 +252,89 @@ async fn update_single_distro(
                                 progress_bar.set_position(*bytes_downloaded);
                             }
                         }
+                        DownloadProgress::DeltaFailed { error, .. } => {
+                            progress_bar.set_message(format!(
+                                "Delta transfer failed ({}), downloading in full",
+                                error
+                            ));
+                        }
+                        DownloadProgress::Decompressing { .. } => {
+                            progress_bar.set_message("Decompressing...");
+                        }
                         DownloadProgress::VerifyingChecksum { .. } => {
                             progress_bar.set_message("Verifying checksum...");
                         }
-                        DownloadProgress::Completed { .. } => {
-                            progress_bar.finish_with_message(format!(
-                                "{} {}",
-                                style("✅").green(),
-                                iso_info.filename
-                            ));
+                        DownloadProgress::Completed {
+                            bytes_downloaded,
+                            checksum_verified,
+                            url,
+                            etag,
+                            last_modified,
+                            image_checksum,
+                            ..
+                        } => {
+                            let provenance = Provenance::new(
+                                iso_info,
+                                DownloadOrigin {
+                                    url: url.clone(),
+                                    etag: etag.clone(),
+                                    last_modified: last_modified.clone(),
+                                },
+                                *bytes_downloaded,
+                                started_at,
+                            )
+                            .with_checksum(
+                                entry.checksum_url.clone(),
+                                *checksum_verified,
+                                entry.signature(),
+                            );
+                            let finished = super::finish_download(
+                                &term,
+                                config_manager,
+                                iso_registry,
+                                super::FinishedDownload {
+                                    iso_info,
+                                    output_dir: &download_options.output_directory,
+                                    checksum_signature: entry.signature(),
+                                    provenance,
+                                    image_checksum: image_checksum.clone(),
+                                },
+                            )
+                            .await;
+                            match finished {
+                                Ok(_) => {
+                                    // Only images that made it to disk are pinned
+                                    lockfile.record(entry.clone());
+                                    progress_bar.finish_with_message(format!(
+                                        "{} {}",
+                                        style("✅").green(),
+                                        iso_info.local_filename()
+                                    ))
+                                }
+                                Err(e) => progress_bar.finish_with_message(format!(
+                                    "{} {:#}",
+                                    style("❌").red(),
+                                    e
+                                )),
+                            }
                             completed_downloads += 1;
                         }
-                        DownloadProgress::Failed { error, .. } => {
+                        DownloadProgress::Failed { error, class, .. } => {
                             progress_bar.finish_with_message(format!(
-                                "{} Failed: {}",
+                                "{} Failed ({}): {}",
                                 style("❌").red(),
+                                class,
                                 error
                             ));
                             completed_downloads += 1;
                         }
+                        DownloadProgress::Cancelled { .. } => {
+                            progress_bar.abandon_with_message(format!(
+                                "{} Cancelled",
+                                style("⏸️").yellow()
+                            ));
+                            completed_downloads += 1;
+                        }
                         _ => {}
                     }
                 }
@@ -268,6 +344,14 @@ async fn update_single_distro(
                 }
             }
 
+            lockfile.retain_configured(config_manager.config());
+            lockfile.save()?;
+            term.write_line(&format!(
+                "{} Lockfile updated: {}",
+                style("🔒").cyan(),
+                style(lockfile.path().display()).dim()
+            ))?;
+
             term.write_line(&format!(
                 "{} Update complete for {}",
                 style("✅").green(),
@@ -295,6 +379,7 @@ async fn update_all_distros(
     force: bool,
     check_only: bool,
     include_beta: bool,
+    bandwidth: &BandwidthPolicy,
 ) -> Result<()> {
     let term = Term::stdout();
 
@@ -335,6 +420,7 @@ async fn update_all_distros(
             force,
             check_only,
             include_beta,
+            bandwidth,
         )
         .await
         {
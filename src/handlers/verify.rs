@@ -0,0 +1,212 @@
+use anyhow::Result;
+use console::{Term, style};
+use isod::config::ConfigManager;
+use isod::manifest::DeviceManifest;
+use isod::registry::IsoRegistry;
+use isod::usb::UsbManager;
+use isod::verify::{self, ExpectedChecksum, ScrubItem, VerifyStatus};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// What `isod verify` scrubs and how
+pub struct VerifyOptions {
+    pub distro: Option<String>,
+    pub mount_point: Option<String>,
+    pub device_only: bool,
+    pub library_only: bool,
+    pub upstream: bool,
+    pub jobs: usize,
+}
+
+pub async fn handle_verify(
+    config_manager: &ConfigManager,
+    usb_manager: &UsbManager,
+    iso_registry: &IsoRegistry,
+    options: VerifyOptions,
+) -> Result<()> {
+    let VerifyOptions {
+        distro,
+        mount_point,
+        device_only,
+        library_only,
+        upstream,
+        jobs,
+    } = options;
+    let term = Term::stdout();
+    let mut groups: Vec<(String, Vec<ScrubItem>)> = Vec::new();
+
+    if !library_only {
+        let mount_points: Vec<PathBuf> = match mount_point {
+            Some(mount_point) => vec![PathBuf::from(mount_point)],
+            None => usb_manager
+                .find_ventoy_devices()
+                .await?
+                .into_iter()
+                .filter_map(|device| device.mount_point)
+                .collect(),
+        };
+        if mount_points.is_empty() && device_only {
+            term.write_line(&format!(
+                "{} No mounted Ventoy devices found.",
+                style("❌").red()
+            ))?;
+            process::exit(VerifyStatus::Missing.exit_code());
+        }
+        for mount_point in mount_points {
+            let items = device_items(&mount_point)?;
+            groups.push((format!("device {}", mount_point.display()), items));
+        }
+    }
+
+    if !device_only {
+        let manifest =
+            DeviceManifest::load(DeviceManifest::library_in(config_manager.state_dir()))?;
+        let directories: BTreeSet<PathBuf> = manifest
+            .entries()
+            .iter()
+            .filter_map(|entry| entry.directory.clone())
+            .collect();
+        let files = directories
+            .iter()
+            .flat_map(|dir| verify::find_isos(dir, false))
+            .collect();
+        let current_dir = std::env::current_dir()?;
+        groups.push((
+            "library".to_string(),
+            verify::plan(files, &manifest, &current_dir),
+        ));
+    }
+
+    if let Some(distro) = &distro {
+        for (_, items) in &mut groups {
+            items.retain(|item| item.matches_distro(distro));
+        }
+    }
+
+    if upstream {
+        term.write_line(&format!(
+            "{} Fetching upstream checksums...",
+            style("🌐").cyan()
+        ))?;
+        for (_, items) in &mut groups {
+            add_upstream_checksums(&term, iso_registry, items).await?;
+        }
+    }
+
+    let mut worst = VerifyStatus::Ok;
+    let mut total = 0;
+    for (label, items) in groups {
+        total += items.len();
+        worst = worst.max(scrub_and_report(&term, &label, items, jobs).await?);
+    }
+
+    if total == 0 {
+        term.write_line(&format!("{} No ISOs to verify", style("📭").dim()))?;
+    } else if worst == VerifyStatus::Ok {
+        term.write_line(&format!(
+            "{} All {} ISOs verified",
+            style("✅").green(),
+            total
+        ))?;
+    }
+
+    if worst != VerifyStatus::Ok {
+        process::exit(worst.exit_code());
+    }
+    Ok(())
+}
+
+/// Every ISO on the device mounted at `mount_point`, paired with its manifest entry
+pub fn device_items(mount_point: &Path) -> Result<Vec<ScrubItem>> {
+    let manifest = DeviceManifest::load(DeviceManifest::on_device(mount_point))?;
+    Ok(verify::plan(
+        verify::find_isos(mount_point, true),
+        &manifest,
+        &mount_point.join("iso"),
+    ))
+}
+
+/// Hash `items`, print one line per file and return the worst status
+pub async fn scrub_and_report(
+    term: &Term,
+    label: &str,
+    items: Vec<ScrubItem>,
+    jobs: usize,
+) -> Result<VerifyStatus> {
+    if items.is_empty() {
+        return Ok(VerifyStatus::Ok);
+    }
+
+    term.write_line(&format!(
+        "{} Verifying {} ISOs in {}...",
+        style("🔍").cyan(),
+        items.len(),
+        style(label).cyan()
+    ))?;
+
+    let results = verify::scrub(items, jobs).await;
+    for result in &results {
+        let status = match result.status {
+            VerifyStatus::Ok => style(format!("{:<8}", result.status)).green(),
+            VerifyStatus::Unknown => style(format!("{:<8}", result.status)).yellow(),
+            VerifyStatus::Missing | VerifyStatus::Mismatch => {
+                style(format!("{:<8}", result.status)).red()
+            }
+        };
+        let detail = result
+            .detail
+            .as_ref()
+            .map(|detail| format!(" ({})", style(detail).dim()))
+            .unwrap_or_default();
+        term.write_line(&format!(
+            "   {} {}{}",
+            status,
+            result.path.display(),
+            detail
+        ))?;
+    }
+
+    Ok(verify::worst(&results))
+}
+
+/// Add the currently published checksum of every tracked item
+async fn add_upstream_checksums(
+    term: &Term,
+    iso_registry: &IsoRegistry,
+    items: &mut [ScrubItem],
+) -> Result<()> {
+    for item in items {
+        let Some(entry) = &item.entry else {
+            continue;
+        };
+
+        let iso_info = entry.iso_info();
+        match iso_registry.get_signed_checksum(&iso_info).await {
+            Ok(Some(checksum))
+                if iso_info.format.is_compressed() && checksum.layer.is_download() =>
+            {
+                term.write_line(&format!(
+                    "{} Upstream only publishes a checksum of the compressed {}",
+                    style("⚠️").yellow(),
+                    iso_info.filename
+                ))?;
+            }
+            Ok(Some(checksum)) => item.expect(ExpectedChecksum {
+                checksum_type: checksum.checksum_type,
+                value: checksum.value,
+                source: "upstream",
+            }),
+            Ok(None) => {}
+            Err(e) => {
+                term.write_line(&format!(
+                    "{} No upstream checksum for {}: {}",
+                    style("⚠️").yellow(),
+                    entry.filename,
+                    e
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
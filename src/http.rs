@@ -0,0 +1,169 @@
+use anyhow::{Context, Result, anyhow};
+use reqwest::{Certificate, Client, NoProxy, Proxy, RequestBuilder, Url};
+use std::fs;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::credentials::{Credential, CredentialStore};
+
+static CONTEXT: OnceLock<HttpContext> = OnceLock::new();
+
+/// Default user agent, e.g. "isod/0.1.0"
+pub fn user_agent() -> String {
+    format!("isod/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Configure the process-wide HTTP context; must run before any network access
+pub fn init(config: &Config) -> Result<()> {
+    let context = HttpContext::from_config(config)?;
+    CONTEXT
+        .set(context)
+        .map_err(|_| anyhow!("HTTP context is already initialized"))
+}
+
+/// Shared HTTP context, falling back to defaults when `init` was never called
+pub fn context() -> &'static HttpContext {
+    CONTEXT.get_or_init(HttpContext::default)
+}
+
+/// HTTP client with the timeouts, proxies and certificates from `[network]`,
+/// plus per-host credentials.
+///
+/// The client itself carries no total timeout so multi-gigabyte transfers are
+/// only bounded by the connect and idle-read timeouts. Small metadata requests
+/// should go through [`HttpContext::get`] which adds the total request timeout.
+#[derive(Debug, Clone)]
+pub struct HttpContext {
+    client: Client,
+    credentials: Arc<CredentialStore>,
+    request_timeout: Duration,
+    read_timeout: Duration,
+    mirror_timeout: Duration,
+}
+
+impl HttpContext {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let network = &config.network;
+
+        let mut builder = Client::builder()
+            .connect_timeout(Duration::from_secs(network.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(network.read_timeout_secs))
+            .user_agent(network.user_agent.clone().unwrap_or_else(user_agent));
+
+        // Explicit proxies replace the HTTP_PROXY/HTTPS_PROXY/ALL_PROXY environment
+        let no_proxy = network.no_proxy.as_deref().and_then(NoProxy::from_string);
+        let proxies = [
+            network
+                .http_proxy
+                .as_deref()
+                .map(|url| (url, Proxy::http(url))),
+            network
+                .https_proxy
+                .as_deref()
+                .map(|url| (url, Proxy::https(url))),
+            network.proxy.as_deref().map(|url| (url, Proxy::all(url))),
+        ];
+        for (url, proxy) in proxies.into_iter().flatten() {
+            let proxy = proxy.with_context(|| format!("Invalid proxy URL: '{}'", url))?;
+            builder = builder.proxy(proxy.no_proxy(no_proxy.clone()));
+        }
+
+        for path in &network.ca_certificates {
+            let pem = fs::read(path)
+                .with_context(|| format!("Failed to read CA certificate: {:?}", path))?;
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("Invalid PEM certificate bundle: {:?}", path))?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        let client = builder.build().context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            client,
+            credentials: Arc::new(CredentialStore::from_config(config)),
+            request_timeout: Duration::from_secs(network.request_timeout_secs),
+            read_timeout: Duration::from_secs(network.read_timeout_secs),
+            mirror_timeout: Duration::from_secs(config.sources.mirror_timeout_secs),
+        })
+    }
+
+    /// Underlying client, without credentials or a total timeout
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// GET a small document, bounded by the total request timeout
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.get(url), url)
+            .timeout(self.request_timeout)
+    }
+
+    /// HEAD request bounded by the total request timeout
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.head(url), url)
+            .timeout(self.request_timeout)
+    }
+
+    /// GET for bulk transfers such as ISO images, without a total timeout
+    pub fn transfer(&self, url: &str) -> RequestBuilder {
+        self.authorize(self.client.get(url), url)
+    }
+
+    /// Stored credential for `url`, for protocols other than HTTP
+    pub fn credential(&self, url: &Url) -> Option<Credential> {
+        self.credentials.for_url(url)
+    }
+
+    fn authorize(&self, request: RequestBuilder, url: &str) -> RequestBuilder {
+        match Url::parse(url)
+            .ok()
+            .and_then(|url| self.credentials.for_url(&url))
+        {
+            Some(credential) => credential.apply(request),
+            None => request,
+        }
+    }
+
+    /// Total timeout for requests that only query a mirror (metalink, mirror lists)
+    pub fn mirror_timeout(&self) -> Duration {
+        self.mirror_timeout
+    }
+
+    /// Longest a transfer may stall before it is abandoned
+    pub fn read_timeout(&self) -> Duration {
+        self.read_timeout
+    }
+}
+
+impl Default for HttpContext {
+    fn default() -> Self {
+        Self::from_config(&Config::default()).expect("Failed to create default HTTP context")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_context_from_config() {
+        let mut config = Config::default();
+        config.network.proxy = Some("socks5h://127.0.0.1:1080".to_string());
+        config.network.https_proxy = Some("http://proxy.example.org:3128".to_string());
+        config.network.no_proxy = Some("localhost,.internal".to_string());
+        config.sources.mirror_timeout_secs = 5;
+
+        let context = HttpContext::from_config(&config).unwrap();
+        assert_eq!(context.mirror_timeout(), Duration::from_secs(5));
+
+        config.network.proxy = Some("not a url".to_string());
+        assert!(HttpContext::from_config(&config).is_err());
+
+        config.network.proxy = None;
+        config.network.ca_certificates = vec!["/nonexistent/ca.pem".into()];
+        assert!(HttpContext::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
+
+use crate::download::checksum::{ChecksumType, ChecksumWriter};
+
+/// Kind of bootable image, by the extension Ventoy recognises it with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageType {
+    #[default]
+    Iso,
+    Img,
+    Wim,
+    Vhd,
+    Vhdx,
+    Efi,
+}
+
+impl ImageType {
+    const ALL: [ImageType; 6] = [
+        ImageType::Iso,
+        ImageType::Img,
+        ImageType::Wim,
+        ImageType::Vhd,
+        ImageType::Vhdx,
+        ImageType::Efi,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageType::Iso => "iso",
+            ImageType::Img => "img",
+            ImageType::Wim => "wim",
+            ImageType::Vhd => "vhd",
+            ImageType::Vhdx => "vhdx",
+            ImageType::Efi => "efi",
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|image_type| extension.eq_ignore_ascii_case(image_type.extension()))
+    }
+}
+
+/// Compression an image is published with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    const ALL: [Compression; 3] = [Compression::Gzip, Compression::Xz, Compression::Zstd];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|compression| extension.eq_ignore_ascii_case(compression.extension()))
+    }
+
+    /// Decompress all of `input` into `output`
+    fn decode(&self, input: &mut impl BufRead, output: &mut impl Write) -> Result<()> {
+        match self {
+            Compression::Gzip => {
+                std::io::copy(&mut flate2::bufread::MultiGzDecoder::new(input), output)?;
+            }
+            Compression::Xz => lzma_rs::xz_decompress(input, output)
+                .map_err(|e| anyhow::anyhow!("Invalid xz data: {:?}", e))?,
+            Compression::Zstd => {
+                let mut decoder = ruzstd::decoding::StreamingDecoder::new(input)
+                    .map_err(|e| anyhow::anyhow!("Invalid zstd data: {}", e))?;
+                std::io::copy(&mut decoder, output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How an image is published: its type and, for `.iso.xz` and the like, its compression
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageFormat {
+    pub image_type: ImageType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+}
+
+impl ImageFormat {
+    /// Format of a file named `filename`, if it is an image Ventoy boots or a compressed one
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        let mut parts = filename.rsplit('.');
+        let last = parts.next()?;
+        let compression = Compression::from_extension(last);
+        let extension = match compression {
+            Some(_) => parts.next()?,
+            None => last,
+        };
+        Some(Self {
+            image_type: ImageType::from_extension(extension)?,
+            compression,
+        })
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compression.is_some()
+    }
+
+    /// `name` with the extension of the image as it is stored locally: any compression
+    /// extension is dropped and `.iso` from a naming template becomes the real type
+    pub fn local_filename(&self, name: &str) -> String {
+        let mut name = name.to_string();
+        if let Some((stem, extension)) = name.rsplit_once('.')
+            && Compression::from_extension(extension).is_some()
+        {
+            name = stem.to_string();
+        }
+
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension);
+        match extension.and_then(ImageType::from_extension) {
+            Some(image_type) if image_type == self.image_type => name,
+            Some(ImageType::Iso) => format!(
+                "{}.{}",
+                &name[..name.len() - ".iso".len()],
+                self.image_type.extension()
+            ),
+            _ => format!("{}.{}", name, self.image_type.extension()),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.compression {
+            Some(compression) => f.pad(&format!(
+                "{}.{}",
+                self.image_type.extension(),
+                compression.extension()
+            )),
+            None => f.pad(self.image_type.extension()),
+        }
+    }
+}
+
+/// Which bytes a published checksum covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumLayer {
+    /// The file as downloaded, compressed or not
+    #[default]
+    Download,
+    /// The decompressed image
+    Image,
+}
+
+impl ChecksumLayer {
+    pub fn is_download(&self) -> bool {
+        *self == ChecksumLayer::Download
+    }
+}
+
+/// Whether Ventoy boots the file at `path`, going by its extension
+pub fn is_bootable(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(ImageFormat::from_filename)
+        .is_some_and(|format| !format.is_compressed())
+}
+
+/// Checksums of both layers of a decompressed download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decompressed {
+    /// Checksum of the compressed file
+    pub download: String,
+    /// Checksum of the image
+    pub image: String,
+}
+
+/// Decompress the complete download `input` into `output`, hashing the compressed and
+/// the decompressed bytes with `checksum_type` while reading it once.
+///
+/// Stops when `cancel` fires; `output` is removed whenever decompression fails.
+pub fn decompress(
+    input: &Path,
+    output: &Path,
+    compression: Compression,
+    checksum_type: ChecksumType,
+    cancel: &CancellationToken,
+) -> Result<Decompressed> {
+    let file = File::open(input).with_context(|| format!("Failed to open {:?}", input))?;
+    let mut download = ChecksumWriter::new(checksum_type);
+    let mut reader = BufReader::with_capacity(
+        1024 * 1024,
+        TeeReader {
+            inner: file,
+            copy: &mut download,
+            cancel,
+        },
+    );
+
+    let file = File::create(output).with_context(|| format!("Failed to create {:?}", output))?;
+    let mut image = ChecksumWriter::new(checksum_type);
+    let mut writer = BufWriter::with_capacity(
+        1024 * 1024,
+        TeeWriter {
+            inner: file,
+            copy: &mut image,
+        },
+    );
+
+    let decoded = compression
+        .decode(&mut reader, &mut writer)
+        .and_then(|()| writer.flush().map_err(Into::into));
+    drop(writer);
+    // Trailing bytes after the compressed stream still count for the download checksum
+    let decoded = decoded.and_then(|()| {
+        std::io::copy(&mut reader, &mut std::io::sink())
+            .map(drop)
+            .map_err(Into::into)
+    });
+    drop(reader);
+    if let Err(e) = decoded {
+        let _ = std::fs::remove_file(output);
+        bail!("Failed to decompress {:?}: {:#}", input, e);
+    }
+
+    Ok(Decompressed {
+        download: download.finish(),
+        image: image.finish(),
+    })
+}
+
+/// Reader that copies everything read through it into `copy`, failing once `cancel` fires
+struct TeeReader<'a, R> {
+    inner: R,
+    copy: &'a mut ChecksumWriter,
+    cancel: &'a CancellationToken,
+}
+
+impl<R: Read> Read for TeeReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(std::io::Error::other("decompression cancelled"));
+        }
+        let read = self.inner.read(buf)?;
+        self.copy.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+/// Writer that copies everything written through it into `copy`
+struct TeeWriter<'a, W> {
+    inner: W,
+    copy: &'a mut ChecksumWriter,
+}
+
+impl<W: Write> Write for TeeWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.copy.write_all(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::ChecksumVerifier;
+
+    #[test]
+    fn test_formats_from_filenames() {
+        let format = ImageFormat::from_filename("FreeBSD-14.3-RELEASE-amd64-disc1.iso.xz").unwrap();
+        assert_eq!(format.image_type, ImageType::Iso);
+        assert_eq!(format.compression, Some(Compression::Xz));
+        assert_eq!(format.to_string(), "iso.xz");
+
+        let format = ImageFormat::from_filename("Armbian_25.5.1_Rockpro64.IMG.ZST").unwrap();
+        assert_eq!(format.image_type, ImageType::Img);
+        assert_eq!(format.compression, Some(Compression::Zstd));
+        assert_eq!(
+            ImageFormat::from_filename("install.wim").unwrap(),
+            ImageFormat {
+                image_type: ImageType::Wim,
+                compression: None
+            }
+        );
+        assert_eq!(ImageFormat::from_filename("notes.txt.gz"), None);
+        assert_eq!(ImageFormat::from_filename("SHA256SUMS"), None);
+
+        // Templates say `.iso`; the local name keeps the real type
+        let img = ImageFormat::from_filename("raspios.img.xz").unwrap();
+        assert_eq!(
+            img.local_filename("raspios-2025-05-13-arm64.iso"),
+            "raspios-2025-05-13-arm64.img"
+        );
+        assert_eq!(img.local_filename("raspios.img.xz"), "raspios.img");
+        assert_eq!(img.local_filename("raspios"), "raspios.img");
+        assert_eq!(
+            ImageFormat::default().local_filename("debian-12.11.0-amd64-netinst.iso"),
+            "debian-12.11.0-amd64-netinst.iso"
+        );
+
+        assert!(is_bootable(Path::new("/media/ventoy/iso/win11.vhdx")));
+        assert!(!is_bootable(Path::new("/media/ventoy/iso/pfsense.iso.gz")));
+        assert!(!is_bootable(Path::new("/media/ventoy/iso/README")));
+    }
+
+    #[test]
+    fn test_decompress_hashes_both_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let image_path = dir.path().join("expected.img");
+        std::fs::write(&image_path, &content).unwrap();
+        let image =
+            ChecksumVerifier::calculate_checksum_blocking(&image_path, ChecksumType::Sha256)
+                .unwrap();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gzip.write_all(&content).unwrap();
+        let mut xz = Vec::new();
+        lzma_rs::xz_compress(&mut content.as_slice(), &mut xz).unwrap();
+
+        for (compression, compressed) in [
+            (Compression::Gzip, gzip.finish().unwrap()),
+            (Compression::Xz, xz),
+            (
+                Compression::Zstd,
+                ruzstd::encoding::compress_to_vec(
+                    content.as_slice(),
+                    ruzstd::encoding::CompressionLevel::Fastest,
+                ),
+            ),
+        ] {
+            let input = dir
+                .path()
+                .join(format!("image.img.{}", compression.extension()));
+            let output = dir.path().join("image.img");
+            std::fs::write(&input, &compressed).unwrap();
+            let download =
+                ChecksumVerifier::calculate_checksum_blocking(&input, ChecksumType::Sha256)
+                    .unwrap();
+
+            let cancel = CancellationToken::new();
+            let hashes =
+                decompress(&input, &output, compression, ChecksumType::Sha256, &cancel).unwrap();
+            assert_eq!(hashes.download, download);
+            assert_eq!(hashes.image, image);
+            assert_eq!(std::fs::read(&output).unwrap(), content);
+
+            // Cancelling or corrupt data leaves no half-written image behind
+            cancel.cancel();
+            assert!(
+                decompress(&input, &output, compression, ChecksumType::Sha256, &cancel).is_err()
+            );
+            assert!(!output.exists());
+            let cancel = CancellationToken::new();
+            std::fs::write(&input, &compressed[..compressed.len() / 2]).unwrap();
+            assert!(
+                decompress(&input, &output, compression, ChecksumType::Sha256, &cancel).is_err()
+            );
+            assert!(!output.exists());
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Logical sector size of ISO9660 images
+pub const SECTOR_SIZE: u64 = 2048;
+
+/// Volume descriptors start after the 32 KiB system area
+const FIRST_DESCRIPTOR: u64 = 16;
+/// Stop looking for the terminator after this many descriptors
+const MAX_DESCRIPTORS: u64 = 64;
+
+const STANDARD_ID: &[u8] = b"CD001";
+const EL_TORITO_ID: &[u8] = b"EL TORITO SPECIFICATION";
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+/// Firmware a boot catalog entry is meant for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BootPlatform {
+    Bios,
+    Uefi,
+    PowerPc,
+    Mac,
+    Other(u8),
+}
+
+impl BootPlatform {
+    fn from_id(id: u8) -> Self {
+        match id {
+            0x00 => BootPlatform::Bios,
+            0x01 => BootPlatform::PowerPc,
+            0x02 => BootPlatform::Mac,
+            0xEF => BootPlatform::Uefi,
+            other => BootPlatform::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for BootPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BootPlatform::Bios => f.pad("BIOS"),
+            BootPlatform::Uefi => f.pad("UEFI"),
+            BootPlatform::PowerPc => f.pad("PowerPC"),
+            BootPlatform::Mac => f.pad("Mac"),
+            BootPlatform::Other(id) => f.pad(&format!("platform {:#04x}", id)),
+        }
+    }
+}
+
+/// Entry of the El Torito boot catalog
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BootEntry {
+    pub platform: BootPlatform,
+    pub bootable: bool,
+    /// Boot image is loaded as-is rather than emulating a floppy or hard disk
+    pub no_emulation: bool,
+    /// Sector the boot image starts at
+    pub load_rba: u32,
+    /// Length of the boot image in 512-byte sectors (0 for "up to the loader")
+    pub sector_count: u16,
+}
+
+impl BootEntry {
+    fn parse(platform: u8, entry: &[u8]) -> Self {
+        Self {
+            platform: BootPlatform::from_id(platform),
+            bootable: entry[0] == 0x88,
+            no_emulation: entry[1] & 0x0F == 0,
+            sector_count: u16::from_le_bytes([entry[6], entry[7]]),
+            load_rba: u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]),
+        }
+    }
+}
+
+/// Partition tables in the system area that let the image boot when written to a USB stick
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct HybridLayout {
+    pub mbr: bool,
+    pub gpt: bool,
+}
+
+impl HybridLayout {
+    pub fn is_hybrid(&self) -> bool {
+        self.mbr || self.gpt
+    }
+}
+
+impl fmt::Display for HybridLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.mbr, self.gpt) {
+            (true, true) => f.pad("MBR + GPT"),
+            (true, false) => f.pad("MBR"),
+            (false, true) => f.pad("GPT"),
+            (false, false) => f.pad("none"),
+        }
+    }
+}
+
+/// What the volume descriptors, boot catalog and system area of an ISO say about it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IsoImage {
+    pub system_id: String,
+    pub volume_id: String,
+    pub publisher: String,
+    pub application: String,
+    pub created: Option<DateTime<FixedOffset>>,
+    /// Size the primary volume descriptor declares, in bytes
+    pub volume_size: u64,
+    /// Size of the file on disk, in bytes
+    pub file_size: u64,
+    pub joliet: bool,
+    pub rock_ridge: bool,
+    /// El Torito boot entries, empty when the image is not bootable from optical media
+    pub boot_entries: Vec<BootEntry>,
+    pub hybrid: HybridLayout,
+}
+
+impl IsoImage {
+    /// Read the ISO9660 structures of the image at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open ISO: {:?}", path))?;
+        let file_size = file
+            .metadata()
+            .with_context(|| format!("Failed to read ISO metadata: {:?}", path))?
+            .len();
+        Self::read(&mut file, file_size)
+            .with_context(|| format!("Failed to inspect ISO: {:?}", path))
+    }
+
+    /// Read the ISO9660 structures from `reader`, an image `file_size` bytes long
+    pub fn read<R: Read + Seek>(reader: &mut R, file_size: u64) -> Result<Self> {
+        let mut primary = None;
+        let mut joliet = false;
+        let mut boot_catalog = None;
+
+        for index in FIRST_DESCRIPTOR..FIRST_DESCRIPTOR + MAX_DESCRIPTORS {
+            let descriptor = read_sector(reader, index)
+                .context("Image ends before its volume descriptors do")?;
+            if &descriptor[1..6] != STANDARD_ID {
+                anyhow::bail!(
+                    "Not an ISO9660 image (no volume descriptor at sector {})",
+                    index
+                );
+            }
+
+            match descriptor[0] {
+                0 if descriptor[7..7 + EL_TORITO_ID.len()] == *EL_TORITO_ID => {
+                    boot_catalog = Some(le_u32(&descriptor[71..75]));
+                }
+                1 if primary.is_none() => primary = Some(descriptor),
+                2 if JOLIET_ESCAPES.contains(&&descriptor[88..91]) => joliet = true,
+                255 => break,
+                _ => {}
+            }
+        }
+
+        let primary = primary.context("Not an ISO9660 image (no primary volume descriptor)")?;
+        let block_size = u16::from_le_bytes([primary[128], primary[129]]) as u64;
+        let volume_size = le_u32(&primary[80..84]) as u64 * block_size;
+
+        let boot_entries = match boot_catalog {
+            Some(sector) => read_boot_catalog(&read_sector(reader, sector as u64)?)?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            system_id: text(&primary[8..40]),
+            volume_id: text(&primary[40..72]),
+            publisher: text(&primary[318..446]),
+            application: text(&primary[574..702]),
+            created: timestamp(&primary[813..830]),
+            volume_size,
+            file_size,
+            joliet,
+            rock_ridge: has_rock_ridge(reader, &primary[156..190])?,
+            boot_entries,
+            hybrid: read_hybrid_layout(reader)?,
+        })
+    }
+
+    /// The file is shorter than the volume it holds, as after an interrupted copy
+    pub fn is_truncated(&self) -> bool {
+        self.file_size < self.volume_size
+    }
+
+    pub fn boots_bios(&self) -> bool {
+        self.boots(BootPlatform::Bios)
+    }
+
+    pub fn boots_uefi(&self) -> bool {
+        self.boots(BootPlatform::Uefi)
+    }
+
+    fn boots(&self, platform: BootPlatform) -> bool {
+        self.boot_entries
+            .iter()
+            .any(|entry| entry.bootable && entry.platform == platform)
+    }
+}
+
+fn read_sector<R: Read + Seek>(reader: &mut R, index: u64) -> Result<Vec<u8>> {
+    let mut sector = vec![0; SECTOR_SIZE as usize];
+    reader.seek(SeekFrom::Start(index * SECTOR_SIZE))?;
+    reader
+        .read_exact(&mut sector)
+        .with_context(|| format!("Failed to read sector {}", index))?;
+    Ok(sector)
+}
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Space-padded descriptor string
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches([' ', '\0'])
+        .to_string()
+}
+
+/// Decimal `YYYYMMDDHHMMSScc` timestamp followed by a UTC offset in 15 minute steps
+fn timestamp(bytes: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let digits = std::str::from_utf8(&bytes[..16]).ok()?;
+    let field = |range: std::ops::Range<usize>| digits.get(range)?.parse::<u32>().ok();
+
+    let date = NaiveDate::from_ymd_opt(field(0..4)? as i32, field(4..6)?, field(6..8)?)?;
+    let time = date.and_hms_milli_opt(
+        field(8..10)?,
+        field(10..12)?,
+        field(12..14)?,
+        field(14..16)? * 10,
+    )?;
+    let offset = FixedOffset::east_opt(bytes[16] as i8 as i32 * 15 * 60)?;
+    time.and_local_timezone(offset).single()
+}
+
+/// Boot entries of an El Torito catalog: the default entry, then any sections
+fn read_boot_catalog(catalog: &[u8]) -> Result<Vec<BootEntry>> {
+    let validation = &catalog[..32];
+    let checksum = validation.chunks_exact(2).fold(0u16, |sum, word| {
+        sum.wrapping_add(u16::from_le_bytes([word[0], word[1]]))
+    });
+    if validation[0] != 0x01 || validation[30..32] != [0x55, 0xAA] || checksum != 0 {
+        anyhow::bail!("Invalid El Torito boot catalog");
+    }
+
+    let mut entries = vec![BootEntry::parse(validation[1], &catalog[32..64])];
+    let mut offset = 64;
+    let mut last = false;
+    while !last && offset + 32 <= catalog.len() {
+        let header = &catalog[offset..offset + 32];
+        match header[0] {
+            0x90 => {}
+            0x91 => last = true,
+            _ => break,
+        }
+        let platform = header[1];
+        let count = u16::from_le_bytes([header[2], header[3]]);
+        offset += 32;
+
+        let mut read = 0;
+        while read < count && offset + 32 <= catalog.len() {
+            let entry = &catalog[offset..offset + 32];
+            offset += 32;
+            // Extension entries continue the selection criteria of the entry before them
+            if entry[0] == 0x44 {
+                continue;
+            }
+            entries.push(BootEntry::parse(platform, entry));
+            read += 1;
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Look for Rock Ridge (SUSP) entries in the "." record of the root directory
+fn has_rock_ridge<R: Read + Seek>(reader: &mut R, root_record: &[u8]) -> Result<bool> {
+    let root = read_sector(reader, le_u32(&root_record[2..6]) as u64)?;
+    let length = root[0] as usize;
+    let name_length = root[32] as usize;
+    // Names of even length are followed by a padding byte
+    let system_use = 33 + name_length + (1 - name_length % 2);
+    if length <= system_use || length > root.len() {
+        return Ok(false);
+    }
+
+    let mut entries = &root[system_use..length];
+    while entries.len() >= 4 {
+        let entry_length = entries[2] as usize;
+        if entry_length < 4 || entry_length > entries.len() {
+            break;
+        }
+        let entry = &entries[..entry_length];
+        match &entry[..2] {
+            b"RR" | b"PX" | b"NM" => return Ok(true),
+            b"ER" if entry.len() > 8 => {
+                let id_length = entry[4] as usize;
+                let id = &entry[8..(8 + id_length).min(entry.len())];
+                let id = String::from_utf8_lossy(id);
+                if id.contains("RRIP") || id.contains("1282") {
+                    return Ok(true);
+                }
+            }
+            _ => {}
+        }
+        entries = &entries[entry_length..];
+    }
+
+    Ok(false)
+}
+
+/// Partition tables isohybrid and xorriso put in the system area
+fn read_hybrid_layout<R: Read + Seek>(reader: &mut R) -> Result<HybridLayout> {
+    let system_area = read_sector(reader, 0)?;
+    let has_partition = system_area[446..510]
+        .chunks_exact(16)
+        .any(|partition| partition[4] != 0);
+
+    Ok(HybridLayout {
+        mbr: system_area[510..512] == [0x55, 0xAA] && has_partition,
+        gpt: &system_area[512..520] == b"EFI PART",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SECTORS: usize = 24;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn descriptor(image: &mut [u8], sector: usize, kind: u8) -> usize {
+        let offset = sector * SECTOR_SIZE as usize;
+        image[offset] = kind;
+        put(image, offset + 1, STANDARD_ID);
+        image[offset + 6] = 1;
+        offset
+    }
+
+    /// Hybrid image with Joliet, Rock Ridge and BIOS plus UEFI boot entries
+    fn build_image() -> Vec<u8> {
+        let mut image = vec![0; SECTORS * SECTOR_SIZE as usize];
+
+        // MBR with one partition, and a GPT header
+        put(&mut image, 446 + 4, &[0x17]);
+        put(&mut image, 510, &[0x55, 0xAA]);
+        put(&mut image, 512, b"EFI PART");
+
+        let pvd = descriptor(&mut image, 16, 1);
+        image[pvd + 8..pvd + 72].fill(b' ');
+        put(&mut image, pvd + 8, b"LINUX");
+        put(&mut image, pvd + 40, b"ARCH_202506");
+        put(&mut image, pvd + 80, &(SECTORS as u32).to_le_bytes());
+        put(&mut image, pvd + 128, &2048u16.to_le_bytes());
+        put(&mut image, pvd + 318, b"Arch Linux <https://archlinux.org>");
+        put(&mut image, pvd + 813, b"2025060112304500");
+        image[pvd + 829] = 8; // UTC+2
+        // Root directory record pointing at sector 21
+        image[pvd + 156] = 34;
+        put(&mut image, pvd + 156 + 2, &21u32.to_le_bytes());
+
+        let boot = descriptor(&mut image, 17, 0);
+        put(&mut image, boot + 7, EL_TORITO_ID);
+        put(&mut image, boot + 71, &20u32.to_le_bytes());
+
+        let joliet = descriptor(&mut image, 18, 2);
+        put(&mut image, joliet + 88, b"%/E");
+        descriptor(&mut image, 19, 255);
+
+        // Boot catalog: validation entry, BIOS default entry, one UEFI section
+        let catalog = 20 * SECTOR_SIZE as usize;
+        image[catalog] = 0x01;
+        put(&mut image, catalog + 30, &[0x55, 0xAA]);
+        let sum = image[catalog..catalog + 32]
+            .chunks_exact(2)
+            .fold(0u16, |sum, w| {
+                sum.wrapping_add(u16::from_le_bytes([w[0], w[1]]))
+            });
+        put(
+            &mut image,
+            catalog + 28,
+            &0u16.wrapping_sub(sum).to_le_bytes(),
+        );
+        put(
+            &mut image,
+            catalog + 32,
+            &[0x88, 0, 0, 0, 0, 0, 4, 0, 22, 0, 0, 0],
+        );
+        put(&mut image, catalog + 64, &[0x91, 0xEF, 1, 0]);
+        put(
+            &mut image,
+            catalog + 96,
+            &[0x88, 0, 0, 0, 0, 0, 0, 0, 23, 0, 0, 0],
+        );
+
+        // "." record of the root directory with SUSP SP and ER entries
+        let root = 21 * SECTOR_SIZE as usize;
+        let er = [b"ER".as_slice(), &[18, 1, 10, 0, 0, 1], b"RRIP_1991A"].concat();
+        let sp = [b"SP".as_slice(), &[7, 1, 0xBE, 0xEF, 0]].concat();
+        let record_length = 34 + sp.len() + er.len();
+        image[root] = record_length as u8;
+        image[root + 32] = 1;
+        put(&mut image, root + 34, &sp);
+        put(&mut image, root + 34 + sp.len(), &er);
+
+        image
+    }
+
+    #[test]
+    fn test_inspect_hybrid_image() {
+        let image = build_image();
+        let iso = IsoImage::read(&mut Cursor::new(&image), image.len() as u64).unwrap();
+
+        assert_eq!(iso.system_id, "LINUX");
+        assert_eq!(iso.volume_id, "ARCH_202506");
+        assert_eq!(iso.publisher, "Arch Linux <https://archlinux.org>");
+        assert_eq!(
+            iso.created.unwrap().to_rfc3339(),
+            "2025-06-01T12:30:45+02:00"
+        );
+        assert_eq!(iso.volume_size, image.len() as u64);
+        assert!(!iso.is_truncated());
+        assert!(iso.joliet);
+        assert!(iso.rock_ridge);
+        assert!(iso.boots_bios() && iso.boots_uefi());
+        assert_eq!(iso.boot_entries[0].sector_count, 4);
+        assert_eq!(iso.boot_entries[1].load_rba, 23);
+        assert_eq!(
+            iso.hybrid,
+            HybridLayout {
+                mbr: true,
+                gpt: true
+            }
+        );
+
+        let truncated = IsoImage::read(&mut Cursor::new(&image), image.len() as u64 - 1).unwrap();
+        assert!(truncated.is_truncated());
+    }
+
+    #[test]
+    fn test_rejects_non_iso_and_bad_catalog() {
+        let zeros = vec![0; SECTORS * SECTOR_SIZE as usize];
+        assert!(IsoImage::read(&mut Cursor::new(&zeros), zeros.len() as u64).is_err());
+
+        let mut image = build_image();
+        image[20 * SECTOR_SIZE as usize + 31] = 0;
+        assert!(IsoImage::read(&mut Cursor::new(&image), image.len() as u64).is_err());
+    }
+}
@@ -1,7 +1,18 @@
+pub mod adopt;
 pub mod config;
+pub mod credentials;
 pub mod download;
+pub mod http;
+pub mod image;
+pub mod iso9660;
+pub mod lock;
+pub mod manifest;
+pub mod naming;
+pub mod policy;
+pub mod provenance;
 pub mod registry;
 pub mod usb;
+pub mod verify;
 
 // Re-export commonly used types for easier access in tests
 pub use config::ConfigManager;
@@ -0,0 +1,374 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::image::{ChecksumLayer, ImageFormat};
+use crate::registry::signature::SignatureStatus;
+use crate::registry::sources::{SourcePriority, SourceType};
+use crate::registry::{DownloadSource, IsoInfo, IsoRegistry, ReleaseType, SignedChecksum};
+
+const LOCK_FILE: &str = "isod.lock";
+const LOCK_VERSION: u32 = 1;
+const LOCK_HEADER: &str = "# Generated by `isod update`. Commit it to share the exact ISO set.\n\n";
+
+/// One download source of a locked ISO
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedSource {
+    #[serde(rename = "type")]
+    pub source_type: SourceType,
+    pub priority: SourcePriority,
+    /// URL, or the magnet link for magnet sources
+    pub url: String,
+}
+
+impl LockedSource {
+    fn from_source(source: &DownloadSource) -> Option<Self> {
+        Some(Self {
+            source_type: source.source_type.clone(),
+            priority: source.priority,
+            url: source.url.clone().or_else(|| source.magnet_link.clone())?,
+        })
+    }
+
+    fn to_source(&self) -> DownloadSource {
+        match self.source_type {
+            SourceType::Direct => DownloadSource::direct(&self.url, self.priority),
+            SourceType::Mirror => DownloadSource::mirror(&self.url, self.priority, None),
+            SourceType::Torrent => DownloadSource::torrent(&self.url, self.priority),
+            SourceType::Magnet => DownloadSource::magnet(&self.url, self.priority, Vec::new()),
+            SourceType::Zsync => DownloadSource::zsync(&self.url, self.priority),
+            SourceType::Metalink => DownloadSource::metalink(&self.url, self.priority),
+        }
+    }
+}
+
+/// The exact ISO resolved for one configured (distro, architecture, variant)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockEntry {
+    pub distro: String,
+    pub architecture: String,
+    pub variant: String,
+    pub version: String,
+    pub filename: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_type: Option<String>,
+    /// Whether the checksum covers the download or the decompressed image
+    #[serde(default, skip_serializing_if = "ChecksumLayer::is_download")]
+    pub checksum_layer: ChecksumLayer,
+    /// Checksum file the checksum was taken from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_url: Option<String>,
+    /// Fingerprint of the pinned key the checksum was signed with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_by: Option<String>,
+    #[serde(default)]
+    pub sources: Vec<LockedSource>,
+}
+
+impl LockEntry {
+    pub fn new(variant: &str, iso_info: &IsoInfo, checksum: Option<&SignedChecksum>) -> Self {
+        Self {
+            distro: iso_info.distro.clone(),
+            architecture: iso_info.architecture.clone(),
+            variant: variant.to_string(),
+            version: iso_info.version.clone(),
+            filename: iso_info.filename.clone(),
+            checksum: checksum
+                .map(|c| c.value.clone())
+                .or_else(|| iso_info.checksum.clone()),
+            checksum_type: checksum
+                .map(|c| c.checksum_type.algorithm().to_string())
+                .or_else(|| iso_info.checksum_type.clone()),
+            checksum_layer: checksum.map_or(iso_info.checksum_layer, |c| c.layer),
+            checksum_url: checksum.map(|c| c.url.clone()),
+            signed_by: checksum.and_then(|c| match &c.signature {
+                SignatureStatus::Signed { fingerprint } => Some(fingerprint.clone()),
+                _ => None,
+            }),
+            sources: iso_info
+                .download_sources
+                .iter()
+                .filter_map(LockedSource::from_source)
+                .collect(),
+        }
+    }
+
+    /// Whether this entry is for the same configured (distro, architecture, variant)
+    pub fn same_key(&self, other: &LockEntry) -> bool {
+        self.distro == other.distro
+            && self.architecture == other.architecture
+            && self.variant == other.variant
+    }
+
+    /// Signature status to record for a download reproduced from the lock
+    pub fn signature(&self) -> SignatureStatus {
+        match &self.signed_by {
+            Some(fingerprint) => SignatureStatus::Signed {
+                fingerprint: fingerprint.clone(),
+            },
+            None => SignatureStatus::unsigned("checksum was not signed when locked"),
+        }
+    }
+
+    /// The locked ISO, ready to hand to the download manager
+    pub fn iso_info(&self) -> IsoInfo {
+        IsoInfo {
+            distro: self.distro.clone(),
+            version: self.version.clone(),
+            architecture: self.architecture.clone(),
+            variant: Some(self.variant.clone()),
+            filename: self.filename.clone(),
+            local_filename: None,
+            format: ImageFormat::from_filename(&self.filename).unwrap_or_default(),
+            download_sources: self.sources.iter().map(LockedSource::to_source).collect(),
+            checksum: self.checksum.clone(),
+            checksum_type: self.checksum_type.clone(),
+            checksum_layer: self.checksum_layer,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        }
+    }
+}
+
+/// A difference between the lockfile and a fresh resolution
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockChange {
+    Added(LockEntry),
+    Removed(LockEntry),
+    Changed {
+        old: Box<LockEntry>,
+        new: Box<LockEntry>,
+    },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LockFile {
+    version: u32,
+    #[serde(default, rename = "iso")]
+    isos: Vec<LockEntry>,
+}
+
+/// Exact ISO set of a kit, stored as `isod.lock` next to the config
+#[derive(Debug)]
+pub struct Lockfile {
+    path: PathBuf,
+    entries: Vec<LockEntry>,
+}
+
+impl Lockfile {
+    pub fn path_in(config_dir: &Path) -> PathBuf {
+        config_dir.join(LOCK_FILE)
+    }
+
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file: LockFile = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read lockfile: {:?}", path))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse lockfile: {:?}", path))?
+        } else {
+            LockFile::default()
+        };
+
+        if file.version > LOCK_VERSION {
+            bail!(
+                "Lockfile {:?} has format version {}, this isod understands up to {}",
+                path,
+                file.version,
+                LOCK_VERSION
+            );
+        }
+
+        Ok(Self {
+            path,
+            entries: file.isos,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create config directory: {:?}", parent))?;
+        }
+
+        let mut isos = self.entries.clone();
+        isos.sort_by(|a, b| {
+            (&a.distro, &a.architecture, &a.variant).cmp(&(&b.distro, &b.architecture, &b.variant))
+        });
+        let file = LockFile {
+            version: LOCK_VERSION,
+            isos,
+        };
+        let content = toml::to_string_pretty(&file).context("Failed to serialize lockfile")?;
+
+        let tmp_path = self.path.with_extension("lock.tmp");
+        fs::write(&tmp_path, format!("{}{}", LOCK_HEADER, content))
+            .with_context(|| format!("Failed to write lockfile: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write lockfile: {:?}", self.path))?;
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[LockEntry] {
+        &self.entries
+    }
+
+    /// Add `entry`, replacing the one locked for the same key
+    pub fn record(&mut self, entry: LockEntry) {
+        self.entries.retain(|e| !e.same_key(&entry));
+        self.entries.push(entry);
+    }
+
+    /// Drop entries no longer covered by an enabled distro's architectures and variants
+    pub fn retain_configured(&mut self, config: &Config) {
+        self.entries.retain(|entry| {
+            config.distros.get(&entry.distro).is_some_and(|distro| {
+                distro.enabled
+                    && distro.architectures.contains(&entry.architecture)
+                    && distro.variants.contains(&entry.variant)
+            })
+        });
+    }
+}
+
+/// Resolve the exact ISO for one (distro, version, architecture, variant), with its
+/// published checksum
+pub async fn resolve(
+    iso_registry: &IsoRegistry,
+    distro: &str,
+    version: &str,
+    architecture: &str,
+    variant: &str,
+) -> Result<(IsoInfo, LockEntry)> {
+    let mut iso_info = iso_registry
+        .get_iso_info(distro, Some(version), Some(architecture), Some(variant))
+        .await?;
+    let checksum = iso_registry.get_signed_checksum(&iso_info).await?;
+    if let Some(checksum) = &checksum {
+        iso_info.checksum = Some(checksum.value.clone());
+        iso_info.checksum_type = Some(checksum.checksum_type.algorithm().to_string());
+        iso_info.checksum_layer = checksum.layer;
+    }
+
+    let entry = LockEntry::new(variant, &iso_info, checksum.as_ref());
+    Ok((iso_info, entry))
+}
+
+/// Changes that replacing the `locked` entries with the `resolved` ones would make
+pub fn diff(locked: &[LockEntry], resolved: &[LockEntry]) -> Vec<LockChange> {
+    let mut changes = Vec::new();
+
+    for new in resolved {
+        match locked.iter().find(|old| old.same_key(new)) {
+            Some(old) if old == new => {}
+            Some(old) => changes.push(LockChange::Changed {
+                old: Box::new(old.clone()),
+                new: Box::new(new.clone()),
+            }),
+            None => changes.push(LockChange::Added(new.clone())),
+        }
+    }
+    for old in locked {
+        if !resolved.iter().any(|new| new.same_key(old)) {
+            changes.push(LockChange::Removed(old.clone()));
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DistroConfig;
+
+    fn entry(version: &str, arch: &str) -> LockEntry {
+        LockEntry {
+            distro: "debian".to_string(),
+            architecture: arch.to_string(),
+            variant: "netinst".to_string(),
+            version: version.to_string(),
+            filename: format!("debian-{}-{}-netinst.iso", version, arch),
+            checksum: Some("ab".repeat(32)),
+            checksum_type: Some("sha256".to_string()),
+            checksum_layer: ChecksumLayer::Download,
+            checksum_url: Some(format!(
+                "https://deb.debian.org/debian-cd/{}/{}/iso-cd/SHA256SUMS",
+                version, arch
+            )),
+            signed_by: Some("DF9B9C49EAA9298432589D76DA87E80D6294BE9B".to_string()),
+            sources: vec![LockedSource {
+                source_type: SourceType::Mirror,
+                priority: SourcePriority::High,
+                url: format!(
+                    "https://deb.debian.org/debian-cd/{}/{}/iso-cd/debian-{}-{}-netinst.iso",
+                    version, arch, version, arch
+                ),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_lockfile_roundtrip_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Lockfile::path_in(dir.path());
+
+        let mut lock = Lockfile::load(&path).unwrap();
+        lock.record(entry("12.10.0", "amd64"));
+        lock.record(entry("12.11.0", "amd64"));
+        lock.record(entry("12.11.0", "arm64"));
+        lock.save().unwrap();
+
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(raw.starts_with("# Generated by `isod update`"));
+        assert!(raw.contains("[[iso]]"));
+
+        let mut lock = Lockfile::load(&path).unwrap();
+        assert_eq!(lock.entries().len(), 2);
+        assert_eq!(lock.entries()[0], entry("12.11.0", "amd64"));
+        assert_eq!(
+            lock.entries()[0].iso_info().download_sources[0].source_type,
+            SourceType::Mirror
+        );
+
+        let mut config = Config::default();
+        config.distros.insert(
+            "debian".to_string(),
+            DistroConfig {
+                variants: vec!["netinst".to_string()],
+                architectures: vec!["amd64".to_string()],
+                ..Default::default()
+            },
+        );
+        lock.retain_configured(&config);
+        assert_eq!(lock.entries(), &[entry("12.11.0", "amd64")]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let locked = vec![entry("12.10.0", "amd64"), entry("12.10.0", "i386")];
+        let resolved = vec![entry("12.11.0", "amd64"), entry("12.11.0", "arm64")];
+
+        let changes = diff(&locked, &resolved);
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(
+            &changes[0],
+            LockChange::Changed { old, new } if old.version == "12.10.0" && new.version == "12.11.0"
+        ));
+        assert!(matches!(&changes[1], LockChange::Added(e) if e.architecture == "arm64"));
+        assert!(matches!(&changes[2], LockChange::Removed(e) if e.architecture == "i386"));
+
+        assert!(diff(&resolved, &resolved).is_empty());
+    }
+}
@@ -5,6 +5,9 @@ use anyhow::Result;
 use clap::Parser;
 use cli::{Cli, Commands};
 use console::{Term, style};
+use isod::registry::definitions;
+use isod::registry::mirror_lists::MirrorListCache;
+use isod::registry::signature::KeyStore;
 use isod::usb::UsbManager;
 use isod::{ConfigManager, IsoRegistry};
 use std::process;
@@ -23,7 +26,6 @@ async fn main() -> Result<()> {
     // Initialize systems
     let mut config_manager = ConfigManager::new()?;
     let mut usb_manager = UsbManager::new();
-    let iso_registry = IsoRegistry::new();
 
     // Validate config on startup (unless we're about to fix it)
     let skip_config_validation = handlers::should_skip_config_validation(&args.command);
@@ -48,6 +50,28 @@ async fn main() -> Result<()> {
         }
     }
 
+    // Every network path shares one client configured from [network]. Config
+    // commands fall back to the defaults so a broken [network] can be fixed.
+    if let Err(e) = isod::http::init(config_manager.config()) {
+        if !matches!(args.command, Commands::Config { .. }) {
+            return Err(e);
+        }
+        Term::stderr().write_line(&format!(
+            "{} {:#}; using default network settings",
+            style("Warning:").yellow().bold(),
+            e
+        ))?;
+    }
+    let iso_registry = IsoRegistry::new()
+        .with_config(config_manager.config())
+        .with_mirror_lists(MirrorListCache::dir_in(config_manager.state_dir()))
+        .with_signing_keys(KeyStore::dir_in(config_manager.state_dir()))
+        .with_distro_definitions(&definitions::dir_in(config_manager.config_dir()));
+
+    if args.starts_downloads() {
+        install_interrupt_handler();
+    }
+
     // Handle commands
     match args.command {
         Commands::Add {
@@ -75,6 +99,7 @@ async fn main() -> Result<()> {
             force,
             check_only,
             include_beta,
+            limit_rate,
         } => {
             handlers::handle_update(
                 &config_manager,
@@ -83,6 +108,7 @@ async fn main() -> Result<()> {
                 force,
                 check_only,
                 include_beta,
+                limit_rate,
             )
             .await?;
         }
@@ -126,14 +152,19 @@ async fn main() -> Result<()> {
             auto,
             verify,
             download,
+            locked,
         } => {
             handlers::handle_sync(
                 &config_manager,
                 &mut usb_manager,
-                mount_point,
-                auto,
-                verify,
-                download,
+                &iso_registry,
+                handlers::sync::SyncOptions {
+                    mount_point,
+                    auto_select: auto,
+                    verify_checksums: verify,
+                    download_missing: download,
+                    locked,
+                },
             )
             .await?;
         }
@@ -167,8 +198,10 @@ async fn main() -> Result<()> {
             torrent,
             max_concurrent,
             verify,
+            limit_rate,
         } => {
             handlers::handle_download(
+                &config_manager,
                 &iso_registry,
                 distro,
                 output_dir,
@@ -178,6 +211,7 @@ async fn main() -> Result<()> {
                 torrent,
                 max_concurrent,
                 verify,
+                limit_rate,
             )
             .await?;
         }
@@ -194,13 +228,98 @@ async fn main() -> Result<()> {
             sources,
             details,
         } => {
-            handlers::handle_info(&iso_registry, distro, versions, sources, details).await?;
+            handlers::handle_info(
+                &config_manager,
+                &iso_registry,
+                distro,
+                versions,
+                sources,
+                details,
+            )
+            .await?;
+        }
+        Commands::Queue { action } => {
+            handlers::handle_queue(&config_manager, action).await?;
+        }
+        Commands::Mirrors { action } => {
+            handlers::handle_mirrors(&config_manager, action)?;
+        }
+        Commands::Adopt {
+            mount_point,
+            rename,
+            add_distros,
+            dry_run,
+        } => {
+            handlers::handle_adopt(
+                &mut config_manager,
+                &usb_manager,
+                &iso_registry,
+                handlers::adopt::AdoptOptions {
+                    mount_point,
+                    rename,
+                    add_distros,
+                    dry_run,
+                },
+            )
+            .await?;
+        }
+        Commands::Provenance { file, json } => {
+            handlers::handle_provenance(&config_manager, file, json)?;
+        }
+        Commands::Inspect { file, json } => {
+            handlers::handle_inspect(file, json)?;
+        }
+        Commands::Lock { action } => {
+            handlers::handle_lock(&config_manager, &iso_registry, action).await?;
+        }
+        Commands::Verify {
+            distro,
+            mount_point,
+            device,
+            library,
+            upstream,
+            jobs,
+        } => {
+            handlers::handle_verify(
+                &config_manager,
+                &usb_manager,
+                &iso_registry,
+                handlers::verify::VerifyOptions {
+                    distro,
+                    mount_point,
+                    device_only: device,
+                    library_only: library,
+                    upstream,
+                    jobs,
+                },
+            )
+            .await?;
         }
     }
 
     Ok(())
 }
 
+/// First Ctrl-C cancels downloads cleanly so they can be resumed, a second one exits at once
+fn install_interrupt_handler() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+
+        let term = Term::stderr();
+        let _ = term.write_line(&format!(
+            "\n{} Cancelling downloads, press Ctrl-C again to exit immediately",
+            style("⏹️").yellow()
+        ));
+        isod::download::shutdown_token().cancel();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            process::exit(130);
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::image::{ChecksumLayer, ImageFormat};
+use crate::registry::signature::SignatureStatus;
+use crate::registry::{IsoInfo, ReleaseType};
+
+/// Metadata directory isod keeps on a Ventoy device
+const METADATA_DIR: &str = "isod";
+const MANIFEST_FILE: &str = "manifest.json";
+const LIBRARY_FILE: &str = "library.json";
+
+/// One ISO placed on the device or downloaded into the library
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub filename: String,
+    /// Filename upstream, when the file was stored under a different local name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_filename: Option<String>,
+    pub distro: String,
+    pub version: String,
+    pub architecture: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_type: Option<String>,
+    pub signature: SignatureStatus,
+    pub added: DateTime<Utc>,
+    /// Directory the file is in, for library entries and ISOs adopted outside the
+    /// device's ISO directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory: Option<PathBuf>,
+}
+
+impl ManifestEntry {
+    pub fn new(iso_info: &IsoInfo, signature: SignatureStatus) -> Self {
+        // A checksum of the compressed download says nothing about the image on disk
+        let (checksum, checksum_type) =
+            if iso_info.format.is_compressed() && iso_info.checksum_layer.is_download() {
+                (None, None)
+            } else {
+                (iso_info.checksum.clone(), iso_info.checksum_type.clone())
+            };
+
+        Self {
+            filename: iso_info.local_filename().to_string(),
+            upstream_filename: (iso_info.local_filename() != iso_info.filename)
+                .then(|| iso_info.filename.clone()),
+            distro: iso_info.distro.clone(),
+            version: iso_info.version.clone(),
+            architecture: iso_info.architecture.clone(),
+            variant: iso_info.variant.clone(),
+            checksum,
+            checksum_type,
+            signature,
+            added: Utc::now(),
+            directory: None,
+        }
+    }
+
+    /// Checksum of the image on disk, when it was computed while decompressing it
+    pub fn with_image_checksum(
+        mut self,
+        checksum: Option<String>,
+        checksum_type: Option<String>,
+    ) -> Self {
+        if checksum.is_some() {
+            self.checksum = checksum;
+            self.checksum_type = checksum_type;
+        }
+        self
+    }
+
+    pub fn with_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Where the file should be, given the directory ISOs are kept in on a device
+    pub fn path(&self, iso_dir: &Path) -> PathBuf {
+        self.directory
+            .as_deref()
+            .unwrap_or(iso_dir)
+            .join(&self.filename)
+    }
+
+    /// Release this entry was recorded for, to look up its current upstream checksum
+    pub fn iso_info(&self) -> IsoInfo {
+        IsoInfo {
+            distro: self.distro.clone(),
+            version: self.version.clone(),
+            architecture: self.architecture.clone(),
+            variant: self.variant.clone(),
+            filename: self
+                .upstream_filename
+                .clone()
+                .unwrap_or_else(|| self.filename.clone()),
+            local_filename: self
+                .upstream_filename
+                .is_some()
+                .then(|| self.filename.clone()),
+            format: ImageFormat::from_filename(
+                self.upstream_filename.as_ref().unwrap_or(&self.filename),
+            )
+            .unwrap_or_default(),
+            download_sources: Vec::new(),
+            checksum: None,
+            checksum_type: None,
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ManifestFile {
+    isos: Vec<ManifestEntry>,
+}
+
+/// Record of the ISOs isod put on a device, stored in `isod/manifest.json`.
+///
+/// Downloads made outside a device go to a library manifest of the same format
+/// in the state directory, with the download directory kept per entry.
+#[derive(Debug)]
+pub struct DeviceManifest {
+    path: PathBuf,
+    entries: Vec<ManifestEntry>,
+}
+
+impl DeviceManifest {
+    /// Location of the manifest inside the device metadata directory
+    pub fn path_in(metadata_dir: &Path) -> PathBuf {
+        metadata_dir.join(MANIFEST_FILE)
+    }
+
+    /// Location of the manifest on the device mounted at `mount_point`
+    pub fn on_device(mount_point: &Path) -> PathBuf {
+        Self::path_in(&mount_point.join(METADATA_DIR))
+    }
+
+    /// Location of the library manifest in the isod state directory
+    pub fn library_in(state_dir: &Path) -> PathBuf {
+        state_dir.join(LIBRARY_FILE)
+    }
+
+    /// Manifest of the Ventoy device `dir` is on, if it is on one
+    pub fn locate(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .find(|root| root.join("ventoy").join("ventoy.json").exists())
+            .map(Self::on_device)
+    }
+
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file: ManifestFile = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read device manifest: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse device manifest: {:?}", path))?
+        } else {
+            ManifestFile::default()
+        };
+
+        Ok(Self {
+            path,
+            entries: file.isos,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create metadata directory: {:?}", parent))?;
+        }
+
+        let file = ManifestFile {
+            isos: self.entries.clone(),
+        };
+        let content =
+            serde_json::to_string_pretty(&file).context("Failed to serialize device manifest")?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write device manifest: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write device manifest: {:?}", self.path))?;
+
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// Add `entry`, replacing an earlier one for the same file
+    pub fn record(&mut self, entry: ManifestEntry) {
+        self.entries
+            .retain(|e| e.filename != entry.filename || e.directory != entry.directory);
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_located_on_ventoy_device() {
+        let device = tempfile::tempdir().unwrap();
+        let iso_dir = device.path().join("iso");
+        fs::create_dir_all(device.path().join("ventoy")).unwrap();
+        fs::create_dir_all(&iso_dir).unwrap();
+        assert_eq!(DeviceManifest::locate(&iso_dir), None);
+
+        fs::write(device.path().join("ventoy").join("ventoy.json"), "{}").unwrap();
+        let path = DeviceManifest::locate(&iso_dir).unwrap();
+        assert_eq!(path, device.path().join("isod").join("manifest.json"));
+
+        let iso_info = IsoInfo {
+            distro: "debian".to_string(),
+            version: "12.11.0".to_string(),
+            architecture: "amd64".to_string(),
+            variant: None,
+            filename: "debian-12.11.0-amd64-netinst.iso".to_string(),
+            local_filename: None,
+            format: ImageFormat::default(),
+            download_sources: Vec::new(),
+            checksum: Some("ab".repeat(32)),
+            checksum_type: Some("sha256".to_string()),
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        };
+        let mut manifest = DeviceManifest::load(&path).unwrap();
+        manifest.record(ManifestEntry::new(
+            &iso_info,
+            SignatureStatus::unsigned("no signature published"),
+        ));
+        manifest.record(ManifestEntry::new(
+            &iso_info,
+            SignatureStatus::Signed {
+                fingerprint: "DF9B9C49EAA9298432589D76DA87E80D6294BE9B".to_string(),
+            },
+        ));
+        manifest.save().unwrap();
+
+        let manifest = DeviceManifest::load(&path).unwrap();
+        assert_eq!(manifest.entries().len(), 1);
+        assert!(matches!(
+            manifest.entries()[0].signature,
+            SignatureStatus::Signed { .. }
+        ));
+        let raw = fs::read_to_string(&path).unwrap();
+        assert!(raw.contains("\"status\": \"signed\""));
+    }
+}
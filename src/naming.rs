@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::registry::IsoInfo;
+
+const NAMES_FILE: &str = "names.json";
+
+/// Default local naming scheme, the one described in the README
+pub const DEFAULT_TEMPLATE: &str = "{distro}-{version}-{arch}-{variant}.iso";
+
+const PLACEHOLDERS: [&str; 5] = ["distro", "version", "arch", "variant", "filename"];
+
+/// Local filename of `iso_info` under `template`.
+///
+/// `{filename}` stands for the upstream filename; `{variant}` and the separator
+/// next to it are dropped for releases without a variant. The extension follows
+/// the image type and leaves out any compression.
+pub fn render(template: &str, iso_info: &IsoInfo) -> String {
+    // Values end up in a single path component
+    let clean = |value: &str| value.replace(['/', '\\'], "_");
+
+    let mut filename = template
+        .replace("{distro}", &clean(&iso_info.distro))
+        .replace("{version}", &clean(&iso_info.version))
+        .replace("{arch}", &clean(&iso_info.architecture))
+        .replace("{filename}", &clean(&iso_info.filename));
+
+    match &iso_info.variant {
+        Some(variant) => filename = filename.replace("{variant}", &clean(variant)),
+        None => {
+            for placeholder in [
+                "-{variant}",
+                "_{variant}",
+                "{variant}-",
+                "{variant}_",
+                "{variant}",
+            ] {
+                filename = filename.replace(placeholder, "");
+            }
+        }
+    }
+
+    iso_info.format.local_filename(&filename)
+}
+
+/// Check that `template` only uses known placeholders and yields a plain filename
+pub fn validate_template(template: &str) -> Result<()> {
+    if template.trim().is_empty() {
+        anyhow::bail!("naming_template cannot be empty");
+    }
+    if template.contains(['/', '\\']) {
+        anyhow::bail!(
+            "naming_template must be a filename, not a path: '{}'",
+            template
+        );
+    }
+
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            anyhow::bail!("Unclosed placeholder in naming_template: '{}'", template);
+        };
+        let placeholder = &rest[start + 1..end];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            anyhow::bail!(
+                "Unknown placeholder '{{{}}}' in naming_template, expected one of {}",
+                placeholder,
+                PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("{{{}}}", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        rest = &rest[end + 1..];
+    }
+
+    Ok(())
+}
+
+/// Which release a local file holds and what it is called upstream
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameRecord {
+    pub directory: PathBuf,
+    pub local_filename: String,
+    pub upstream_filename: String,
+    pub distro: String,
+    pub version: String,
+    pub architecture: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    pub recorded: DateTime<Utc>,
+}
+
+impl NameRecord {
+    fn is_release(&self, iso_info: &IsoInfo) -> bool {
+        self.distro == iso_info.distro
+            && self.version == iso_info.version
+            && self.architecture == iso_info.architecture
+            && self.variant == iso_info.variant
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NamesFile {
+    names: Vec<NameRecord>,
+}
+
+/// Local filenames isod has handed out, stored in `names.json` in the state directory
+#[derive(Debug)]
+pub struct NameMap {
+    path: PathBuf,
+    records: Vec<NameRecord>,
+}
+
+impl NameMap {
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir.join(NAMES_FILE)
+    }
+
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file: NamesFile = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read name map: {:?}", path))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse name map: {:?}", path))?
+        } else {
+            NamesFile::default()
+        };
+
+        Ok(Self {
+            path,
+            records: file.names,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory: {:?}", parent))?;
+        }
+
+        let file = NamesFile {
+            names: self.records.clone(),
+        };
+        let content =
+            serde_json::to_string_pretty(&file).context("Failed to serialize name map")?;
+
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write name map: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write name map: {:?}", self.path))?;
+
+        Ok(())
+    }
+
+    pub fn records(&self) -> &[NameRecord] {
+        &self.records
+    }
+
+    /// Release stored as `local_filename` in `directory`
+    pub fn find(&self, directory: &Path, local_filename: &str) -> Option<&NameRecord> {
+        self.records
+            .iter()
+            .find(|r| r.directory == directory && r.local_filename == local_filename)
+    }
+
+    /// Settle the local filename of `iso_info` in `directory` and record it.
+    ///
+    /// When another release already has the templated name there, `-2`, `-3`, ... is
+    /// added before the extension until the name is free.
+    pub fn assign(&mut self, iso_info: &mut IsoInfo, directory: &Path) -> String {
+        let local_filename = self.free_name(iso_info, directory, |_| false);
+        iso_info.local_filename = Some(local_filename.clone());
+        self.record(iso_info, directory);
+        local_filename
+    }
+
+    /// Name [`Self::assign`] would settle on, also skipping names `taken` reports as
+    /// in use, without recording it
+    pub fn free_name(
+        &self,
+        iso_info: &IsoInfo,
+        directory: &Path,
+        taken: impl Fn(&str) -> bool,
+    ) -> String {
+        let wanted = iso_info.local_filename().to_string();
+        let (stem, extension) = match wanted.rfind('.') {
+            Some(dot) if dot > 0 => wanted.split_at(dot),
+            _ => (wanted.as_str(), ""),
+        };
+
+        (1..)
+            .map(|n| match n {
+                1 => wanted.clone(),
+                n => format!("{}-{}{}", stem, n, extension),
+            })
+            .find(|candidate| {
+                !taken(candidate)
+                    && self
+                        .find(directory, candidate)
+                        .is_none_or(|record| record.is_release(iso_info))
+            })
+            .unwrap_or(wanted.clone())
+    }
+
+    /// Record that `iso_info` is stored under its local filename in `directory`,
+    /// replacing whatever was recorded for that name
+    pub fn record(&mut self, iso_info: &IsoInfo, directory: &Path) {
+        let local_filename = iso_info.local_filename();
+        self.records
+            .retain(|r| r.directory != directory || r.local_filename != local_filename);
+        self.records.push(NameRecord {
+            directory: directory.to_path_buf(),
+            local_filename: local_filename.to_string(),
+            upstream_filename: iso_info.filename.clone(),
+            distro: iso_info.distro.clone(),
+            version: iso_info.version.clone(),
+            architecture: iso_info.architecture.clone(),
+            variant: iso_info.variant.clone(),
+            recorded: Utc::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ChecksumLayer, ImageFormat};
+    use crate::registry::ReleaseType;
+
+    fn iso_info(version: &str, architecture: &str, variant: Option<&str>) -> IsoInfo {
+        IsoInfo {
+            distro: "ubuntu".to_string(),
+            version: version.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(String::from),
+            filename: format!(
+                "ubuntu-{}-{}-{}.iso",
+                version,
+                variant.unwrap_or("desktop"),
+                architecture
+            ),
+            local_filename: None,
+            format: ImageFormat::default(),
+            download_sources: Vec::new(),
+            checksum: None,
+            checksum_type: None,
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        }
+    }
+
+    #[test]
+    fn test_render_and_validate_template() {
+        let desktop = iso_info("24.04.2", "amd64", Some("desktop"));
+        assert_eq!(
+            render(DEFAULT_TEMPLATE, &desktop),
+            "ubuntu-24.04.2-amd64-desktop.iso"
+        );
+        assert_eq!(
+            render(DEFAULT_TEMPLATE, &iso_info("24.04.2", "amd64", None)),
+            "ubuntu-24.04.2-amd64.iso"
+        );
+        assert_eq!(render("{filename}", &desktop), desktop.filename);
+
+        assert!(validate_template(DEFAULT_TEMPLATE).is_ok());
+        assert!(validate_template("{distro}-{release}.iso").is_err());
+        assert!(validate_template("{distro}/{version}.iso").is_err());
+        assert!(validate_template("{distro.iso").is_err());
+    }
+
+    #[test]
+    fn test_assign_avoids_collisions_and_records_names() {
+        let state = tempfile::tempdir().unwrap();
+        let directory = Path::new("/media/ventoy/iso");
+        let mut names = NameMap::load(NameMap::path_in(state.path())).unwrap();
+
+        // A template without the architecture maps both releases to the same name
+        let mut amd64 = iso_info("24.04.2", "amd64", Some("desktop"));
+        let mut arm64 = iso_info("24.04.2", "arm64", Some("desktop"));
+        amd64.local_filename = Some(render("{distro}-{version}.iso", &amd64));
+        arm64.local_filename = Some(render("{distro}-{version}.iso", &arm64));
+
+        assert_eq!(names.assign(&mut amd64, directory), "ubuntu-24.04.2.iso");
+        assert_eq!(names.assign(&mut arm64, directory), "ubuntu-24.04.2-2.iso");
+        assert_eq!(arm64.local_filename(), "ubuntu-24.04.2-2.iso");
+        // The same release keeps its name
+        assert_eq!(names.assign(&mut amd64, directory), "ubuntu-24.04.2.iso");
+        // Files isod does not know about can be avoided too
+        let mut riscv = iso_info("24.04.2", "riscv64", Some("desktop"));
+        riscv.local_filename = Some(render("{distro}-{version}.iso", &riscv));
+        assert_eq!(
+            names.free_name(&riscv, directory, |name| name == "ubuntu-24.04.2-3.iso"),
+            "ubuntu-24.04.2-4.iso"
+        );
+        names.save().unwrap();
+
+        let names = NameMap::load(NameMap::path_in(state.path())).unwrap();
+        assert_eq!(names.records().len(), 2);
+        let record = names.find(directory, "ubuntu-24.04.2-2.iso").unwrap();
+        assert_eq!(record.architecture, "arm64");
+        assert_eq!(record.upstream_filename, "ubuntu-24.04.2-desktop-arm64.iso");
+        assert!(
+            names
+                .find(Path::new("/tmp"), "ubuntu-24.04.2.iso")
+                .is_none()
+        );
+    }
+}
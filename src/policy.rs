@@ -0,0 +1,284 @@
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::registry::DownloadSource;
+use crate::registry::signature::SignatureStatus;
+
+/// Organisation rules isod refuses to break, from the `[policy]` config section
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    /// Only download over HTTPS
+    #[serde(default)]
+    pub https_only: bool,
+    /// Only use sources marked as verified in the distro definitions
+    #[serde(default)]
+    pub verified_sources_only: bool,
+    /// Refuse ISOs that are not signed by a pinned key, directly or through their checksum file
+    #[serde(default)]
+    pub require_signature: bool,
+    /// Refuse ISOs without a published checksum instead of downloading them unverified
+    #[serde(default)]
+    pub require_checksum: bool,
+    /// Distros that may be downloaded; all of them when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_distros: Vec<String>,
+    /// Mirrors never to download from: a URL prefix, `host`, `host:port` or `*.domain`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_mirrors: Vec<String>,
+}
+
+/// A rule of the policy that a download would break
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PolicyViolation {
+    #[error("distro '{0}' is not in the policy's allowed_distros")]
+    DistroNotAllowed(String),
+
+    #[error("{0} is not HTTPS (policy: https_only)")]
+    InsecureSource(String),
+
+    #[error("{0} is not a verified source (policy: verified_sources_only)")]
+    UnverifiedSource(String),
+
+    #[error("{0} is on a blocked mirror (policy: blocked_mirrors)")]
+    BlockedMirror(String),
+
+    #[error("no checksum is published for {0} (policy: require_checksum)")]
+    MissingChecksum(String),
+
+    #[error("{what} is not signed by a pinned key: {reason} (policy: require_signature)")]
+    MissingSignature { what: String, reason: String },
+}
+
+impl PolicyConfig {
+    /// Whether any rule is switched on
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    pub fn check_distro(&self, distro: &str) -> Result<(), PolicyViolation> {
+        if self.allowed_distros.is_empty()
+            || self
+                .allowed_distros
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(distro))
+        {
+            Ok(())
+        } else {
+            Err(PolicyViolation::DistroNotAllowed(distro.to_string()))
+        }
+    }
+
+    /// Check a source URL against the HTTPS and blocked mirror rules
+    pub fn check_url(&self, url: &str) -> Result<(), PolicyViolation> {
+        if self.https_only && !url.starts_with("https://") {
+            return Err(PolicyViolation::InsecureSource(url.to_string()));
+        }
+        if self.is_blocked(url) {
+            return Err(PolicyViolation::BlockedMirror(url.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn check_source(&self, source: &DownloadSource) -> Result<(), PolicyViolation> {
+        let url = source.get_url().unwrap_or_default();
+        self.check_url(url)?;
+        if self.verified_sources_only && !source.verified {
+            return Err(PolicyViolation::UnverifiedSource(url.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn check_checksum(
+        &self,
+        filename: &str,
+        checksum: Option<&str>,
+    ) -> Result<(), PolicyViolation> {
+        if self.require_checksum && checksum.is_none() {
+            return Err(PolicyViolation::MissingChecksum(filename.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn check_signature(
+        &self,
+        what: &str,
+        signature: &SignatureStatus,
+    ) -> Result<(), PolicyViolation> {
+        match signature {
+            SignatureStatus::Signed { .. } => Ok(()),
+            _ if !self.require_signature => Ok(()),
+            SignatureStatus::Unsigned { reason }
+            | SignatureStatus::Unverifiable { reason }
+            | SignatureStatus::Invalid { reason } => Err(PolicyViolation::MissingSignature {
+                what: what.to_string(),
+                reason: reason.clone(),
+            }),
+        }
+    }
+
+    pub fn is_blocked(&self, url: &str) -> bool {
+        let parsed = Url::parse(url).ok();
+        let host = parsed
+            .as_ref()
+            .and_then(|u| u.host_str())
+            .unwrap_or_default();
+        let host_port = parsed
+            .as_ref()
+            .and_then(|u| u.port())
+            .map(|port| format!("{}:{}", host, port));
+
+        self.blocked_mirrors.iter().any(|blocked| {
+            if blocked.contains("://") {
+                url.starts_with(blocked.as_str())
+            } else if let Some(domain) = blocked.strip_prefix("*.") {
+                host == domain || host.ends_with(&format!(".{}", domain))
+            } else {
+                host == blocked || host_port.as_deref() == Some(blocked.as_str())
+            }
+        })
+    }
+
+    /// Settings elsewhere in `config` that the policy makes useless or contradicts
+    pub fn conflicts(&self, config: &Config) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        let mut distros: Vec<_> = config.distros.iter().filter(|(_, d)| d.enabled).collect();
+        distros.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, _) in &distros {
+            if let Err(violation) = self.check_distro(name) {
+                conflicts.push(format!("distros.{}: {}", name, violation));
+            }
+        }
+
+        let mirrors = config
+            .sources
+            .custom_mirrors
+            .iter()
+            .map(|mirror| ("sources.custom_mirrors".to_string(), mirror))
+            .chain(distros.iter().flat_map(|(name, distro)| {
+                distro
+                    .mirrors
+                    .iter()
+                    .map(move |mirror| (format!("distros.{}.mirrors", name), mirror))
+            }));
+        for (section, mirror) in mirrors {
+            if let Err(violation) = self.check_url(&mirror.url) {
+                conflicts.push(format!("{}: {}", section, violation));
+            } else if self.verified_sources_only {
+                conflicts.push(format!(
+                    "{}: {} is never used, user mirrors are not verified sources",
+                    section, mirror.url
+                ));
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CustomMirror, DistroConfig};
+    use crate::registry::sources::SourcePriority;
+
+    #[test]
+    fn test_source_rules() {
+        let policy = PolicyConfig {
+            https_only: true,
+            verified_sources_only: true,
+            blocked_mirrors: vec![
+                "*.example.org".to_string(),
+                "mirror.local:8080".to_string(),
+                "https://bad.example.com/debian/".to_string(),
+            ],
+            ..Default::default()
+        };
+
+        let verified = DownloadSource::mirror(
+            "https://deb.debian.org/debian-cd/x.iso",
+            SourcePriority::High,
+            None,
+        )
+        .verified();
+        assert_eq!(policy.check_source(&verified), Ok(()));
+
+        let plain = DownloadSource::direct("http://deb.debian.org/x.iso", SourcePriority::High);
+        assert!(matches!(
+            policy.check_source(&plain),
+            Err(PolicyViolation::InsecureSource(_))
+        ));
+        let unverified =
+            DownloadSource::direct("https://deb.debian.org/x.iso", SourcePriority::High);
+        assert!(matches!(
+            policy.check_source(&unverified),
+            Err(PolicyViolation::UnverifiedSource(_))
+        ));
+
+        assert!(policy.is_blocked("https://ftp.example.org/x.iso"));
+        assert!(policy.is_blocked("https://example.org/x.iso"));
+        assert!(policy.is_blocked("https://mirror.local:8080/x.iso"));
+        assert!(policy.is_blocked("https://bad.example.com/debian/x.iso"));
+        assert!(!policy.is_blocked("https://bad.example.com/ubuntu/x.iso"));
+        assert!(!policy.is_blocked("https://notexample.org/x.iso"));
+    }
+
+    #[test]
+    fn test_verification_rules_and_conflicts() {
+        let policy = PolicyConfig {
+            require_signature: true,
+            allowed_distros: vec!["debian".to_string()],
+            https_only: true,
+            ..Default::default()
+        };
+        assert_eq!(policy.check_checksum("x.iso", None), Ok(()));
+        assert!(policy.check_distro("Debian").is_ok());
+        assert!(policy.check_distro("ubuntu").is_err());
+        assert!(
+            policy
+                .check_signature("x.iso", &SignatureStatus::unsigned("no signature"))
+                .is_err()
+        );
+        assert!(
+            PolicyConfig::default()
+                .check_signature("x.iso", &SignatureStatus::unsigned("no signature"))
+                .is_ok()
+        );
+        assert!(
+            policy
+                .check_signature(
+                    "x.iso",
+                    &SignatureStatus::unverifiable("gpgv is not installed")
+                )
+                .is_err()
+        );
+
+        let mut config = Config {
+            policy,
+            ..Default::default()
+        };
+        config.distros.insert(
+            "debian".to_string(),
+            DistroConfig {
+                mirrors: vec![CustomMirror {
+                    url: "http://mirror.example.org/debian-cd".to_string(),
+                    priority: SourcePriority::High,
+                    region: None,
+                    distros: Vec::new(),
+                }],
+                ..Default::default()
+            },
+        );
+
+        let conflicts = config.policy.conflicts(&config);
+        assert!(conflicts.iter().any(|c| c.starts_with("distros.ubuntu:")));
+        assert!(
+            conflicts
+                .iter()
+                .any(|c| c.starts_with("distros.debian.mirrors:"))
+        );
+        assert!(!conflicts.iter().any(|c| c.starts_with("distros.debian:")));
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::DeviceManifest;
+use crate::registry::IsoInfo;
+use crate::registry::signature::SignatureStatus;
+
+const PROVENANCE_DIR: &str = "provenance";
+const PROVENANCE_FORMAT: u32 = 1;
+
+/// Where the bytes of a completed download came from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DownloadOrigin {
+    /// Mirror or source URL the file was finally fetched from
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// How the downloaded file was checked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    /// Checksum file the expected checksum was taken from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum_url: Option<String>,
+    pub checksum_verified: bool,
+    pub checksum_signature: SignatureStatus,
+    /// Detached signature of the ISO itself, for distros that publish one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iso_signature: Option<SignatureStatus>,
+}
+
+/// Machine-readable record of where an ISO came from and how it was verified
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub format: u32,
+    pub isod_version: String,
+    pub iso: IsoInfo,
+    pub size_bytes: u64,
+    pub origin: DownloadOrigin,
+    pub verification: Verification,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+impl Provenance {
+    /// Record for `iso_info`, downloaded from `origin` and completed just now
+    pub fn new(
+        iso_info: &IsoInfo,
+        origin: DownloadOrigin,
+        size_bytes: u64,
+        started_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            format: PROVENANCE_FORMAT,
+            isod_version: env!("CARGO_PKG_VERSION").to_string(),
+            iso: iso_info.clone(),
+            size_bytes,
+            origin,
+            verification: Verification {
+                checksum_url: None,
+                checksum_verified: false,
+                checksum_signature: SignatureStatus::unsigned("no checksum available"),
+                iso_signature: None,
+            },
+            started_at,
+            completed_at: Utc::now(),
+        }
+    }
+
+    pub fn with_checksum(
+        mut self,
+        checksum_url: Option<String>,
+        verified: bool,
+        signature: SignatureStatus,
+    ) -> Self {
+        self.verification.checksum_url = checksum_url;
+        self.verification.checksum_verified = verified;
+        self.verification.checksum_signature = signature;
+        self
+    }
+
+    pub fn with_iso_signature(mut self, signature: Option<SignatureStatus>) -> Self {
+        self.verification.iso_signature = signature;
+        self
+    }
+
+    /// Directory holding provenance records under the state or device metadata directory
+    pub fn dir_in(base: &Path) -> PathBuf {
+        base.join(PROVENANCE_DIR)
+    }
+
+    /// Record of the ISO named `filename` in `dir`
+    pub fn path_in(dir: &Path, filename: &str) -> PathBuf {
+        dir.join(format!("{}.json", filename))
+    }
+
+    /// Provenance directory of the Ventoy device `dir` is on, if it is on one
+    pub fn device_dir(dir: &Path) -> Option<PathBuf> {
+        DeviceManifest::locate(dir)
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Self::dir_in)
+    }
+
+    /// Record for the ISO at `iso_path`, preferring the copy kept on its device
+    pub fn locate(iso_path: &Path, state_dir: &Path) -> Option<PathBuf> {
+        let filename = iso_path.file_name()?.to_str()?;
+        let device = iso_path
+            .parent()
+            .and_then(Self::device_dir)
+            .map(|dir| Self::path_in(&dir, filename));
+
+        device
+            .into_iter()
+            .chain([Self::path_in(&Self::dir_in(state_dir), filename)])
+            .find(|path| path.exists())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read provenance: {:?}", path))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse provenance: {:?}", path))
+    }
+
+    /// Write the record into `dir`, returning its path
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create provenance directory: {:?}", dir))?;
+
+        let path = Self::path_in(dir, self.iso.local_filename());
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize provenance")?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write provenance: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to write provenance: {:?}", path))?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ChecksumLayer, ImageFormat};
+    use crate::registry::ReleaseType;
+
+    #[test]
+    fn test_provenance_saved_and_located() {
+        let state = tempfile::tempdir().unwrap();
+        let device = tempfile::tempdir().unwrap();
+        let iso_dir = device.path().join("iso");
+        fs::create_dir_all(device.path().join("ventoy")).unwrap();
+        fs::write(device.path().join("ventoy").join("ventoy.json"), "{}").unwrap();
+        fs::create_dir_all(&iso_dir).unwrap();
+
+        let iso_info = IsoInfo {
+            distro: "arch".to_string(),
+            version: "2025.06.01".to_string(),
+            architecture: "x86_64".to_string(),
+            variant: None,
+            filename: "archlinux-2025.06.01-x86_64.iso".to_string(),
+            local_filename: None,
+            format: ImageFormat::default(),
+            download_sources: Vec::new(),
+            checksum: Some("ab".repeat(32)),
+            checksum_type: Some("sha256".to_string()),
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        };
+        let provenance = Provenance::new(
+            &iso_info,
+            DownloadOrigin {
+                url: "https://geo.mirror.pkgbuild.com/iso/latest/archlinux-x86_64.iso".to_string(),
+                etag: Some("\"683c1a8e-4a5d0000\"".to_string()),
+                last_modified: None,
+            },
+            1_247_000_000,
+            Utc::now(),
+        )
+        .with_checksum(
+            Some("https://geo.mirror.pkgbuild.com/iso/2025.06.01/sha256sums.txt".to_string()),
+            true,
+            SignatureStatus::unsigned("the ISO signature is checked after download"),
+        )
+        .with_iso_signature(Some(SignatureStatus::Signed {
+            fingerprint: "3E80CA1A8B89F69CBA57D98A76A5EF9054449A5C".to_string(),
+        }));
+
+        let iso_path = iso_dir.join(&iso_info.filename);
+        assert_eq!(Provenance::locate(&iso_path, state.path()), None);
+
+        let library = provenance.save(&Provenance::dir_in(state.path())).unwrap();
+        assert_eq!(Provenance::locate(&iso_path, state.path()), Some(library));
+
+        let device_dir = Provenance::device_dir(&iso_dir).unwrap();
+        assert_eq!(device_dir, device.path().join("isod").join("provenance"));
+        let on_device = provenance.save(&device_dir).unwrap();
+        assert_eq!(
+            Provenance::locate(&iso_path, state.path()),
+            Some(on_device.clone())
+        );
+
+        let loaded = Provenance::load(&on_device).unwrap();
+        assert_eq!(loaded, provenance);
+        assert_eq!(loaded.isod_version, env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,689 @@
+use anyhow::{Context, Result, bail};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::DistroDefinition;
+use super::signature::{SignatureSource, SigningKey};
+use super::sources::{DownloadSource, SourcePriority, SourceType};
+use super::version_detection::{
+    ApiVersionDetector, CompositeVersionDetector, FeedVersionDetector, GitHubVersionDetector,
+    ReleaseType, StaticVersionDetector, VersionDetector, VersionInfo, WebScrapingDetector,
+};
+
+/// Directory under the config directory holding user distro definitions
+const DEFINITIONS_DIR: &str = "distros.d";
+
+/// Placeholders source URLs, checksum URLs and filename patterns may use
+const PLACEHOLDERS: [&str; 5] = ["distro", "version", "arch", "variant", "filename"];
+
+/// Distro definition as written in a `distros.d/*.toml` file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DistroSpec {
+    pub name: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub homepage: String,
+    pub architectures: Vec<String>,
+    #[serde(default)]
+    pub variants: Vec<String>,
+    #[serde(default)]
+    pub default_variant: Option<String>,
+    pub filename_pattern: String,
+    #[serde(default)]
+    pub checksum_urls: Vec<String>,
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeySpec>,
+    #[serde(default)]
+    pub signature: Option<SignatureSpec>,
+    pub detector: DetectorSpec,
+    pub sources: Vec<SourceSpec>,
+}
+
+/// How versions of a distro are found, one per `VersionDetector` implementation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DetectorSpec {
+    /// GitHub releases of `owner/repo`
+    Github {
+        owner: String,
+        repo: String,
+        #[serde(default)]
+        include_prereleases: bool,
+        #[serde(default)]
+        version_prefix: Option<String>,
+    },
+    /// First capture group of `version_regex` over an RSS or Atom feed
+    Rss {
+        url: String,
+        version_regex: String,
+        #[serde(default = "stable")]
+        release_type: ReleaseType,
+    },
+    /// First capture group of `version_regex` over a web page
+    Scrape {
+        url: String,
+        version_regex: String,
+        #[serde(default)]
+        selector: String,
+    },
+    /// `version_path` (`$.field`) of each item of a JSON array
+    Api { url: String, version_path: String },
+    /// Fixed list of versions
+    Static { versions: Vec<StaticVersion> },
+    /// Versions found by any of `detectors`
+    Composite { detectors: Vec<DetectorSpec> },
+}
+
+/// Version of a `static` detector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticVersion {
+    pub version: String,
+    #[serde(default = "stable")]
+    pub release_type: ReleaseType,
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+/// OpenPGP key signatures of the distro are checked against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKeySpec {
+    pub fingerprint: String,
+    #[serde(default)]
+    pub description: String,
+    /// Where the public key is published, a keyserver lookup by default
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// Where the distro publishes signatures, one per `SignatureSource` variant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SignatureSpec {
+    DetachedChecksum { suffix: String },
+    ClearsignedChecksum,
+    DetachedIso { url: String },
+}
+
+/// Download source of a distro; `url` holds the link itself for magnet sources
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceSpec {
+    #[serde(rename = "type", default = "direct")]
+    pub source_type: SourceType,
+    pub url: String,
+    #[serde(default = "medium")]
+    pub priority: SourcePriority,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub verified: bool,
+    #[serde(default)]
+    pub trackers: Vec<String>,
+}
+
+fn stable() -> ReleaseType {
+    ReleaseType::Stable
+}
+
+fn direct() -> SourceType {
+    SourceType::Direct
+}
+
+fn medium() -> SourcePriority {
+    SourcePriority::Medium
+}
+
+/// Directory user distro definitions are loaded from
+pub fn dir_in(config_dir: &Path) -> PathBuf {
+    config_dir.join(DEFINITIONS_DIR)
+}
+
+/// Parse every `*.toml` file in `dir`, in filename order.
+///
+/// A missing directory holds no definitions; files that fail to read or parse
+/// are returned with their error so the rest still load.
+pub fn load_dir(dir: &Path) -> Vec<(PathBuf, Result<DistroSpec>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let spec = DistroSpec::load(&path);
+            (path, spec)
+        })
+        .collect()
+}
+
+impl DistroSpec {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read distro definition: {:?}", path))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse distro definition: {:?}", path))
+    }
+
+    /// Check everything that can be checked without fetching anything
+    pub fn validate(&self) -> Result<()> {
+        let valid_name = self.name.starts_with(|c: char| c.is_ascii_lowercase())
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid_name {
+            bail!(
+                "Distro name '{}' must start with a letter and only use a-z, 0-9 and '-'",
+                self.name
+            );
+        }
+
+        if self.architectures.is_empty() {
+            bail!("At least one architecture is required");
+        }
+        if let Some(variant) = &self.default_variant
+            && !self.variants.contains(variant)
+        {
+            bail!("Default variant '{}' is not in variants", variant);
+        }
+
+        if self.filename_pattern.trim().is_empty() {
+            bail!("filename_pattern cannot be empty");
+        }
+        if self.filename_pattern.contains(['/', '\\']) {
+            bail!(
+                "filename_pattern must be a filename, not a path: '{}'",
+                self.filename_pattern
+            );
+        }
+        check_placeholders("filename_pattern", &self.filename_pattern)?;
+
+        for url in &self.checksum_urls {
+            check_url("checksum URL", url)?;
+        }
+
+        for key in &self.signing_keys {
+            key.validate()?;
+        }
+        if let Some(signature) = &self.signature {
+            if self.signing_keys.is_empty() {
+                bail!("signature needs at least one signing key");
+            }
+            signature.validate(!self.checksum_urls.is_empty())?;
+        }
+
+        if self.sources.is_empty() {
+            bail!("At least one source is required");
+        }
+        for source in &self.sources {
+            source.validate()?;
+        }
+
+        self.detector.validate()
+    }
+
+    /// Validate the spec and turn it into a registry definition
+    pub fn into_definition(self) -> Result<DistroDefinition> {
+        self.validate()
+            .with_context(|| format!("Invalid distro definition '{}'", self.name))?;
+
+        Ok(DistroDefinition {
+            display_name: self.display_name.unwrap_or_else(|| self.name.clone()),
+            name: self.name,
+            description: self.description,
+            homepage: self.homepage,
+            supported_architectures: self.architectures,
+            supported_variants: self.variants,
+            version_detector: self.detector.build(),
+            download_sources: self.sources.iter().map(SourceSpec::build).collect(),
+            filename_pattern: self.filename_pattern,
+            default_variant: self.default_variant,
+            checksum_urls: self.checksum_urls,
+            mirror_list: None,
+            signing_keys: self
+                .signing_keys
+                .iter()
+                .map(SigningKeySpec::build)
+                .collect(),
+            signature: self.signature.as_ref().map(SignatureSpec::build),
+        })
+    }
+}
+
+impl DetectorSpec {
+    fn validate(&self) -> Result<()> {
+        match self {
+            DetectorSpec::Github { owner, repo, .. } => {
+                if owner.is_empty() || repo.is_empty() {
+                    bail!("github detector needs both owner and repo");
+                }
+            }
+            DetectorSpec::Rss {
+                url, version_regex, ..
+            }
+            | DetectorSpec::Scrape {
+                url, version_regex, ..
+            } => {
+                check_url("detector URL", url)?;
+                let regex = regex::Regex::new(version_regex)
+                    .with_context(|| format!("Invalid version_regex '{}'", version_regex))?;
+                // Detectors take the version from the first capture group
+                if regex.captures_len() < 2 {
+                    bail!(
+                        "version_regex '{}' needs a capture group around the version",
+                        version_regex
+                    );
+                }
+            }
+            DetectorSpec::Api { url, version_path } => {
+                check_url("detector URL", url)?;
+                if version_path.strip_prefix("$.").is_none_or(str::is_empty) {
+                    bail!(
+                        "version_path must look like '$.field', got '{}'",
+                        version_path
+                    );
+                }
+            }
+            DetectorSpec::Static { versions } => {
+                if versions.is_empty() {
+                    bail!("static detector needs at least one version");
+                }
+            }
+            DetectorSpec::Composite { detectors } => {
+                if detectors.is_empty() {
+                    bail!("composite detector needs at least one detector");
+                }
+                for detector in detectors {
+                    detector.validate()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn build(&self) -> Box<dyn VersionDetector> {
+        match self {
+            DetectorSpec::Github {
+                owner,
+                repo,
+                include_prereleases,
+                version_prefix,
+            } => {
+                let mut detector =
+                    GitHubVersionDetector::new(owner.clone(), repo.clone(), *include_prereleases);
+                if let Some(prefix) = version_prefix {
+                    detector = detector.with_version_prefix(prefix.clone());
+                }
+                Box::new(detector)
+            }
+            DetectorSpec::Rss {
+                url,
+                version_regex,
+                release_type,
+            } => Box::new(FeedVersionDetector::new(
+                url.clone(),
+                version_regex.clone(),
+                release_type.clone(),
+            )),
+            DetectorSpec::Scrape {
+                url,
+                version_regex,
+                selector,
+            } => Box::new(WebScrapingDetector::new(
+                url.clone(),
+                selector.clone(),
+                version_regex.clone(),
+            )),
+            DetectorSpec::Api { url, version_path } => {
+                Box::new(ApiVersionDetector::new(url.clone(), version_path.clone()))
+            }
+            DetectorSpec::Static { versions } => Box::new(StaticVersionDetector {
+                versions: versions.iter().map(StaticVersion::build).collect(),
+            }),
+            DetectorSpec::Composite { detectors } => Box::new(CompositeVersionDetector {
+                detectors: detectors.iter().map(DetectorSpec::build).collect(),
+            }),
+        }
+    }
+}
+
+impl StaticVersion {
+    fn build(&self) -> VersionInfo {
+        let mut version = VersionInfo::new(&self.version, self.release_type.clone());
+        if let Some(date) = &self.release_date {
+            version = version.with_release_date(date);
+        }
+        if let Some(notes) = &self.notes {
+            version = version.with_notes(notes);
+        }
+        version
+    }
+}
+
+impl SigningKeySpec {
+    fn validate(&self) -> Result<()> {
+        let fingerprint = self.fingerprint.replace(' ', "");
+        if fingerprint.len() != 40 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!(
+                "Signing key fingerprint must be 40 hex digits, got '{}'",
+                self.fingerprint
+            );
+        }
+        if let Some(url) = &self.url {
+            check_url("signing key URL", url)?;
+        }
+        Ok(())
+    }
+
+    fn build(&self) -> SigningKey {
+        let key = SigningKey::new(&self.fingerprint, &self.description);
+        match &self.url {
+            Some(url) => key.with_url(url),
+            None => key,
+        }
+    }
+}
+
+impl SignatureSpec {
+    fn validate(&self, has_checksum_urls: bool) -> Result<()> {
+        match self {
+            SignatureSpec::DetachedChecksum { suffix } => {
+                if suffix.is_empty() || suffix.contains(['/', '\\']) {
+                    bail!(
+                        "detached_checksum signature needs a suffix such as '.gpg', got '{}'",
+                        suffix
+                    );
+                }
+            }
+            SignatureSpec::ClearsignedChecksum => {}
+            SignatureSpec::DetachedIso { url } => return check_url("signature URL", url),
+        }
+        if !has_checksum_urls {
+            bail!("Checksum signatures need checksum_urls");
+        }
+        Ok(())
+    }
+
+    fn build(&self) -> SignatureSource {
+        match self {
+            SignatureSpec::DetachedChecksum { suffix } => SignatureSource::DetachedChecksum {
+                suffix: suffix.clone(),
+            },
+            SignatureSpec::ClearsignedChecksum => SignatureSource::ClearsignedChecksum,
+            SignatureSpec::DetachedIso { url } => SignatureSource::DetachedIso { url: url.clone() },
+        }
+    }
+}
+
+impl SourceSpec {
+    fn validate(&self) -> Result<()> {
+        if self.source_type == SourceType::Magnet {
+            if !self.url.starts_with("magnet:") {
+                bail!("Magnet source must be a magnet: link, got '{}'", self.url);
+            }
+            return check_placeholders("magnet link", &self.url);
+        }
+        if !self.trackers.is_empty() {
+            bail!("trackers only apply to magnet sources: '{}'", self.url);
+        }
+        check_url("source URL", &self.url)
+    }
+
+    fn build(&self) -> DownloadSource {
+        let mut source = match self.source_type {
+            SourceType::Direct => DownloadSource::direct(&self.url, self.priority),
+            SourceType::Mirror => {
+                DownloadSource::mirror(&self.url, self.priority, self.region.as_deref())
+            }
+            SourceType::Torrent => DownloadSource::torrent(&self.url, self.priority),
+            SourceType::Magnet => {
+                DownloadSource::magnet(&self.url, self.priority, self.trackers.clone())
+            }
+            SourceType::Zsync => DownloadSource::zsync(&self.url, self.priority),
+            SourceType::Metalink => DownloadSource::metalink(&self.url, self.priority),
+        };
+
+        if let Some(region) = &self.region {
+            source = source.with_region(region);
+        }
+        if let Some(description) = &self.description {
+            source = source.with_description(description);
+        }
+        if self.verified {
+            source = source.verified();
+        }
+        source
+    }
+}
+
+/// Reject placeholders the registry does not fill in
+fn check_placeholders(what: &str, value: &str) -> Result<()> {
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            bail!("Unclosed placeholder in {}: '{}'", what, value);
+        };
+        let placeholder = &rest[start + 1..end];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            bail!(
+                "Unknown placeholder '{{{}}}' in {}: '{}'",
+                placeholder,
+                what,
+                value
+            );
+        }
+        rest = &rest[end + 1..];
+    }
+    Ok(())
+}
+
+/// Check that `url` is an HTTP(S) or FTP URL once its placeholders are filled in
+fn check_url(what: &str, url: &str) -> Result<()> {
+    check_placeholders(what, url)?;
+
+    let filled = PLACEHOLDERS
+        .iter()
+        .fold(url.to_string(), |url, placeholder| {
+            url.replace(&format!("{{{}}}", placeholder), "x")
+        });
+    let parsed = Url::parse(&filled).with_context(|| format!("Invalid {}: '{}'", what, url))?;
+    if !matches!(parsed.scheme(), "http" | "https" | "ftp") {
+        bail!("Unsupported scheme in {}: '{}'", what, url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAILS: &str = r#"
+name = "tails"
+display_name = "Tails"
+description = "Portable amnesic operating system"
+homepage = "https://tails.net"
+architectures = ["amd64"]
+variants = ["usb", "dvd"]
+default_variant = "usb"
+filename_pattern = "tails-{arch}-{version}.img"
+checksum_urls = ["https://tails.net/install/v2/Tails/amd64/stable/latest.json"]
+
+[[signing_keys]]
+fingerprint = "A490 D0F4 D311 A415 3E2B  B7CA DBB8 02B2 58AC D84F"
+description = "Tails developers"
+url = "https://tails.net/tails-signing.key"
+
+[signature]
+type = "detached_iso"
+url = "https://tails.net/torrents/files/tails-{arch}-{version}.img.sig"
+
+[detector]
+type = "composite"
+
+[[detector.detectors]]
+type = "rss"
+url = "https://tails.net/news/index.en.rss"
+version_regex = 'Tails (\d+\.\d+)'
+
+[[detector.detectors]]
+type = "static"
+versions = [{ version = "6.16", release_date = "2025-06-10" }]
+
+[[sources]]
+url = "https://download.tails.net/tails/stable/tails-{arch}-{version}/{filename}"
+priority = "preferred"
+verified = true
+
+[[sources]]
+type = "mirror"
+url = "https://mirrors.edge.kernel.org/tails/stable/tails-{arch}-{version}/{filename}"
+priority = "high"
+region = "US"
+"#;
+
+    #[test]
+    fn test_spec_parses_into_definition() {
+        let spec: DistroSpec = toml::from_str(TAILS).unwrap();
+        assert_eq!(
+            spec.detector,
+            DetectorSpec::Composite {
+                detectors: vec![
+                    DetectorSpec::Rss {
+                        url: "https://tails.net/news/index.en.rss".to_string(),
+                        version_regex: r"Tails (\d+\.\d+)".to_string(),
+                        release_type: ReleaseType::Stable,
+                    },
+                    DetectorSpec::Static {
+                        versions: vec![StaticVersion {
+                            version: "6.16".to_string(),
+                            release_type: ReleaseType::Stable,
+                            release_date: Some("2025-06-10".to_string()),
+                            notes: None,
+                        }],
+                    },
+                ],
+            }
+        );
+        // Specs survive a round trip, so they can be written back out
+        let written = toml::to_string(&spec).unwrap();
+        assert_eq!(toml::from_str::<DistroSpec>(&written).unwrap(), spec);
+
+        let definition = spec.into_definition().unwrap();
+        assert_eq!(definition.display_name, "Tails");
+        assert_eq!(definition.default_variant.as_deref(), Some("usb"));
+        assert_eq!(definition.download_sources.len(), 2);
+        let direct = &definition.download_sources[0];
+        assert_eq!(direct.source_type, SourceType::Direct);
+        assert_eq!(direct.priority, SourcePriority::Preferred);
+        assert!(direct.verified);
+        let mirror = &definition.download_sources[1];
+        assert_eq!(mirror.source_type, SourceType::Mirror);
+        assert_eq!(mirror.region.as_deref(), Some("US"));
+        assert_eq!(
+            definition.signing_keys,
+            vec![
+                SigningKey::new(
+                    "A490D0F4D311A4153E2BB7CADBB802B258ACD84F",
+                    "Tails developers"
+                )
+                .with_url("https://tails.net/tails-signing.key")
+            ]
+        );
+        assert_eq!(
+            definition.signature,
+            Some(SignatureSource::DetachedIso {
+                url: "https://tails.net/torrents/files/tails-{arch}-{version}.img.sig".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_specs_are_rejected() {
+        let spec: DistroSpec = toml::from_str(TAILS).unwrap();
+        let invalid = |change: fn(&mut DistroSpec)| {
+            let mut spec = spec.clone();
+            change(&mut spec);
+            spec.validate().is_err()
+        };
+
+        assert!(invalid(|s| s.name = "Tails OS".to_string()));
+        assert!(invalid(|s| s.default_variant = Some("netinst".to_string())));
+        assert!(invalid(
+            |s| s.filename_pattern = "tails/{version}.img".to_string()
+        ));
+        assert!(invalid(
+            |s| s.sources[0].url = "https://x/{release}.img".to_string()
+        ));
+        assert!(invalid(
+            |s| s.sources[0].url = "file:///srv/{filename}".to_string()
+        ));
+        assert!(invalid(|s| s.sources.clear()));
+        assert!(invalid(
+            |s| s.signing_keys[0].fingerprint = "DBB802B258ACD84F".to_string()
+        ));
+        assert!(invalid(|s| s.signing_keys.clear()));
+        assert!(invalid(|s| {
+            s.signature = Some(SignatureSpec::DetachedIso {
+                url: "https://tails.net/{release}.sig".to_string(),
+            })
+        }));
+        assert!(invalid(|s| {
+            s.checksum_urls.clear();
+            s.signature = Some(SignatureSpec::ClearsignedChecksum);
+        }));
+        assert!(invalid(|s| {
+            s.detector = DetectorSpec::Scrape {
+                url: "https://tails.net/".to_string(),
+                version_regex: r"Tails \d+\.\d+".to_string(),
+                selector: String::new(),
+            }
+        }));
+        assert!(invalid(|s| {
+            s.detector = DetectorSpec::Composite {
+                detectors: vec![DetectorSpec::Static { versions: vec![] }],
+            }
+        }));
+
+        // Typos in keys and detector types fail to parse instead of being ignored
+        assert!(toml::from_str::<DistroSpec>(&TAILS.replace("homepage", "home_page")).is_err());
+        assert!(
+            toml::from_str::<DistroSpec>(&TAILS.replace("type = \"rss\"", "type = \"atom\""))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_load_dir_reports_bad_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_dir(&dir.path().join("missing")).is_empty());
+
+        fs::write(dir.path().join("tails.toml"), TAILS).unwrap();
+        fs::write(dir.path().join("broken.toml"), "name = \"broken\"\n").unwrap();
+        fs::write(dir.path().join("README.md"), "not a definition").unwrap();
+
+        let loaded = load_dir(dir.path());
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded[0].0.ends_with("broken.toml"));
+        assert!(loaded[0].1.is_err());
+        assert_eq!(loaded[1].1.as_ref().unwrap().name, "tails");
+    }
+}
@@ -137,5 +137,14 @@ pub fn create_definition() -> Result<DistroDefinition> {
             "https://archlinux.org/iso/latest/b2sums.txt".to_string(),
             "https://archive.archlinux.org/iso/{version}/sha256sums.txt".to_string(),
         ],
+        mirror_list: Some(Box::new(ArchMirrorStatus)),
+        // Arch signs the ISO itself rather than the checksum files
+        signing_keys: vec![SigningKey::new(
+            "3E80 CA1A 8B89 F69C BA57  D98A 76A5 EF90 5444 9A5C",
+            "Pierre Schmitz (Arch Linux release engineering)",
+        )],
+        signature: Some(SignatureSource::DetachedIso {
+            url: "https://archive.archlinux.org/iso/{version}/{filename}.sig".to_string(),
+        }),
     })
 }
@@ -123,6 +123,20 @@ pub fn create_definition() -> Result<DistroDefinition> {
             "https://cdimage.debian.org/debian-cd/current/{arch}/iso-cd/SHA512SUMS".to_string(),
             "https://cdimage.debian.org/debian-cd/current/{arch}/iso-cd/MD5SUMS".to_string(),
         ],
+        mirror_list: Some(Box::new(DebianCdMirrors)),
+        signing_keys: vec![
+            SigningKey::new(
+                "DF9B 9C49 EAA9 2984 3258  9D76 DA87 E80D 6294 BE9B",
+                "Debian CD signing key",
+            ),
+            SigningKey::new(
+                "F41D 3034 2F35 4669 5F65  C669 4246 8F40 09EA 8AC3",
+                "Debian Testing CDs Automatic Signing Key",
+            ),
+        ],
+        signature: Some(SignatureSource::DetachedChecksum {
+            suffix: ".sign".to_string(),
+        }),
     })
 }
 
@@ -44,14 +44,14 @@ pub fn create_definition() -> Result<DistroDefinition> {
 
     // Fedora download sources including official and mirrors
     let download_sources = vec![
-        // Official Fedora download
-        DownloadSource::direct(
+        // Official Fedora download via MirrorManager (metalink lists mirrors and hashes)
+        DownloadSource::metalink(
             "https://download.fedoraproject.org/pub/fedora/linux/releases/{version}/Workstation/{arch}/iso/{filename}",
             SourcePriority::Preferred
         ).with_description("Official Fedora downloads").verified(),
 
         // Alternative path for Server variant
-        DownloadSource::direct(
+        DownloadSource::metalink(
             "https://download.fedoraproject.org/pub/fedora/linux/releases/{version}/Server/{arch}/iso/{filename}",
             SourcePriority::Preferred
         ).with_description("Official Fedora Server downloads").verified(),
@@ -121,5 +121,19 @@ pub fn create_definition() -> Result<DistroDefinition> {
             "https://download.fedoraproject.org/pub/fedora/linux/releases/{version}/Workstation/{arch}/iso/Fedora-Workstation-{version}-1.5-{arch}-CHECKSUM".to_string(),
             "https://getfedora.org/static/checksums/Fedora-Workstation-{version}-1.5-{arch}-CHECKSUM".to_string(),
         ],
+        mirror_list: None,
+        // One key per release, all published in fedora.gpg
+        signing_keys: [
+            ("115D F9AE F857 853E E844  5D0A 0727 707E A15B 79CC", "Fedora 40"),
+            ("466C F2D8 B60B C305 7AA9  453E D062 2462 E99D 6AD1", "Fedora 41"),
+            ("B0F4 9504 58F6 9E11 50C6  C5ED C8AC 4916 105E F944", "Fedora 42"),
+        ]
+        .into_iter()
+        .map(|(fingerprint, release)| {
+            SigningKey::new(fingerprint, &format!("{} release key", release))
+                .with_url("https://fedoraproject.org/fedora.gpg")
+        })
+        .collect(),
+        signature: Some(SignatureSource::ClearsignedChecksum),
     })
 }
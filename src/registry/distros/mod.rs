@@ -4,4 +4,6 @@ pub mod fedora;
 pub mod ubuntu;
 
 // Re-export common functionality that distro modules might need
-pub use crate::registry::{DistroDefinition, sources::*, version_detection::*};
+pub use crate::registry::{
+    DistroDefinition, mirror_lists::*, signature::*, sources::*, version_detection::*,
+};
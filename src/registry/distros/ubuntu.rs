@@ -95,6 +95,13 @@ pub fn create_definition() -> Result<DistroDefinition> {
             SourcePriority::High,
         )
         .with_description("Official Ubuntu torrent"),
+        // zsync control files allow delta updates from an older local ISO
+        DownloadSource::zsync(
+            "https://releases.ubuntu.com/{version}/{filename}.zsync",
+            SourcePriority::High,
+        )
+        .with_description("Official Ubuntu zsync control file")
+        .verified(),
     ];
 
     Ok(DistroDefinition {
@@ -123,5 +130,19 @@ pub fn create_definition() -> Result<DistroDefinition> {
             "https://releases.ubuntu.com/{version}/MD5SUMS".to_string(),
             "https://old-releases.ubuntu.com/releases/{version}/SHA256SUMS".to_string(),
         ],
+        mirror_list: Some(Box::new(LaunchpadCdMirrors)),
+        signing_keys: vec![
+            SigningKey::new(
+                "8439 38DF 228D 22F7 B374  2BC0 D94A A3F0 EFE2 1092",
+                "Ubuntu CD Image Automatic Signing Key (2012)",
+            ),
+            SigningKey::new(
+                "C598 6B4F 1257 FFA8 6632  CBA7 4618 1433 FBB7 5451",
+                "Ubuntu CD Image Automatic Signing Key",
+            ),
+        ],
+        signature: Some(SignatureSource::DetachedChecksum {
+            suffix: ".gpg".to_string(),
+        }),
     })
 }
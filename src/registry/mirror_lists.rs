@@ -0,0 +1,498 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::sources::{DownloadSource, SourcePriority};
+use crate::download::SOURCE_SCHEMES;
+use crate::http::HttpContext;
+
+const CACHE_DIR: &str = "mirror_lists";
+
+/// Mirror announced by an official mirror list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedMirror {
+    /// Root of the distro's image tree on the mirror, ending in `/`
+    pub base_url: String,
+    /// URL scheme the mirror is reached with (http, https, ftp, rsync)
+    pub protocol: String,
+    /// ISO country code
+    pub region: Option<String>,
+}
+
+impl ListedMirror {
+    fn new(base_url: &str, region: Option<&str>) -> Option<Self> {
+        let url = Url::parse(base_url).ok()?;
+        url.host_str()?;
+
+        let mut base_url = url.to_string();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+
+        Some(Self {
+            protocol: url.scheme().to_string(),
+            base_url,
+            region: region
+                .map(str::trim)
+                .filter(|r| !r.is_empty())
+                .map(str::to_uppercase),
+        })
+    }
+
+    /// Download source for `iso_path` below the mirror root
+    pub fn to_source(&self, provider: &str, iso_path: &str) -> DownloadSource {
+        let url = format!("{}{}", self.base_url, iso_path);
+        DownloadSource::mirror(&url, SourcePriority::Medium, self.region.as_deref())
+            .with_description(&format!("From the {} mirror list", provider))
+    }
+
+    fn is_http(&self) -> bool {
+        matches!(self.protocol.as_str(), "http" | "https")
+    }
+
+    fn is_supported(&self) -> bool {
+        SOURCE_SCHEMES.contains(&self.protocol.as_str())
+    }
+}
+
+/// Official list of mirrors published by a distro
+pub trait MirrorListProvider: Send + Sync + fmt::Debug {
+    /// Human readable name of the list
+    fn name(&self) -> &str;
+
+    /// Where the list is published
+    fn list_url(&self) -> &str;
+
+    /// ISO path below a mirror root, with the usual placeholders
+    fn iso_path(&self) -> &str;
+
+    /// Parse the published list, best mirrors first
+    fn parse(&self, content: &str) -> Result<Vec<ListedMirror>>;
+}
+
+/// Arch Linux mirror status JSON, ordered by its mirror score
+#[derive(Debug)]
+pub struct ArchMirrorStatus;
+
+#[derive(Deserialize)]
+struct ArchStatus {
+    urls: Vec<ArchMirror>,
+}
+
+#[derive(Deserialize)]
+struct ArchMirror {
+    url: String,
+    #[serde(default)]
+    country_code: Option<String>,
+    #[serde(default)]
+    active: bool,
+    #[serde(default)]
+    isos: bool,
+    #[serde(default)]
+    completion_pct: Option<f64>,
+    /// Lower is better
+    #[serde(default)]
+    score: Option<f64>,
+}
+
+impl MirrorListProvider for ArchMirrorStatus {
+    fn name(&self) -> &str {
+        "Arch Linux"
+    }
+
+    fn list_url(&self) -> &str {
+        "https://archlinux.org/mirrors/status/json/"
+    }
+
+    fn iso_path(&self) -> &str {
+        "iso/{version}/{filename}"
+    }
+
+    fn parse(&self, content: &str) -> Result<Vec<ListedMirror>> {
+        let status: ArchStatus =
+            serde_json::from_str(content).context("Failed to parse Arch mirror status")?;
+
+        // Only mirrors that carry ISOs and are fully synced
+        let mut mirrors: Vec<_> = status
+            .urls
+            .into_iter()
+            .filter(|m| m.active && m.isos && m.completion_pct.is_some_and(|pct| pct >= 1.0))
+            .collect();
+        mirrors.sort_by(|a, b| {
+            let score = |m: &ArchMirror| m.score.unwrap_or(f64::MAX);
+            score(a).total_cmp(&score(b))
+        });
+
+        Ok(mirrors
+            .iter()
+            .filter_map(|m| ListedMirror::new(&m.url, m.country_code.as_deref()))
+            .collect())
+    }
+}
+
+/// Debian mirror masterlist, the source of the CD mirror page on debian.org
+#[derive(Debug)]
+pub struct DebianCdMirrors;
+
+impl MirrorListProvider for DebianCdMirrors {
+    fn name(&self) -> &str {
+        "Debian"
+    }
+
+    fn list_url(&self) -> &str {
+        "https://salsa.debian.org/mirror-team/masterlist/-/raw/master/Mirrors.masterlist"
+    }
+
+    /// CD mirrors keep the current point release under its version as well as under
+    /// `current/`, so older releases miss instead of getting the newest image
+    fn iso_path(&self) -> &str {
+        "{version}/{arch}/iso-cd/{filename}"
+    }
+
+    /// Stanzas of `Field: value` lines; `CDImage-<protocol>` gives the path of the CD tree
+    fn parse(&self, content: &str) -> Result<Vec<ListedMirror>> {
+        let mut mirrors = Vec::new();
+
+        for stanza in content.split("\n\n") {
+            let mut site = None;
+            let mut country = None;
+            let mut paths = Vec::new();
+
+            for line in stanza.lines() {
+                // Continuation lines of multi-line fields
+                if line.starts_with(char::is_whitespace) {
+                    continue;
+                }
+                let Some((field, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+
+                match field {
+                    "Site" => site = Some(value),
+                    // "AU Australia"
+                    "Country" => country = value.split_whitespace().next(),
+                    _ => {
+                        if let Some(protocol) = field.strip_prefix("CDImage-") {
+                            paths.push((protocol.to_lowercase(), value));
+                        }
+                    }
+                }
+            }
+
+            let Some(site) = site else {
+                continue;
+            };
+            for (protocol, path) in paths {
+                let url = format!("{}://{}/{}", protocol, site, path.trim_start_matches('/'));
+                mirrors.extend(ListedMirror::new(&url, country));
+            }
+        }
+
+        if mirrors.is_empty() {
+            bail!("No CD image mirrors found in Debian masterlist");
+        }
+        Ok(mirrors)
+    }
+}
+
+/// Launchpad's RSS listing of official Ubuntu CD image mirrors
+#[derive(Debug)]
+pub struct LaunchpadCdMirrors;
+
+impl MirrorListProvider for LaunchpadCdMirrors {
+    fn name(&self) -> &str {
+        "Ubuntu"
+    }
+
+    fn list_url(&self) -> &str {
+        "https://launchpad.net/ubuntu/+cdmirrors-rss"
+    }
+
+    fn iso_path(&self) -> &str {
+        "{version}/{filename}"
+    }
+
+    /// One `<item>` per mirror and protocol, with the URL in `<link>` and the
+    /// country in `<mirror:countrycode>`
+    fn parse(&self, content: &str) -> Result<Vec<ListedMirror>> {
+        let document =
+            roxmltree::Document::parse(content).context("Failed to parse Launchpad mirror RSS")?;
+
+        Ok(document
+            .descendants()
+            .filter(|n| n.tag_name().name() == "item")
+            .filter_map(|item| {
+                ListedMirror::new(
+                    descendant_text(item, "link")?,
+                    descendant_text(item, "countrycode"),
+                )
+            })
+            .collect())
+    }
+}
+
+/// Text of the first descendant named `name`, ignoring namespaces
+fn descendant_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.descendants()
+        .find(|n| n.tag_name().name() == name)
+        .and_then(|n| n.text())
+        .map(str::trim)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedList {
+    fetched_at: DateTime<Utc>,
+    mirrors: Vec<ListedMirror>,
+}
+
+/// Downloaded mirror lists, kept in the state directory for `ttl`
+#[derive(Debug, Clone)]
+pub struct MirrorListCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl MirrorListCache {
+    pub fn new(dir: PathBuf, ttl_hours: u64) -> Self {
+        Self {
+            dir,
+            ttl: Duration::hours(ttl_hours.min(i64::MAX as u64) as i64),
+        }
+    }
+
+    /// Location of the cached lists inside a state directory
+    pub fn dir_in(state_dir: &Path) -> PathBuf {
+        state_dir.join(CACHE_DIR)
+    }
+
+    /// Mirrors for `distro`, fetched again once the cached copy expires.
+    ///
+    /// A stale copy is still used when the list cannot be fetched, so an
+    /// offline run falls back to what was last seen.
+    pub async fn mirrors(
+        &self,
+        http: &HttpContext,
+        distro: &str,
+        provider: &dyn MirrorListProvider,
+    ) -> Vec<ListedMirror> {
+        let path = self.dir.join(format!("{}.json", distro));
+        let cached = Self::load(&path);
+
+        if let Some(cached) = &cached
+            && Utc::now() - cached.fetched_at < self.ttl
+        {
+            return cached.mirrors.clone();
+        }
+
+        match fetch(http, provider).await {
+            Ok(mirrors) => {
+                let _ = self.save(
+                    &path,
+                    &CachedList {
+                        fetched_at: Utc::now(),
+                        mirrors: mirrors.clone(),
+                    },
+                );
+                mirrors
+            }
+            Err(_) => cached.map(|c| c.mirrors).unwrap_or_default(),
+        }
+    }
+
+    fn load(path: &Path) -> Option<CachedList> {
+        let content = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save(&self, path: &Path, list: &CachedList) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create mirror list cache: {:?}", self.dir))?;
+        let content = serde_json::to_string(list).context("Failed to serialize mirror list")?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .with_context(|| format!("Failed to write mirror list: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace mirror list: {:?}", path))?;
+        Ok(())
+    }
+}
+
+async fn fetch(http: &HttpContext, provider: &dyn MirrorListProvider) -> Result<Vec<ListedMirror>> {
+    let url = provider.list_url();
+    let response = http
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch mirror list: {}", url))?;
+
+    if !response.status().is_success() {
+        bail!(
+            "Mirror list request failed with status: {}",
+            response.status()
+        );
+    }
+
+    let content = response
+        .text()
+        .await
+        .context("Failed to read mirror list")?;
+    provider.parse(&content)
+}
+
+/// Pick mirrors to merge: up to `limit` from `preferred_region`, then up to
+/// `limit` of the best overall. HTTP is preferred over FTP and rsync for the
+/// same host, and hosts in `known_hosts` are skipped since the built-in entry
+/// for them is curated.
+pub fn select<'a>(
+    mirrors: &'a [ListedMirror],
+    preferred_region: Option<&str>,
+    known_hosts: &[String],
+    limit: usize,
+) -> Vec<&'a ListedMirror> {
+    let mut hosts = known_hosts.to_vec();
+    let mut usable = Vec::new();
+    let by_protocol = mirrors
+        .iter()
+        .filter(|m| m.is_http())
+        .chain(mirrors.iter().filter(|m| !m.is_http() && m.is_supported()));
+    for mirror in by_protocol {
+        // A site is often listed once per protocol; keep the first
+        let Some(host) = Url::parse(&mirror.base_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+        else {
+            continue;
+        };
+        if !hosts.contains(&host) {
+            hosts.push(host);
+            usable.push(mirror);
+        }
+    }
+
+    let in_region = |m: &&ListedMirror| {
+        preferred_region.is_some_and(|region| {
+            m.region
+                .as_deref()
+                .is_some_and(|r| r.eq_ignore_ascii_case(region))
+        })
+    };
+
+    let mut selected: Vec<_> = usable
+        .iter()
+        .copied()
+        .filter(in_region)
+        .take(limit)
+        .collect();
+    selected.extend(usable.iter().copied().filter(|m| !in_region(m)).take(limit));
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_arch_mirror_status() {
+        let content = r#"{"urls": [
+            {"url": "https://slow.example.org/arch/", "protocol": "https", "country_code": "US",
+             "active": true, "isos": true, "completion_pct": 1.0, "score": 9.5},
+            {"url": "https://fast.example.de/archlinux/", "protocol": "https", "country_code": "DE",
+             "active": true, "isos": true, "completion_pct": 1.0, "score": 0.8},
+            {"url": "rsync://fast.example.de/archlinux/", "protocol": "rsync", "country_code": "DE",
+             "active": true, "isos": true, "completion_pct": 1.0, "score": 0.8},
+            {"url": "https://behind.example.org/arch/", "protocol": "https", "country_code": "US",
+             "active": true, "isos": true, "completion_pct": 0.6, "score": 1.0},
+            {"url": "https://noisos.example.org/arch/", "protocol": "https", "country_code": "",
+             "active": true, "isos": false, "completion_pct": 1.0, "score": 1.0}
+        ]}"#;
+
+        let mirrors = ArchMirrorStatus.parse(content).unwrap();
+        assert_eq!(mirrors.len(), 3);
+        assert_eq!(mirrors[0].base_url, "https://fast.example.de/archlinux/");
+        assert_eq!(mirrors[0].region.as_deref(), Some("DE"));
+        assert_eq!(mirrors[1].protocol, "rsync");
+        assert_eq!(mirrors[2].base_url, "https://slow.example.org/arch/");
+
+        // Only HTTP mirrors are merged, those in the preferred region first
+        let selected = select(&mirrors, Some("us"), &[], 5);
+        let urls: Vec<_> = selected.iter().map(|m| m.base_url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://slow.example.org/arch/",
+                "https://fast.example.de/archlinux/"
+            ]
+        );
+
+        let source = mirrors[0].to_source(ArchMirrorStatus.name(), ArchMirrorStatus.iso_path());
+        assert_eq!(
+            source.get_url(),
+            Some("https://fast.example.de/archlinux/iso/{version}/{filename}")
+        );
+    }
+
+    #[test]
+    fn test_parse_debian_masterlist() {
+        let content = "Site: mirror.example.edu.au\n\
+                       Type: Push-Secondary\n\
+                       CDImage-ftp: /pub/debian-cd/\n\
+                       CDImage-http: /pub/debian-cd/\n\
+                       Sponsor: Example University\n \
+                       https://www.example.edu.au/\n\
+                       Country: AU Australia\n\
+                       \n\
+                       Site: archive-only.example.org\n\
+                       Archive-http: /debian/\n\
+                       Country: US United States\n";
+
+        let mirrors = DebianCdMirrors.parse(content).unwrap();
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(
+            mirrors[0].base_url,
+            "ftp://mirror.example.edu.au/pub/debian-cd/"
+        );
+        assert_eq!(
+            mirrors[1].base_url,
+            "http://mirror.example.edu.au/pub/debian-cd/"
+        );
+        assert_eq!(mirrors[1].region.as_deref(), Some("AU"));
+
+        let source = mirrors[1].to_source(DebianCdMirrors.name(), DebianCdMirrors.iso_path());
+        assert_eq!(
+            source.get_url(),
+            Some("http://mirror.example.edu.au/pub/debian-cd/{version}/{arch}/iso-cd/{filename}")
+        );
+    }
+
+    #[test]
+    fn test_parse_launchpad_rss() {
+        let content = r#"<?xml version="1.0"?>
+            <rss version="2.0" xmlns:mirror="https://launchpad.net/">
+              <channel>
+                <item>
+                  <title>Example Mirror</title>
+                  <link>https://mirror.example.jp/ubuntu-releases/</link>
+                  <mirror:location>
+                    <mirror:country>Japan</mirror:country>
+                    <mirror:countrycode>JP</mirror:countrycode>
+                  </mirror:location>
+                </item>
+                <item>
+                  <title>Example Mirror</title>
+                  <link>rsync://mirror.example.jp/ubuntu-releases/</link>
+                </item>
+              </channel>
+            </rss>"#;
+
+        let mirrors = LaunchpadCdMirrors.parse(content).unwrap();
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(mirrors[0].protocol, "https");
+        assert_eq!(mirrors[0].region.as_deref(), Some("JP"));
+        assert_eq!(mirrors[1].region, None);
+    }
+}
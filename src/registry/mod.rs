@@ -1,33 +1,72 @@
+pub mod definitions;
 pub mod distros;
+pub mod mirror_lists;
+pub mod signature;
 pub mod sources;
 pub mod version_detection;
 
 use anyhow::{Context, Result, bail};
 use console::{Term, style};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, DistroConfig, SourcesConfig};
+use crate::download::checksum::{self, ChecksumType};
+use crate::http::{self, HttpContext};
+use crate::image::{ChecksumLayer, ImageFormat};
+use crate::naming;
+use mirror_lists::{MirrorListCache, MirrorListProvider};
+use signature::{InvalidSignature, KeyStore, SignatureSource, SignatureStatus, SigningKey};
+use sources::{SourceOrigin, SourceType};
 
 pub use sources::DownloadSource;
 pub use version_detection::{ReleaseType, VersionDetector, VersionInfo};
 
+/// Mirrors taken from an official mirror list, per region group
+const LISTED_MIRRORS: usize = 5;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct IsoInfo {
     pub distro: String,
     pub version: String,
     pub architecture: String,
     pub variant: Option<String>,
+    /// Filename upstream, as used in source URLs
     pub filename: String,
+    /// Name the ISO is stored under locally, when it differs from the upstream filename
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_filename: Option<String>,
+    /// Image type and compression of the upstream file
+    #[serde(default)]
+    pub format: ImageFormat,
     pub download_sources: Vec<DownloadSource>,
     pub checksum: Option<String>,
     pub checksum_type: Option<String>,
+    /// Whether `checksum` covers the download or the decompressed image
+    #[serde(default)]
+    pub checksum_layer: ChecksumLayer,
     pub release_date: Option<String>,
     pub size_bytes: Option<u64>,
     pub release_type: ReleaseType,
 }
 
+impl IsoInfo {
+    /// Filename of the ISO on disk
+    pub fn local_filename(&self) -> &str {
+        self.local_filename.as_deref().unwrap_or(&self.filename)
+    }
+
+    /// Filename the download is written to, before a compressed image is unpacked
+    pub fn download_filename(&self) -> String {
+        match self.format.compression {
+            Some(compression) => format!("{}.{}", self.local_filename(), compression.extension()),
+            None => self.local_filename().to_string(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DistroDefinition {
     pub name: String,
@@ -41,26 +80,47 @@ pub struct DistroDefinition {
     pub filename_pattern: String,
     pub default_variant: Option<String>,
     pub checksum_urls: Vec<String>,
+    /// Official mirror list merged with the built-in mirrors
+    pub mirror_list: Option<Box<dyn MirrorListProvider>>,
+    /// Keys release signatures must come from
+    pub signing_keys: Vec<SigningKey>,
+    pub signature: Option<SignatureSource>,
+}
+
+/// Published checksum of an ISO and whether its checksum file was signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedChecksum {
+    pub value: String,
+    pub checksum_type: ChecksumType,
+    pub signature: SignatureStatus,
+    /// Checksum file the value was taken from
+    pub url: String,
+    /// Whether the value covers the download or the decompressed image
+    pub layer: ChecksumLayer,
 }
 
 pub struct IsoRegistry {
     distros: HashMap<String, DistroDefinition>,
     custom_distros: HashMap<String, DistroDefinition>,
-    http_client: Client,
+    http: HttpContext,
+    sources_config: SourcesConfig,
+    distro_configs: HashMap<String, DistroConfig>,
+    mirror_list_dir: Option<PathBuf>,
+    key_dir: Option<PathBuf>,
+    naming_template: String,
 }
 
 impl IsoRegistry {
     pub fn new() -> Self {
-        let http_client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("isod/0.1.0")
-            .build()
-            .unwrap_or_else(|_| Client::new());
-
         let mut registry = Self {
             distros: HashMap::new(),
             custom_distros: HashMap::new(),
-            http_client,
+            http: http::context().clone(),
+            sources_config: SourcesConfig::default(),
+            distro_configs: HashMap::new(),
+            mirror_list_dir: None,
+            key_dir: None,
+            naming_template: naming::DEFAULT_TEMPLATE.to_string(),
         };
 
         // Load built-in distro definitions
@@ -68,6 +128,60 @@ impl IsoRegistry {
         registry
     }
 
+    /// Apply user mirror settings from `[sources]` and `[distros.<name>]`
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.sources_config = config.sources.clone();
+        self.distro_configs = config.distros.clone();
+        self.naming_template = config.general.naming_template.clone();
+        self
+    }
+
+    /// Template local filenames are rendered from
+    pub fn naming_template(&self) -> &str {
+        &self.naming_template
+    }
+
+    /// Discover mirrors from official mirror lists, caching them in `cache_dir`
+    pub fn with_mirror_lists(mut self, cache_dir: PathBuf) -> Self {
+        self.mirror_list_dir = Some(cache_dir);
+        self
+    }
+
+    /// Store fetched signing keys in `key_dir`; signatures are not checked without one
+    pub fn with_signing_keys(mut self, key_dir: PathBuf) -> Self {
+        self.key_dir = Some(key_dir);
+        self
+    }
+
+    /// Add the user distro definitions in `dir`, reporting the ones that cannot be used
+    pub fn with_distro_definitions(mut self, dir: &Path) -> Self {
+        let term = Term::stderr();
+        for (path, spec) in definitions::load_dir(dir) {
+            let definition = spec.and_then(|spec| {
+                if self.distros.contains_key(&spec.name) {
+                    bail!("'{}' is already a built-in distro", spec.name);
+                }
+                if self.custom_distros.contains_key(&spec.name) {
+                    bail!("'{}' is already defined by another file", spec.name);
+                }
+                spec.into_definition()
+            });
+
+            match definition {
+                Ok(definition) => self.add_custom_distro(definition),
+                Err(e) => {
+                    let _ = term.write_line(&format!(
+                        "{} Skipping distro definition {}: {:#}",
+                        style("⚠️").yellow(),
+                        style(path.display()).cyan(),
+                        e
+                    ));
+                }
+            }
+        }
+        self
+    }
+
     /// Load all built-in distro definitions
     fn load_builtin_distros(&mut self) {
         let term = Term::stderr();
@@ -221,19 +335,28 @@ impl IsoRegistry {
             )
             .await?;
 
-        Ok(IsoInfo {
+        let format = ImageFormat::from_filename(&filename).unwrap_or_default();
+        let mut iso_info = IsoInfo {
             distro: distro.to_string(),
             version: version_info.version,
             architecture: arch.to_string(),
             variant: variant_str.map(|s| s.to_string()),
             filename,
+            local_filename: None,
+            format,
             download_sources,
             checksum: None, // Will be fetched when needed
-            checksum_type: Some("sha256".to_string()),
+            checksum_type: None,
+            checksum_layer: ChecksumLayer::Download,
             release_date: version_info.release_date,
             size_bytes: None, // Will be determined during download
             release_type: version_info.release_type,
-        })
+        };
+        let local_filename = naming::render(&self.naming_template, &iso_info);
+        if local_filename != iso_info.filename {
+            iso_info.local_filename = Some(local_filename);
+        }
+        Ok(iso_info)
     }
 
     /// Add a custom distro definition
@@ -335,13 +458,44 @@ impl IsoRegistry {
         variant: Option<&str>,
         filename: &str,
     ) -> Result<Vec<DownloadSource>> {
-        let mut resolved_sources = Vec::new();
+        let distro_config = self.distro_configs.get(&definition.name);
+        let disable_builtin_mirrors = self.sources_config.disable_builtin_mirrors
+            || distro_config.is_some_and(|c| c.disable_builtin_mirrors);
+
+        let listed_mirrors = self.listed_mirrors(definition).await;
+        let builtin_sources = definition
+            .download_sources
+            .iter()
+            .cloned()
+            .chain(listed_mirrors)
+            .filter(|s| !(disable_builtin_mirrors && s.source_type == SourceType::Mirror));
+
+        // Per-distro mirrors first, then global ones that apply to this distro
+        let user_mirrors = distro_config
+            .into_iter()
+            .flat_map(|c| &c.mirrors)
+            .chain(
+                self.sources_config
+                    .custom_mirrors
+                    .iter()
+                    .filter(|m| m.applies_to(&definition.name)),
+            )
+            .map(|mirror| {
+                DownloadSource::mirror(
+                    &mirror.url_template(),
+                    mirror.priority,
+                    mirror.region.as_deref(),
+                )
+                .with_description("Custom mirror")
+                .with_origin(SourceOrigin::User)
+            });
 
-        for source in &definition.download_sources {
-            let mut resolved_source = source.clone();
+        let mut resolved_sources = Vec::new();
 
+        for mut resolved_source in user_mirrors.chain(builtin_sources) {
             // Replace placeholders in URLs
             if let Some(url) = &mut resolved_source.url {
+                *url = url.replace("{distro}", &definition.name);
                 *url = url.replace("{version}", version);
                 *url = url.replace("{arch}", architecture);
                 *url = url.replace("{filename}", filename);
@@ -354,95 +508,294 @@ impl IsoRegistry {
             resolved_sources.push(resolved_source);
         }
 
+        if !self.sources_config.enable_mirrors {
+            resolved_sources.retain(|s| s.source_type == SourceType::Direct);
+        }
+
+        if let Some(region) = &self.sources_config.preferred_region {
+            resolved_sources = resolved_sources
+                .into_iter()
+                .map(|s| s.with_region_preference(region))
+                .collect();
+        }
+
         Ok(resolved_sources)
     }
 
+    /// Mirrors from the distro's official mirror list that are not built in already
+    async fn listed_mirrors(&self, definition: &DistroDefinition) -> Vec<DownloadSource> {
+        let (Some(dir), Some(provider)) = (&self.mirror_list_dir, &definition.mirror_list) else {
+            return Vec::new();
+        };
+        if !self.sources_config.discover_mirrors || !self.sources_config.enable_mirrors {
+            return Vec::new();
+        }
+
+        let cache = MirrorListCache::new(dir.clone(), self.sources_config.mirror_list_ttl_hours);
+        let mirrors = cache
+            .mirrors(&self.http, &definition.name, provider.as_ref())
+            .await;
+
+        let known_hosts: Vec<String> = definition
+            .download_sources
+            .iter()
+            .filter_map(|s| reqwest::Url::parse(s.get_url()?).ok())
+            .filter_map(|url| url.host_str().map(str::to_string))
+            .collect();
+
+        mirror_lists::select(
+            &mirrors,
+            self.sources_config.preferred_region.as_deref(),
+            &known_hosts,
+            LISTED_MIRRORS,
+        )
+        .into_iter()
+        .map(|mirror| mirror.to_source(provider.name(), provider.iso_path()))
+        .collect()
+    }
+
     /// Get checksum for an ISO with actual HTTP fetching
     pub async fn get_checksum(&self, iso_info: &IsoInfo) -> Result<Option<String>> {
+        Ok(self
+            .get_signed_checksum(iso_info)
+            .await?
+            .map(|checksum| checksum.value))
+    }
+
+    /// Published checksum of `iso_info` with the result of its signature check.
+    ///
+    /// Fails when the checksum file carries a signature that does not verify,
+    /// since a tampered checksum must never be used.
+    pub async fn get_signed_checksum(&self, iso_info: &IsoInfo) -> Result<Option<SignedChecksum>> {
         let definition = self
             .get_distro(&iso_info.distro)
             .context("Distro definition not found")?;
 
         for checksum_url_pattern in &definition.checksum_urls {
-            let mut checksum_url = checksum_url_pattern.clone();
+            let checksum_url = Self::resolve_placeholders(checksum_url_pattern, iso_info);
+
+            // Try to fetch checksum from this URL
+            let Ok(content) = self.fetch_bytes(&checksum_url).await else {
+                continue;
+            };
+            let text = String::from_utf8_lossy(&content);
+            let (signature, text) = match &definition.signature {
+                Some(SignatureSource::DetachedChecksum { suffix }) => {
+                    let signature_url = format!("{}{}", checksum_url, suffix);
+                    let status = match self.fetch_bytes(&signature_url).await {
+                        Ok(signature) => {
+                            self.verify_signature(definition, &content, Some(&signature))
+                                .await?
+                        }
+                        Err(_) => {
+                            SignatureStatus::unsigned(format!("no signature at {}", signature_url))
+                        }
+                    };
+                    (status, text.into_owned())
+                }
+                Some(SignatureSource::ClearsignedChecksum) => match signature::cleartext(&text) {
+                    Some(signed) => (
+                        self.verify_signature(definition, &content, None).await?,
+                        signed,
+                    ),
+                    None => (
+                        SignatureStatus::unsigned("checksum file is not clearsigned"),
+                        text.into_owned(),
+                    ),
+                },
+                Some(SignatureSource::DetachedIso { .. }) => (
+                    SignatureStatus::unsigned("the ISO signature is checked after download"),
+                    text.into_owned(),
+                ),
+                None => (
+                    SignatureStatus::unsigned("distro does not sign its checksums"),
+                    text.into_owned(),
+                ),
+            };
+
+            if let SignatureStatus::Invalid { reason } = &signature {
+                return Err(InvalidSignature {
+                    url: checksum_url,
+                    reason: reason.clone(),
+                }
+                .into());
+            }
+            // Compressed images are sometimes only listed by their decompressed name
+            let image_filename = iso_info.format.local_filename(&iso_info.filename);
+            let found = checksum::parse_checksum_file(&text, &iso_info.filename, &checksum_url)
+                .map(|found| (found, ChecksumLayer::Download))
+                .or_else(|| {
+                    iso_info
+                        .format
+                        .is_compressed()
+                        .then(|| {
+                            checksum::parse_checksum_file(&text, &image_filename, &checksum_url)
+                        })
+                        .flatten()
+                        .map(|found| (found, ChecksumLayer::Image))
+                });
+            if let Some((found, layer)) = found {
+                return Ok(Some(SignedChecksum {
+                    value: found.value,
+                    checksum_type: found.checksum_type,
+                    signature,
+                    url: checksum_url,
+                    layer,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Whether the distro publishes a detached signature for the ISO itself
+    pub fn signs_iso(&self, distro: &str) -> bool {
+        self.get_distro(distro)
+            .is_some_and(|d| matches!(d.signature, Some(SignatureSource::DetachedIso { .. })))
+    }
 
-            // Replace placeholders
-            checksum_url = checksum_url.replace("{version}", &iso_info.version);
-            checksum_url = checksum_url.replace("{arch}", &iso_info.architecture);
-            checksum_url = checksum_url.replace("{filename}", &iso_info.filename);
+    /// Check the detached signature a distro publishes for the ISO itself.
+    ///
+    /// Returns `None` for distros that only sign their checksum files.
+    pub async fn verify_iso_signature(
+        &self,
+        iso_info: &IsoInfo,
+        path: &Path,
+    ) -> Result<Option<SignatureStatus>> {
+        let definition = self
+            .get_distro(&iso_info.distro)
+            .context("Distro definition not found")?;
+        let Some(SignatureSource::DetachedIso { url }) = &definition.signature else {
+            return Ok(None);
+        };
 
-            if let Some(variant) = &iso_info.variant {
-                checksum_url = checksum_url.replace("{variant}", variant);
+        let signature_url = Self::resolve_placeholders(url, iso_info);
+        let signature = match self.fetch_bytes(&signature_url).await {
+            Ok(signature) => signature,
+            Err(_) => {
+                return Ok(Some(SignatureStatus::unsigned(format!(
+                    "no signature at {}",
+                    signature_url
+                ))));
             }
+        };
 
-            // Try to fetch checksum from this URL
-            if let Ok(checksum) = self.fetch_checksum(&checksum_url, &iso_info.filename).await {
-                return Ok(Some(checksum));
+        let signature_path = TempFile::write(&signature)?;
+        Ok(Some(
+            self.check_signature(definition, path, Some(signature_path.path()))
+                .await,
+        ))
+    }
+
+    /// Verify a detached `signature` of `data`, or `data` as a clearsigned file
+    async fn verify_signature(
+        &self,
+        definition: &DistroDefinition,
+        data: &[u8],
+        signature: Option<&[u8]>,
+    ) -> Result<SignatureStatus> {
+        let data_path = TempFile::write(data)?;
+        let signature_path = signature.map(TempFile::write).transpose()?;
+        Ok(self
+            .check_signature(
+                definition,
+                data_path.path(),
+                signature_path.as_ref().map(TempFile::path),
+            )
+            .await)
+    }
+
+    /// Check a signature against the distro's pinned keys.
+    ///
+    /// Keys that cannot be fetched leave the file unverifiable rather than
+    /// invalid, so an offline run with an empty key store still works.
+    async fn check_signature(
+        &self,
+        definition: &DistroDefinition,
+        data: &Path,
+        signature: Option<&Path>,
+    ) -> SignatureStatus {
+        let Some(key_dir) = &self.key_dir else {
+            return SignatureStatus::unsigned("no key store configured");
+        };
+        if definition.signing_keys.is_empty() {
+            return SignatureStatus::unsigned("no signing keys pinned");
+        }
+
+        match KeyStore::new(key_dir.clone())
+            .keyrings(&self.http, &definition.signing_keys)
+            .await
+        {
+            Ok(keyrings) => {
+                let status =
+                    signature::verify(&keyrings.paths, &definition.signing_keys, data, signature)
+                        .await;
+                match status {
+                    // A missing key may be why no pinned signature was found
+                    SignatureStatus::Invalid { reason } if !keyrings.warnings.is_empty() => {
+                        SignatureStatus::invalid(format!(
+                            "{} ({})",
+                            reason,
+                            keyrings.warnings.join("; ")
+                        ))
+                    }
+                    status => status,
+                }
             }
+            Err(e) => SignatureStatus::unverifiable(format!("{:#}", e)),
         }
+    }
 
-        Ok(None)
+    fn resolve_placeholders(pattern: &str, iso_info: &IsoInfo) -> String {
+        let mut url = pattern
+            .replace("{version}", &iso_info.version)
+            .replace("{arch}", &iso_info.architecture)
+            .replace("{filename}", &iso_info.filename);
+        if let Some(variant) = &iso_info.variant {
+            url = url.replace("{variant}", variant);
+        }
+        url
     }
 
-    /// Fetch checksum from a URL with actual HTTP implementation
-    async fn fetch_checksum(&self, url: &str, filename: &str) -> Result<String> {
+    async fn fetch_bytes(&self, url: &str) -> Result<Vec<u8>> {
         let response = self
-            .http_client
+            .http
             .get(url)
             .send()
             .await
-            .with_context(|| format!("Failed to fetch checksum from: {}", url))?;
+            .with_context(|| format!("Failed to fetch: {}", url))?;
 
         if !response.status().is_success() {
             bail!("HTTP request failed with status: {}", response.status());
         }
 
-        let content = response
-            .text()
+        Ok(response
+            .bytes()
             .await
-            .context("Failed to read checksum response as text")?;
-
-        // Parse checksum file formats (SHA256SUMS, MD5SUMS, etc.)
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
+            .with_context(|| format!("Failed to read response from: {}", url))?
+            .to_vec())
+    }
+}
 
-            // Handle different checksum file formats:
-            // Format 1: "checksum filename" (space separated)
-            // Format 2: "checksum *filename" (binary mode indicator)
-            // Format 3: "checksum  filename" (double space)
-
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let checksum = parts[0];
-                let file_in_line = parts[1].trim_start_matches('*'); // Remove binary mode indicator
-
-                // Check if this line contains our filename
-                if file_in_line == filename || file_in_line.ends_with(filename) {
-                    // Validate checksum format (should be hex)
-                    if checksum.chars().all(|c| c.is_ascii_hexdigit()) {
-                        return Ok(checksum.to_lowercase());
-                    }
-                }
-            }
+/// Scratch file handed to gpgv, removed on drop
+struct TempFile(PathBuf);
 
-            // Alternative format: "filename: checksum"
-            if let Some(colon_pos) = line.find(':') {
-                let (file_part, checksum_part) = line.split_at(colon_pos);
-                let file_part = file_part.trim();
-                let checksum = checksum_part[1..].trim(); // Remove the colon
+impl TempFile {
+    fn write(content: &[u8]) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("isod-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write temporary file: {:?}", path))?;
+        Ok(Self(path))
+    }
 
-                if (file_part == filename || file_part.ends_with(filename))
-                    && checksum.chars().all(|c| c.is_ascii_hexdigit())
-                {
-                    return Ok(checksum.to_lowercase());
-                }
-            }
-        }
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
 
-        bail!("Checksum for '{}' not found in checksum file", filename);
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
     }
 }
 
@@ -458,6 +811,155 @@ impl fmt::Display for IsoInfo {
         if let Some(variant) = &self.variant {
             write!(f, "-{}", variant)?;
         }
-        write!(f, ".iso")
+        write!(f, ".{}", self.format.image_type.extension())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CustomMirror;
+    use sources::SourcePriority;
+
+    fn mirror(url: &str, distros: &[&str]) -> CustomMirror {
+        CustomMirror {
+            url: url.to_string(),
+            priority: SourcePriority::Preferred,
+            region: None,
+            distros: distros.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_user_mirrors_win_over_builtin_sources() {
+        let mut config = Config::default();
+        config.sources.custom_mirrors = vec![
+            mirror("https://isos.example.org/{distro}/{version}", &[]),
+            mirror("https://fedora.example.org/", &["fedora"]),
+        ];
+        let ubuntu = config.distros.entry("ubuntu".to_string()).or_default();
+        ubuntu.mirrors = vec![mirror("https://mirror.internal/ubuntu/", &[])];
+        ubuntu.disable_builtin_mirrors = true;
+
+        let registry = IsoRegistry::new().with_config(&config);
+        let definition = distros::ubuntu::create_definition().unwrap();
+        let mut sources = registry
+            .resolve_download_sources(&definition, "24.04", "amd64", None, "test.iso")
+            .await
+            .unwrap();
+        sources.sort();
+
+        let urls: Vec<_> = sources.iter().filter_map(|s| s.get_url()).collect();
+        assert_eq!(urls[0], "https://mirror.internal/ubuntu/test.iso");
+        assert_eq!(urls[1], "https://isos.example.org/ubuntu/24.04/test.iso");
+        assert!(!urls.iter().any(|u| u.contains("fedora.example.org")));
+        assert!(
+            sources
+                .iter()
+                .filter(|s| s.source_type == SourceType::Mirror)
+                .all(|s| s.origin == SourceOrigin::User)
+        );
+
+        // With mirrors disabled only the official direct downloads remain
+        config.sources.enable_mirrors = false;
+        let registry = IsoRegistry::new().with_config(&config);
+        let sources = registry
+            .resolve_download_sources(&definition, "24.04", "amd64", None, "test.iso")
+            .await
+            .unwrap();
+        assert!(!sources.is_empty());
+        assert!(sources.iter().all(|s| s.source_type == SourceType::Direct));
+    }
+
+    #[tokio::test]
+    async fn test_preferred_region_outranks_distant_sources() {
+        let mut config = Config::default();
+        config.sources.preferred_region = Some("au".to_string());
+
+        let registry = IsoRegistry::new().with_config(&config);
+        let definition = distros::ubuntu::create_definition().unwrap();
+        let mut sources = registry
+            .resolve_download_sources(&definition, "24.04", "amd64", None, "test.iso")
+            .await
+            .unwrap();
+        sources.sort();
+
+        assert_eq!(sources[0].region.as_deref(), Some("AU"));
+        assert!(sources[0].get_url().unwrap().contains("aarnet"));
+    }
+
+    #[tokio::test]
+    async fn test_listed_mirrors_merge_with_builtin_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let cached = serde_json::json!({
+            "fetched_at": chrono::Utc::now(),
+            "mirrors": [
+                { "base_url": "https://mirror.example.nz/ubuntu-releases/", "protocol": "https", "region": "NZ" },
+                { "base_url": "https://mirror.aarnet.edu.au/pub/ubuntu/releases/", "protocol": "https", "region": "AU" },
+            ],
+        });
+        std::fs::write(dir.path().join("ubuntu.json"), cached.to_string()).unwrap();
+
+        let mut config = Config::default();
+        config.sources.preferred_region = Some("NZ".to_string());
+        let registry = IsoRegistry::new()
+            .with_config(&config)
+            .with_mirror_lists(dir.path().to_path_buf());
+        let definition = distros::ubuntu::create_definition().unwrap();
+        let mut sources = registry
+            .resolve_download_sources(&definition, "24.04", "amd64", None, "test.iso")
+            .await
+            .unwrap();
+        sources.sort();
+
+        // The nearby listed mirror wins; hosts that are built in are not duplicated
+        assert_eq!(
+            sources[0].get_url(),
+            Some("https://mirror.example.nz/ubuntu-releases/24.04/test.iso")
+        );
+        assert_eq!(
+            sources
+                .iter()
+                .filter(|s| s.get_url().unwrap().contains("aarnet"))
+                .count(),
+            1
+        );
+
+        config.sources.discover_mirrors = false;
+        let registry = IsoRegistry::new()
+            .with_config(&config)
+            .with_mirror_lists(dir.path().to_path_buf());
+        let sources = registry
+            .resolve_download_sources(&definition, "24.04", "amd64", None, "test.iso")
+            .await
+            .unwrap();
+        assert!(!sources.iter().any(|s| s.region.as_deref() == Some("NZ")));
+    }
+
+    #[test]
+    fn test_custom_mirror_config_formats() {
+        let config: Config = toml::from_str(
+            r#"
+            [sources]
+            custom_mirrors = [
+                "https://mirror.example.org/isos",
+                { url = "https://eu.example.org/{filename}", priority = "high", region = "EU" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let mirrors = &config.sources.custom_mirrors;
+        assert_eq!(mirrors[0].priority, SourcePriority::Preferred);
+        assert_eq!(
+            mirrors[0].url_template(),
+            "https://mirror.example.org/isos/{filename}"
+        );
+        assert_eq!(mirrors[1].priority, SourcePriority::High);
+        assert_eq!(mirrors[1].region.as_deref(), Some("EU"));
+        assert_eq!(
+            mirrors[1].url_template(),
+            "https://eu.example.org/{filename}"
+        );
     }
 }
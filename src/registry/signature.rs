@@ -0,0 +1,399 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::http::HttpContext;
+
+/// Directory of the state dir holding fetched public keys
+const KEY_DIR: &str = "keys";
+
+/// Keys without a published URL are looked up here by fingerprint
+const KEYSERVER_LOOKUP: &str =
+    "https://keyserver.ubuntu.com/pks/lookup?op=get&options=mr&search=0x";
+
+/// OpenPGP key a distro signs its releases with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningKey {
+    /// Primary key fingerprint, 40 uppercase hex digits
+    pub fingerprint: String,
+    pub description: String,
+    /// Where the public key is published
+    pub url: Option<String>,
+}
+
+impl SigningKey {
+    pub fn new(fingerprint: &str, description: &str) -> Self {
+        Self {
+            fingerprint: fingerprint.replace(' ', "").to_uppercase(),
+            description: description.to_string(),
+            url: None,
+        }
+    }
+
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    fn key_url(&self) -> String {
+        self.url
+            .clone()
+            .unwrap_or_else(|| format!("{}{}", KEYSERVER_LOOKUP, self.fingerprint))
+    }
+}
+
+/// Where a distro publishes the signature of a release
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureSource {
+    /// Detached signature of each checksum file at `<checksum url><suffix>`
+    DetachedChecksum { suffix: String },
+    /// Checksum files are clearsigned
+    ClearsignedChecksum,
+    /// Detached signature of the ISO itself; `url` may use the usual placeholders
+    DetachedIso { url: String },
+}
+
+/// Outcome of checking a signature against the pinned keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SignatureStatus {
+    /// Good signature from a pinned key
+    Signed {
+        fingerprint: String,
+    },
+    Unsigned {
+        reason: String,
+    },
+    /// Signed by a pinned key but the signature could not be checked, e.g. without gpgv
+    Unverifiable {
+        reason: String,
+    },
+    Invalid {
+        reason: String,
+    },
+}
+
+impl SignatureStatus {
+    pub fn unsigned(reason: impl Into<String>) -> Self {
+        SignatureStatus::Unsigned {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn invalid(reason: impl Into<String>) -> Self {
+        SignatureStatus::Invalid {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn unverifiable(reason: impl Into<String>) -> Self {
+        SignatureStatus::Unverifiable {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, SignatureStatus::Invalid { .. })
+    }
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Signed { fingerprint } => write!(f, "signed by {}", fingerprint),
+            SignatureStatus::Unsigned { reason } => write!(f, "unsigned ({})", reason),
+            SignatureStatus::Unverifiable { reason } => write!(f, "cannot verify ({})", reason),
+            SignatureStatus::Invalid { reason } => write!(f, "invalid signature: {}", reason),
+        }
+    }
+}
+
+/// A published signature that does not verify against the pinned keys
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Signature of {url} is invalid: {reason}")]
+pub struct InvalidSignature {
+    pub url: String,
+    pub reason: String,
+}
+
+/// Keyring files of the pinned keys that could be fetched
+#[derive(Debug, Clone, Default)]
+pub struct Keyrings {
+    pub paths: Vec<PathBuf>,
+    /// Why the other keys are missing
+    pub warnings: Vec<String>,
+}
+
+/// Public keys fetched for the pinned fingerprints, stored as binary keyrings
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Location of the key store inside a state directory
+    pub fn dir_in(state_dir: &Path) -> PathBuf {
+        state_dir.join(KEY_DIR)
+    }
+
+    /// Keyring files for `keys`, fetching the ones not seen before.
+    ///
+    /// Whatever the server returns is only trusted as far as the signature
+    /// fingerprint checks in `verify` allow, so a wrong key cannot pass.
+    pub async fn keyrings(&self, http: &HttpContext, keys: &[SigningKey]) -> Result<Keyrings> {
+        let mut keyrings = Keyrings::default();
+        for key in keys {
+            let path = self.dir.join(format!("{}.gpg", key.fingerprint));
+            if !path.exists()
+                && let Err(e) = self.fetch(http, key, &path).await
+            {
+                keyrings.warnings.push(format!(
+                    "failed to fetch signing key {}: {:#}",
+                    key.fingerprint, e
+                ));
+                continue;
+            }
+            keyrings.paths.push(path);
+        }
+
+        if keyrings.paths.is_empty() && !keys.is_empty() {
+            bail!(
+                "None of the pinned signing keys could be fetched: {}",
+                keyrings.warnings.join("; ")
+            );
+        }
+        Ok(keyrings)
+    }
+
+    async fn fetch(&self, http: &HttpContext, key: &SigningKey, path: &Path) -> Result<()> {
+        let url = key.key_url();
+        let response = http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch signing key: {}", url))?;
+        if !response.status().is_success() {
+            bail!("Key request failed with status: {}", response.status());
+        }
+        let content = response
+            .bytes()
+            .await
+            .context("Failed to read signing key")?;
+        let keyring = dearmor(&content)?;
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create key store: {:?}", self.dir))?;
+        let tmp_path = path.with_extension("gpg.tmp");
+        fs::write(&tmp_path, keyring)
+            .with_context(|| format!("Failed to write signing key: {:?}", tmp_path))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to write signing key: {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// Check `signature` over `data` with gpgv, or a clearsigned `data` without one
+pub async fn verify(
+    keyrings: &[PathBuf],
+    keys: &[SigningKey],
+    data: &Path,
+    signature: Option<&Path>,
+) -> SignatureStatus {
+    let mut command = Command::new("gpgv");
+    command.args(["--batch", "--status-fd", "1"]);
+    for keyring in keyrings {
+        command.arg("--keyring").arg(keyring);
+    }
+    if let Some(signature) = signature {
+        command.arg(signature);
+    }
+
+    let output = command
+        .arg(data)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await;
+    match output {
+        Ok(output) => parse_status(&String::from_utf8_lossy(&output.stdout), keys),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            SignatureStatus::unverifiable("gpgv is not installed")
+        }
+        Err(e) => SignatureStatus::unverifiable(format!("failed to run gpgv: {}", e)),
+    }
+}
+
+/// Interpret gpgv `--status-fd` output.
+///
+/// Files may carry several signatures (Ubuntu signs with two keys), so one
+/// from an unknown key is fine as long as a pinned key signed too; any bad
+/// signature fails the whole file.
+fn parse_status(output: &str, keys: &[SigningKey]) -> SignatureStatus {
+    let mut valid = Vec::new();
+    let mut problem = None;
+
+    for line in output.lines().filter_map(|l| l.strip_prefix("[GNUPG:] ")) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["BADSIG", ..] => return SignatureStatus::invalid("bad signature"),
+            ["REVKEYSIG", key_id, ..] => {
+                return SignatureStatus::invalid(format!("signing key {} is revoked", key_id));
+            }
+            ["VALIDSIG", .., primary] => valid.push(primary.to_uppercase()),
+            ["ERRSIG", key_id, ..] => {
+                problem = Some(format!("signed by unknown key {}", key_id));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(fingerprint) = valid
+        .iter()
+        .find(|fpr| keys.iter().any(|k| &k.fingerprint == *fpr))
+    {
+        return SignatureStatus::Signed {
+            fingerprint: fingerprint.clone(),
+        };
+    }
+    match valid.first() {
+        Some(fingerprint) => {
+            SignatureStatus::invalid(format!("signed by {} which is not pinned", fingerprint))
+        }
+        None => SignatureStatus::invalid(problem.unwrap_or_else(|| "no signature found".into())),
+    }
+}
+
+/// Signed text of a clearsigned message with dash-escaping undone
+pub fn cleartext(content: &str) -> Option<String> {
+    let mut lines = content.lines();
+    lines.find(|l| l.trim_end() == "-----BEGIN PGP SIGNED MESSAGE-----")?;
+    // Armor headers ("Hash: SHA256") end at the first blank line
+    lines.find(|l| l.trim().is_empty())?;
+
+    let mut text = Vec::new();
+    for line in lines {
+        if line.trim_end() == "-----BEGIN PGP SIGNATURE-----" {
+            return Some(text.join("\n"));
+        }
+        text.push(line.strip_prefix("- ").unwrap_or(line));
+    }
+    None
+}
+
+/// Binary form of ASCII-armored key blocks, which gpgv cannot read
+fn dearmor(data: &[u8]) -> Result<Vec<u8>> {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return Ok(data.to_vec());
+    };
+    if !text.contains("-----BEGIN PGP PUBLIC KEY BLOCK-----") {
+        bail!("Response is not an OpenPGP public key");
+    }
+
+    let mut keyring = Vec::new();
+    let mut lines = text.lines().map(str::trim);
+    while lines
+        .by_ref()
+        .any(|l| l == "-----BEGIN PGP PUBLIC KEY BLOCK-----")
+    {
+        lines.by_ref().find(|l| l.is_empty());
+        let encoded: String = lines
+            .by_ref()
+            .take_while(|l| !l.starts_with("-----END"))
+            // The "=XXXX" line is a CRC-24 of the block
+            .filter(|l| !(l.starts_with('=') && l.len() == 5))
+            .collect();
+        keyring.extend(
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .context("Invalid base64 in public key block")?,
+        );
+    }
+    Ok(keyring)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PINNED: &str = "DC2DFB35C62753783F9983DF9CC338AE6EAED172";
+
+    #[test]
+    fn test_parse_gpgv_status() {
+        let keys = [SigningKey::new(
+            "DC2D FB35 C627 5378 3F99  83DF 9CC3 38AE 6EAE D172",
+            "Test CD key",
+        )];
+        let good = format!(
+            "[GNUPG:] NEWSIG\n\
+             [GNUPG:] ERRSIG 46181433FBB75451 17 2 00 1714000000 9 -\n\
+             [GNUPG:] NO_PUBKEY 46181433FBB75451\n\
+             [GNUPG:] GOODSIG 9CC338AE6EAED172 Test CD <cd@example.org>\n\
+             [GNUPG:] VALIDSIG {0} 2026-10-18 1792328695 0 4 0 22 8 00 {0}\n",
+            PINNED
+        );
+        assert_eq!(
+            parse_status(&good, &keys),
+            SignatureStatus::Signed {
+                fingerprint: PINNED.to_string()
+            }
+        );
+
+        let bad = "[GNUPG:] BADSIG 9CC338AE6EAED172 Test CD <cd@example.org>\n";
+        assert!(parse_status(bad, &keys).is_invalid());
+
+        let unknown = "[GNUPG:] ERRSIG 46181433FBB75451 17 2 00 1714000000 9 -\n";
+        assert_eq!(
+            parse_status(unknown, &keys),
+            SignatureStatus::invalid("signed by unknown key 46181433FBB75451")
+        );
+    }
+
+    #[test]
+    fn test_cleartext_and_dearmor() {
+        let clearsigned = "-----BEGIN PGP SIGNED MESSAGE-----\n\
+                           Hash: SHA256\n\
+                           \n\
+                           # Fedora-Workstation-Live-x86_64-41-1.4.iso: 2398523392 bytes\n\
+                           - -- not a comment\n\
+                           SHA256 (Fedora.iso) = abc\n\
+                           -----BEGIN PGP SIGNATURE-----\n\
+                           \n\
+                           iHUEARYIAB0WIQ=\n\
+                           -----END PGP SIGNATURE-----\n";
+        assert_eq!(
+            cleartext(clearsigned).unwrap(),
+            "# Fedora-Workstation-Live-x86_64-41-1.4.iso: 2398523392 bytes\n\
+             -- not a comment\n\
+             SHA256 (Fedora.iso) = abc"
+        );
+        assert_eq!(cleartext("abc  x.iso\n"), None);
+
+        let armored = "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\
+                       Comment: two blocks\n\
+                       \n\
+                       AQID\n\
+                       =AAAA\n\
+                       -----END PGP PUBLIC KEY BLOCK-----\n\
+                       -----BEGIN PGP PUBLIC KEY BLOCK-----\n\
+                       \n\
+                       BAUG\n\
+                       -----END PGP PUBLIC KEY BLOCK-----\n";
+        assert_eq!(dearmor(armored.as_bytes()).unwrap(), vec![1, 2, 3, 4, 5, 6]);
+        assert!(dearmor(b"<html>Not found</html>").is_err());
+        assert_eq!(
+            dearmor(&[0x99, 0x01, 0xff]).unwrap(),
+            vec![0x99, 0x01, 0xff]
+        );
+    }
+}
@@ -4,20 +4,42 @@ use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum SourceType {
+    #[serde(alias = "direct")]
     Direct,
+    #[serde(alias = "mirror")]
     Mirror,
+    #[serde(alias = "torrent")]
     Torrent,
+    #[serde(alias = "magnet")]
     Magnet,
+    #[serde(alias = "zsync")]
+    Zsync,
+    #[serde(alias = "metalink")]
+    Metalink,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SourcePriority {
+    #[serde(alias = "low")]
     Low = 1,
+    #[serde(alias = "medium")]
     Medium = 2,
+    #[serde(alias = "high")]
     High = 3,
+    #[serde(alias = "preferred")]
     Preferred = 4,
 }
 
+/// Where a source came from
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SourceOrigin {
+    /// Shipped with a distro definition
+    #[default]
+    Builtin,
+    /// Configured by the user in `[sources]` or `[distros.<name>]`
+    User,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadSource {
     pub source_type: SourceType,
@@ -29,6 +51,17 @@ pub struct DownloadSource {
     pub description: Option<String>,
     pub verified: bool,
     pub speed_rating: Option<u8>, // 1-10 rating
+    #[serde(default)]
+    pub origin: SourceOrigin,
+    /// Source is in the user's preferred region
+    #[serde(default)]
+    pub region_preferred: bool,
+    /// Round-trip time measured by a mirror probe
+    #[serde(default)]
+    pub latency_ms: Option<u32>,
+    /// Score deducted for recent failures recorded in the mirror health history
+    #[serde(default)]
+    pub health_penalty: u32,
 }
 
 impl DownloadSource {
@@ -44,6 +77,10 @@ impl DownloadSource {
             description: None,
             verified: false,
             speed_rating: None,
+            origin: SourceOrigin::Builtin,
+            region_preferred: false,
+            latency_ms: None,
+            health_penalty: 0,
         }
     }
 
@@ -59,6 +96,10 @@ impl DownloadSource {
             description: None,
             verified: false,
             speed_rating: None,
+            origin: SourceOrigin::Builtin,
+            region_preferred: false,
+            latency_ms: None,
+            health_penalty: 0,
         }
     }
 
@@ -74,6 +115,10 @@ impl DownloadSource {
             description: None,
             verified: false,
             speed_rating: None,
+            origin: SourceOrigin::Builtin,
+            region_preferred: false,
+            latency_ms: None,
+            health_penalty: 0,
         }
     }
 
@@ -89,6 +134,48 @@ impl DownloadSource {
             description: None,
             verified: false,
             speed_rating: None,
+            origin: SourceOrigin::Builtin,
+            region_preferred: false,
+            latency_ms: None,
+            health_penalty: 0,
+        }
+    }
+
+    /// Create a new zsync control file source for delta updates
+    pub fn zsync(control_url: &str, priority: SourcePriority) -> Self {
+        Self {
+            source_type: SourceType::Zsync,
+            priority,
+            url: Some(control_url.to_string()),
+            magnet_link: None,
+            trackers: Vec::new(),
+            region: None,
+            description: None,
+            verified: false,
+            speed_rating: None,
+            origin: SourceOrigin::Builtin,
+            region_preferred: false,
+            latency_ms: None,
+            health_penalty: 0,
+        }
+    }
+
+    /// Create a new metalink source (a `.meta4` document or a metalink-aware redirector)
+    pub fn metalink(url: &str, priority: SourcePriority) -> Self {
+        Self {
+            source_type: SourceType::Metalink,
+            priority,
+            url: Some(url.to_string()),
+            magnet_link: None,
+            trackers: Vec::new(),
+            region: None,
+            description: None,
+            verified: false,
+            speed_rating: None,
+            origin: SourceOrigin::Builtin,
+            region_preferred: false,
+            latency_ms: None,
+            health_penalty: 0,
         }
     }
 
@@ -110,6 +197,21 @@ impl DownloadSource {
         self
     }
 
+    /// Set where the source came from
+    pub fn with_origin(mut self, origin: SourceOrigin) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Mark the source if it is in `preferred_region` (case-insensitive)
+    pub fn with_region_preference(mut self, preferred_region: &str) -> Self {
+        self.region_preferred = self
+            .region
+            .as_deref()
+            .is_some_and(|region| region.eq_ignore_ascii_case(preferred_region));
+        self
+    }
+
     /// Set region for the source
     pub fn with_region(mut self, region: &str) -> Self {
         self.region = Some(region.to_string());
@@ -124,7 +226,11 @@ impl DownloadSource {
     /// Check if this source is usable (has required fields)
     pub fn is_usable(&self) -> bool {
         match self.source_type {
-            SourceType::Direct | SourceType::Mirror | SourceType::Torrent => self.url.is_some(),
+            SourceType::Direct
+            | SourceType::Mirror
+            | SourceType::Torrent
+            | SourceType::Zsync
+            | SourceType::Metalink => self.url.is_some(),
             SourceType::Magnet => self.magnet_link.is_some(),
         }
     }
@@ -143,15 +249,31 @@ impl DownloadSource {
             score += 500;
         }
 
+        // User mirrors outrank every built-in source
+        if self.origin == SourceOrigin::User {
+            score += 4000;
+        }
+
+        // A nearby mirror beats distant sources up to three priority tiers above it
+        if self.region_preferred {
+            score += 3000;
+        }
+
         // Prefer direct sources over mirrors for reliability
         match self.source_type {
-            SourceType::Direct => score += 200,
+            SourceType::Direct | SourceType::Metalink => score += 200,
             SourceType::Torrent => score += 150,
             SourceType::Magnet => score += 100,
             SourceType::Mirror => score += 50,
+            SourceType::Zsync => {}
+        }
+
+        // Slow round trips cost up to half a priority tier
+        if let Some(latency_ms) = self.latency_ms {
+            score = score.saturating_sub(latency_ms.min(1000) / 2);
         }
 
-        score
+        score.saturating_sub(self.health_penalty)
     }
 }
 
@@ -183,6 +305,8 @@ impl fmt::Display for SourceType {
             SourceType::Mirror => write!(f, "Mirror"),
             SourceType::Torrent => write!(f, "Torrent"),
             SourceType::Magnet => write!(f, "Magnet"),
+            SourceType::Zsync => write!(f, "Zsync"),
+            SourceType::Metalink => write!(f, "Metalink"),
         }
     }
 }
@@ -1,20 +1,28 @@
 use anyhow::{Context, Result, bail};
 use async_trait::async_trait;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
-use std::time::Duration;
+
+use crate::http::{self, HttpContext};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ReleaseType {
+    #[serde(alias = "stable")]
     Stable,
+    #[serde(alias = "lts")]
     LTS, // Long Term Support
+    #[serde(alias = "beta")]
     Beta,
+    #[serde(alias = "alpha")]
     Alpha,
-    RC,       // Release Candidate
-    Daily,    // Daily builds
-    Weekly,   // Weekly builds
+    #[serde(alias = "rc")]
+    RC, // Release Candidate
+    #[serde(alias = "daily")]
+    Daily, // Daily builds
+    #[serde(alias = "weekly")]
+    Weekly, // Weekly builds
+    #[serde(alias = "snapshot")]
     Snapshot, // Development snapshots
 }
 
@@ -187,22 +195,16 @@ pub struct FeedVersionDetector {
     pub feed_url: String,
     pub version_regex: String,
     pub release_type: ReleaseType,
-    client: Client,
+    http: HttpContext,
 }
 
 impl FeedVersionDetector {
     pub fn new(feed_url: String, version_regex: String, release_type: ReleaseType) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("isod/0.1.0")
-            .build()
-            .unwrap_or_else(|_| Client::new());
-
         Self {
             feed_url,
             version_regex,
             release_type,
-            client,
+            http: http::context().clone(),
         }
     }
 }
@@ -211,7 +213,7 @@ impl FeedVersionDetector {
 impl VersionDetector for FeedVersionDetector {
     async fn detect_versions(&self) -> Result<Vec<VersionInfo>> {
         let response = self
-            .client
+            .http
             .get(&self.feed_url)
             .send()
             .await
@@ -259,23 +261,17 @@ pub struct GitHubVersionDetector {
     pub repo_name: String,
     pub version_prefix: Option<String>,
     pub include_prereleases: bool,
-    client: Client,
+    http: HttpContext,
 }
 
 impl GitHubVersionDetector {
     pub fn new(repo_owner: String, repo_name: String, include_prereleases: bool) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("isod/0.1.0")
-            .build()
-            .unwrap_or_else(|_| Client::new());
-
         Self {
             repo_owner,
             repo_name,
             version_prefix: None,
             include_prereleases,
-            client,
+            http: http::context().clone(),
         }
     }
 
@@ -303,7 +299,7 @@ impl VersionDetector for GitHubVersionDetector {
         );
 
         let response = self
-            .client
+            .http
             .get(&url)
             .header("Accept", "application/vnd.github.v3+json")
             .send()
@@ -385,24 +381,18 @@ pub struct WebScrapingDetector {
     pub version_regex: String,    // Regex to extract version from text
     pub date_selector: Option<String>,
     pub date_format: Option<String>,
-    client: Client,
+    http: HttpContext,
 }
 
 impl WebScrapingDetector {
     pub fn new(base_url: String, version_selector: String, version_regex: String) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("isod/0.1.0")
-            .build()
-            .unwrap_or_else(|_| Client::new());
-
         Self {
             base_url,
             version_selector,
             version_regex,
             date_selector: None,
             date_format: None,
-            client,
+            http: http::context().clone(),
         }
     }
 }
@@ -411,7 +401,7 @@ impl WebScrapingDetector {
 impl VersionDetector for WebScrapingDetector {
     async fn detect_versions(&self) -> Result<Vec<VersionInfo>> {
         let response = self
-            .client
+            .http
             .get(&self.base_url)
             .send()
             .await
@@ -456,23 +446,17 @@ pub struct ApiVersionDetector {
     pub auth_header: Option<String>,
     pub version_json_path: String, // JSONPath to version field
     pub date_json_path: Option<String>,
-    client: Client,
+    http: HttpContext,
 }
 
 impl ApiVersionDetector {
     pub fn new(api_url: String, version_json_path: String) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("isod/0.1.0")
-            .build()
-            .unwrap_or_else(|_| Client::new());
-
         Self {
             api_url,
             auth_header: None,
             version_json_path,
             date_json_path: None,
-            client,
+            http: http::context().clone(),
         }
     }
 }
@@ -480,10 +464,16 @@ impl ApiVersionDetector {
 #[async_trait]
 impl VersionDetector for ApiVersionDetector {
     async fn detect_versions(&self) -> Result<Vec<VersionInfo>> {
-        let mut request = self.client.get(&self.api_url);
+        let mut request = self.http.get(&self.api_url);
 
         if let Some(auth) = &self.auth_header {
-            request = request.header("Authorization", auth);
+            // An explicit header replaces any stored credential for the host
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                auth.parse().context("Invalid API auth header")?,
+            );
+            request = request.headers(headers);
         }
 
         let response = request.send().await.context("Failed to fetch API data")?;
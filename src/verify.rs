@@ -0,0 +1,367 @@
+use futures_util::StreamExt;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::download::{ChecksumType, ChecksumVerifier};
+use crate::image;
+use crate::iso9660::IsoImage;
+use crate::manifest::{DeviceManifest, ManifestEntry};
+
+/// Outcome of scrubbing one ISO
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerifyStatus {
+    /// Hash matches every known checksum
+    Ok,
+    /// No checksum is known for the file
+    Unknown,
+    /// A recorded file is gone
+    Missing,
+    /// Hash differs from a known checksum, or the file could not be read
+    Mismatch,
+}
+
+impl VerifyStatus {
+    /// Process exit code for a scrub whose worst result is `self`.
+    ///
+    /// 0 when everything is OK, 2 on a mismatch, 3 when a file is missing and
+    /// 4 when some files could only be reported as unknown. 1 is left to
+    /// errors that stopped the scrub itself.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            VerifyStatus::Ok => 0,
+            VerifyStatus::Mismatch => 2,
+            VerifyStatus::Missing => 3,
+            VerifyStatus::Unknown => 4,
+        }
+    }
+}
+
+impl fmt::Display for VerifyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            VerifyStatus::Ok => "OK",
+            VerifyStatus::Unknown => "UNKNOWN",
+            VerifyStatus::Missing => "MISSING",
+            VerifyStatus::Mismatch => "MISMATCH",
+        })
+    }
+}
+
+/// A checksum the file is expected to have, and where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedChecksum {
+    pub checksum_type: ChecksumType,
+    pub value: String,
+    /// `manifest` or `upstream`
+    pub source: &'static str,
+}
+
+/// One file to scrub
+#[derive(Debug, Clone)]
+pub struct ScrubItem {
+    pub path: PathBuf,
+    pub entry: Option<ManifestEntry>,
+    pub expected: Vec<ExpectedChecksum>,
+}
+
+impl ScrubItem {
+    fn new(path: PathBuf, entry: Option<ManifestEntry>) -> Self {
+        let expected = entry
+            .as_ref()
+            .and_then(|entry| {
+                let checksum_type = ChecksumType::from_algorithm(entry.checksum_type.as_deref()?)?;
+                Some(ExpectedChecksum {
+                    checksum_type,
+                    value: entry.checksum.clone()?.to_lowercase(),
+                    source: "manifest",
+                })
+            })
+            .into_iter()
+            .collect();
+
+        Self {
+            path,
+            entry,
+            expected,
+        }
+    }
+
+    /// Also expect `checksum`, unless it is already known
+    pub fn expect(&mut self, checksum: ExpectedChecksum) {
+        let value = checksum.value.to_lowercase();
+        if !self
+            .expected
+            .iter()
+            .any(|e| e.checksum_type == checksum.checksum_type && e.value == value)
+        {
+            self.expected.push(ExpectedChecksum { value, ..checksum });
+        }
+    }
+
+    /// Whether the item belongs to `distro`, judged by the file name when untracked
+    pub fn matches_distro(&self, distro: &str) -> bool {
+        match &self.entry {
+            Some(entry) => entry.distro.eq_ignore_ascii_case(distro),
+            None => self.path.file_name().is_some_and(|name| {
+                name.to_string_lossy()
+                    .to_lowercase()
+                    .contains(&distro.to_lowercase())
+            }),
+        }
+    }
+}
+
+/// Result of scrubbing one file
+#[derive(Debug, Clone)]
+pub struct ScrubResult {
+    pub path: PathBuf,
+    pub status: VerifyStatus,
+    pub detail: Option<String>,
+}
+
+/// ISOs and the other images Ventoy boots under `dir`, skipping Ventoy's own directory
+pub fn find_isos(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkDir::new(dir)
+        .max_depth(if recursive { usize::MAX } else { 1 })
+        .into_iter()
+        .filter_entry(|e| e.depth() == 0 || e.file_name() != "ventoy")
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| image::is_bootable(e.path()))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Pair the files found with manifest entries.
+///
+/// Files without an entry are scrubbed as untracked; entries whose file was
+/// not found are kept at their expected path and reported missing. `iso_dir`
+/// is where device entries live.
+pub fn plan(files: Vec<PathBuf>, manifest: &DeviceManifest, iso_dir: &Path) -> Vec<ScrubItem> {
+    let mut items = Vec::new();
+    let mut seen = HashSet::new();
+
+    for path in files {
+        let entry = manifest
+            .entries()
+            .iter()
+            .find(|e| e.path(iso_dir) == path)
+            .or_else(|| {
+                // Files moved around on the device keep their recorded checksum
+                manifest.entries().iter().find(|e| {
+                    e.directory.is_none()
+                        && path.file_name().is_some_and(|name| *name == *e.filename)
+                })
+            })
+            .cloned();
+        if let Some(entry) = &entry {
+            seen.insert((entry.filename.clone(), entry.directory.clone()));
+        }
+        items.push(ScrubItem::new(path, entry));
+    }
+
+    for entry in manifest.entries() {
+        if !seen.contains(&(entry.filename.clone(), entry.directory.clone())) {
+            items.push(ScrubItem::new(entry.path(iso_dir), Some(entry.clone())));
+        }
+    }
+
+    items
+}
+
+/// Hash `items` on the blocking pool, `jobs` files at a time, keeping their order
+pub async fn scrub(items: Vec<ScrubItem>, jobs: usize) -> Vec<ScrubResult> {
+    futures_util::stream::iter(items)
+        .map(|item| async move {
+            let path = item.path.clone();
+            tokio::task::spawn_blocking(move || scrub_one(item))
+                .await
+                .unwrap_or_else(|e| ScrubResult {
+                    path,
+                    status: VerifyStatus::Mismatch,
+                    detail: Some(format!("hashing failed: {}", e)),
+                })
+        })
+        .buffered(jobs.max(1))
+        .collect()
+        .await
+}
+
+fn scrub_one(item: ScrubItem) -> ScrubResult {
+    let result = |status, detail| ScrubResult {
+        path: item.path.clone(),
+        status,
+        detail,
+    };
+
+    if !item.path.is_file() {
+        return result(
+            if item.entry.is_some() {
+                VerifyStatus::Missing
+            } else {
+                VerifyStatus::Unknown
+            },
+            None,
+        );
+    }
+    if item.expected.is_empty() {
+        // Without a checksum, the size the volume declares is all there is to check
+        return match IsoImage::open(&item.path) {
+            Ok(iso) if iso.is_truncated() => result(
+                VerifyStatus::Mismatch,
+                Some(format!(
+                    "truncated: {} of {} bytes",
+                    iso.file_size, iso.volume_size
+                )),
+            ),
+            _ => result(VerifyStatus::Unknown, Some("no checksum known".to_string())),
+        };
+    }
+
+    let mut hashed: Vec<(ChecksumType, String)> = Vec::new();
+    for expected in &item.expected {
+        let actual = match hashed
+            .iter()
+            .find(|(checksum_type, _)| *checksum_type == expected.checksum_type)
+        {
+            Some((_, actual)) => actual.clone(),
+            None => {
+                match ChecksumVerifier::calculate_checksum_blocking(
+                    &item.path,
+                    expected.checksum_type,
+                ) {
+                    Ok(actual) => {
+                        hashed.push((expected.checksum_type, actual.clone()));
+                        actual
+                    }
+                    Err(e) => {
+                        return result(
+                            VerifyStatus::Mismatch,
+                            Some(format!("unreadable: {:#}", e)),
+                        );
+                    }
+                }
+            }
+        };
+
+        if actual != expected.value {
+            return result(
+                VerifyStatus::Mismatch,
+                Some(format!(
+                    "{} from {} is {}, file has {}",
+                    expected.checksum_type.algorithm(),
+                    expected.source,
+                    expected.value,
+                    actual
+                )),
+            );
+        }
+    }
+
+    result(VerifyStatus::Ok, None)
+}
+
+/// Worst status among `results`, `Ok` when there are none
+pub fn worst(results: &[ScrubResult]) -> VerifyStatus {
+    results
+        .iter()
+        .map(|r| r.status)
+        .max()
+        .unwrap_or(VerifyStatus::Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ChecksumLayer, ImageFormat};
+    use crate::registry::signature::SignatureStatus;
+    use crate::registry::{IsoInfo, ReleaseType};
+    use std::fs;
+
+    fn iso_info(filename: &str, checksum: Option<String>) -> IsoInfo {
+        IsoInfo {
+            distro: "debian".to_string(),
+            version: "12.11.0".to_string(),
+            architecture: "amd64".to_string(),
+            variant: None,
+            filename: filename.to_string(),
+            local_filename: None,
+            format: ImageFormat::default(),
+            download_sources: Vec::new(),
+            checksum,
+            checksum_type: Some("sha256".to_string()),
+            checksum_layer: ChecksumLayer::Download,
+            release_date: None,
+            size_bytes: None,
+            release_type: ReleaseType::Stable,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scrub_reports_each_status() {
+        let device = tempfile::tempdir().unwrap();
+        let iso_dir = device.path().join("iso");
+        fs::create_dir_all(device.path().join("ventoy")).unwrap();
+        fs::create_dir_all(&iso_dir).unwrap();
+        fs::write(device.path().join("ventoy").join("ventoy.iso"), "x").unwrap();
+
+        fs::write(iso_dir.join("good.iso"), "good").unwrap();
+        fs::write(iso_dir.join("rotten.iso"), "bit rot").unwrap();
+        fs::write(iso_dir.join("untracked.iso"), "?").unwrap();
+        // Compressed downloads are not bootable until unpacked
+        fs::write(iso_dir.join("pending.img.xz"), "?").unwrap();
+        let good = ChecksumVerifier::calculate_checksum_blocking(
+            &iso_dir.join("good.iso"),
+            ChecksumType::Sha256,
+        )
+        .unwrap();
+
+        let mut manifest = DeviceManifest::load(DeviceManifest::on_device(device.path())).unwrap();
+        for (filename, checksum) in [
+            ("good.iso", Some(good.to_uppercase())),
+            ("rotten.iso", Some(good.clone())),
+            ("gone.iso", Some(good.clone())),
+        ] {
+            manifest.record(ManifestEntry::new(
+                &iso_info(filename, checksum),
+                SignatureStatus::unsigned("test"),
+            ));
+        }
+
+        let files = find_isos(device.path(), true);
+        assert_eq!(files.len(), 3);
+        let items = plan(files, &manifest, &iso_dir);
+        let results = scrub(items, 2).await;
+
+        let status = |name: &str| {
+            results
+                .iter()
+                .find(|r| r.path.file_name().unwrap() == name)
+                .unwrap()
+                .status
+        };
+        assert_eq!(status("good.iso"), VerifyStatus::Ok);
+        assert_eq!(status("rotten.iso"), VerifyStatus::Mismatch);
+        assert_eq!(status("untracked.iso"), VerifyStatus::Unknown);
+        assert_eq!(status("gone.iso"), VerifyStatus::Missing);
+        assert_eq!(worst(&results).exit_code(), 2);
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct_from_errors() {
+        let codes: Vec<i32> = [
+            VerifyStatus::Ok,
+            VerifyStatus::Unknown,
+            VerifyStatus::Missing,
+            VerifyStatus::Mismatch,
+        ]
+        .iter()
+        .map(VerifyStatus::exit_code)
+        .collect();
+        assert_eq!(codes, vec![0, 4, 3, 2]);
+    }
+}
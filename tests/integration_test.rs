@@ -2,7 +2,7 @@ use anyhow::Result;
 use isod::download::{
     ChecksumType, DownloadManager, DownloadOptions, DownloadProgress, DownloadRequest,
 };
-use isod::registry::{IsoRegistry, ReleaseType};
+use isod::registry::{IsoRegistry, ReleaseType, definitions};
 use tempfile::TempDir;
 
 #[tokio::test]
@@ -106,6 +106,61 @@ async fn test_search_functionality() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_distro_definitions_from_directory() -> Result<()> {
+    let config_dir = TempDir::new()?;
+    let dir = definitions::dir_in(config_dir.path());
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(
+        dir.join("labos.toml"),
+        r#"
+name = "labos"
+display_name = "Lab OS"
+description = "Internal lab image"
+architectures = ["x86_64"]
+filename_pattern = "labos-{version}-{arch}.iso"
+checksum_urls = ["https://isos.lab.example/{version}/SHA256SUMS"]
+
+[detector]
+type = "static"
+versions = [{ version = "3.1" }, { version = "3.2", release_type = "lts" }]
+
+[[sources]]
+url = "https://isos.lab.example/{version}/{filename}"
+"#,
+    )?;
+    // Built-in distros cannot be replaced
+    std::fs::write(
+        dir.join("ubuntu.toml"),
+        r#"
+name = "ubuntu"
+architectures = ["amd64"]
+filename_pattern = "ubuntu-{version}.iso"
+detector = { type = "static", versions = [{ version = "1.0" }] }
+sources = [{ url = "https://example.com/{filename}" }]
+"#,
+    )?;
+
+    let registry = IsoRegistry::new().with_distro_definitions(&dir);
+    assert!(registry.is_supported("labos"));
+    assert_eq!(registry.search_distros("lab os"), vec!["labos"]);
+    assert_eq!(
+        registry.get_distro("ubuntu").unwrap().display_name,
+        "Ubuntu"
+    );
+
+    let iso_info = registry.get_iso_info("labos", None, None, None).await?;
+    assert_eq!(iso_info.version, "3.2");
+    assert_eq!(iso_info.release_type, ReleaseType::LTS);
+    assert_eq!(iso_info.filename, "labos-3.2-x86_64.iso");
+    assert_eq!(
+        iso_info.download_sources[0].get_url(),
+        Some("https://isos.lab.example/3.2/labos-3.2-x86_64.iso")
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_download_request_creation() -> Result<()> {
     let temp_dir = TempDir::new()?;
@@ -119,7 +174,8 @@ async fn test_download_request_creation() -> Result<()> {
     assert_eq!(request.url, "https://example.com/test.iso");
     assert_eq!(request.output_path, output_path);
     assert!(request.resume);
-    assert!(request.user_agent.is_some());
+    // The shared HTTP client's configured user agent applies unless overridden
+    assert!(request.user_agent.is_none());
     assert!(request.expected_checksum.is_none());
 
     // Test with checksum